@@ -0,0 +1,189 @@
+// リクエストのprotocol_versionを解釈し、過去のバージョンの形のリクエストを現在のDTOの形に変換する
+// protocol_versionを省略したリクエストは、現在のバージョンとして扱う
+// 既存のクライアントが扱えなくなる変更を加えてメジャーバージョンを上げる場合は、
+// 変更前のメジャーバージョンからの変換をADAPTERSに追加する
+use serde_json::Value;
+
+use crate::error;
+use crate::version::PROTOCOL_VERSION;
+
+/// MAJOR.MINOR.PATCH形式のバージョン
+/// MINOR, PATCHは省略でき、省略した場合は0とみなす
+/// PATCHの違いは形に影響しないので保持しない
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ProtocolVersion {
+    major: u64,
+    minor: u64,
+}
+
+impl ProtocolVersion {
+    fn parse(version: &str) -> Option<Self> {
+        let mut numbers = version.split('.').map(|n| {
+            // u64::from_strが受け付ける"+1"のような表記は除外する
+            if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            n.parse::<u64>().ok()
+        });
+        let major = numbers.next()??;
+        let minor = numbers.next().unwrap_or(Some(0))?;
+        let _patch = numbers.next().unwrap_or(Some(0))?;
+        if numbers.next().is_some() {
+            return None;
+        }
+        Some(ProtocolVersion { major, minor })
+    }
+
+    fn current() -> Self {
+        // PROTOCOL_VERSIONが正しい形式であることはテストで確認している
+        Self::parse(PROTOCOL_VERSION).unwrap()
+    }
+}
+
+// 1つ新しいメジャーバージョンの形への変換
+type Adapter = fn(&mut Value);
+
+// (変換元のメジャーバージョン, 変換)
+// 古いバージョンのリクエストは、現在のバージョンになるまで順に変換する
+const ADAPTERS: &[(u64, Adapter)] = &[(0, upgrade_from_0)];
+
+/// protocol_versionに応じてリクエストを現在のバージョンの形に変換する
+/// 解釈できないバージョンや、変換できないバージョンが指定された場合はエラーを返す
+pub(crate) fn upgrade(json: &str, protocol_version: Option<&str>) -> Result<String, error::Error> {
+    let requested = match protocol_version {
+        Some(requested) => requested,
+        None => return Ok(json.to_string()),
+    };
+    let unsupported = || error::Error::UnsupportedProtocolVersion {
+        requested: requested.to_string(),
+        current: PROTOCOL_VERSION.to_string(),
+    };
+
+    let version = ProtocolVersion::parse(requested).ok_or_else(|| {
+        error::Error::invalid_request(format!(
+            "protocol_version must be MAJOR.MINOR.PATCH: {}",
+            requested
+        ))
+    })?;
+    let current = ProtocolVersion::current();
+    // 同じメジャーバージョンであっても、未実装の機能を前提とした新しいマイナーバージョンには応じられない
+    if version.major > current.major
+        || (version.major == current.major && version.minor > current.minor)
+    {
+        return Err(unsupported());
+    }
+    if version.major == current.major {
+        return Ok(json.to_string());
+    }
+
+    let mut message = serde_json::from_str::<Value>(json)
+        .map_err(|e| error::Error::invalid_request(e.to_string()))?;
+    for major in version.major..current.major {
+        let adapter = ADAPTERS
+            .iter()
+            .find(|(from, _)| *from == major)
+            .ok_or_else(unsupported)?;
+        (adapter.1)(&mut message);
+    }
+    Ok(message.to_string())
+}
+
+// 0: バージョン番号を付ける前の形
+// - SYSTEMリクエストはcommandを解釈せず、全て終了命令として扱っていた
+// - RTCP Socketの削除は、RTCP_CREATEにrtcp_idを渡していた
+fn upgrade_from_0(message: &mut Value) {
+    let request_type = message.get("request_type").and_then(Value::as_str);
+    let command = message.get("command").and_then(Value::as_str);
+    match (request_type, command) {
+        (Some("SYSTEM"), _) => {
+            message["command"] = Value::from("SHUTDOWN");
+        }
+        (Some("MEDIA"), Some("RTCP_CREATE"))
+            if message
+                .get("params")
+                .and_then(|params| params.get("rtcp_id"))
+                .is_some() =>
+        {
+            message["command"] = Value::from("RTCP_DELETE");
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod compat_test {
+    use super::*;
+    use crate::application::dto::request::{MediaRequestDto, RequestDto, SystemRequestDto};
+
+    fn upgrade_dto(json: &str, protocol_version: Option<&str>) -> Result<RequestDto, error::Error> {
+        upgrade(json, protocol_version).and_then(|json| RequestDto::from_str(&json))
+    }
+
+    #[test]
+    fn current_version() {
+        assert!(ProtocolVersion::parse(PROTOCOL_VERSION).is_some());
+        let json = r#"{"request_type":"SYSTEM","command":"STATUS"}"#;
+        // 省略した場合と、現在と同じメジャーバージョンで互換性のあるものを指定した場合は変換しない
+        for version in [None, Some("1"), Some("1.0"), Some("1.0.3")] {
+            assert_eq!(
+                upgrade_dto(json, version),
+                Ok(RequestDto::System(SystemRequestDto::Status))
+            );
+        }
+    }
+
+    #[test]
+    fn from_version_0() {
+        let json = r#"{"request_type":"SYSTEM","command":"STATUS"}"#;
+        assert_eq!(
+            upgrade_dto(json, Some("0")),
+            Ok(RequestDto::System(SystemRequestDto::Shutdown))
+        );
+
+        let json = r#"{
+            "request_type":"MEDIA",
+            "command":"RTCP_CREATE",
+            "params":{"rtcp_id":"rc-970f2e4d-0ac1-4ab8-b2a6-b27dbe40b4d2"}
+        }"#;
+        let result = upgrade_dto(json, Some("0.9.1"));
+        assert!(matches!(
+            result,
+            Ok(RequestDto::Media(MediaRequestDto::RtcpDelete { .. }))
+        ));
+
+        // 形の変わっていないリクエストはそのまま解釈できる
+        let json = r#"{"request_type":"MEDIA","command":"RTCP_CREATE","params":null}"#;
+        let result = upgrade_dto(json, Some("0"));
+        assert!(matches!(
+            result,
+            Ok(RequestDto::Media(MediaRequestDto::RtcpCreate { .. }))
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let json = r#"{"request_type":"SYSTEM","command":"STATUS"}"#;
+        for version in ["2", "1.1", "2.0.0"] {
+            assert_eq!(
+                upgrade(json, Some(version)),
+                Err(error::Error::UnsupportedProtocolVersion {
+                    requested: version.to_string(),
+                    current: PROTOCOL_VERSION.to_string(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_version() {
+        let json = r#"{"request_type":"SYSTEM","command":"STATUS"}"#;
+        for version in ["", "v1", "1.0.0.0", "1..0", "+1"] {
+            let result = upgrade(json, Some(version));
+            assert!(
+                matches!(result, Err(error::Error::InvalidRequest { .. })),
+                "{}",
+                version
+            );
+        }
+    }
+}
@@ -1,11 +1,15 @@
+pub(crate) mod compat;
 pub(crate) mod request;
 pub(crate) mod response;
+pub(crate) mod schema;
 
-use crate::application::dto::request::{DataRequestDto, MediaRequestDto, RequestDto};
+use crate::application::dto::request::{
+    DataRequestDto, MediaRequestDto, PeerRequestDto, RequestDto,
+};
 use crate::application::dto::response::{
     DataResponseDto, MediaResponseDto, PeerResponseDto, ResponseDto, ResponseDtoResult,
 };
-use crate::domain::entity::request::{DataRequest, MediaRequest, Request};
+use crate::domain::entity::request::{DataRequest, MediaRequest, PeerRequest, Request};
 use crate::domain::entity::response::{
     DataResponse, MediaResponse, PeerResponse, Response, ResponseResult,
 };
@@ -19,7 +23,15 @@ pub(crate) trait Command {
 /// Dto objectからDomain objectへの変換
 pub(crate) fn dto_to_request(dto: RequestDto) -> Result<Request, error::Error> {
     match dto {
-        RequestDto::Peer(parameter) => Ok(Request::Peer(parameter)),
+        RequestDto::Peer(PeerRequestDto::Create { params }) => {
+            Ok(Request::Peer(PeerRequest::Create { params }))
+        }
+        RequestDto::Peer(PeerRequestDto::Status { params }) => {
+            Ok(Request::Peer(PeerRequest::Status { params }))
+        }
+        RequestDto::Peer(PeerRequestDto::Delete { params }) => {
+            Ok(Request::Peer(PeerRequest::Delete { params }))
+        }
         RequestDto::Data(DataRequestDto::Create) => {
             Ok(Request::Data(DataRequest::Create { params: true }))
         }
@@ -63,7 +75,7 @@ pub(crate) fn dto_to_request(dto: RequestDto) -> Result<Request, error::Error> {
         }
         param => {
             let message = format!("invalid parameter for GeneralService {:?}", param);
-            Err(error::Error::create_local_error(&message))
+            Err(error::Error::internal(message))
         }
     }
 }
@@ -112,7 +124,7 @@ pub(crate) fn result_to_dto(response: ResponseResult) -> Result<ResponseDtoResul
         ),
         param => {
             let message = format!("invalid response for GeneralService {:?}", param);
-            Err(error::Error::create_local_error(&message))
+            Err(error::Error::internal(message))
         }
     }
 }
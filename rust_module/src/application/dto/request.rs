@@ -1,38 +1,141 @@
-pub(crate) use crate::domain::entity::request::PeerRequest as PeerRequestDto;
-
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tracing::level_filters::LevelFilter;
 
+use crate::application::dto::schema;
 use crate::application::dto::Command;
 use crate::domain::entity::request::IsVideo;
 use crate::domain::entity::{
-    ConnectQueryOption, DataConnectionId, DataConnectionIdWrapper, DataIdWrapper,
-    MediaConnectionId, MediaConnectionIdWrapper, MediaIdWrapper, PeerId, PhantomId,
+    ConnectQueryOption, CreatePeerParams, DataConnectionId, DataConnectionIdWrapper, DataIdWrapper,
+    MediaConnectionId, MediaConnectionIdWrapper, MediaIdWrapper, PeerId, PeerInfo, PhantomId,
     RedirectParameters, RtcpIdWrapper, SocketInfo, Token,
 };
 use crate::error;
 
 //========== System ==========
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct SystemRequestDto {
-    pub(crate) command: String,
+// WebRTC Gatewayの操作ではなく、rust_module自身に対する命令
+// 未知のcommandはパースの時点でエラーにする
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "command")]
+pub(crate) enum SystemRequestDto {
+    #[serde(rename = "SHUTDOWN")]
+    Shutdown,
+    #[serde(rename = "STATUS")]
+    Status,
+    #[serde(rename = "VERSION")]
+    Version,
+    #[serde(rename = "LIST_CONNECTIONS")]
+    ListConnections,
+    #[serde(rename = "PING_GATEWAY")]
+    PingGateway,
+    /// paramsを省略した場合は、レベルを変更せずに現在の値を返す
+    #[serde(rename = "LOG_LEVEL")]
+    LogLevel {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        params: Option<LogLevelParams>,
+    },
+    #[serde(rename = "METRICS")]
+    Metrics,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct LogLevelParams {
+    pub level: LogLevelDto,
+}
+
+/// C++側のLoggerに転送するログのレベル。指定したレベル以上のログのみを転送する
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub(crate) enum LogLevelDto {
+    #[serde(rename = "DEBUG")]
+    Debug,
+    #[serde(rename = "INFO")]
+    Info,
+    #[serde(rename = "WARN")]
+    Warn,
+    #[serde(rename = "ERROR")]
+    Error,
+    #[serde(rename = "OFF")]
+    Off,
+}
+
+impl LogLevelDto {
+    pub fn to_filter(self) -> LevelFilter {
+        match self {
+            LogLevelDto::Debug => LevelFilter::DEBUG,
+            LogLevelDto::Info => LevelFilter::INFO,
+            LogLevelDto::Warn => LevelFilter::WARN,
+            LogLevelDto::Error => LevelFilter::ERROR,
+            LogLevelDto::Off => LevelFilter::OFF,
+        }
+    }
+
+    // C++側にはTRACEに相当するレベルがないので、DEBUGとして扱う
+    pub fn from_filter(filter: LevelFilter) -> Self {
+        match filter {
+            LevelFilter::OFF => LogLevelDto::Off,
+            LevelFilter::ERROR => LogLevelDto::Error,
+            LevelFilter::WARN => LogLevelDto::Warn,
+            LevelFilter::INFO => LogLevelDto::Info,
+            _ => LogLevelDto::Debug,
+        }
+    }
+}
+
+impl Command for SystemRequestDto {
+    fn command(&self) -> String {
+        match self {
+            SystemRequestDto::Shutdown => "SHUTDOWN".to_string(),
+            SystemRequestDto::Status => "STATUS".to_string(),
+            SystemRequestDto::Version => "VERSION".to_string(),
+            SystemRequestDto::ListConnections => "LIST_CONNECTIONS".to_string(),
+            SystemRequestDto::PingGateway => "PING_GATEWAY".to_string(),
+            SystemRequestDto::LogLevel { .. } => "LOG_LEVEL".to_string(),
+            SystemRequestDto::Metrics => "METRICS".to_string(),
+        }
+    }
 }
 
 //========== Peer ==========
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "command")]
+pub(crate) enum PeerRequestDto {
+    #[serde(rename = "CREATE")]
+    Create {
+        #[schemars(with = "schema::CreatePeerParams")]
+        params: CreatePeerParams,
+    },
+    #[serde(rename = "STATUS")]
+    Status {
+        #[schemars(with = "schema::PeerInfo")]
+        params: PeerInfo,
+    },
+    #[serde(rename = "DELETE")]
+    Delete {
+        #[schemars(with = "schema::PeerInfo")]
+        params: PeerInfo,
+    },
+    // WebRTC GatewayのAPIは呼ばず、このノードが生成したPeer Objectの一覧を返す
+    #[serde(rename = "LIST")]
+    List,
+}
+
 impl Command for PeerRequestDto {
     fn command(&self) -> String {
         match self {
             PeerRequestDto::Create { params: ref _p } => "CREATE".to_string(),
             PeerRequestDto::Delete { params: ref _p } => "DELETE".to_string(),
             PeerRequestDto::Status { params: ref _p } => "STATUS".to_string(),
+            PeerRequestDto::List => "LIST".to_string(),
         }
     }
 }
 
 //========== Media ==========
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct MediaParamsDto {
     /// band width between Peers
     pub band_width: usize,
@@ -46,7 +149,7 @@ pub struct MediaParamsDto {
     pub sampling_rate: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[allow(non_snake_case)]
 pub struct ConstraintsDto {
     /// Parameters for sending video
@@ -60,13 +163,37 @@ pub struct ConstraintsDto {
     pub metadata: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Direction of a media track
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub enum MediaDirection {
+    #[serde(rename = "sendrecv")]
+    SendRecv,
+    #[serde(rename = "sendonly")]
+    SendOnly,
+    #[serde(rename = "recvonly")]
+    RecvOnly,
+}
+
+impl MediaDirection {
+    pub fn sends(&self) -> bool {
+        matches!(self, MediaDirection::SendRecv | MediaDirection::SendOnly)
+    }
+
+    pub fn receives(&self) -> bool {
+        matches!(self, MediaDirection::SendRecv | MediaDirection::RecvOnly)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct CallQueryDto {
     /// to identify which PeerObject calls to neighbour
+    #[schemars(with = "schema::PeerId")]
     pub peer_id: PeerId,
     /// to show that this program has permission to control PeerObject
+    #[schemars(with = "schema::Token")]
     pub token: Token,
     /// connect to the neighbour which has this PeerId
+    #[schemars(with = "schema::PeerId")]
     pub target_id: PeerId,
     /// Parameters for MediaConnection
     /// It contains source socket. If the field is None, this MediaConnection works as RecvOnly.
@@ -75,10 +202,19 @@ pub struct CallQueryDto {
     /// Shows destination socket to which received data is redirected
     /// If this field is not set, DataConnection works as SendOnly.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::RedirectParameters>")]
     pub redirect_params: Option<RedirectParameters>,
+    /// Direction of the video track.
+    /// If this field is not set, it is decided by video_params and redirect_params.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_direction: Option<MediaDirection>,
+    /// Direction of the audio track.
+    /// If this field is not set, it is decided by audio_params and redirect_params.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_direction: Option<MediaDirection>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct AnswerQueryDto {
     /// Parameters for MediaConnection
     /// It contains source socket. If the field is None, this MediaConnection works as RecvOnly.
@@ -86,34 +222,61 @@ pub struct AnswerQueryDto {
     /// Shows destiation socket to which received data is redirected
     /// If this field is not set, DataConnection works as SendOnly.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::RedirectParameters>")]
     pub redirect_params: Option<RedirectParameters>,
+    /// Direction of the video track.
+    /// If this field is not set, it is decided by video_params and redirect_params.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_direction: Option<MediaDirection>,
+    /// Direction of the audio track.
+    /// If this field is not set, it is decided by audio_params and redirect_params.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_direction: Option<MediaDirection>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub(crate) struct AnswerParametersDto {
+    #[schemars(with = "schema::MediaConnectionId")]
     pub media_connection_id: MediaConnectionId,
     pub answer_query: AnswerQueryDto,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "command")]
 pub(crate) enum MediaRequestDto {
     #[serde(rename = "CONTENT_CREATE")]
     ContentCreate { params: IsVideo },
     #[serde(rename = "CONTENT_DELETE")]
-    ContentDelete { params: MediaIdWrapper },
+    ContentDelete {
+        #[schemars(with = "schema::MediaIdWrapper")]
+        params: MediaIdWrapper,
+    },
     #[serde(rename = "RTCP_CREATE")]
     RtcpCreate { params: Option<()> },
-    #[serde(rename = "RTCP_CREATE")]
-    RtcpDelete { params: RtcpIdWrapper },
+    #[serde(rename = "RTCP_DELETE")]
+    RtcpDelete {
+        #[schemars(with = "schema::RtcpIdWrapper")]
+        params: RtcpIdWrapper,
+    },
     #[serde(rename = "CALL")]
     Call { params: CallQueryDto },
     #[serde(rename = "STATUS")]
-    Status { params: MediaConnectionIdWrapper },
+    Status {
+        #[schemars(with = "schema::MediaConnectionIdWrapper")]
+        params: MediaConnectionIdWrapper,
+    },
     #[serde(rename = "ANSWER")]
     Answer { params: AnswerParametersDto },
     #[serde(rename = "DISCONNECT")]
-    Disconnect { params: MediaConnectionIdWrapper },
+    Disconnect {
+        #[schemars(with = "schema::MediaConnectionIdWrapper")]
+        params: MediaConnectionIdWrapper,
+    },
+    #[serde(rename = "LIST")]
+    List {
+        #[serde(default, skip_serializing_if = "ListParamsDto::is_default")]
+        params: ListParamsDto,
+    },
 }
 
 impl Command for MediaRequestDto {
@@ -127,52 +290,92 @@ impl Command for MediaRequestDto {
             MediaRequestDto::Status { .. } => "STATUS".to_string(),
             MediaRequestDto::Answer { .. } => "ANSWER".to_string(),
             MediaRequestDto::Disconnect { .. } => "DISCONNECT".to_string(),
+            MediaRequestDto::List { .. } => "LIST".to_string(),
         }
     }
 }
 
 //========== Data ==========
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// DATA LIST, MEDIA LISTのparams
+// paramsごと省略した場合は、このノードが保持している情報のみを返す
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ListParamsDto {
+    // trueの場合、各ConnectionのSTATUSをWebRTC Gatewayに問い合わせて結果に含める
+    #[serde(default)]
+    pub with_status: bool,
+}
+
+impl ListParamsDto {
+    // paramsを省略したリクエストは、省略した形のままシリアライズする
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub(crate) struct ConnectDtoParams {
+    #[schemars(with = "schema::PeerId")]
     pub peer_id: PeerId,
+    #[schemars(with = "schema::Token")]
     pub token: Token,
+    #[schemars(with = "schema::PeerId")]
     pub target_id: PeerId,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::ConnectQueryOption>")]
     pub options: Option<ConnectQueryOption>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::DataIdWrapper>")]
     pub params: Option<DataIdWrapper>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::Socket>")]
     pub redirect_params: Option<SocketInfo<PhantomId>>,
     pub plugin_info: PluginInfo,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub(crate) struct PluginInfo {
     pub r#type: String,
     pub plugins: Vec<Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub(crate) struct RedirectDtoParams {
+    #[schemars(with = "schema::DataConnectionId")]
     pub data_connection_id: DataConnectionId,
     pub plugin_info: PluginInfo,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "command")]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum DataRequestDto {
     #[serde(rename = "CREATE")]
     Create,
     #[serde(rename = "DELETE")]
-    Delete { params: DataIdWrapper },
+    Delete {
+        #[schemars(with = "schema::DataIdWrapper")]
+        params: DataIdWrapper,
+    },
     #[serde(rename = "CONNECT")]
     Connect { params: ConnectDtoParams },
     #[serde(rename = "REDIRECT")]
     Redirect { params: RedirectDtoParams },
     #[serde(rename = "DISCONNECT")]
-    Disconnect { params: DataConnectionIdWrapper },
+    Disconnect {
+        #[schemars(with = "schema::DataConnectionIdWrapper")]
+        params: DataConnectionIdWrapper,
+    },
     #[serde(rename = "STATUS")]
-    Status { params: DataConnectionIdWrapper },
+    Status {
+        #[schemars(with = "schema::DataConnectionIdWrapper")]
+        params: DataConnectionIdWrapper,
+    },
+    #[serde(rename = "LIST")]
+    List {
+        #[serde(default, skip_serializing_if = "ListParamsDto::is_default")]
+        params: ListParamsDto,
+    },
 }
 
 impl Command for DataRequestDto {
@@ -184,13 +387,119 @@ impl Command for DataRequestDto {
             DataRequestDto::Redirect { .. } => "REDIRECT".to_string(),
             DataRequestDto::Disconnect { .. } => "DISCONNECT".to_string(),
             DataRequestDto::Status { .. } => "STATUS".to_string(),
+            DataRequestDto::List { .. } => "LIST".to_string(),
+        }
+    }
+}
+
+//========== Event ==========
+
+/// Conditions to select the events delivered to a subscription.
+/// Every specified field must match. Omitted fields match any event.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EventFilterDto {
+    /// request_type of the event. `"PEER"`, `"DATA"`, `"MEDIA"` or `"SYSTEM"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_type: Option<String>,
+    /// Name of the event such as `"OPEN"` or `"CLOSE"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    /// PeerId contained in the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub peer_id: Option<PeerId>,
+    /// DataConnectionId contained in the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::DataConnectionId>")]
+    pub data_connection_id: Option<DataConnectionId>,
+    /// MediaConnectionId contained in the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::MediaConnectionId>")]
+    pub media_connection_id: Option<MediaConnectionId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct SubscribeParams {
+    /// Name to identify the subscription. It is specified when polling events.
+    pub subscription_id: String,
+    /// Only the events matching this filter are delivered. All events are delivered if omitted.
+    #[serde(default)]
+    pub filter: EventFilterDto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct SubscriptionIdWrapper {
+    pub subscription_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "command")]
+pub(crate) enum EventRequestDto {
+    #[serde(rename = "SUBSCRIBE")]
+    Subscribe { params: SubscribeParams },
+    #[serde(rename = "UNSUBSCRIBE")]
+    Unsubscribe { params: SubscriptionIdWrapper },
+}
+
+impl Command for EventRequestDto {
+    fn command(&self) -> String {
+        match self {
+            EventRequestDto::Subscribe { .. } => "SUBSCRIBE".to_string(),
+            EventRequestDto::Unsubscribe { .. } => "UNSUBSCRIBE".to_string(),
+        }
+    }
+}
+
+//========== Batch ==========
+
+/// BATCHリクエストの処理方法
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub(crate) enum BatchMode {
+    /// 先頭から順に処理し、失敗した時点で以降の処理を行わない
+    #[serde(rename = "SEQUENTIAL")]
+    Sequential,
+    /// SEQUENTIALに加え、失敗した場合はそれまでに生成したオブジェクトを逆順に解放する
+    #[serde(rename = "ATOMIC")]
+    Atomic,
+}
+
+/// 複数のリクエストを1度のcall_serviceで処理するためのメッセージ
+/// requestsの各要素は通常のリクエストと同じ形だが、先に処理したリクエストの結果を参照できるよう、
+/// 参照を解決するまではJSONのまま保持する
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct BatchRequestDto {
+    pub request_type: BatchRequestType,
+    pub mode: BatchMode,
+    pub requests: Vec<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub(crate) enum BatchRequestType {
+    #[serde(rename = "BATCH")]
+    Batch,
+}
+
+impl BatchRequestDto {
+    /// request_typeがBATCHでない場合はNoneを返す
+    pub fn from_str(json: &str) -> Option<Result<Self, error::Error>> {
+        #[derive(Deserialize)]
+        struct RequestType {
+            request_type: String,
+        }
+        match serde_json::from_str::<RequestType>(json) {
+            Ok(RequestType { request_type }) if request_type == "BATCH" => Some(
+                serde_json::from_str::<BatchRequestDto>(json)
+                    .map_err(|e| error::Error::invalid_request(e.to_string())),
+            ),
+            _ => None,
         }
     }
 }
 
 //========== General ==========
 // JSONでクライアントから受け取るメッセージ
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "request_type")]
 pub(crate) enum RequestDto {
     #[serde(rename = "PEER")]
@@ -201,13 +510,16 @@ pub(crate) enum RequestDto {
     Media(MediaRequestDto),
     #[serde(rename = "SYSTEM")]
     System(SystemRequestDto),
+    #[serde(rename = "EVENT")]
+    Event(EventRequestDto),
     #[cfg(test)]
     Test,
 }
 
 impl RequestDto {
     pub fn from_str(json: &str) -> Result<Self, error::Error> {
-        serde_json::from_str::<RequestDto>(json).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::from_str::<RequestDto>(json)
+            .map_err(|e| error::Error::invalid_request(e.to_string()))
     }
 
     pub fn dto_type(&self) -> String {
@@ -216,6 +528,7 @@ impl RequestDto {
             RequestDto::Data(ref _d) => "DATA".to_string(),
             RequestDto::Media(ref _m) => "MEDIA".to_string(),
             RequestDto::System(ref _m) => "SYSTEM".to_string(),
+            RequestDto::Event(ref _e) => "EVENT".to_string(),
             #[cfg(test)]
             _ => "TEST".to_string(),
         }
@@ -223,7 +536,43 @@ impl RequestDto {
 
     #[allow(dead_code)]
     pub fn to_string(&self) -> Result<String, error::Error> {
-        serde_json::to_string(self).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::to_string(self).map_err(|e| error::Error::internal(e.to_string()))
+    }
+}
+
+/// request_typeによらず、全てのリクエストに付与できるオプション
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+pub(crate) struct RequestOptions {
+    /// WebRTC Gatewayの応答を待つ期限。省略した場合は設定値が用いられる
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// リクエストの形式のバージョン。省略した場合は現在のバージョンとして扱う
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>,
+    /// 応答と、このリクエストが原因で発生したイベントに付与する識別子。省略した場合は自動で生成する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl RequestOptions {
+    pub fn from_str(json: &str) -> Result<Self, error::Error> {
+        let options = serde_json::from_str::<RequestOptions>(json)
+            .map_err(|e| error::Error::invalid_request(e.to_string()))?;
+        if options.timeout_ms == Some(0) {
+            return Err(error::Error::invalid_request(
+                "timeout_ms must be greater than 0",
+            ));
+        }
+        if options.request_id.as_deref() == Some("") {
+            return Err(error::Error::invalid_request(
+                "request_id must not be empty",
+            ));
+        }
+        Ok(options)
+    }
+
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_ms.map(std::time::Duration::from_millis)
     }
 }
 
@@ -233,7 +582,8 @@ impl Command for RequestDto {
             RequestDto::Peer(ref peer) => peer.command(),
             RequestDto::Data(ref data) => data.command(),
             RequestDto::Media(ref media) => media.command(),
-            RequestDto::System(_) => "SYSTEM".to_string(),
+            RequestDto::System(ref system) => system.command(),
+            RequestDto::Event(ref event) => event.command(),
             #[cfg(test)]
             RequestDto::Test => {
                 unreachable!()
@@ -1,65 +1,218 @@
+use schemars::JsonSchema;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::application::dto::request::{LogLevelDto, SubscribeParams, SubscriptionIdWrapper};
+use crate::application::dto::schema;
+use crate::domain::entity::event::SystemEvent;
 use crate::domain::entity::response::{DataResponse, MediaResponse, PeerResponse};
 use crate::domain::entity::{
     AnswerResult, DataConnectionId, DataConnectionIdWrapper, DataConnectionStatus, DataId,
     DataIdWrapper, MediaConnectionId, MediaConnectionIdWrapper, MediaConnectionStatus, MediaId,
-    MediaIdWrapper, PeerCallEvent, PeerCloseEvent, PeerErrorEvent, PeerInfo, PeerOpenEvent,
+    MediaIdWrapper, PeerCloseEvent, PeerErrorEvent, PeerId, PeerInfo, PeerOpenEvent,
     PeerStatusMessage, RedirectParameters, RtcpId, RtcpIdWrapper, SerializableId, SocketInfo,
+    Token,
 };
 use crate::error;
 
 //========== System ==========
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct SystemResponseDto {
-    pub(crate) is_success: bool,
+
+/// SHUTDOWNの各段階
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum ShutdownStepKind {
+    DataDisconnect,
+    MediaDisconnect,
+    MediaDelete,
+    RtcpDelete,
+    DataDelete,
+    PeerDelete,
+}
+
+/// SHUTDOWNで解放を試みたオブジェクト1つ分の結果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct ShutdownStepDto {
+    pub step: ShutdownStepKind,
+    /// 解放したオブジェクトのID
+    pub target: String,
+    pub is_success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<error::Error>,
+}
+
+/// PING_GATEWAYの結果。STATUSにも含まれる
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct GatewayPingDto {
+    /// WebRTC GatewayからHTTPの応答が得られた場合はtrue
+    pub reachable: bool,
+    /// 応答を得るまで、または到達できないと判明するまでの時間
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<error::Error>,
+}
+
+/// METRICSの結果。値は起動時からの累計
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct MetricsDto {
+    pub uptime_ms: u64,
+    /// call_serviceで処理したリクエストのrequest_type, commandごとの統計
+    pub requests: Vec<RequestMetricsDto>,
+    /// 配信したイベントのrequest_type, eventごとの数
+    pub events: Vec<EventMetricsDto>,
+    /// WebRTC Gatewayの呼び出しの、request_typeごとの応答時間
+    pub gateway_calls: Vec<GatewayCallMetricsDto>,
+    /// 現在このノードが把握しているPeer Object, Connectionの数
+    pub peers: usize,
+    pub data_connections: usize,
+    pub media_connections: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct RequestMetricsDto {
+    pub request_type: String,
+    pub command: String,
+    pub count: u64,
+    /// WebRTC Gatewayが返したエラーを含む、失敗したリクエストの数
+    pub error_count: u64,
+    pub latency: LatencyHistogramDto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct EventMetricsDto {
+    pub request_type: String,
+    pub event: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct GatewayCallMetricsDto {
+    pub request_type: String,
+    /// 期限切れなどで応答が得られなかったものも、諦めるまでの時間として含む
+    pub latency: LatencyHistogramDto,
+}
+
+/// 応答時間の分布
+/// bucketsは上限の昇順に並び、countはそれぞれの上限以下だったものの累計
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct LatencyHistogramDto {
+    pub buckets: Vec<LatencyBucketDto>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct LatencyBucketDto {
+    pub le_ms: u64,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "command")]
+pub(crate) enum SystemResponseDto {
+    /// is_successは全ての段階が成功した場合のみtrue
+    #[serde(rename = "SHUTDOWN")]
+    Shutdown {
+        is_success: bool,
+        steps: Vec<ShutdownStepDto>,
+    },
+    #[serde(rename = "STATUS")]
+    Status {
+        uptime_ms: u64,
+        gateway: GatewayPingDto,
+        peer_count: usize,
+    },
+    #[serde(rename = "VERSION")]
+    Version {
+        crate_version: String,
+        protocol_version: String,
+        gateway_caller_version: String,
+    },
+    /// 自身から確立したものと、相手側から確立されたものの両方を含む
+    #[serde(rename = "LIST_CONNECTIONS")]
+    ListConnections {
+        #[schemars(with = "Vec<schema::DataConnectionId>")]
+        data_connection_ids: Vec<DataConnectionId>,
+        #[schemars(with = "Vec<schema::MediaConnectionId>")]
+        media_connection_ids: Vec<MediaConnectionId>,
+    },
+    #[serde(rename = "PING_GATEWAY")]
+    PingGateway(GatewayPingDto),
+    /// 変更後のレベル
+    #[serde(rename = "LOG_LEVEL")]
+    LogLevel { level: LogLevelDto },
+    #[serde(rename = "METRICS")]
+    Metrics(MetricsDto),
+    #[serde(rename = "EVENT")]
+    Event(SystemEvent),
 }
 
 //========== Peer ==========
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct PeerCallEventDto {
     /// Pair of PeerId and Token. Indicate which Peer Object is regarded.
+    #[schemars(with = "schema::PeerInfo")]
     pub params: PeerInfo,
     /// Id to identify the DataConnection
+    #[schemars(with = "schema::MediaConnectionIdWrapper")]
     pub call_params: MediaConnectionIdWrapper,
     /// status of the DataConnection
+    #[schemars(with = "schema::MediaConnectionStatus")]
     pub status: MediaConnectionStatus,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct PeerConnectionEventDto {
     /// Pair of PeerId and Token. Indicate which Peer Object is regarded.
+    #[schemars(with = "schema::PeerInfo")]
     pub params: PeerInfo,
     /// Id to identify the DataConnection
+    #[schemars(with = "schema::DataConnectionIdWrapper")]
     pub data_params: DataConnectionIdWrapper,
     /// status of the DataConnection
+    #[schemars(with = "schema::DataConnectionStatus")]
     pub status: DataConnectionStatus,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "event")]
+#[allow(clippy::upper_case_acronyms)]
 pub enum PeerEventEnumDto {
-    OPEN(PeerOpenEvent),
-    CLOSE(PeerCloseEvent),
+    OPEN(#[schemars(with = "schema::PeerOpenEvent")] PeerOpenEvent),
+    CLOSE(#[schemars(with = "schema::PeerCloseEvent")] PeerCloseEvent),
     CONNECTION(PeerConnectionEventDto),
     CALL(PeerCallEventDto),
-    ERROR(PeerErrorEvent),
+    ERROR(#[schemars(with = "schema::PeerErrorEvent")] PeerErrorEvent),
     TIMEOUT,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// このノードが生成したPeer Objectと、それに属するConnection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct PeerEntryDto {
+    #[schemars(with = "schema::PeerId")]
+    pub peer_id: PeerId,
+    #[schemars(with = "schema::Token")]
+    pub token: Token,
+    #[schemars(with = "Vec<schema::DataConnectionId>")]
+    pub data_connection_ids: Vec<DataConnectionId>,
+    #[schemars(with = "Vec<schema::MediaConnectionId>")]
+    pub media_connection_ids: Vec<MediaConnectionId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "command")]
 pub(crate) enum PeerResponseDto {
     #[serde(rename = "CREATE")]
-    Create(PeerInfo),
+    Create(#[schemars(with = "schema::PeerInfo")] PeerInfo),
     #[serde(rename = "STATUS")]
-    Status(PeerStatusMessage),
+    Status(#[schemars(with = "schema::PeerStatusMessage")] PeerStatusMessage),
     #[serde(rename = "DELETE")]
-    Delete(PeerInfo),
+    Delete(#[schemars(with = "schema::PeerInfo")] PeerInfo),
     #[serde(rename = "EVENT")]
     Event(PeerEventEnumDto),
+    // 配列はtag付きのenumとしてシリアライズできないので、フィールド名を付ける
+    #[serde(rename = "LIST")]
+    List { peers: Vec<PeerEntryDto> },
 }
 
 impl PeerResponseDto {
@@ -69,14 +222,14 @@ impl PeerResponseDto {
             PeerResponse::Create(item) => PeerResponseDto::Create(item),
             PeerResponse::Delete(item) => PeerResponseDto::Delete(item),
             PeerResponse::Status(item) => PeerResponseDto::Status(item),
-            PeerResponse::Event(item) => unreachable!(),
+            PeerResponse::Event(_item) => unreachable!(),
         }
     }
 }
 
 //========== Media ==========
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "event")]
 pub(crate) enum MediaConnectionEventEnumDto {
     #[serde(rename = "READY")]
@@ -84,52 +237,100 @@ pub(crate) enum MediaConnectionEventEnumDto {
     #[serde(rename = "STREAM")]
     Stream(CallResponseDto),
     #[serde(rename = "CLOSE")]
-    Close(MediaConnectionIdWrapper),
+    Close(#[schemars(with = "schema::MediaConnectionIdWrapper")] MediaConnectionIdWrapper),
+    // タプルのままではtag付きのenumとしてシリアライズできないので、フィールド名を付ける
     #[serde(rename = "ERROR")]
-    Error((MediaConnectionId, String)),
+    Error {
+        #[schemars(with = "schema::MediaConnectionId")]
+        media_connection_id: MediaConnectionId,
+        error: String,
+    },
+    #[serde(rename = "TIMEOUT")]
+    Timeout,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub(crate) struct CallResponseDto {
     pub send_params: SendParams,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::RedirectParameters>")]
     pub redirect_params: Option<RedirectParameters>,
+    #[schemars(with = "schema::MediaConnectionId")]
     pub media_connection_id: MediaConnectionId,
+    // CALLした場合は接続先が分かっているが、ANSWERの場合はSTATUSを問い合わせるまで分からない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub remote_peer_id: Option<PeerId>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// このノードが確立したMediaConnectionと、その転送設定
+/// peer_idはこのノードがCALLした場合のみ、statusはSTATUSを問い合わせた場合のみ含まれる
+/// ANSWERしたMediaConnectionのremote_peer_idは、STATUSを問い合わせた場合のみ含まれる
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct MediaConnectionEntryDto {
+    #[schemars(with = "schema::MediaConnectionId")]
+    pub media_connection_id: MediaConnectionId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub peer_id: Option<PeerId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub remote_peer_id: Option<PeerId>,
+    pub send_params: SendParams,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::RedirectParameters>")]
+    pub redirect_params: Option<RedirectParameters>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::MediaConnectionStatus>")]
+    pub status: Option<MediaConnectionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_error: Option<error::Error>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[schemars(bound = "", rename = "MediaPair")]
 pub(crate) struct MediaPair<M: SerializableId, R: SerializableId> {
+    #[schemars(with = "schema::MediaSocket")]
     pub media: SocketInfo<M>,
+    #[schemars(with = "schema::RtcpSocket")]
     pub rtcp: SocketInfo<R>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// 送信しないトラックのソケットは開放しないので、含まれない
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub(crate) struct SendParams {
-    pub video: MediaPair<MediaId, RtcpId>,
-    pub audio: MediaPair<MediaId, RtcpId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video: Option<MediaPair<MediaId, RtcpId>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<MediaPair<MediaId, RtcpId>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "command")]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum MediaResponseDto {
     #[serde(rename = "CONTENT_CREATE")]
-    ContentCreate(SocketInfo<MediaId>),
+    ContentCreate(#[schemars(with = "schema::MediaSocket")] SocketInfo<MediaId>),
     #[serde(rename = "CONTENT_DELETE")]
-    ContentDelete(MediaIdWrapper),
+    ContentDelete(#[schemars(with = "schema::MediaIdWrapper")] MediaIdWrapper),
     #[serde(rename = "RTCP_CREATE")]
-    RtcpCreate(SocketInfo<RtcpId>),
+    RtcpCreate(#[schemars(with = "schema::RtcpSocket")] SocketInfo<RtcpId>),
     #[serde(rename = "RTCP_DELETE")]
-    RtcpDelete(RtcpIdWrapper),
+    RtcpDelete(#[schemars(with = "schema::RtcpIdWrapper")] RtcpIdWrapper),
     #[serde(rename = "CALL")]
-    Call(MediaConnectionIdWrapper),
+    Call(#[schemars(with = "schema::MediaConnectionIdWrapper")] MediaConnectionIdWrapper),
     #[serde(rename = "ANSWER")]
-    Answer(AnswerResult),
+    Answer(#[schemars(with = "schema::AnswerResult")] AnswerResult),
     #[serde(rename = "EVENT")]
     Event(MediaConnectionEventEnumDto),
     #[serde(rename = "DISCONNECT")]
     Disconnect(Option<()>),
     #[serde(rename = "STATUS")]
-    Status(MediaConnectionStatus),
+    Status(#[schemars(with = "schema::MediaConnectionStatus")] MediaConnectionStatus),
+    #[serde(rename = "LIST")]
+    List {
+        media_connections: Vec<MediaConnectionEntryDto>,
+    },
 }
 
 impl MediaResponseDto {
@@ -151,31 +352,66 @@ impl MediaResponseDto {
 
 //========== Data ==========
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "event")]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum DataConnectionEventDto {
-    OPEN(DataConnectionIdWrapper),
-    CLOSE(DataConnectionIdWrapper),
-    ERROR((DataConnectionId, String)),
+    OPEN(#[schemars(with = "schema::DataConnectionIdWrapper")] DataConnectionIdWrapper),
+    CLOSE(#[schemars(with = "schema::DataConnectionIdWrapper")] DataConnectionIdWrapper),
+    // タプルのままではtag付きのenumとしてシリアライズできないので、フィールド名を付ける
+    ERROR {
+        #[schemars(with = "schema::DataConnectionId")]
+        data_connection_id: DataConnectionId,
+        error: String,
+    },
+    TIMEOUT,
+}
+
+/// このノードがPluginをロードしたDataConnection
+/// peer_idはこのノードがCONNECTした場合のみ含まれる
+/// REDIRECTしたDataConnectionのremote_peer_idは、STATUSを問い合わせた場合のみ含まれる
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub(crate) struct DataConnectionEntryDto {
+    #[schemars(with = "schema::DataConnectionId")]
+    pub data_connection_id: DataConnectionId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub peer_id: Option<PeerId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::PeerId>")]
+    pub remote_peer_id: Option<PeerId>,
+    pub plugin_type: String,
+    pub data_pipe_port_num: u16,
+    #[schemars(with = "schema::DataId")]
+    pub data_id: DataId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<schema::DataConnectionStatus>")]
+    pub status: Option<DataConnectionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_error: Option<error::Error>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "command")]
 pub(crate) enum DataResponseDto {
     #[serde(rename = "CREATE")]
-    Create(SocketInfo<DataId>),
+    Create(#[schemars(with = "schema::DataSocket")] SocketInfo<DataId>),
     #[serde(rename = "CONNECT")]
-    Connect(DataConnectionIdWrapper),
+    Connect(#[schemars(with = "schema::DataConnectionIdWrapper")] DataConnectionIdWrapper),
     #[serde(rename = "DELETE")]
-    Delete(DataIdWrapper),
+    Delete(#[schemars(with = "schema::DataIdWrapper")] DataIdWrapper),
     #[serde(rename = "DISCONNECT")]
-    Disconnect(DataConnectionIdWrapper),
+    Disconnect(#[schemars(with = "schema::DataConnectionIdWrapper")] DataConnectionIdWrapper),
     #[serde(rename = "REDIRECT")]
-    Redirect(DataConnectionIdWrapper),
+    Redirect(#[schemars(with = "schema::DataConnectionIdWrapper")] DataConnectionIdWrapper),
     #[serde(rename = "EVENT")]
     Event(DataConnectionEventDto),
     #[serde(rename = "STATUS")]
-    Status(DataConnectionStatus),
+    Status(#[schemars(with = "schema::DataConnectionStatus")] DataConnectionStatus),
+    #[serde(rename = "LIST")]
+    List {
+        data_connections: Vec<DataConnectionEntryDto>,
+    },
 }
 
 impl DataResponseDto {
@@ -193,8 +429,20 @@ impl DataResponseDto {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+//========== Event ==========
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "command")]
+pub(crate) enum EventResponseDto {
+    #[serde(rename = "SUBSCRIBE")]
+    Subscribe(SubscribeParams),
+    #[serde(rename = "UNSUBSCRIBE")]
+    Unsubscribe(SubscriptionIdWrapper),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(tag = "request_type")]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum ResponseDto {
     #[serde(rename = "PEER")]
     Peer(PeerResponseDto),
@@ -204,9 +452,12 @@ pub(crate) enum ResponseDto {
     Data(DataResponseDto),
     #[serde(rename = "SYSTEM")]
     System(SystemResponseDto),
+    #[serde(rename = "EVENT")]
+    Event(EventResponseDto),
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum ResponseDtoResult {
     Success(ResponseDto),
     Error(String),
@@ -216,29 +467,29 @@ pub(crate) enum ResponseDtoResult {
 impl ResponseDtoResult {
     pub(crate) fn from_str(json: &str) -> Result<ResponseDtoResult, error::Error> {
         #[allow(dead_code)]
-        #[derive(Deserialize)]
+        #[derive(Deserialize, JsonSchema)]
         struct ResponseMessageStruct {
             is_success: bool,
             result: serde_json::Value,
         }
         let value = serde_json::from_str::<ResponseMessageStruct>(json)
-            .map_err(|e| error::Error::SerdeError { error: e })?;
+            .map_err(|e| error::Error::internal(e.to_string()))?;
         match value.is_success {
             true => {
                 let content: ResponseDto = serde_json::from_value(value.result)
-                    .map_err(|e| error::Error::SerdeError { error: e })?;
+                    .map_err(|e| error::Error::internal(e.to_string()))?;
                 Ok(ResponseDtoResult::Success(content))
             }
             _ => {
                 let content: String = serde_json::from_value(value.result)
-                    .map_err(|e| error::Error::SerdeError { error: e })?;
+                    .map_err(|e| error::Error::internal(e.to_string()))?;
                 Ok(ResponseDtoResult::Error(content))
             }
         }
     }
 
     pub(crate) fn to_string(&self) -> Result<String, error::Error> {
-        serde_json::to_string(self).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::to_string(self).map_err(|e| error::Error::internal(e.to_string()))
     }
 }
 
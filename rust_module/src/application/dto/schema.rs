@@ -0,0 +1,794 @@
+// SkyWayControl, SkyWayEventsで送受信するJSONのSchemaを、DTOの定義から生成するモジュール
+// SkyWay Crateの型にはJsonSchemaを実装できないので、同じ形でシリアライズされる型をここで定義し、
+// DTOのフィールドから#[schemars(with = "...")]で参照する
+// ここで定義する型はSchemaの生成にのみ利用し、値を生成することはない
+// 実際の形との食い違いは、ドキュメントの例を生成したSchemaで検証するテストで検出する
+#![allow(dead_code)]
+
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::application::dto::request::{BatchRequestDto, RequestDto, RequestOptions};
+use crate::application::dto::response::{
+    DataConnectionEventDto, MediaConnectionEventEnumDto, PeerEventEnumDto, ResponseDto,
+    ShutdownStepDto,
+};
+use crate::application::ErrorMessage;
+use crate::domain::entity::event::SystemEvent;
+use crate::version;
+
+//========== SkyWay Crateの型 ==========
+
+/// ID of a Peer Object. Any string chosen by the user.
+#[derive(JsonSchema)]
+pub(crate) struct PeerId(String);
+
+/// Token to show that the caller has permission to control the Peer Object
+#[derive(JsonSchema)]
+pub(crate) struct Token(#[schemars(regex(pattern = r"^pt-.{36}$"))] String);
+
+#[derive(JsonSchema)]
+pub(crate) struct DataConnectionId(#[schemars(regex(pattern = r"^dc-.{36}$"))] String);
+
+#[derive(JsonSchema)]
+pub(crate) struct MediaConnectionId(#[schemars(regex(pattern = r"^mc-.{36}$"))] String);
+
+#[derive(JsonSchema)]
+pub(crate) struct DataId(#[schemars(regex(pattern = r"^da-.{36}$"))] String);
+
+/// ID of a Media Socket. `vi-` for video, `au-` for audio.
+#[derive(JsonSchema)]
+pub(crate) struct MediaId(#[schemars(regex(pattern = r"^(vi|au)-.{36}$"))] String);
+
+#[derive(JsonSchema)]
+pub(crate) struct RtcpId(#[schemars(regex(pattern = r"^rc-.{36}$"))] String);
+
+#[derive(JsonSchema)]
+pub(crate) struct PeerInfo {
+    peer_id: PeerId,
+    token: Token,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct CreatePeerParams {
+    /// API Key of SkyWay
+    key: String,
+    /// Domain registered with the API Key
+    domain: String,
+    peer_id: PeerId,
+    /// Whether to use TURN servers
+    turn: bool,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct PeerStatusMessage {
+    peer_id: PeerId,
+    disconnected: bool,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct PeerOpenEvent {
+    params: PeerInfo,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct PeerCloseEvent {
+    params: PeerInfo,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct PeerErrorEvent {
+    params: PeerInfo,
+    error_message: String,
+}
+
+// SocketInfoはIDの種類によってキーの名前が変わり、IPアドレスの種類によってip_v4, ip_v6のいずれかを持つ
+macro_rules! socket_schema {
+    ($name:ident, $doc:literal $(, $id_name:ident: $id_type:ty)?) => {
+        #[doc = $doc]
+        #[derive(JsonSchema)]
+        pub(crate) struct $name {
+            $($id_name: $id_type,)?
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            ip_v4: Option<String>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            ip_v6: Option<String>,
+            port: u16,
+        }
+    };
+}
+
+socket_schema!(DataSocket, "Data Socket opened on the WebRTC Gateway", data_id: DataId);
+socket_schema!(MediaSocket, "Media Socket opened on the WebRTC Gateway", media_id: MediaId);
+socket_schema!(RtcpSocket, "RTCP Socket opened on the WebRTC Gateway", rtcp_id: RtcpId);
+socket_schema!(
+    Socket,
+    "Address and port of a socket. Either ip_v4 or ip_v6 is set."
+);
+
+#[derive(JsonSchema)]
+pub(crate) struct DataIdWrapper {
+    data_id: DataId,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct DataConnectionIdWrapper {
+    data_connection_id: DataConnectionId,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct MediaIdWrapper {
+    media_id: MediaId,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct RtcpIdWrapper {
+    rtcp_id: RtcpId,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct MediaConnectionIdWrapper {
+    media_connection_id: MediaConnectionId,
+}
+
+#[derive(JsonSchema)]
+#[allow(non_snake_case)]
+pub(crate) struct DcInit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ordered: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    maxPacketLifeTime: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    maxRetransmits: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    negotiated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+}
+
+#[derive(JsonSchema)]
+#[allow(non_snake_case)]
+pub(crate) struct ConnectQueryOption {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    serialization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dcInit: Option<DcInit>,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct DataConnectionStatus {
+    remote_id: String,
+    buffersize: usize,
+    label: String,
+    metadata: String,
+    open: bool,
+    reliable: bool,
+    serialization: String,
+    r#type: String,
+}
+
+/// Destination sockets to which received media is redirected
+#[derive(JsonSchema)]
+pub(crate) struct RedirectParameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    video: Option<Socket>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    video_rtcp: Option<Socket>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio: Option<Socket>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio_rtcp: Option<Socket>,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct SsrcPair {
+    media_id: MediaId,
+    ssrc: usize,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct MediaConnectionStatus {
+    metadata: String,
+    open: bool,
+    remote_id: PeerId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ssrc: Option<Vec<SsrcPair>>,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct AnswerResponseParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    video_id: Option<MediaId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio_id: Option<MediaId>,
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct AnswerResult {
+    media_connection_id: MediaConnectionId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    send_sockets: Option<AnswerResponseParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recv_sockets: Option<RedirectParameters>,
+}
+
+//========== メッセージ全体 ==========
+
+// request_typeによらず付与できるオプションは、リクエストと同じ階層に置く
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum RequestMessage {
+    Single {
+        #[serde(flatten)]
+        request: RequestDto,
+        #[serde(flatten)]
+        options: RequestOptions,
+    },
+    // requestsの各要素は単体のリクエストと同じ形だが、先に処理したリクエストの結果を参照できるので形を限定しない
+    Batch {
+        #[serde(flatten)]
+        batch: BatchRequestDto,
+        #[serde(flatten)]
+        options: RequestOptions,
+    },
+}
+
+// call_serviceは、応答の形式のバージョンと、リクエストのrequest_idを付与して返す
+#[derive(JsonSchema)]
+struct ResponseMessage {
+    protocol_version: String,
+    request_id: String,
+    #[serde(flatten)]
+    response: ResponseBody,
+}
+
+// ResponseDtoResultは独自にシリアライズしているので、同じ形をここで定義する
+// 失敗時は、rust_moduleが生成したエラーの場合はErrorMessage全体、
+// WebRTC Gatewayが返したエラーの場合はresultが文字列になる
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum ResponseBody {
+    Success {
+        #[schemars(with = "True")]
+        is_success: bool,
+        result: ResponseDto,
+    },
+    Error(ErrorMessage),
+    GatewayError {
+        #[schemars(with = "False")]
+        is_success: bool,
+        result: String,
+    },
+    // BATCHの場合は、処理したリクエストごとの応答を配列で返す
+    Batch {
+        is_success: bool,
+        result: Vec<BatchItemMessage>,
+        rollback: Option<Vec<ShutdownStepDto>>,
+    },
+}
+
+#[derive(JsonSchema)]
+struct BatchItemMessage {
+    request_id: String,
+    #[serde(flatten)]
+    result: BatchItemResult,
+}
+
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum BatchItemResult {
+    Success {
+        #[schemars(with = "True")]
+        is_success: bool,
+        result: ResponseDto,
+    },
+    Error(ErrorMessage),
+}
+
+// SkyWayEventsが配信するイベント
+// このノードのリクエストが原因で発生したイベントには、そのリクエストのrequest_idが付与される
+#[derive(JsonSchema)]
+struct EventMessage {
+    request_id: Option<String>,
+    #[serde(flatten)]
+    event: EventBody,
+}
+
+// 通常のレスポンスのうち、commandがEVENTのものに限られる
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum EventBody {
+    Success {
+        #[schemars(with = "True")]
+        is_success: bool,
+        result: EventDto,
+    },
+    Error(ErrorMessage),
+}
+
+#[derive(JsonSchema)]
+#[serde(tag = "request_type")]
+#[allow(clippy::large_enum_variant)]
+enum EventDto {
+    #[serde(rename = "PEER")]
+    Peer(PeerEvent),
+    #[serde(rename = "DATA")]
+    Data(DataEvent),
+    #[serde(rename = "MEDIA")]
+    Media(MediaEvent),
+    #[serde(rename = "SYSTEM")]
+    System(SystemEventDto),
+}
+
+#[derive(JsonSchema)]
+#[serde(tag = "command")]
+enum PeerEvent {
+    #[serde(rename = "EVENT")]
+    Event(PeerEventEnumDto),
+}
+
+#[derive(JsonSchema)]
+#[serde(tag = "command")]
+enum DataEvent {
+    #[serde(rename = "EVENT")]
+    Event(DataConnectionEventDto),
+}
+
+#[derive(JsonSchema)]
+#[serde(tag = "command")]
+enum MediaEvent {
+    #[serde(rename = "EVENT")]
+    Event(MediaConnectionEventEnumDto),
+}
+
+#[derive(JsonSchema)]
+#[serde(tag = "command")]
+enum SystemEventDto {
+    #[serde(rename = "EVENT")]
+    Event(SystemEvent),
+}
+
+// is_successの値を固定するための型
+pub(crate) struct True;
+pub(crate) struct False;
+
+impl JsonSchema for True {
+    fn schema_name() -> String {
+        "True".to_string()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            const_value: Some(serde_json::Value::Bool(true)),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+impl JsonSchema for False {
+    fn schema_name() -> String {
+        "False".to_string()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            const_value: Some(serde_json::Value::Bool(false)),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+/// SkyWayControl, SkyWayEventsで送受信するJSONのSchema
+#[derive(Serialize)]
+pub(crate) struct ProtocolSchema {
+    pub protocol_version: &'static str,
+    /// SkyWayControlに送信するリクエスト
+    pub request: RootSchema,
+    /// SkyWayControlから返されるレスポンス
+    pub response: RootSchema,
+    /// SkyWayEventsから配信されるイベント
+    pub event: RootSchema,
+}
+
+impl ProtocolSchema {
+    pub fn generate() -> Self {
+        ProtocolSchema {
+            protocol_version: version::PROTOCOL_VERSION,
+            request: root_schema::<RequestMessage>(
+                "SkyWayControl request",
+                "Message sent to the SkyWayControl service",
+            ),
+            response: root_schema::<ResponseMessage>(
+                "SkyWayControl response",
+                "Message returned from the SkyWayControl service",
+            ),
+            event: root_schema::<EventMessage>(
+                "SkyWayEvents event",
+                "Message delivered from the SkyWayEvents service",
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Schemaのserializeが失敗するケースはRustの型システムにより発生しない
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// 最上位の型のdoc commentではなく、メッセージ全体の説明を付ける
+fn root_schema<T: JsonSchema>(title: &str, description: &str) -> RootSchema {
+    let mut schema = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<T>();
+    let metadata = schema.schema.metadata();
+    metadata.title = Some(title.to_string());
+    metadata.description = Some(description.to_string());
+    schema
+}
+
+#[cfg(test)]
+mod schema_test {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::application::dto::response::ResponseDtoResult;
+    use crate::error;
+
+    // doc以下のMarkdownに記載されたJSONの例を、ファイル名とともに全て取り出す
+    // 一部を...で省略した例はJSONとして読めないので除外する
+    fn documented_examples() -> Vec<(String, Value)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../doc");
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .collect();
+        paths.sort();
+
+        let mut examples = vec![];
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let markdown = std::fs::read_to_string(&path).unwrap();
+            for block in markdown.split("```json\n").skip(1) {
+                let json = block.split("```").next().unwrap();
+                if json.contains("...") {
+                    continue;
+                }
+                let value = serde_json::from_str::<Value>(json)
+                    .unwrap_or_else(|e| panic!("invalid json in {}: {}\n{}", name, e, json));
+                examples.push((name.clone(), value));
+            }
+        }
+        examples
+    }
+
+    // 例をDTOとしてパースし、シリアライズし直した結果を返す
+    fn round_trip(example: &Value) -> Result<Value, error::Error> {
+        let json = example.to_string();
+        if let Some(batch) = BatchRequestDto::from_str(&json) {
+            let mut value = serde_json::to_value(batch?).unwrap();
+            let options = serde_json::to_value(RequestOptions::from_str(&json)?).unwrap();
+            value
+                .as_object_mut()
+                .unwrap()
+                .extend(options.as_object().unwrap().clone());
+            return Ok(value);
+        }
+        if example.get("request_type").is_some() {
+            // request_typeによらないオプションは、同じ階層に置かれる
+            let mut value = serde_json::to_value(RequestDto::from_str(&json)?).unwrap();
+            let options = serde_json::to_value(RequestOptions::from_str(&json)?).unwrap();
+            value
+                .as_object_mut()
+                .unwrap()
+                .extend(options.as_object().unwrap().clone());
+            return Ok(value);
+        }
+
+        // 応答とイベントに付与されるprotocol_version, request_idは、DTOの外側にある
+        let mut example = example.clone();
+        let envelope: Vec<_> = ["protocol_version", "request_id"]
+            .into_iter()
+            .filter_map(|key| {
+                let value = example.as_object_mut()?.remove(key)?;
+                Some((key.to_string(), value))
+            })
+            .collect();
+        if !envelope.is_empty() {
+            let mut value = round_trip(&example)?;
+            value.as_object_mut().unwrap().extend(envelope);
+            return Ok(value);
+        }
+        let json = example.to_string();
+
+        match example.get("result") {
+            // BATCHの応答は、各要素が単体の応答と同じ形になる
+            Some(Value::Array(items)) => {
+                let mut value = example.clone();
+                let result = items.iter().map(round_trip).collect::<Result<_, _>>()?;
+                value["result"] = Value::Array(result);
+                if let Some(rollback) = example.get("rollback") {
+                    let steps = serde_json::from_value::<Vec<ShutdownStepDto>>(rollback.clone())
+                        .map_err(|e| error::Error::internal(e.to_string()))?;
+                    value["rollback"] = serde_json::to_value(steps).unwrap();
+                }
+                Ok(value)
+            }
+            // rust_moduleが生成したエラー
+            Some(Value::Object(result)) if result.contains_key("code") => {
+                let message = serde_json::from_str::<ErrorMessage>(&json)
+                    .map_err(|e| error::Error::internal(e.to_string()))?;
+                Ok(serde_json::to_value(message).unwrap())
+            }
+            _ => {
+                let response = ResponseDtoResult::from_str(&json)?;
+                Ok(serde_json::to_value(response).unwrap())
+            }
+        }
+    }
+
+    #[test]
+    // ドキュメントに記載された全ての例が、DTOとして解釈でき、同じ形に戻ることを確認する
+    fn documented_examples_round_trip() {
+        let examples = documented_examples();
+        assert!(!examples.is_empty());
+
+        let failures: Vec<String> = examples
+            .iter()
+            .filter_map(|(name, example)| match round_trip(example) {
+                Ok(value) if &value == example => None,
+                Ok(value) => Some(format!(
+                    "{}: serialized differently\n  expected: {}\n  actual:   {}",
+                    name, example, value
+                )),
+                Err(e) => Some(format!("{}: {}\n  example: {}", name, e, example)),
+            })
+            .collect();
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    // 生成したSchemaに対して値を検証し、違反した箇所を返す
+    // schemarsが生成するdraft-07のキーワードのうち、このモジュールのSchemaに現れるものだけを扱う
+    fn validate(root: &Value, schema: &Value, value: &Value, path: &str) -> Vec<String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.trim_start_matches("#/definitions/");
+            return validate(root, &root["definitions"][name], value, path);
+        }
+
+        let mut errors = vec![];
+        let mut fail = |message: String| errors.push(format!("{}: {}", path, message));
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                types => types.as_str().into_iter().collect(),
+            };
+            let matches = |name: &str| match name {
+                "null" => value.is_null(),
+                "boolean" => value.is_boolean(),
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                _ => false,
+            };
+            if !types.iter().any(|name| matches(name)) {
+                fail(format!("expected {:?}, but {}", types, value));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                fail(format!("expected {}, but {}", expected, value));
+            }
+        }
+        if let Some(Value::Array(candidates)) = schema.get("enum") {
+            if !candidates.contains(value) {
+                fail(format!("expected one of {:?}, but {}", candidates, value));
+            }
+        }
+        if let (Some(pattern), Some(string)) = (
+            schema.get("pattern").and_then(Value::as_str),
+            value.as_str(),
+        ) {
+            if !regex::Regex::new(pattern).unwrap().is_match(string) {
+                fail(format!("{} does not match {}", string, pattern));
+            }
+        }
+        if let Some(number) = value.as_f64() {
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < minimum {
+                    fail(format!("{} is less than {}", number, minimum));
+                }
+            }
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > maximum {
+                    fail(format!("{} is greater than {}", number, maximum));
+                }
+            }
+        }
+        if let Some(object) = value.as_object() {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        fail(format!("missing {}", key));
+                    }
+                }
+            }
+            for (key, item) in object {
+                let item_path = format!("{}.{}", path, key);
+                match (
+                    properties.and_then(|properties| properties.get(key)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property), _) => {
+                        errors.extend(validate(root, property, item, &item_path))
+                    }
+                    (None, Some(Value::Bool(false))) => {
+                        errors.push(format!("{}: unknown property", item_path))
+                    }
+                    (None, Some(additional @ Value::Object(_))) => {
+                        errors.extend(validate(root, additional, item, &item_path))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(array) = value.as_array() {
+            match schema.get("items") {
+                Some(Value::Array(items)) => {
+                    for (index, (item, schema)) in array.iter().zip(items).enumerate() {
+                        errors.extend(validate(
+                            root,
+                            schema,
+                            item,
+                            &format!("{}[{}]", path, index),
+                        ));
+                    }
+                }
+                Some(items) => {
+                    for (index, item) in array.iter().enumerate() {
+                        errors.extend(validate(root, items, item, &format!("{}[{}]", path, index)));
+                    }
+                }
+                None => {}
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                errors.extend(validate(root, schema, value, path));
+            }
+        }
+        let matched = |key: &str| {
+            schema.get(key).and_then(Value::as_array).map(|schemas| {
+                schemas
+                    .iter()
+                    .filter(|schema| validate(root, schema, value, path).is_empty())
+                    .count()
+            })
+        };
+        if let Some(count) = matched("anyOf") {
+            if count == 0 {
+                errors.push(format!("{}: matches none of anyOf: {}", path, value));
+            }
+        }
+        if let Some(count) = matched("oneOf") {
+            if count != 1 {
+                errors.push(format!(
+                    "{}: matches {} schemas of oneOf: {}",
+                    path, count, value
+                ));
+            }
+        }
+        errors
+    }
+
+    #[test]
+    // ドキュメントに記載された全ての例が、生成したSchemaに適合することを確認する
+    // Schemaの生成にのみ利用するSkyWay Crateの型の写しが、実際の形と食い違っていないかをここで検出する
+    fn documented_examples_match_schema() {
+        let schema = serde_json::to_value(ProtocolSchema::generate()).unwrap();
+        let failures: Vec<String> = documented_examples()
+            .into_iter()
+            .flat_map(|(name, example)| {
+                let mut example = example;
+                let root = if example.get("is_success").is_none() {
+                    &schema["request"]
+                } else {
+                    // 応答とイベントに付与されるprotocol_version, request_idは、例では省略される場合がある
+                    let object = example.as_object_mut().unwrap();
+                    object
+                        .entry("protocol_version")
+                        .or_insert_with(|| Value::from(version::PROTOCOL_VERSION));
+                    object
+                        .entry("request_id")
+                        .or_insert_with(|| Value::from("request_id"));
+                    if example["result"]["command"] == "EVENT" {
+                        &schema["event"]
+                    } else {
+                        &schema["response"]
+                    }
+                };
+                validate(root, root, &example, "$")
+                    .into_iter()
+                    .map(move |error| format!("{}: {}", name, error))
+            })
+            .collect();
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    // Schemaに適合しない値は検出する
+    fn validate_invalid_examples() {
+        let schema = serde_json::to_value(ProtocolSchema::generate()).unwrap();
+        let response = &schema["response"];
+        let valid = serde_json::json!({
+            "protocol_version": version::PROTOCOL_VERSION,
+            "request_id": "request_id",
+            "is_success": true,
+            "result": {
+                "request_type": "PEER",
+                "command": "CREATE",
+                "peer_id": "robot",
+                "token": "pt-9749250e-d157-4f80-9ee2-359ce8524308"
+            }
+        });
+        assert_eq!(
+            validate(response, response, &valid, "$"),
+            Vec::<String>::new()
+        );
+
+        let mut invalid_token = valid.clone();
+        invalid_token["result"]["token"] = Value::from("9749250e");
+        assert!(!validate(response, response, &invalid_token, "$").is_empty());
+
+        let mut missing_peer_id = valid.clone();
+        missing_peer_id["result"]
+            .as_object_mut()
+            .unwrap()
+            .remove("peer_id");
+        assert!(!validate(response, response, &missing_peer_id, "$").is_empty());
+
+        let mut unknown_command = valid;
+        unknown_command["result"]["command"] = Value::from("UNKNOWN");
+        assert!(!validate(response, response, &unknown_command, "$").is_empty());
+    }
+
+    #[test]
+    fn protocol_schema() {
+        let schema = serde_json::to_value(ProtocolSchema::generate()).unwrap();
+        assert_eq!(schema["protocol_version"], version::PROTOCOL_VERSION);
+        for key in ["request", "response", "event"] {
+            assert_eq!(
+                schema[key]["$schema"],
+                "http://json-schema.org/draft-07/schema#"
+            );
+        }
+        // 各commandの名前はSerializeの定義から生成される
+        let request = schema["request"].to_string();
+        assert!(request.contains(r#"["RTCP_CREATE"]"#));
+        assert!(request.contains(r#"["RTCP_DELETE"]"#));
+    }
+}
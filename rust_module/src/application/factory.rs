@@ -30,6 +30,10 @@ impl Factory for FactoryImpl {
                 let module = PeerCreateService::builder().build();
                 module.resolve()
             }
+            RequestDto::Peer(PeerRequestDto::List) => {
+                let module = PeerListService::builder().build();
+                module.resolve()
+            }
             RequestDto::Data(DataRequestDto::Connect { params: _ }) => {
                 let module = DataConnectService::builder().build();
                 module.resolve()
@@ -46,6 +50,10 @@ impl Factory for FactoryImpl {
                 let module = GeneralService::builder().build();
                 module.resolve()
             }
+            RequestDto::Data(DataRequestDto::List { params: _ }) => {
+                let module = DataListService::builder().build();
+                module.resolve()
+            }
             RequestDto::Media(MediaRequestDto::Call { params: _ }) => {
                 let module = MediaCallService::builder().build();
                 module.resolve()
@@ -54,10 +62,18 @@ impl Factory for FactoryImpl {
                 let module = MediaAnswerService::builder().build();
                 module.resolve()
             }
+            RequestDto::Media(MediaRequestDto::List { params: _ }) => {
+                let module = MediaListService::builder().build();
+                module.resolve()
+            }
             RequestDto::System(_) => {
                 let module = SystemService::builder().build();
                 module.resolve()
             }
+            RequestDto::Event(_) => {
+                let module = EventSubscriptionService::builder().build();
+                module.resolve()
+            }
             _ => {
                 let module = GeneralService::builder().build();
                 module.resolve()
@@ -4,35 +4,102 @@ pub(crate) mod dto;
 pub(crate) mod factory;
 pub(crate) mod usecase;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use shaku::HasComponent;
+use tracing::Instrument;
 
-use crate::application::dto::request::RequestDto;
+use crate::application::dto::compat;
+use crate::application::dto::request::{
+    BatchRequestDto, RequestDto, RequestOptions, SystemRequestDto,
+};
+use crate::application::dto::response::{
+    ResponseDto, ResponseDtoResult, ShutdownStepDto, SystemResponseDto,
+};
 use crate::application::dto::Command;
 use crate::application::factory::Factory;
+use crate::application::usecase::batch::BatchSequence;
+use crate::application::usecase::event::correlation;
+use crate::application::usecase::event::subscription::DEFAULT_SUBSCRIPTION;
 use crate::application::usecase::event::EventReceive;
+use crate::application::usecase::system::metrics;
+use crate::application::usecase::system::ping::ping;
+use crate::application::usecase::system::reconcile::{ReconcileSequence, Reconciled};
+use crate::application::usecase::Service;
 use crate::di::*;
 use crate::domain::entity::Stringify;
+use crate::domain::repository::{Repository, REQUEST_ID, REQUEST_TIMEOUT};
 use crate::error;
-use crate::ffi::rust_to_c_bridge::c_functions_wrapper::LoggerHolder;
+use crate::ffi::rust_to_c_bridge::snapshot::{self, StateSnapshot};
+use crate::ffi::rust_to_c_bridge::state_objects::{
+    GlobalState, METRICS_INSTANCE, PEER_REGISTRY_INSTANCE, RECOVERY_STATE_INSTANCE,
+};
+use crate::utils::Backoff;
+use crate::version;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 struct ErrorMessage {
+    #[schemars(with = "dto::schema::False")]
     is_success: bool,
     result: ErrorMessageInternal,
 }
 
 impl Stringify for ErrorMessage {
     fn to_string(&self) -> Result<String, error::Error> {
-        serde_json::to_string(self).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::to_string(self).map_err(|e| error::Error::internal(e.to_string()))
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Error generated by rust_module
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 struct ErrorMessageInternal {
     request_type: Option<String>,
     command: Option<String>,
+    // error::Error::codeの値。エラーの種類を文字列の照合なしに判別するために利用する
+    code: String,
     error: String,
+    // codeごとに定まる付随情報
+    details: serde_json::Value,
+}
+
+/// call_serviceの応答
+/// クライアントが応答の形を判別できるよう、その形式のバージョンを付与する
+/// 過去のバージョンのリクエストに対しても、応答は現在のバージョンの形で返す
+/// また、どのリクエストに対する応答かを判別できるよう、処理中のリクエストのrequest_idを付与する
+#[derive(Serialize)]
+struct VersionedMessage<'a, T: Serialize> {
+    protocol_version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(flatten)]
+    message: &'a T,
+}
+
+impl<'a, T: Serialize> VersionedMessage<'a, T> {
+    fn to_string(message: &'a T) -> String {
+        let message = VersionedMessage {
+            protocol_version: version::PROTOCOL_VERSION,
+            request_id: REQUEST_ID.try_with(|request_id| request_id.clone()).ok(),
+            message,
+        };
+        // 応答として返す型はserializeでエラーを出すことはない
+        serde_json::to_string(&message).unwrap()
+    }
+}
+
+impl ErrorMessage {
+    fn new(request_type: Option<String>, command: Option<String>, error: &error::Error) -> Self {
+        ErrorMessage {
+            is_success: false,
+            result: ErrorMessageInternal {
+                request_type,
+                command,
+                code: error.code().to_string(),
+                error: error.to_string(),
+                details: error.details(),
+            },
+        }
+    }
 }
 
 /// called from ffi::call_service
@@ -42,6 +109,26 @@ struct ErrorMessageInternal {
 /// 特別な処理を行うものは、usecase内のdata, media, peer moduleの中で実装される。
 /// その他のものはgeneral moduleの中で処理される。
 pub(crate) async fn call_service(message: String) -> String {
+    // 応答、処理中のログ、このリクエストが原因で発生したイベントを結びつけるため、処理の間request_idを保持する
+    let request_id = serde_json::from_str::<serde_json::Value>(&message)
+        .ok()
+        .as_ref()
+        .and_then(correlation::read_request_id)
+        .unwrap_or_else(correlation::generate_request_id);
+    REQUEST_ID.scope(request_id, call_request(message)).await
+}
+
+/// called from ffi::call_service, ffi::call_service_async
+/// C++側から渡された文字列がUTF-8として解釈できないなど、call_serviceに渡す前に失敗した場合の応答を生成する
+pub(crate) fn error_response(error: &error::Error) -> String {
+    let error_message = ErrorMessage::new(None, None, error);
+    let message = VersionedMessage::to_string(&error_message);
+    tracing::error!("{}", message);
+    message
+}
+
+// called from call_service
+async fn call_request(message: String) -> String {
     // 正常にparseできなかった場合に、request_typeとcommandをユーザに返すために取得を試みる
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub(crate) struct RequestTypeAndCommand {
@@ -49,8 +136,17 @@ pub(crate) async fn call_service(message: String) -> String {
         command: String,
     }
 
-    match RequestDto::from_str(&message) {
-        Ok(dto) => {
+    if let Some(batch) = BatchRequestDto::from_str(&message) {
+        return call_batch(batch, &message).await;
+    }
+
+    // protocol_versionが指定されている場合は、現在のバージョンの形に変換してからparseする
+    let dto = RequestOptions::from_str(&message).and_then(|options| {
+        let message = compat::upgrade(&message, options.protocol_version.as_deref())?;
+        RequestDto::from_str(&message).map(|dto| (dto, options))
+    });
+    match dto {
+        Ok((dto, options)) => {
             let module = GeneralFactory::builder().build();
             let factory: &dyn Factory = module.resolve_ref();
             let service = factory.create_service(&dto);
@@ -58,27 +154,51 @@ pub(crate) async fn call_service(message: String) -> String {
             // errorメッセージを生成する際に必要なので確保しておく
             let command = dto.command();
             let dto_type = dto.dto_type();
+            // PEER CLOSE時の自動復旧のため、成功したリクエストのパラメータを記録する
+            let request = dto.clone();
 
-            match service.execute(dto).await {
+            // timeout_msが指定されている場合は、このリクエストの処理中に限り設定値より優先する
+            // 処理中のログには、どのリクエストの処理かをspanとして付与する
+            let span = tracing::info_span!("request", request_type = %dto_type, command = %command);
+            let started_at = std::time::Instant::now();
+            let result = REQUEST_TIMEOUT
+                .scope(options.timeout(), service.execute(dto))
+                .instrument(span.clone())
+                .await;
+            METRICS_INSTANCE.record_request(
+                &dto_type,
+                &command,
+                started_at.elapsed(),
+                matches!(result, Ok(ResponseDtoResult::Success(_))),
+            );
+            if let Err(ref e) = result {
+                span.in_scope(|| tracing::warn!(error = %e, "request failed"));
+            }
+            match result {
+                // WebRTC Gatewayが返したエラーも、他のエラーと同じ形式でユーザに返す
+                Ok(ResponseDtoResult::Error(message)) => {
+                    let error = error::Error::gateway_api_error(message);
+                    let error_message = ErrorMessage::new(Some(dto_type), Some(command), &error);
+                    VersionedMessage::to_string(&error_message)
+                }
                 Ok(response) => {
-                    // ResponseMessageはto_stringでエラーを出すことはない
-                    response.to_string().unwrap()
+                    if let Some(state) = RECOVERY_STATE_INSTANCE.get() {
+                        state.lock().unwrap().record(&request, &response);
+                    }
+                    // 終了時に全てのPeer Objectを削除するため、生成したPeer Objectと、それに属するConnectionを記録する
+                    if let Some(registry) = PEER_REGISTRY_INSTANCE.get() {
+                        registry.lock().unwrap().record(&request, &response);
+                    }
+                    snapshot::save();
+                    VersionedMessage::to_string(&response)
                 }
                 Err(e) => {
-                    let internal = ErrorMessageInternal {
-                        request_type: Some(dto_type),
-                        command: Some(command),
-                        error: format!("{:?}", e),
-                    };
-                    let error_message = ErrorMessage {
-                        is_success: false,
-                        result: internal,
-                    };
-                    error_message.to_string().unwrap()
+                    let error_message = ErrorMessage::new(Some(dto_type), Some(command), &e);
+                    VersionedMessage::to_string(&error_message)
                 }
             }
         }
-        Err(_e) => {
+        Err(e) => {
             let type_and_command = match serde_json::from_str::<RequestTypeAndCommand>(&message) {
                 Ok(request_type_and_command) => (
                     Some(request_type_and_command.request_type),
@@ -87,46 +207,316 @@ pub(crate) async fn call_service(message: String) -> String {
                 Err(_e) => (None, None),
             };
 
-            let internal = ErrorMessageInternal {
-                request_type: type_and_command.0,
-                command: type_and_command.1,
-                error: format!("invalid message in call_service: {}", message),
-            };
-            let error_message = ErrorMessage {
-                is_success: false,
-                result: internal,
-            };
-            let message = error_message.to_string().unwrap();
-            LoggerHolder::global().error(message.as_str());
+            let error_message = ErrorMessage::new(type_and_command.0, type_and_command.1, &e);
+            let message = VersionedMessage::to_string(&error_message);
+            tracing::error!("{}", message);
             message
         }
     }
 }
 
-/// called from ffi::receive_events
+/// BATCHの応答
+/// resultには処理したリクエストごとに、単体で送られた場合と同じ形の応答を格納する
+#[derive(Serialize)]
+struct BatchMessage {
+    is_success: bool,
+    result: Vec<BatchItemMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollback: Option<Vec<ShutdownStepDto>>,
+}
+
+// 単体で送られた場合と同様に、各リクエストのrequest_idを付与する
+#[derive(Serialize)]
+struct BatchItemMessage {
+    request_id: String,
+    #[serde(flatten)]
+    result: BatchItemResult,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum BatchItemResult {
+    Success(ResponseDtoResult),
+    Error(ErrorMessage),
+}
+
+// called from call_service
+// 含まれるリクエストを順に処理し、それぞれの結果を1つの応答にまとめて返す
+async fn call_batch(batch: Result<BatchRequestDto, error::Error>, message: &str) -> String {
+    // BATCH自体のprotocol_versionも、含まれるリクエストと同様に確認する
+    let batch = batch.and_then(|batch| {
+        let options = RequestOptions::from_str(message)?;
+        compat::upgrade(message, options.protocol_version.as_deref())?;
+        Ok((batch, options))
+    });
+    let (batch, options) = match batch {
+        Ok(batch) => batch,
+        Err(e) => {
+            let error_message = ErrorMessage::new(Some("BATCH".to_string()), None, &e);
+            let message = VersionedMessage::to_string(&error_message);
+            tracing::error!("{}", message);
+            return message;
+        }
+    };
+
+    let module = BatchService::builder().build();
+    let sequence: &dyn BatchSequence = module.resolve_ref();
+    let result = sequence.execute(batch, options).await;
+    snapshot::save();
+
+    let message = BatchMessage {
+        is_success: result.is_success(),
+        result: result
+            .items
+            .into_iter()
+            .map(|item| BatchItemMessage {
+                request_id: item.request_id,
+                result: match item.result {
+                    Ok(response) => BatchItemResult::Success(ResponseDtoResult::Success(response)),
+                    Err(e) => BatchItemResult::Error(ErrorMessage::new(
+                        item.request_type,
+                        item.command,
+                        &e,
+                    )),
+                },
+            })
+            .collect(),
+        rollback: result.rollback,
+    };
+    VersionedMessage::to_string(&message)
+}
+
+/// SkyWayEventsで配信するイベント
+/// このノードのリクエストが原因で発生したイベントには、そのリクエストのrequest_idを付与する
+#[derive(Serialize)]
+struct CorrelatedEvent<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(flatten)]
+    event: &'a ResponseDtoResult,
+}
+
+/// called from rust_main
 /// 起動時に開始されたEventListenerが常時WebRTC Gatewayのイベントを監視している。
-/// この関数を通してC++側のプログラムがイベントを取得する。
-/// 取得したイベントはそのままの形ではなく、C++側/End Userが必要とする形に変換される。
+/// 受け取ったイベントはそのままの形ではなく、C++側/End Userが必要とする形に変換される。
 /// また、イベントによってはRust側のEventListenerが受信時に処理を行うものもある
-pub async fn receive_events() -> String {
+/// 1つのイベントの処理は1度だけ行い、その結果を全ての購読に配信する
+pub(crate) async fn dispatch_events() {
     let module = EventReceiveService::builder().build();
     let service: &dyn EventReceive = module.resolve_ref();
-    let event = service.execute().await;
-    match event {
-        Ok(event) => serde_json::to_string(&event).unwrap(),
+    let module = CppObjctsModule::builder().build();
+    let state: &dyn GlobalState = module.resolve_ref();
+
+    while !state.program_state().is_shutting_down() {
+        // 配信までの処理で出力したログを、イベントごとのspanにまとめる
+        let span = tracing::debug_span!("dispatch_event", request_id = tracing::field::Empty);
+        let message = match service.execute().instrument(span.clone()).await {
+            Ok(event) => {
+                let request_id = state.correlations().correlate(&event);
+                if let Some(ref request_id) = request_id {
+                    span.record("request_id", request_id.as_str());
+                }
+                span.in_scope(|| tracing::debug!("dispatching event"));
+                let value = serde_json::to_value(&CorrelatedEvent {
+                    request_id,
+                    event: &event,
+                })
+                .unwrap();
+                state.metrics().record_event(&value);
+                value.to_string()
+            }
+            Err(error) => {
+                let error_message = ErrorMessage::new(None, None, &error);
+                let message = error_message.to_string().unwrap();
+                state
+                    .metrics()
+                    .record_event(&serde_json::to_value(&error_message).unwrap());
+                span.in_scope(|| tracing::error!("{}", message));
+                message
+            }
+        };
+        // イベントの処理でPeer Object, Connectionの登録が変化している場合があるので書き出す
+        snapshot::save();
+        state.subscriptions().publish(message);
+    }
+}
+
+/// called from rust_main
+/// 前回の起動時に書き出したスナップショットをWebRTC Gatewayの状態と突き合わせ、
+/// 引き継げるものはこのノードの管理下に戻し、残りは削除する
+/// 突き合わせが終わってから、以降の状態の変化をスナップショットに書き出すようにする
+pub(crate) async fn reconcile_snapshot(path: std::path::PathBuf) {
+    let snapshot = match StateSnapshot::read(&path) {
+        Ok(snapshot) => snapshot.unwrap_or_default(),
+        // 読めないファイルは引き継げないので、新しい内容で上書きする
+        Err(e) => {
+            tracing::error!("snapshot error: {}", e);
+            StateSnapshot::default()
+        }
+    };
+
+    let module = ReconcileService::builder().build();
+    let state: &dyn GlobalState = module.resolve_ref();
+    if !snapshot.is_empty() {
+        // WebRTC Gatewayと通信できない状態で突き合わせると、何も引き継げないまま前回の内容を上書きしてしまう
+        // 通信できるようになるまで待機する
+        let repository: &dyn Repository = module.resolve_ref();
+        let config = state.config();
+        let backoff = Backoff {
+            initial: std::time::Duration::from_millis(config.reconnect_initial_backoff_ms),
+            max: std::time::Duration::from_millis(config.reconnect_max_backoff_ms),
+        };
+        let mut attempts = 0u32;
+        while !ping(repository).await.reachable {
+            if state.program_state().is_shutting_down() {
+                return;
+            }
+            attempts += 1;
+            tokio::time::sleep(backoff.delay(attempts)).await;
+        }
+
+        let reconcile: &dyn ReconcileSequence = module.resolve_ref();
+        for step in reconcile.execute(snapshot).await {
+            match step.result {
+                Ok(Reconciled::Adopted) => tracing::info!("snapshot: adopted {}", step.target),
+                Ok(Reconciled::Deleted) => tracing::info!("snapshot: deleted {}", step.target),
+                Err(e) => tracing::warn!("snapshot: failed to reconcile {}: {}", step.target, e),
+            }
+        }
+    }
+
+    snapshot::enable(path);
+}
+
+/// called from infra::metrics_server
+/// SYSTEM METRICSと同じ内容を、Prometheusのテキスト形式で返す
+pub(crate) async fn metrics_text() -> String {
+    let module = SystemService::builder().build();
+    let service: &dyn Service = module.resolve_ref();
+    match service
+        .execute(RequestDto::System(SystemRequestDto::Metrics))
+        .await
+    {
+        Ok(ResponseDtoResult::Success(ResponseDto::System(SystemResponseDto::Metrics(
+            metrics,
+        )))) => metrics::to_prometheus(&metrics),
+        // SYSTEM METRICSは失敗しないので、ここには到達しない
+        _ => String::new(),
+    }
+}
+
+/// called from ffi::receive_events
+/// 既定の購読に配信されたイベントを1つ取得する
+pub async fn receive_events() -> String {
+    receive_subscription_events(DEFAULT_SUBSCRIPTION).await
+}
+
+/// called from ffi::receive_subscription_events
+/// EVENT SUBSCRIBEで開始した購読に配信されたイベントのうち、フィルタに合致するものを1つ取得する
+/// 購読ごとに全てのイベントの複製を受け取るので、複数のクライアントがイベントを奪い合うことはない
+pub async fn receive_subscription_events(subscription_id: &str) -> String {
+    let module = CppObjctsModule::builder().build();
+    let state: &dyn GlobalState = module.resolve_ref();
+    let timeout = std::time::Duration::from_millis(state.config().event_timeout_ms);
+    let result = state
+        .subscriptions()
+        .receive(subscription_id, timeout, || {
+            state.program_state().is_shutting_down()
+        })
+        .await;
+    match result {
+        Ok(message) => message,
         Err(error) => {
-            let internal = ErrorMessageInternal {
-                request_type: None,
-                command: None,
-                error: format!("invalid message in receive_events: {:?}", error),
-            };
-            let error_message = ErrorMessage {
-                is_success: false,
-                result: internal,
-            };
+            let error_message = ErrorMessage::new(None, None, &error);
             let message = error_message.to_string().unwrap();
-            LoggerHolder::global().error(message.as_str());
-            return message;
+            tracing::error!("{}", message);
+            message
         }
     }
 }
+
+#[cfg(test)]
+mod application_test {
+    use super::*;
+
+    #[test]
+    // 成功時、失敗時のどちらも、応答と同じ階層にprotocol_versionを付与する
+    fn versioned_message() {
+        let result = serde_json::json!({
+            "request_type": "SYSTEM",
+            "command": "LIST_CONNECTIONS",
+            "data_connection_ids": [],
+            "media_connection_ids": []
+        });
+        let json = serde_json::json!({"is_success": true, "result": result}).to_string();
+        let response = ResponseDtoResult::from_str(&json).unwrap();
+        let message = VersionedMessage::to_string(&response);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&message).unwrap(),
+            serde_json::json!({
+                "protocol_version": version::PROTOCOL_VERSION,
+                "is_success": true,
+                "result": result
+            })
+        );
+
+        let error = error::Error::UnsupportedProtocolVersion {
+            requested: "2.0.0".to_string(),
+            current: version::PROTOCOL_VERSION.to_string(),
+        };
+        let message = VersionedMessage::to_string(&ErrorMessage::new(None, None, &error));
+        let message = serde_json::from_str::<serde_json::Value>(&message).unwrap();
+        assert_eq!(message["protocol_version"], version::PROTOCOL_VERSION);
+        assert_eq!(message["is_success"], false);
+        assert_eq!(message["result"]["code"], "UNSUPPORTED_PROTOCOL_VERSION");
+        assert_eq!(message["result"]["details"]["requested"], "2.0.0");
+    }
+
+    #[tokio::test]
+    // call_serviceの処理中は、応答にそのリクエストのrequest_idを付与する
+    async fn request_id() {
+        let error = error::Error::invalid_request("invalid");
+        let message = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                VersionedMessage::to_string(&ErrorMessage::new(None, None, &error))
+            })
+            .await;
+        let message = serde_json::from_str::<serde_json::Value>(&message).unwrap();
+        assert_eq!(message["protocol_version"], version::PROTOCOL_VERSION);
+        assert_eq!(message["request_id"], "req-1");
+        assert_eq!(message["result"]["code"], "INVALID_REQUEST");
+    }
+
+    #[test]
+    // リクエストと結びついたイベントのみ、request_idを付与する
+    fn correlated_event() {
+        let json = r#"{
+            "is_success":true,
+            "result":{
+                "request_type":"DATA",
+                "command":"EVENT",
+                "event":"OPEN",
+                "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"
+            }
+        }"#;
+        let event = ResponseDtoResult::from_str(json).unwrap();
+        let expected = serde_json::from_str::<serde_json::Value>(json).unwrap();
+
+        let message = serde_json::to_value(CorrelatedEvent {
+            request_id: None,
+            event: &event,
+        })
+        .unwrap();
+        assert_eq!(message, expected);
+
+        let message = serde_json::to_value(CorrelatedEvent {
+            request_id: Some("connect".to_string()),
+            event: &event,
+        })
+        .unwrap();
+        let mut expected = expected;
+        expected["request_id"] = "connect".into();
+        assert_eq!(message, expected);
+    }
+}
@@ -0,0 +1,605 @@
+// BATCHリクエストで、複数のリクエストを1度のcall_serviceで処理するモジュール
+// 各リクエストは先頭から順に、単体で送られた場合と同じServiceで処理し、失敗した時点で以降の処理を行わない
+// リクエスト中の{"$result": "0/peer_id"}のようなオブジェクトは、
+// 先に処理したリクエストの結果(resultの中身)からJSON Pointerで取り出した値に置き換える
+// ATOMICの場合、失敗した時点でそれまでに生成したオブジェクトを逆順に解放する
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use shaku::{Component, Interface};
+use tracing::Instrument;
+
+use crate::application::dto::compat;
+use crate::application::dto::request::{
+    BatchMode, BatchRequestDto, DataRequestDto, MediaRequestDto, PeerRequestDto, RequestDto,
+    RequestOptions,
+};
+use crate::application::dto::response::{
+    DataResponseDto, MediaResponseDto, PeerResponseDto, ResponseDto, ResponseDtoResult,
+    ShutdownStepDto, ShutdownStepKind,
+};
+use crate::application::dto::Command;
+use crate::application::factory::Factory;
+use crate::application::usecase::event::correlation;
+use crate::application::usecase::rollback::Resource;
+use crate::domain::entity::{
+    DataConnectionId, DataConnectionIdWrapper, MediaConnectionId, MediaConnectionIdWrapper,
+    SerializableSocket,
+};
+use crate::domain::repository::{REQUEST_ID, REQUEST_TIMEOUT};
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+#[cfg(test)]
+use mockall::automock;
+
+/// BATCHに含まれるリクエスト1つ分の結果
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BatchItem {
+    /// リクエストに指定されたrequest_id。省略した場合は"<BATCHのrequest_id>.<添字>"とする
+    pub request_id: String,
+    /// エラーメッセージを生成するため、リクエストから読み取れた場合は保持する
+    pub request_type: Option<String>,
+    pub command: Option<String>,
+    pub result: Result<ResponseDto, error::Error>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BatchResult {
+    /// 処理したリクエストの結果。失敗したリクエストより後のものは含まない
+    pub items: Vec<BatchItem>,
+    /// ATOMICで失敗した場合のみ、解放を試みたオブジェクトごとの結果を解放した順に格納する
+    pub rollback: Option<Vec<ShutdownStepDto>>,
+}
+
+impl BatchResult {
+    pub fn is_success(&self) -> bool {
+        self.items.iter().all(|item| item.result.is_ok())
+    }
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub(crate) trait BatchSequence: Interface {
+    async fn execute(&self, batch: BatchRequestDto, options: RequestOptions) -> BatchResult;
+}
+
+#[derive(Component)]
+#[shaku(interface = BatchSequence)]
+pub(crate) struct Batch {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    factory: Arc<dyn Factory>,
+}
+
+// 生成したオブジェクトを解放するためのリクエスト
+struct Release {
+    step: ShutdownStepKind,
+    target: String,
+    request: RequestDto,
+}
+
+#[async_trait]
+impl BatchSequence for Batch {
+    async fn execute(&self, batch: BatchRequestDto, options: RequestOptions) -> BatchResult {
+        let mut items = vec![];
+        // 成功したリクエストの結果。失敗した時点で終了するので、添字はリクエストの順序と一致する
+        let mut results = vec![];
+        // リクエストごとの解放手順
+        let mut releases = vec![];
+
+        for (index, request) in batch.requests.into_iter().enumerate() {
+            let request_type = read_string(&request, "request_type");
+            let command = read_string(&request, "command");
+            let request_id =
+                correlation::read_request_id(&request).unwrap_or_else(|| item_request_id(index));
+            // 単体で送られた場合と同様に、ログとイベントをリクエストごとのrequest_idと結びつける
+            let span = tracing::info_span!(
+                "request",
+                index,
+                request_type = request_type.as_deref().unwrap_or_default(),
+                command = command.as_deref().unwrap_or_default()
+            );
+            let result = REQUEST_ID
+                .scope(request_id.clone(), self.item(request, &results, &options))
+                .instrument(span.clone())
+                .await;
+            if let Err(ref e) = result {
+                span.in_scope(|| tracing::warn!(error = %e, "request failed"));
+            }
+            let is_success = result.is_ok();
+            if let Ok(ref response) = result {
+                // ResponseDtoのserializeが失敗するケースはRustの型システムにより発生しない
+                results.push(serde_json::to_value(response).unwrap());
+                releases.push(self.releases(response));
+            }
+            items.push(BatchItem {
+                request_id,
+                request_type,
+                command,
+                result,
+            });
+            if !is_success {
+                break;
+            }
+        }
+
+        let failed = items.iter().any(|item| item.result.is_err());
+        let rollback = match batch.mode {
+            BatchMode::Atomic if failed => Some(self.rollback(releases).await),
+            _ => None,
+        };
+        BatchResult { items, rollback }
+    }
+}
+
+impl Batch {
+    // 参照を解決した上で、単体のリクエストと同様に処理する
+    async fn item(
+        &self,
+        request: Value,
+        results: &[Value],
+        options: &RequestOptions,
+    ) -> Result<ResponseDto, error::Error> {
+        let json = resolve(request, results)?.to_string();
+        // リクエスト自身に指定がない場合は、BATCHに指定されたオプションを用いる
+        let item_options = RequestOptions::from_str(&json)?;
+        let protocol_version = item_options
+            .protocol_version
+            .as_deref()
+            .or(options.protocol_version.as_deref());
+        let json = compat::upgrade(&json, protocol_version)?;
+        let dto = RequestDto::from_str(&json)?;
+        let timeout = item_options.timeout().or_else(|| options.timeout());
+
+        let service = self.factory.create_service(&dto);
+        let started_at = std::time::Instant::now();
+        let response = REQUEST_TIMEOUT
+            .scope(timeout, service.execute(dto.clone()))
+            .await;
+        // 単体で送られた場合と同様に、解釈できたリクエストのみrequest_type, commandごとの統計に含める
+        // クライアントが送ってきた文字列をそのままラベルにすると、種類が際限なく増えてしまう
+        self.state.metrics().record_request(
+            &dto.dto_type(),
+            &dto.command(),
+            started_at.elapsed(),
+            matches!(response, Ok(ResponseDtoResult::Success(_))),
+        );
+        let response = response?;
+        self.record(&dto, &response);
+        match response {
+            ResponseDtoResult::Success(response) => Ok(response),
+            ResponseDtoResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+        }
+    }
+
+    // call_serviceと同様に、成功したリクエストを状態の記録に反映する
+    fn record(&self, request: &RequestDto, response: &ResponseDtoResult) {
+        if let ResponseDtoResult::Error(_) = response {
+            return;
+        }
+        self.state
+            .recovery_state()
+            .lock()
+            .unwrap()
+            .record(request, response);
+        self.state
+            .peer_registry()
+            .lock()
+            .unwrap()
+            .record(request, response);
+    }
+
+    // 成功したリクエストが生成したオブジェクトと、それを解放するためのリクエスト
+    // Connectionは、紐づけたSocketより先に切断する
+    fn releases(&self, response: &ResponseDto) -> Vec<Release> {
+        match response {
+            ResponseDto::Peer(PeerResponseDto::Create(peer_info)) => vec![Release {
+                step: ShutdownStepKind::PeerDelete,
+                target: peer_info.peer_id().as_str().to_string(),
+                request: RequestDto::Peer(PeerRequestDto::Delete {
+                    params: peer_info.clone(),
+                }),
+            }],
+            ResponseDto::Data(DataResponseDto::Create(socket)) => socket
+                .get_id()
+                .map(|data_id| release(ShutdownStepKind::DataDelete, Resource::Data(data_id)))
+                .into_iter()
+                .collect(),
+            ResponseDto::Data(DataResponseDto::Connect(wrapper))
+            | ResponseDto::Data(DataResponseDto::Redirect(wrapper)) => {
+                self.data_connection_releases(&wrapper.data_connection_id)
+            }
+            ResponseDto::Media(MediaResponseDto::ContentCreate(socket)) => socket
+                .get_id()
+                .map(|media_id| release(ShutdownStepKind::MediaDelete, Resource::Media(media_id)))
+                .into_iter()
+                .collect(),
+            ResponseDto::Media(MediaResponseDto::RtcpCreate(socket)) => socket
+                .get_id()
+                .map(|rtcp_id| release(ShutdownStepKind::RtcpDelete, Resource::Rtcp(rtcp_id)))
+                .into_iter()
+                .collect(),
+            ResponseDto::Media(MediaResponseDto::Call(wrapper)) => {
+                self.media_connection_releases(&wrapper.media_connection_id)
+            }
+            ResponseDto::Media(MediaResponseDto::Answer(answer)) => {
+                self.media_connection_releases(&answer.media_connection_id)
+            }
+            // 取得や削除など、オブジェクトを生成しないリクエスト
+            _ => vec![],
+        }
+    }
+
+    // CONNECT, REDIRECTで確保したData Socketは、PluginとともにGlobalStateに記録されている
+    // Pluginの破棄は、切断によって発生するCLOSEイベントの処理で行われる
+    fn data_connection_releases(&self, data_connection_id: &DataConnectionId) -> Vec<Release> {
+        let mut releases = vec![Release {
+            step: ShutdownStepKind::DataDisconnect,
+            target: data_connection_id.as_str().to_string(),
+            request: RequestDto::Data(DataRequestDto::Disconnect {
+                params: DataConnectionIdWrapper {
+                    data_connection_id: data_connection_id.clone(),
+                },
+            }),
+        }];
+        if let Some(topic) = self.state.find_topic(data_connection_id) {
+            releases.push(release(
+                ShutdownStepKind::DataDelete,
+                Resource::Data(topic.data_id),
+            ));
+        }
+        releases
+    }
+
+    // CALL, ANSWERで確保したMedia, Rtcp Socketは、転送設定とともにGlobalStateに記録されている
+    fn media_connection_releases(&self, media_connection_id: &MediaConnectionId) -> Vec<Release> {
+        let mut releases = vec![Release {
+            step: ShutdownStepKind::MediaDisconnect,
+            target: media_connection_id.as_str().to_string(),
+            request: RequestDto::Media(MediaRequestDto::Disconnect {
+                params: MediaConnectionIdWrapper {
+                    media_connection_id: media_connection_id.clone(),
+                },
+            }),
+        }];
+        if let Some(call) = self.state.find_call_response(media_connection_id) {
+            let send_params = &call.send_params;
+            for pair in [&send_params.video, &send_params.audio]
+                .into_iter()
+                .flatten()
+            {
+                if let Some(media_id) = pair.media.get_id() {
+                    releases.push(release(
+                        ShutdownStepKind::MediaDelete,
+                        Resource::Media(media_id),
+                    ));
+                }
+                if let Some(rtcp_id) = pair.rtcp.get_id() {
+                    releases.push(release(
+                        ShutdownStepKind::RtcpDelete,
+                        Resource::Rtcp(rtcp_id),
+                    ));
+                }
+            }
+        }
+        releases
+    }
+
+    // リクエストの逆順に解放する。1つの解放に失敗しても、残りの解放は継続する
+    async fn rollback(&self, releases: Vec<Vec<Release>>) -> Vec<ShutdownStepDto> {
+        let mut steps = vec![];
+        for release in releases.into_iter().rev().flatten() {
+            let service = self.factory.create_service(&release.request);
+            let result = match service.execute(release.request.clone()).await {
+                Ok(ResponseDtoResult::Error(message)) => {
+                    Err(error::Error::gateway_api_error(message))
+                }
+                Ok(response) => {
+                    self.record(&release.request, &response);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            steps.push(ShutdownStepDto {
+                step: release.step,
+                target: release.target,
+                is_success: result.is_ok(),
+                error: result.err(),
+            });
+        }
+        steps
+    }
+}
+
+fn release(step: ShutdownStepKind, resource: Resource) -> Release {
+    Release {
+        step,
+        target: resource.id(),
+        request: resource.delete_request(),
+    }
+}
+
+// BATCHの処理中であれば、そのrequest_idに添字を付けたものを用いる
+fn item_request_id(index: usize) -> String {
+    REQUEST_ID
+        .try_with(|request_id| format!("{}.{}", request_id, index))
+        .unwrap_or_else(|_| correlation::generate_request_id())
+}
+
+fn read_string(request: &Value, key: &str) -> Option<String> {
+    request.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+// {"$result": "<添字><JSON Pointer>"}の形のオブジェクトを、参照先の値に置き換える
+fn resolve(value: Value, results: &[Value]) -> Result<Value, error::Error> {
+    match value {
+        Value::Object(map) => {
+            if map.len() == 1 {
+                if let Some(reference) = map.get("$result") {
+                    return lookup(reference, results);
+                }
+            }
+            map.into_iter()
+                .map(|(key, value)| resolve(value, results).map(|value| (key, value)))
+                .collect::<Result<serde_json::Map<_, _>, _>>()
+                .map(Value::Object)
+        }
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| resolve(value, results))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        value => Ok(value),
+    }
+}
+
+// "0/peer_id"は0番目の結果の/peer_idを、"0"は0番目の結果全体を指す
+// まだ処理していないリクエストや、存在しない値を参照した場合はエラーにする
+fn lookup(reference: &Value, results: &[Value]) -> Result<Value, error::Error> {
+    let invalid = || error::Error::invalid_request(format!("invalid reference: {}", reference));
+    let reference = reference.as_str().ok_or_else(invalid)?;
+    let (index, pointer) = reference.split_at(reference.find('/').unwrap_or(reference.len()));
+    let index = index.parse::<usize>().map_err(|_| invalid())?;
+    results
+        .get(index)
+        .and_then(|result| result.pointer(pointer))
+        .cloned()
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod batch_test {
+    use std::sync::Mutex;
+
+    use once_cell::sync::Lazy;
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::factory::MockFactory;
+    use crate::application::usecase::peer::recovery::RecoveryState;
+    use crate::application::usecase::system::metrics::Metrics;
+    use crate::application::usecase::MockService;
+    use crate::di::BatchService;
+    use crate::domain::entity::{DataId, DataIdWrapper, PeerInfo, SerializableId, SocketInfo};
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    const TOKEN: &str = "pt-9749250e-d157-4f80-9ee2-359ce8524308";
+    const DATA_ID: &str = "da-50a32bab-b3d9-4913-8e20-f79c90a6a211";
+
+    fn state() -> MockGlobalState {
+        state_with_metrics(Box::leak(Box::new(Metrics::default())))
+    }
+
+    fn state_with_metrics(metrics: &'static Metrics) -> MockGlobalState {
+        static RECOVERY_STATE: Lazy<Mutex<RecoveryState>> =
+            Lazy::new(|| Mutex::new(RecoveryState::default()));
+        let registry: &'static Mutex<PeerRegistry> =
+            Box::leak(Box::new(Mutex::new(PeerRegistry::default())));
+
+        let mut state = MockGlobalState::new();
+        state.expect_recovery_state().returning(|| &RECOVERY_STATE);
+        state.expect_metrics().returning(move || metrics);
+        state.expect_peer_registry().returning(move || registry);
+        state
+    }
+
+    fn peer_created() -> ResponseDtoResult {
+        let peer_info = PeerInfo::try_create("peer_id", TOKEN).unwrap();
+        ResponseDtoResult::Success(ResponseDto::Peer(PeerResponseDto::Create(peer_info)))
+    }
+
+    fn data_created() -> ResponseDtoResult {
+        let socket =
+            SocketInfo::<DataId>::try_create(Some(DATA_ID.into()), "127.0.0.1", 50000).unwrap();
+        ResponseDtoResult::Success(ResponseDto::Data(DataResponseDto::Create(socket)))
+    }
+
+    // 受け取ったリクエストを記録し、responseの返す結果を返すFactory
+    fn factory(
+        requests: Arc<Mutex<Vec<RequestDto>>>,
+        response: fn(&RequestDto) -> Result<ResponseDtoResult, error::Error>,
+    ) -> MockFactory {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().returning(move |_| {
+            let requests = requests.clone();
+            let mut service = MockService::new();
+            service.expect_execute().returning(move |request| {
+                requests.lock().unwrap().push(request.clone());
+                response(&request)
+            });
+            Arc::new(service)
+        });
+        factory
+    }
+
+    async fn execute(factory: MockFactory, json: &str) -> BatchResult {
+        let module = BatchService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let batch: &dyn BatchSequence = module.resolve_ref();
+        let request = BatchRequestDto::from_str(json).unwrap().unwrap();
+        let options = RequestOptions::from_str(json).unwrap();
+        batch.execute(request, options).await
+    }
+
+    #[tokio::test]
+    // 先に処理したリクエストの結果を参照し、失敗した時点で以降の処理を行わない
+    async fn sequential() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let factory = factory(requests.clone(), |request| match request {
+            RequestDto::Peer(PeerRequestDto::Create { .. }) => Ok(peer_created()),
+            RequestDto::Peer(PeerRequestDto::Status { .. }) => {
+                Ok(ResponseDtoResult::Error("recv NotFound".to_string()))
+            }
+            _ => unreachable!(),
+        });
+        let json = r#"{
+            "request_type": "BATCH",
+            "mode": "SEQUENTIAL",
+            "requests": [
+                {"request_type":"PEER","command":"CREATE","params":{"key":"key","domain":"localhost","peer_id":"peer_id","turn":false}},
+                {"request_type":"PEER","command":"STATUS","request_id":"status","params":{"peer_id":{"$result":"0/peer_id"},"token":{"$result":"0/token"}}},
+                {"request_type":"PEER","command":"LIST"}
+            ]
+        }"#;
+        let result = REQUEST_ID
+            .scope("batch".to_string(), execute(factory, json))
+            .await;
+
+        assert!(!result.is_success());
+        assert_eq!(result.items.len(), 2);
+        // 指定がなければBATCHのrequest_idと添字から生成する
+        assert_eq!(result.items[0].request_id, "batch.0");
+        assert_eq!(result.items[1].request_id, "status");
+        assert_eq!(result.items[1].command, Some("STATUS".to_string()));
+        assert_eq!(
+            result.items[1].result,
+            Err(error::Error::gateway_api_error("recv NotFound"))
+        );
+        assert_eq!(result.rollback, None);
+        assert_eq!(
+            requests.lock().unwrap()[1],
+            RequestDto::Peer(PeerRequestDto::Status {
+                params: PeerInfo::try_create("peer_id", TOKEN).unwrap()
+            })
+        );
+    }
+
+    #[tokio::test]
+    // 失敗した場合は、それまでに生成したオブジェクトを逆順に解放する
+    async fn atomic() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let factory = factory(requests.clone(), |request| match request {
+            RequestDto::Peer(PeerRequestDto::Create { .. }) => Ok(peer_created()),
+            RequestDto::Data(DataRequestDto::Create) => Ok(data_created()),
+            RequestDto::Data(DataRequestDto::Delete { params }) => Ok(ResponseDtoResult::Success(
+                ResponseDto::Data(DataResponseDto::Delete(params.clone())),
+            )),
+            RequestDto::Peer(PeerRequestDto::Delete { .. }) => {
+                Err(error::Error::gateway_unreachable("error"))
+            }
+            _ => Err(error::Error::Timeout { timeout_ms: 1000 }),
+        });
+        let json = r#"{
+            "request_type": "BATCH",
+            "mode": "ATOMIC",
+            "requests": [
+                {"request_type":"PEER","command":"CREATE","params":{"key":"key","domain":"localhost","peer_id":"peer_id","turn":false}},
+                {"request_type":"DATA","command":"CREATE"},
+                {"request_type":"MEDIA","command":"CONTENT_CREATE","params":{"is_video":true}}
+            ]
+        }"#;
+        let result = execute(factory, json).await;
+
+        assert_eq!(result.items.len(), 3);
+        assert_eq!(
+            result.items[2].result,
+            Err(error::Error::Timeout { timeout_ms: 1000 })
+        );
+        assert_eq!(
+            result.rollback,
+            Some(vec![
+                ShutdownStepDto {
+                    step: ShutdownStepKind::DataDelete,
+                    target: DATA_ID.to_string(),
+                    is_success: true,
+                    error: None,
+                },
+                ShutdownStepDto {
+                    step: ShutdownStepKind::PeerDelete,
+                    target: "peer_id".to_string(),
+                    is_success: false,
+                    error: Some(error::Error::gateway_unreachable("error")),
+                },
+            ])
+        );
+        assert_eq!(
+            requests.lock().unwrap()[3],
+            RequestDto::Data(DataRequestDto::Delete {
+                params: DataIdWrapper {
+                    data_id: DataId::try_create(DATA_ID).unwrap()
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    // まだ処理していないリクエストは参照できない
+    async fn invalid_reference() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let factory = factory(requests.clone(), |_| unreachable!());
+        let json = r#"{
+            "request_type": "BATCH",
+            "mode": "ATOMIC",
+            "requests": [
+                {"request_type":"PEER","command":"STATUS","params":{"peer_id":{"$result":"0/peer_id"},"token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}
+            ]
+        }"#;
+        let result = execute(factory, json).await;
+
+        assert!(matches!(
+            result.items[0].result,
+            Err(error::Error::InvalidRequest { .. })
+        ));
+        assert_eq!(result.rollback, Some(vec![]));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    // 解釈できないリクエストは、クライアントが送ってきた文字列をラベルとして統計に含めない
+    async fn metrics_labels() {
+        let metrics: &'static Metrics = Box::leak(Box::new(Metrics::default()));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let module = BatchService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state_with_metrics(metrics)))
+            .with_component_override::<dyn Factory>(Box::new(factory(requests, |_| {
+                Ok(peer_created())
+            })))
+            .build();
+        let batch: &dyn BatchSequence = module.resolve_ref();
+        let json = r#"{
+            "request_type": "BATCH",
+            "mode": "SEQUENTIAL",
+            "requests": [
+                {"request_type":"PEER","command":"CREATE","params":{"key":"API_KEY","domain":"localhost","peer_id":"peer_id","turn":true}},
+                {"request_type":"UNKNOWN_TYPE_1234","command":"UNKNOWN_COMMAND_1234"}
+            ]
+        }"#;
+        let request = BatchRequestDto::from_str(json).unwrap().unwrap();
+        let options = RequestOptions::from_str(json).unwrap();
+        let result = batch.execute(request, options).await;
+        assert!(result.items[1].result.is_err());
+
+        let labels: Vec<(String, String)> = metrics
+            .snapshot()
+            .requests
+            .into_iter()
+            .map(|request| (request.request_type, request.command))
+            .collect();
+        assert_eq!(labels, vec![("PEER".to_string(), "CREATE".to_string())]);
+    }
+}
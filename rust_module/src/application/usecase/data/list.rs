@@ -0,0 +1,259 @@
+/// DATA LISTは、このノードがPluginをロードしたDataConnectionの一覧を返す
+/// with_statusが指定された場合は、各DataConnectionのSTATUSをWebRTC Gatewayに問い合わせて付加する
+/// 問い合わせに失敗したDataConnectionはstatus_errorに理由を格納し、一覧からは除外しない
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shaku::Component;
+
+use crate::application::dto::request::{DataRequestDto, RequestDto};
+use crate::application::dto::response::{
+    DataConnectionEntryDto, DataResponseDto, ResponseDto, ResponseDtoResult,
+};
+use crate::application::usecase::Service;
+use crate::domain::entity::request::{DataRequest, Request};
+use crate::domain::entity::response::{DataResponse, Response, ResponseResult};
+use crate::domain::entity::{
+    DataConnectionId, DataConnectionIdWrapper, DataConnectionStatus, PeerId,
+};
+use crate::domain::repository::Repository;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+#[derive(Component)]
+#[shaku(interface = Service)]
+pub(crate) struct DataList {
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+}
+
+#[async_trait]
+impl Service for DataList {
+    async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
+        if let RequestDto::Data(DataRequestDto::List { params }) = request {
+            let mut topics = self.state.list_topics();
+            topics.sort_by(|a, b| a.data_connection_id.cmp(&b.data_connection_id));
+
+            let mut data_connections = vec![];
+            for topic in topics {
+                let peer_id = self
+                    .state
+                    .peer_registry()
+                    .lock()
+                    .unwrap()
+                    .find_data_connection_owner(&topic.data_connection_id);
+                let mut entry = DataConnectionEntryDto {
+                    data_connection_id: topic.data_connection_id,
+                    peer_id,
+                    remote_peer_id: topic.remote_peer_id,
+                    plugin_type: topic.plugin_type,
+                    data_pipe_port_num: topic.data_pipe_port_num,
+                    data_id: topic.data_id,
+                    status: None,
+                    status_error: None,
+                };
+
+                if params.with_status {
+                    match self.status(&entry.data_connection_id).await {
+                        Ok(status) => {
+                            if entry.remote_peer_id.is_none() {
+                                entry.remote_peer_id = Some(PeerId::new(status.remote_id.clone()));
+                            }
+                            entry.status = Some(status);
+                        }
+                        Err(e) => entry.status_error = Some(e),
+                    }
+                }
+                data_connections.push(entry);
+            }
+
+            return Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                DataResponseDto::List { data_connections },
+            )));
+        }
+
+        return Err(error::Error::internal(
+            "invalid message in data list service",
+        ));
+    }
+}
+
+impl DataList {
+    async fn status(
+        &self,
+        data_connection_id: &DataConnectionId,
+    ) -> Result<DataConnectionStatus, error::Error> {
+        let request = Request::Data(DataRequest::Status {
+            params: DataConnectionIdWrapper {
+                data_connection_id: data_connection_id.clone(),
+            },
+        });
+        match self.repository.register(request).await? {
+            ResponseResult::Success(Response::Data(DataResponse::Status(status))) => Ok(status),
+            ResponseResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+            response => Err(error::Error::internal(format!(
+                "unexpected response {:?}",
+                response
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod list_data_test {
+    use std::sync::Mutex;
+
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::request::ListParamsDto;
+    use crate::di::DataListService;
+    use crate::domain::entity::{DataId, PeerInfo, SerializableId};
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    const CONNECTED: &str = "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c";
+    const REDIRECTED: &str = "dc-8bdef7a1-65c8-46be-a82e-37d51c776309";
+
+    // CONNECTしたDataConnectionとREDIRECTしたDataConnectionを1つずつ保持している状態
+    fn state() -> MockGlobalState {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info);
+        registry.insert_data_connection(
+            &peer_info.peer_id(),
+            DataConnectionId::try_create(CONNECTED).unwrap(),
+        );
+        let registry: &'static Mutex<PeerRegistry> = Box::leak(Box::new(Mutex::new(registry)));
+
+        let topics = vec![
+            DataPipeInfo {
+                data_connection_id: DataConnectionId::try_create(REDIRECTED).unwrap(),
+                data_pipe_port_num: 60001,
+                data_id: DataId::try_create("da-20a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+                plugin_type: "string".to_string(),
+                plugins: vec![],
+                remote_peer_id: None,
+            },
+            DataPipeInfo {
+                data_connection_id: DataConnectionId::try_create(CONNECTED).unwrap(),
+                data_pipe_port_num: 60000,
+                data_id: DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+                plugin_type: "binary".to_string(),
+                plugins: vec![],
+                remote_peer_id: Some(PeerId::new("target_id")),
+            },
+        ];
+
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        state
+            .expect_list_topics()
+            .times(1)
+            .returning(move || topics.clone());
+        state
+    }
+
+    #[tokio::test]
+    // with_statusを指定しない場合は、WebRTC Gatewayに問い合わせない
+    async fn without_status() {
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(0);
+
+        let module = DataListService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::from_str(r#"{"request_type":"DATA","command":"LIST"}"#);
+        let result = service.execute(request.unwrap()).await.unwrap();
+
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"DATA",
+                    "command":"LIST",
+                    "data_connections":[{
+                        "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+                        "peer_id":"peer_id",
+                        "remote_peer_id":"target_id",
+                        "plugin_type":"binary",
+                        "data_pipe_port_num":60000,
+                        "data_id":"da-50a32bab-b3d9-4913-8e20-f79c90a6a211"
+                    },{
+                        "data_connection_id":"dc-8bdef7a1-65c8-46be-a82e-37d51c776309",
+                        "plugin_type":"string",
+                        "data_pipe_port_num":60001,
+                        "data_id":"da-20a32bab-b3d9-4913-8e20-f79c90a6a211"
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    // STATUSの取得に失敗したDataConnectionも一覧に残し、remote_peer_idはSTATUSから補う
+    async fn with_status() {
+        let status = DataConnectionStatus {
+            remote_id: "remote_id".to_string(),
+            buffersize: 0,
+            label: "".to_string(),
+            metadata: "".to_string(),
+            open: true,
+            reliable: true,
+            serialization: "NONE".to_string(),
+            r#type: "DATA".to_string(),
+        };
+        let status_ref = status.clone();
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(2)
+            .returning(move |request| match request {
+                Request::Data(DataRequest::Status { params })
+                    if params.data_connection_id.as_str() == CONNECTED =>
+                {
+                    Ok(ResponseResult::Error("recv NotFound".to_string()))
+                }
+                _ => Ok(ResponseResult::Success(Response::Data(
+                    DataResponse::Status(status_ref.clone()),
+                ))),
+            });
+
+        let module = DataListService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::Data(DataRequestDto::List {
+            params: ListParamsDto { with_status: true },
+        });
+        let result = service.execute(request).await.unwrap();
+
+        if let ResponseDtoResult::Success(ResponseDto::Data(DataResponseDto::List {
+            data_connections,
+        })) = result
+        {
+            assert_eq!(data_connections.len(), 2);
+            assert_eq!(data_connections[0].status, None);
+            assert_eq!(
+                data_connections[0].status_error,
+                Some(error::Error::gateway_api_error("recv NotFound"))
+            );
+            assert_eq!(data_connections[1].status, Some(status));
+            assert_eq!(
+                data_connections[1].remote_peer_id,
+                Some(PeerId::new("remote_id"))
+            );
+        } else {
+            unreachable!();
+        }
+    }
+}
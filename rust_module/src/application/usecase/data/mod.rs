@@ -1,3 +1,4 @@
 /// /data系のAPIのうち、特別な内部処理を必要とするものはここで実装する
 pub(crate) mod connect;
+pub(crate) mod list;
 pub(crate) mod redirect;
@@ -7,15 +7,18 @@
 ///    ロードエラーが出たら、Dataポートを閉じてエラーを返して終了。
 ///    ロードエラーが発生しない場合、この時点でC++側は送受信の準備ができている
 /// 3. C++側で開放したポート番号を戻り値から取得し、Redirect APIをcallし、戻り値を返す
+///    失敗した場合は、Pluginを破棄させ、Dataポートを閉じてエラーを返して終了。
 use std::ffi::CStr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use shaku::Component;
+use tracing::Instrument;
 
-use crate::application::dto::request::{DataRequestDto, RequestDto};
+use crate::application::dto::request::{DataRequestDto, RedirectDtoParams, RequestDto};
 use crate::application::dto::response::{DataResponseDto, ResponseDto, ResponseDtoResult};
 use crate::application::factory::Factory;
+use crate::application::usecase::rollback::{Resource, Rollback};
 use crate::application::usecase::Service;
 use crate::domain::entity::request::{DataRequest, Request};
 use crate::domain::entity::response::{DataResponse, Response, ResponseResult};
@@ -25,7 +28,7 @@ use crate::domain::entity::{
 use crate::domain::repository::Repository;
 use crate::error;
 use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
-use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState};
+use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState, Logger};
 
 #[derive(Component)]
 #[shaku(interface = Service)]
@@ -38,6 +41,8 @@ pub(crate) struct Redirect {
     factory: Arc<dyn Factory>,
     #[shaku(inject)]
     callback: Arc<dyn CallbackFunctions>,
+    #[shaku(inject)]
+    logger: Arc<dyn Logger>,
 }
 
 #[async_trait]
@@ -47,103 +52,151 @@ impl Service for Redirect {
             params: redirect_params,
         }) = request
         {
-            // 1. Dataポートを開放させ、DataChannelへのSourceとして利用する
-            let (data_id, address, port) = {
-                let create_data_param = RequestDto::Data(DataRequestDto::Create);
-                let service = self.factory.create_service(&create_data_param);
-                let result = service.execute(create_data_param).await?;
-                if let ResponseDtoResult::Success(ResponseDto::Data(DataResponseDto::Create(
-                    socket,
-                ))) = result
-                {
-                    (
-                        socket.get_id().expect("failed to open data port"),
-                        socket.ip(),
-                        socket.port(),
-                    )
-                } else {
+            // 途中で失敗した場合は、それまでに開放させたポートを閉じる
+            let mut rollback = Rollback::new(self.factory.clone());
+            let result = self.redirect(redirect_params, &mut rollback).await;
+            if result.is_err() {
+                for (resource, e) in rollback.release().await {
+                    let message = format!("failed to release {:?}: {}", resource, e);
+                    self.logger.warn(&message);
+                }
+            }
+            return result;
+        }
+
+        return Err(error::Error::internal("invalid parameters"));
+    }
+}
+
+impl Redirect {
+    async fn redirect(
+        &self,
+        redirect_params: RedirectDtoParams,
+        rollback: &mut Rollback,
+    ) -> Result<ResponseDtoResult, error::Error> {
+        // 1. Dataポートを開放させ、DataChannelへのSourceとして利用する
+        let (data_id, address, port) = {
+            let create_data_param = RequestDto::Data(DataRequestDto::Create);
+            let service = self.factory.create_service(&create_data_param);
+            let result = service
+                .execute(create_data_param)
+                .instrument(tracing::info_span!("create_socket", kind = "data"))
+                .await?;
+            match result {
+                ResponseDtoResult::Success(ResponseDto::Data(DataResponseDto::Create(socket))) => {
+                    let data_id = socket.get_id().ok_or_else(|| {
+                        error::Error::gateway_api_error("data_id is not returned")
+                    })?;
+                    rollback.push(Resource::Data(data_id.clone()));
+                    (data_id, socket.ip(), socket.port())
+                }
+                _ => {
                     let message = format!("create data failed {:?}", result);
-                    return Err(error::Error::create_local_error(&message));
+                    return Err(error::Error::gateway_api_error(message));
+                }
+            }
+        };
+
+        // 2. C++側でRos Pluginをロードさせる。
+        // ここでserializeが失敗するケースはRustの型システムにより発生しないので、テストはしていない
+        let plugin_params = serde_json::to_string(&redirect_params.plugin_info.plugins).unwrap();
+
+        let span = tracing::info_span!(
+            "plugin_load",
+            plugin_type = %redirect_params.plugin_info.r#type
+        );
+        let (flag, port, error_message) = span.in_scope(|| {
+            let result = self.callback.data_callback(
+                &address.to_string(),
+                port,
+                &redirect_params.plugin_info.r#type,
+                &plugin_params,
+            );
+
+            let error_message = match result.is_success {
+                true => "".to_string(),
+                false => {
+                    let error_message = unsafe { CStr::from_ptr(result.error_message) }
+                        .to_str()
+                        .unwrap()
+                        .to_string();
+                    self.callback.release_string_callback(result.error_message);
+                    error_message
                 }
             };
 
-            // 2. C++側でRos Pluginをロードさせる。
-            // ここでserializeが失敗するケースはRustの型システムにより発生しないので、テストはしていない
-            let plugin_params =
-                serde_json::to_string(&redirect_params.plugin_info.plugins).unwrap();
-
-            let (flag, port, error_message) = {
-                let result = self.callback.data_callback(
-                    &address.to_string(),
-                    port,
-                    &redirect_params.plugin_info.r#type,
-                    &plugin_params,
-                );
+            (result.is_success, result.port, error_message)
+        });
 
-                let error_message = match result.is_success {
-                    true => "".to_string(),
-                    false => {
-                        let error_message = unsafe { CStr::from_ptr(result.error_message) }
-                            .to_str()
-                            .unwrap()
-                            .to_string();
-                        self.callback.release_string_callback(result.error_message);
-                        error_message
-                    }
-                };
+        span.in_scope(|| match flag {
+            true => tracing::info!(port, "plugin loaded"),
+            false => tracing::warn!(error = %error_message, "failed to load plugin"),
+        });
+        if !flag {
+            return Err(error::Error::PluginLoadFailed {
+                plugin_type: redirect_params.plugin_info.r#type.clone(),
+                message: error_message,
+            });
+        }
 
-                (result.is_success, result.port, error_message)
+        // 3. C++側で開放したポート番号を戻り値から取得し、Redirect APIをcallし、戻り値を返す
+        // REDIRECT APIを呼ぶためのパラメータ生成
+        // Dest ObjectのUDPソケット情報が必要なので、このタイミングで実施する
+        let params = {
+            let params = RedirectParams {
+                data_connection_id: redirect_params.data_connection_id,
+                feed_params: Some(DataIdWrapper {
+                    data_id: data_id.clone(),
+                }),
+                redirect_params: Some(
+                    SocketInfo::<PhantomId>::try_create(None, "127.0.0.1", port).unwrap(),
+                ),
             };
+            Request::Data(DataRequest::Redirect { params })
+        };
 
-            if !flag {
-                let delete_data_param = RequestDto::Data(DataRequestDto::Delete {
-                    params: DataIdWrapper { data_id },
-                });
-                let _ = self.factory.create_service(&delete_data_param);
-                return Err(error::Error::create_local_error(&error_message));
-            }
-
-            // 3. C++側で開放したポート番号を戻り値から取得し、Redirect APIをcallし、戻り値を返す
-            // REDIRECT APIを呼ぶためのパラメータ生成
-            // Dest ObjectのUDPソケット情報が必要なので、このタイミングで実施する
-            let params = {
-                let params = RedirectParams {
-                    data_connection_id: redirect_params.data_connection_id,
-                    feed_params: Some(DataIdWrapper { data_id }),
-                    redirect_params: Some(
-                        SocketInfo::<PhantomId>::try_create(None, "127.0.0.1", port).unwrap(),
-                    ),
+        let result = self.repository.register(params).await;
+        match result {
+            // Redirectに成功した場合
+            Ok(ResponseResult::Success(Response::Data(DataResponse::Redirect(params)))) => {
+                // Topicの情報を保管
+                let response = DataPipeInfo {
+                    data_connection_id: params.data_connection_id.clone(),
+                    data_pipe_port_num: port,
+                    data_id,
+                    plugin_type: redirect_params.plugin_info.r#type,
+                    plugins: redirect_params.plugin_info.plugins,
+                    remote_peer_id: None,
                 };
-                Request::Data(DataRequest::Redirect { params })
-            };
+                self.state
+                    .store_topic(params.data_connection_id.clone(), response);
 
-            let result = self.repository.register(params).await?;
-            match result {
-                // Redirectに成功した場合
-                ResponseResult::Success(Response::Data(DataResponse::Redirect(params))) => {
-                    // Topicの情報を保管
-                    let response = DataPipeInfo {
-                        data_connection_id: params.data_connection_id.clone(),
-                        data_pipe_port_num: port,
-                    };
-                    self.state
-                        .store_topic(params.data_connection_id.clone(), response);
-
-                    return Ok(ResponseDtoResult::Success(ResponseDto::Data(
-                        DataResponseDto::Redirect(params),
-                    )));
+                Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                    DataResponseDto::Redirect(params),
+                )))
+            }
+            // 失敗した場合は、ロードさせたPluginも破棄させる
+            result => {
+                self.callback.data_connection_deleted_callback(port);
+                match result {
+                    Ok(ResponseResult::Error(message)) => {
+                        Err(error::Error::gateway_api_error(message))
+                    }
+                    Ok(response) => Err(error::Error::internal(format!(
+                        "unexpected response {:?}",
+                        response
+                    ))),
+                    Err(e) => Err(e),
                 }
-                _ => unreachable!(),
             }
         }
-
-        return Err(error::Error::create_local_error("invalid parameters"));
     }
 }
 
 #[cfg(test)]
 mod redirect_data_test {
     use std::ffi::CString;
+    use std::sync::Mutex;
 
     use shaku::HasComponent;
 
@@ -152,6 +205,7 @@ mod redirect_data_test {
     use crate::application::usecase::MockService;
     use crate::di::*;
     use crate::domain::entity::response::{DataResponse, ResponseResult};
+    use crate::domain::entity::SerializableId;
     use crate::domain::entity::{DataConnectionId, DataConnectionIdWrapper, DataId, SocketInfo};
     use crate::domain::repository::MockRepository;
     use crate::ffi::rust_to_c_bridge::c_functions_wrapper::PluginLoadResult;
@@ -167,7 +221,7 @@ mod redirect_data_test {
             let mut mock_service = MockService::new();
             mock_service
                 .expect_execute()
-                .returning(|_| Err(error::Error::create_local_error("failed to open data port")));
+                .returning(|_| Err(error::Error::internal("failed to open data port")));
             Arc::new(mock_service)
         });
 
@@ -211,11 +265,11 @@ mod redirect_data_test {
                    }
                }"#;
 
-            RequestDto::from_str(&message).unwrap()
+            RequestDto::from_str(message).unwrap()
         };
 
         let result = service.execute(request).await;
-        if let Err(error::Error::LocalError(e)) = result {
+        if let Err(error::Error::Internal { message: e }) = result {
             assert_eq!(e, "failed to open data port");
         }
     }
@@ -300,11 +354,11 @@ mod redirect_data_test {
                    }
                }"#;
 
-            RequestDto::from_str(&message).unwrap()
+            RequestDto::from_str(message).unwrap()
         };
 
         let result = service.execute(request).await;
-        if let Err(error::Error::LocalError(e)) = result {
+        if let Err(error::Error::PluginLoadFailed { message: e, .. }) = result {
             assert_eq!(e, "plugin_router load error");
         }
     }
@@ -404,10 +458,116 @@ mod redirect_data_test {
                    }
                }"#;
 
-            RequestDto::from_str(&message).unwrap()
+            RequestDto::from_str(message).unwrap()
         };
 
         let result = service.execute(request).await;
         assert_eq!(result.unwrap(), expected);
     }
+
+    #[tokio::test]
+    // 各ステップで失敗させ、それまでに確保したリソースが解放されることを確認する
+    // 0: Dataポートの開放、1: Pluginのロード、2: REDIRECT APIが失敗する
+    async fn rollback_on_failure() {
+        for fail_at in 0..=2usize {
+            let deleted = Arc::new(Mutex::new(vec![]));
+
+            let mut factory = MockFactory::new();
+            let deleted_ref = deleted.clone();
+            factory.expect_create_service().returning(move |_| {
+                let deleted_ref = deleted_ref.clone();
+                let mut mock_service = MockService::new();
+                mock_service
+                    .expect_execute()
+                    .returning(move |request| match request {
+                        RequestDto::Data(DataRequestDto::Create) if fail_at == 0 => {
+                            Err(error::Error::gateway_unreachable("error"))
+                        }
+                        RequestDto::Data(DataRequestDto::Create) => {
+                            let socket = SocketInfo::<DataId>::try_create(
+                                Some("da-06cf1d26-0ef0-4b03-aca6-933027d434c2".to_string()),
+                                "127.0.0.1",
+                                10000,
+                            )
+                            .unwrap();
+                            Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                                DataResponseDto::Create(socket),
+                            )))
+                        }
+                        RequestDto::Data(DataRequestDto::Delete { params }) => {
+                            deleted_ref.lock().unwrap().push(params.data_id.clone());
+                            Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                                DataResponseDto::Delete(params),
+                            )))
+                        }
+                        _ => unreachable!(),
+                    });
+                Arc::new(mock_service)
+            });
+
+            let mut caller = MockCallbackFunctions::new();
+            caller
+                .expect_data_callback()
+                .times(if fail_at >= 1 { 1 } else { 0 })
+                .returning(move |_, _, _, _| PluginLoadResult {
+                    is_success: fail_at != 1,
+                    port: 10001,
+                    error_message: CString::new("plugin_router load error").unwrap().into_raw(),
+                });
+            caller.expect_release_string_callback().returning(|_| ());
+            // ロードに成功したPluginは、REDIRECT APIが失敗した場合に破棄させる
+            caller
+                .expect_data_connection_deleted_callback()
+                .withf(|port| *port == 10001)
+                .times(if fail_at == 2 { 1 } else { 0 })
+                .returning(|_| ());
+
+            let mut repository = MockRepository::new();
+            repository
+                .expect_register()
+                .times(if fail_at == 2 { 1 } else { 0 })
+                .returning(|_| Ok(ResponseResult::Error("error".to_string())));
+            // 失敗した場合は情報を保持しない
+            let mut state = MockGlobalState::new();
+            state.expect_store_topic().times(0);
+
+            let module = DataRedirectService::builder()
+                .with_component_override::<dyn Factory>(Box::new(factory))
+                .with_component_override::<dyn Repository>(Box::new(repository))
+                .with_component_override::<dyn CallbackFunctions>(Box::new(caller))
+                .with_component_override::<dyn GlobalState>(Box::new(state))
+                .build();
+            let service: &dyn Service = module.resolve_ref();
+
+            let request = RequestDto::from_str(
+                r#"{
+                   "request_type":"DATA",
+                   "command":"REDIRECT",
+                   "params":{
+                       "data_connection_id":"dc-8bdef7a1-65c8-46be-a82e-37d51c776309",
+                       "destination_topic":"destination_topic",
+                       "plugin_info": {
+                            "type": "binary",
+                            "plugins": []
+                       }
+                   }
+               }"#,
+            )
+            .unwrap();
+            let result = service.execute(request).await;
+
+            match fail_at {
+                0 => assert_eq!(result, Err(error::Error::gateway_unreachable("error"))),
+                1 => assert!(matches!(result, Err(error::Error::PluginLoadFailed { .. }))),
+                _ => assert_eq!(result, Err(error::Error::gateway_api_error("error"))),
+            }
+            // Dataポートを開放できた場合のみ閉じる
+            let expected_deleted = if fail_at == 0 {
+                vec![]
+            } else {
+                vec![DataId::try_create("da-06cf1d26-0ef0-4b03-aca6-933027d434c2").unwrap()]
+            };
+            assert_eq!(*deleted.lock().unwrap(), expected_deleted);
+        }
+    }
 }
@@ -0,0 +1,627 @@
+// リクエストと、それが原因で発生したイベントを結びつけるためのモジュール
+// call_serviceはリクエストごとにrequest_idを定め、REQUEST_IDとしてその処理の間保持する
+// Repositoryは、その処理の中でWebRTC Gatewayが生成したPeer Object, Connectionをrequest_idと紐づけ、
+// dispatch_eventsは、それらのイベントに同じrequest_idを付与して配信する
+// PEER CREATEのように、応答より先にイベントが発生する場合があるので、紐づけは応答を受け取った時点で行う
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::application::dto::response::{
+    DataConnectionEventDto, DataResponseDto, MediaConnectionEventEnumDto, MediaResponseDto,
+    PeerEventEnumDto, PeerResponseDto, ResponseDto, ResponseDtoResult,
+};
+use crate::domain::entity::request::{DataRequest, MediaRequest, Request};
+use crate::domain::entity::response::{DataResponse, MediaResponse, PeerResponse, Response};
+
+// 再起動の前後で生成したrequest_idが重複しないよう、起動時刻と連番を組み合わせる
+static STARTED_AT_MS: Lazy<u128> = Lazy::new(|| {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default()
+});
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// 保持する紐づけの上限
+// CLOSEなどのイベントを受け取れずに残った紐づけは、古いものから破棄する
+const MAX_CORRELATIONS: usize = 1024;
+
+/// request_idが指定されなかったリクエストのためにrequest_idを生成する
+pub(crate) fn generate_request_id() -> String {
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+    format!("rq-{:x}-{}", *STARTED_AT_MS, sequence)
+}
+
+/// リクエストのトップレベルに指定されたrequest_idを読み取る
+/// 形式の不正なリクエストに対してもログと応答を結びつけられるよう、DTOとしてのparseとは独立して行う
+pub(crate) fn read_request_id(request: &Value) -> Option<String> {
+    request
+        .get("request_id")
+        .and_then(Value::as_str)
+        .filter(|request_id| !request_id.is_empty())
+        .map(str::to_string)
+}
+
+// イベントの発生元となるオブジェクト
+// peer_idは任意の文字列なので、Connectionのidと衝突しないよう種類ごとに区別する
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Origin {
+    Peer(String),
+    Data(String),
+    Media(String),
+}
+
+// 紐づけたrequest_idと、古いものから破棄するための記録順
+// CONNECT, CALLで生成したConnectionは、PEER DELETEで併せて解除するため、属するPeer Objectも保持する
+struct Correlation {
+    sequence: u64,
+    request_id: String,
+    owner: Option<String>,
+}
+
+/// Peer Object, Connectionと、それを生成したリクエストのrequest_idの対応表
+#[derive(Default)]
+pub(crate) struct Correlations {
+    request_ids: std::sync::Mutex<HashMap<Origin, Correlation>>,
+    sequence: AtomicU64,
+}
+
+impl Correlations {
+    /// WebRTC Gatewayの応答が生成したオブジェクトを、request_idと紐づける
+    /// PEER DELETE, DATA DISCONNECT, MEDIA DISCONNECTの応答は、削除したオブジェクトの紐づけを解除する
+    /// PEER DELETEでは、そのPeer ObjectからCONNECT, CALLしたConnectionの紐づけも解除する
+    /// それ以外の応答は無視する
+    pub fn record(&self, request_id: &str, request: &Request, response: &Response) {
+        let origin = match (request, response) {
+            (_, Response::Peer(PeerResponse::Delete(peer_info))) => {
+                let peer_id = peer_info.peer_id().as_str().to_string();
+                let mut request_ids = self.request_ids.lock().unwrap();
+                request_ids.remove(&Origin::Peer(peer_id.clone()));
+                request_ids.retain(|_, correlation| correlation.owner.as_deref() != Some(&peer_id));
+                return;
+            }
+            (_, Response::Data(DataResponse::Disconnect(wrapper))) => {
+                self.remove(&Origin::Data(
+                    wrapper.data_connection_id.as_str().to_string(),
+                ));
+                return;
+            }
+            // MEDIA DISCONNECTの応答は削除したMediaConnectionのIDを含まないので、リクエストから得る
+            (
+                Request::Media(MediaRequest::Disconnect { params }),
+                Response::Media(MediaResponse::Disconnect(_)),
+            ) => {
+                self.remove(&Origin::Media(
+                    params.media_connection_id.as_str().to_string(),
+                ));
+                return;
+            }
+            (_, Response::Peer(PeerResponse::Create(peer_info))) => {
+                Origin::Peer(peer_info.peer_id().as_str().to_string())
+            }
+            (_, Response::Data(DataResponse::Connect(wrapper)))
+            | (_, Response::Data(DataResponse::Redirect(wrapper))) => {
+                Origin::Data(wrapper.data_connection_id.as_str().to_string())
+            }
+            (_, Response::Media(MediaResponse::Call(wrapper))) => {
+                Origin::Media(wrapper.media_connection_id.as_str().to_string())
+            }
+            (_, Response::Media(MediaResponse::Answer(answer))) => {
+                Origin::Media(answer.media_connection_id.as_str().to_string())
+            }
+            _ => return,
+        };
+        let correlation = Correlation {
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            request_id: request_id.to_string(),
+            owner: Self::owner(request),
+        };
+        let mut request_ids = self.request_ids.lock().unwrap();
+        request_ids.insert(origin, correlation);
+        if request_ids.len() > MAX_CORRELATIONS {
+            let oldest = request_ids
+                .iter()
+                .min_by_key(|(_, correlation)| correlation.sequence)
+                .map(|(origin, _)| origin.clone());
+            if let Some(oldest) = oldest {
+                request_ids.remove(&oldest);
+            }
+        }
+    }
+
+    /// イベントの原因となったリクエストのrequest_idを返す
+    /// CLOSE, ERRORイベントを受け取ったオブジェクトは以降利用されないものとみなし、紐づけを解除する
+    /// 相手側から要求されたCONNECTION, CALLイベントは、このノードのリクエストが原因ではないので対象外とする
+    pub fn correlate(&self, event: &ResponseDtoResult) -> Option<String> {
+        let (origin, is_last) = match event {
+            ResponseDtoResult::Success(event) => Self::origin(event)?,
+            ResponseDtoResult::Error(_) => return None,
+        };
+        if is_last {
+            self.remove(&origin)
+        } else {
+            self.request_ids
+                .lock()
+                .unwrap()
+                .get(&origin)
+                .map(|correlation| correlation.request_id.clone())
+        }
+    }
+
+    fn remove(&self, origin: &Origin) -> Option<String> {
+        self.request_ids
+            .lock()
+            .unwrap()
+            .remove(origin)
+            .map(|correlation| correlation.request_id)
+    }
+
+    // このノードのPeer ObjectからCONNECT, CALLしたConnectionが属するPeer Object
+    // 相手側から確立されたConnectionは、REDIRECT, ANSWERのリクエストからは分からないので対象外とする
+    fn owner(request: &Request) -> Option<String> {
+        match request {
+            Request::Data(DataRequest::Connect { params }) => {
+                Some(params.peer_id.as_str().to_string())
+            }
+            Request::Media(MediaRequest::Call { params }) => {
+                Some(params.peer_id.as_str().to_string())
+            }
+            _ => None,
+        }
+    }
+
+    // イベントの発生元と、それが紐づけを解除するイベントかどうか
+    fn origin(event: &ResponseDto) -> Option<(Origin, bool)> {
+        match event {
+            ResponseDto::Peer(PeerResponseDto::Event(event)) => match event {
+                PeerEventEnumDto::OPEN(event) => Some((Self::peer(&event.params), false)),
+                PeerEventEnumDto::ERROR(event) => Some((Self::peer(&event.params), true)),
+                PeerEventEnumDto::CLOSE(event) => Some((Self::peer(&event.params), true)),
+                _ => None,
+            },
+            ResponseDto::Data(DataResponseDto::Event(event)) => match event {
+                DataConnectionEventDto::OPEN(wrapper) => Some((
+                    Origin::Data(wrapper.data_connection_id.as_str().to_string()),
+                    false,
+                )),
+                DataConnectionEventDto::ERROR {
+                    data_connection_id, ..
+                } => Some((Origin::Data(data_connection_id.as_str().to_string()), true)),
+                DataConnectionEventDto::CLOSE(wrapper) => Some((
+                    Origin::Data(wrapper.data_connection_id.as_str().to_string()),
+                    true,
+                )),
+                DataConnectionEventDto::TIMEOUT => None,
+            },
+            ResponseDto::Media(MediaResponseDto::Event(event)) => match event {
+                MediaConnectionEventEnumDto::Ready(call)
+                | MediaConnectionEventEnumDto::Stream(call) => Some((
+                    Origin::Media(call.media_connection_id.as_str().to_string()),
+                    false,
+                )),
+                MediaConnectionEventEnumDto::Error {
+                    media_connection_id,
+                    ..
+                } => Some((
+                    Origin::Media(media_connection_id.as_str().to_string()),
+                    true,
+                )),
+                MediaConnectionEventEnumDto::Close(wrapper) => Some((
+                    Origin::Media(wrapper.media_connection_id.as_str().to_string()),
+                    true,
+                )),
+                MediaConnectionEventEnumDto::Timeout => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn peer(peer_info: &crate::domain::entity::PeerInfo) -> Origin {
+        Origin::Peer(peer_info.peer_id().as_str().to_string())
+    }
+}
+
+#[cfg(test)]
+mod correlation_test {
+    use super::*;
+    use crate::domain::entity::response::ResponseResult;
+    use crate::domain::entity::FromStr;
+
+    const TOKEN: &str = "pt-9749250e-d157-4f80-9ee2-359ce8524308";
+    const DATA_CONNECTION_ID: &str = "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c";
+    const MEDIA_CONNECTION_ID: &str = "mc-102127d9-30de-413b-93f7-41a33e39d82b";
+
+    fn request(json: &str) -> Request {
+        Request::from_str(json).unwrap()
+    }
+
+    fn create_request() -> Request {
+        request(
+            r#"{"type":"PEER","command":"CREATE","params":{"key":"API_KEY","domain":"localhost","peer_id":"robot","turn":false}}"#,
+        )
+    }
+
+    fn delete_request() -> Request {
+        request(&format!(
+            r#"{{"type":"PEER","command":"DELETE","params":{{"peer_id":"robot","token":"{}"}}}}"#,
+            TOKEN
+        ))
+    }
+
+    fn connect_request() -> Request {
+        request(&format!(
+            r#"{{"type":"DATA","command":"CONNECT","params":{{"peer_id":"robot","token":"{}","target_id":"operator","params":{{"data_id":"da-50a32bab-b3d9-4913-8e20-f79c90a6a211"}}}}}}"#,
+            TOKEN
+        ))
+    }
+
+    fn data_disconnect_request() -> Request {
+        request(&format!(
+            r#"{{"type":"DATA","command":"DISCONNECT","params":{{"data_connection_id":"{}"}}}}"#,
+            DATA_CONNECTION_ID
+        ))
+    }
+
+    fn media_disconnect_request() -> Request {
+        request(&format!(
+            r#"{{"type":"MEDIA","command":"DISCONNECT","params":{{"media_connection_id":"{}"}}}}"#,
+            MEDIA_CONNECTION_ID
+        ))
+    }
+
+    fn call_request() -> Request {
+        request(&format!(
+            r#"{{"type":"MEDIA","command":"CALL","params":{{"peer_id":"robot","token":"{}","target_id":"operator","constraints":{{"video":true,"videoReceiveEnabled":false,"audio":false,"audioReceiveEnabled":false}}}}}}"#,
+            TOKEN
+        ))
+    }
+
+    fn call_response() -> Response {
+        response(&format!(
+            r#"{{
+                "is_success":true,
+                "result":{{
+                    "request_type":"MEDIA",
+                    "command":"CALL",
+                    "media_connection_id":"{}"
+                }}
+            }}"#,
+            MEDIA_CONNECTION_ID
+        ))
+    }
+
+    fn response(json: &str) -> Response {
+        match ResponseResult::from_str(json).unwrap() {
+            ResponseResult::Success(response) => response,
+            ResponseResult::Error(e) => panic!("{}", e),
+        }
+    }
+
+    fn event(json: &str) -> ResponseDtoResult {
+        ResponseDtoResult::from_str(json).unwrap()
+    }
+
+    fn data_event(event_name: &str) -> ResponseDtoResult {
+        event(&format!(
+            r#"{{
+                "is_success":true,
+                "result":{{
+                    "request_type":"DATA",
+                    "command":"EVENT",
+                    "event":"{}",
+                    "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"
+                }}
+            }}"#,
+            event_name
+        ))
+    }
+
+    #[test]
+    fn generate_request_id_is_unique() {
+        let first = generate_request_id();
+        let second = generate_request_id();
+        assert!(first.starts_with("rq-"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    // PEER CREATEで生成したPeer ObjectのOPEN, CLOSEに、そのリクエストのrequest_idを付与する
+    fn peer() {
+        let correlations = Correlations::default();
+        correlations.record(
+            "create-robot",
+            &create_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"PEER",
+                        "command":"CREATE",
+                        "peer_id":"robot",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                    }
+                }"#,
+            ),
+        );
+
+        let peer_event = |event_name: &str| {
+            event(&format!(
+                r#"{{
+                    "is_success":true,
+                    "result":{{
+                        "request_type":"PEER",
+                        "command":"EVENT",
+                        "event":"{}",
+                        "params":{{
+                            "peer_id":"robot",
+                            "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                        }}
+                    }}
+                }}"#,
+                event_name
+            ))
+        };
+        assert_eq!(
+            correlations.correlate(&peer_event("OPEN")),
+            Some("create-robot".to_string())
+        );
+        assert_eq!(
+            correlations.correlate(&peer_event("CLOSE")),
+            Some("create-robot".to_string())
+        );
+        // CLOSE以降は紐づけが解除されている
+        assert_eq!(correlations.correlate(&peer_event("OPEN")), None);
+    }
+
+    #[test]
+    // DATA CONNECTで確立したDataConnectionのイベントに、そのリクエストのrequest_idを付与する
+    fn data_connection() {
+        let correlations = Correlations::default();
+        // 紐づけていないDataConnectionのイベント
+        assert_eq!(correlations.correlate(&data_event("OPEN")), None);
+
+        correlations.record(
+            "connect",
+            &connect_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"DATA",
+                        "command":"CONNECT",
+                        "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"
+                    }
+                }"#,
+            ),
+        );
+        assert_eq!(
+            correlations.correlate(&data_event("OPEN")),
+            Some("connect".to_string())
+        );
+        assert_eq!(
+            correlations.correlate(&data_event("CLOSE")),
+            Some("connect".to_string())
+        );
+        assert_eq!(correlations.correlate(&data_event("OPEN")), None);
+    }
+
+    fn connect_response(data_connection_id: &str) -> Response {
+        response(&format!(
+            r#"{{
+                "is_success":true,
+                "result":{{
+                    "request_type":"DATA",
+                    "command":"CONNECT",
+                    "data_connection_id":"{}"
+                }}
+            }}"#,
+            data_connection_id
+        ))
+    }
+
+    #[test]
+    // ERRORイベント以降は紐づけが解除される
+    fn data_connection_error() {
+        let correlations = Correlations::default();
+        correlations.record(
+            "connect",
+            &connect_request(),
+            &connect_response("dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"),
+        );
+
+        let error = event(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"DATA",
+                    "command":"EVENT",
+                    "event":"ERROR",
+                    "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+                    "error":"error"
+                }
+            }"#,
+        );
+        assert_eq!(correlations.correlate(&error), Some("connect".to_string()));
+        assert_eq!(correlations.correlate(&data_event("CLOSE")), None);
+    }
+
+    #[test]
+    // PEER DELETE, DATA DISCONNECTの応答で紐づけが解除される
+    fn release_on_delete() {
+        let correlations = Correlations::default();
+        correlations.record(
+            "create-robot",
+            &create_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"PEER",
+                        "command":"CREATE",
+                        "peer_id":"robot",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                    }
+                }"#,
+            ),
+        );
+        correlations.record(
+            "connect",
+            &connect_request(),
+            &connect_response("dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"),
+        );
+
+        correlations.record(
+            "delete-robot",
+            &delete_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"PEER",
+                        "command":"DELETE",
+                        "peer_id":"robot",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                    }
+                }"#,
+            ),
+        );
+        correlations.record(
+            "disconnect",
+            &data_disconnect_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"DATA",
+                        "command":"DISCONNECT",
+                        "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"
+                    }
+                }"#,
+            ),
+        );
+        assert!(correlations.request_ids.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    // MEDIA DISCONNECTの応答はIDを含まないので、リクエストのmedia_connection_idで紐づけを解除する
+    fn release_on_media_disconnect() {
+        let correlations = Correlations::default();
+        correlations.record("call", &call_request(), &call_response());
+        assert_eq!(correlations.request_ids.lock().unwrap().len(), 1);
+
+        correlations.record(
+            "disconnect",
+            &media_disconnect_request(),
+            &Response::Media(MediaResponse::Disconnect(None)),
+        );
+        assert!(correlations.request_ids.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    // PEER DELETEでは、そのPeer ObjectからCONNECT, CALLしたConnectionの紐づけも解除する
+    fn release_connections_on_peer_delete() {
+        let correlations = Correlations::default();
+        correlations.record(
+            "create-robot",
+            &create_request(),
+            &response(&format!(
+                r#"{{
+                    "is_success":true,
+                    "result":{{
+                        "request_type":"PEER",
+                        "command":"CREATE",
+                        "peer_id":"robot",
+                        "token":"{}"
+                    }}
+                }}"#,
+                TOKEN
+            )),
+        );
+        correlations.record(
+            "connect",
+            &connect_request(),
+            &connect_response(DATA_CONNECTION_ID),
+        );
+        correlations.record("call", &call_request(), &call_response());
+        // 他のPeer ObjectからCONNECTしたDataConnectionは解除しない
+        let other = request(&format!(
+            r#"{{"type":"DATA","command":"CONNECT","params":{{"peer_id":"other","token":"{}","target_id":"operator","params":{{"data_id":"da-50a32bab-b3d9-4913-8e20-f79c90a6a211"}}}}}}"#,
+            TOKEN
+        ));
+        correlations.record(
+            "connect-other",
+            &other,
+            &connect_response("dc-4995f372-fb6a-4196-b30a-000000000000"),
+        );
+
+        correlations.record(
+            "delete-robot",
+            &delete_request(),
+            &response(&format!(
+                r#"{{
+                    "is_success":true,
+                    "result":{{
+                        "request_type":"PEER",
+                        "command":"DELETE",
+                        "peer_id":"robot",
+                        "token":"{}"
+                    }}
+                }}"#,
+                TOKEN
+            )),
+        );
+        let request_ids = correlations.request_ids.lock().unwrap();
+        assert_eq!(request_ids.len(), 1);
+        assert_eq!(
+            request_ids
+                .get(&Origin::Data(
+                    "dc-4995f372-fb6a-4196-b30a-000000000000".to_string()
+                ))
+                .map(|correlation| correlation.request_id.as_str()),
+            Some("connect-other")
+        );
+    }
+
+    #[test]
+    // 上限を超えた場合は、古い紐づけから破棄する
+    fn bounded() {
+        let correlations = Correlations::default();
+        for index in 0..=MAX_CORRELATIONS {
+            correlations.record(
+                &format!("connect-{}", index),
+                &connect_request(),
+                &connect_response(&format!("dc-4995f372-fb6a-4196-b30a-{:012}", index)),
+            );
+        }
+
+        let request_ids = correlations.request_ids.lock().unwrap();
+        assert_eq!(request_ids.len(), MAX_CORRELATIONS);
+        assert!(!request_ids
+            .values()
+            .any(|correlation| correlation.request_id == "connect-0"));
+    }
+
+    #[test]
+    // オブジェクトを生成しない応答は紐づけない
+    fn ignore_other_responses() {
+        let correlations = Correlations::default();
+        correlations.record(
+            "disconnect",
+            &data_disconnect_request(),
+            &response(
+                r#"{
+                    "is_success":true,
+                    "result":{
+                        "request_type":"DATA",
+                        "command":"DISCONNECT",
+                        "data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"
+                    }
+                }"#,
+            ),
+        );
+        assert_eq!(correlations.correlate(&data_event("OPEN")), None);
+    }
+}
@@ -18,31 +18,46 @@ impl EventReceiveImpl {
                         },
                     )))
                 } else {
-                    let message = format!(
-                        "no info about DataConnectionId {:?}",
-                        open.data_connection_id.as_str()
-                    );
-                    Err(error::Error::create_local_error(&message))
+                    Err(error::Error::UnknownConnection {
+                        connection_id: open.data_connection_id.as_str().to_string(),
+                    })
                 }
             }
             DataResponse::Event(DataConnectionEventEnum::CLOSE(close)) => {
+                self.state
+                    .recovery_state()
+                    .lock()
+                    .unwrap()
+                    .mark_data_closed(&close.data_connection_id);
+                self.state
+                    .peer_registry()
+                    .lock()
+                    .unwrap()
+                    .remove_data_connection(&close.data_connection_id);
                 let data_info = self.state.remove_topic(&close.data_connection_id);
-                data_info.map(|item| {
+                if let Some(item) = data_info {
                     self.callback
                         .data_connection_deleted_callback(item.data_pipe_port_num);
-                });
+                }
 
                 Ok(DataResponseDto::Event(DataConnectionEventDto::CLOSE(close)))
             }
-            DataResponse::Event(event) => {
-                let message = format!("This event is not processed {:?}", event);
-                self.logger.error(&message);
-                todo!()
+            DataResponse::Event(DataConnectionEventEnum::ERROR((data_connection_id, error))) => {
+                Ok(DataResponseDto::Event(DataConnectionEventDto::ERROR {
+                    data_connection_id,
+                    error,
+                }))
+            }
+            DataResponse::Event(DataConnectionEventEnum::TIMEOUT) => {
+                Ok(DataResponseDto::Event(DataConnectionEventDto::TIMEOUT))
             }
-            _ => {
-                let message = format!("Non-Event object is processed in EventReceiveImpl as Data");
+            response => {
+                let message = format!(
+                    "Non-Event object is processed in EventReceiveImpl as Data: {:?}",
+                    response
+                );
                 self.logger.error(&message);
-                unreachable!()
+                Err(error::Error::internal(message))
             }
         }
     }
@@ -16,12 +16,15 @@ impl EventReceiveImpl {
                 let response = self
                     .state
                     .find_call_response(&stream.media_connection_id)
-                    .expect("call response info is not stored");
+                    .ok_or_else(|| error::Error::UnknownConnection {
+                        connection_id: stream.media_connection_id.as_str().to_string(),
+                    })?;
 
                 let call_response_dto = CallResponseDto {
                     send_params: response.send_params,
                     redirect_params: response.redirect_params,
                     media_connection_id: stream.media_connection_id,
+                    remote_peer_id: response.remote_peer_id,
                 };
                 Ok(MediaResponseDto::Event(
                     MediaConnectionEventEnumDto::Stream(call_response_dto),
@@ -31,29 +34,53 @@ impl EventReceiveImpl {
                 let response = self
                     .state
                     .find_call_response(&stream.media_connection_id)
-                    .expect("call response info is not stored");
+                    .ok_or_else(|| error::Error::UnknownConnection {
+                        connection_id: stream.media_connection_id.as_str().to_string(),
+                    })?;
 
                 let call_response_dto = CallResponseDto {
                     send_params: response.send_params,
                     redirect_params: response.redirect_params,
                     media_connection_id: stream.media_connection_id,
+                    remote_peer_id: response.remote_peer_id,
                 };
                 Ok(MediaResponseDto::Event(MediaConnectionEventEnumDto::Ready(
                     call_response_dto,
                 )))
             }
-            MediaResponse::Event(MediaConnectionEventEnum::CLOSE(id_wrapper)) => Ok(
-                MediaResponseDto::Event(MediaConnectionEventEnumDto::Close(id_wrapper)),
-            ),
-            MediaResponse::Event(event) => {
-                let message = format!("This event is not processed {:?}", event);
-                self.logger.error(&message);
-                todo!()
+            MediaResponse::Event(MediaConnectionEventEnum::CLOSE(id_wrapper)) => {
+                self.state
+                    .recovery_state()
+                    .lock()
+                    .unwrap()
+                    .mark_media_closed(&id_wrapper.media_connection_id);
+                self.state
+                    .peer_registry()
+                    .lock()
+                    .unwrap()
+                    .remove_media_connection(&id_wrapper.media_connection_id);
+                Ok(MediaResponseDto::Event(MediaConnectionEventEnumDto::Close(
+                    id_wrapper,
+                )))
+            }
+            MediaResponse::Event(MediaConnectionEventEnum::ERROR((media_connection_id, error))) => {
+                Ok(MediaResponseDto::Event(
+                    MediaConnectionEventEnumDto::Error {
+                        media_connection_id,
+                        error,
+                    },
+                ))
             }
-            _ => {
-                let message = format!("Non-Event object is processed in EventReceiveImpl as Media");
+            MediaResponse::Event(MediaConnectionEventEnum::TIMEOUT) => Ok(MediaResponseDto::Event(
+                MediaConnectionEventEnumDto::Timeout,
+            )),
+            response => {
+                let message = format!(
+                    "Non-Event object is processed in EventReceiveImpl as Media: {:?}",
+                    response
+                );
                 self.logger.error(&message);
-                unreachable!()
+                Err(error::Error::internal(message))
             }
         }
     }
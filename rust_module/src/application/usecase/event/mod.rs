@@ -1,13 +1,17 @@
+pub(crate) mod correlation;
 pub(crate) mod data;
 pub(crate) mod media;
 pub(crate) mod peer;
+pub(crate) mod subscription;
 
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use shaku::{Component, Interface};
 
-use crate::application::dto::response::{ResponseDto, ResponseDtoResult};
+use crate::application::dto::response::{ResponseDto, ResponseDtoResult, SystemResponseDto};
+use crate::application::usecase::system::resume::{ResumeSequence, Resumed};
+use crate::domain::entity::event::{Event, SystemEvent};
 use crate::domain::entity::response::{Response, ResponseResult};
 use crate::domain::repository::Repository;
 use crate::error;
@@ -16,6 +20,7 @@ use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState
 #[cfg(test)]
 use mockall::automock;
 
+#[allow(dead_code)]
 #[async_trait]
 #[cfg_attr(test, automock)]
 pub(crate) trait OnEvent: Interface {
@@ -39,17 +44,44 @@ pub(crate) struct EventReceiveImpl {
     state: Arc<dyn GlobalState>,
     #[shaku(inject)]
     callback: Arc<dyn CallbackFunctions>,
+    #[shaku(inject)]
+    resume: Arc<dyn ResumeSequence>,
 }
 
 #[async_trait]
 impl EventReceive for EventReceiveImpl {
     async fn execute(&self) -> Result<ResponseDtoResult, error::Error> {
-        let event = self.repository.receive_event().await?;
-        self.process_event(event).await
+        match self.repository.receive_event().await? {
+            Event::Gateway(event) => self.process_event(event).await,
+            // rust_module自身が生成したイベントは加工せずに返す
+            Event::System(event) => {
+                if let SystemEvent::GatewayReconnected { .. } = event {
+                    self.start_resume();
+                }
+                Ok(ResponseDtoResult::Success(ResponseDto::System(
+                    SystemResponseDto::Event(event),
+                )))
+            }
+        }
     }
 }
 
 impl EventReceiveImpl {
+    // SkyWay Crateの再起動後に、管理しているオブジェクトのイベントの監視を再開する
+    // STATUSの問い合わせを伴うので、receive_eventsの応答を待たせないようrust_mainのruntimeで実行する
+    fn start_resume(&self) {
+        let resume = self.resume.clone();
+        self.state.runtime().spawn(async move {
+            for step in resume.execute().await {
+                match step.result {
+                    Ok(Resumed::Listening) => tracing::info!("resume: listening {}", step.target),
+                    Ok(Resumed::Closed) => tracing::info!("resume: closed {}", step.target),
+                    Err(e) => tracing::warn!("resume: failed to resume {}: {}", step.target, e),
+                }
+            }
+        });
+    }
+
     async fn process_event(
         &self,
         response: ResponseResult,
@@ -64,10 +96,203 @@ impl EventReceiveImpl {
             ResponseResult::Success(Response::Media(response)) => Ok(ResponseDtoResult::Success(
                 ResponseDto::Media(self.process_media_event(response).await?),
             )),
-            ResponseResult::Error(e) => {
-                let message = format!("EventReceiveImpl receives error message {}", e);
-                Err(error::Error::create_local_error(&message))
-            }
+            ResponseResult::Error(e) => Err(error::Error::gateway_api_error(e)),
         }
     }
 }
+
+#[cfg(test)]
+mod event_receive_test {
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::usecase::system::resume::MockResumeSequence;
+    use crate::di::EventReceiveService;
+    use crate::domain::entity::response::{DataResponse, MediaResponse, PeerResponse};
+    use crate::domain::entity::{
+        DataConnectionEventEnum, DataConnectionId, MediaConnectionEventEnum, MediaConnectionId,
+        MediaConnectionIdWrapper, PeerEventEnum,
+    };
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, MockLogger, RUNTIME};
+
+    // WebRTC Gatewayのイベント1件を返すRepositoryを与えて処理させる
+    async fn process(
+        event: ResponseResult,
+        state: MockGlobalState,
+    ) -> Result<ResponseDtoResult, error::Error> {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_receive_event()
+            .times(1)
+            .return_once(move || Ok(Event::Gateway(event)));
+        // 異常系ではloggerにエラーを出力する
+        let mut logger = MockLogger::new();
+        logger.expect_error().returning(|_| ());
+
+        let module = EventReceiveService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .with_component_override::<dyn Logger>(Box::new(logger))
+            .build();
+        let service: &dyn EventReceive = module.resolve_ref();
+        service.execute().await
+    }
+
+    #[tokio::test]
+    // rust_module自身が生成したイベントはそのままSYSTEMのイベントとして返す
+    // SkyWay Crateの再起動を知らせるイベントでは、イベントの監視の再開も開始する
+    async fn system_event() {
+        let mut repository = MockRepository::new();
+        repository.expect_receive_event().times(1).returning(|| {
+            Ok(Event::System(SystemEvent::GatewayReconnected {
+                attempts: 2,
+            }))
+        });
+        let mut state = MockGlobalState::new();
+        state.expect_runtime().returning(|| RUNTIME.handle());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut resume = MockResumeSequence::new();
+        resume.expect_execute().times(1).returning(move || {
+            tx.send(()).unwrap();
+            vec![]
+        });
+
+        let module = EventReceiveService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .with_component_override::<dyn ResumeSequence>(Box::new(resume))
+            .build();
+        let service: &dyn EventReceive = module.resolve_ref();
+
+        let result = service.execute().await.unwrap();
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"SYSTEM",
+                    "command":"EVENT",
+                    "event":"GATEWAY_RECONNECTED",
+                    "attempts":2
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+        rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap();
+    }
+
+    #[tokio::test]
+    // DataConnectionのERRORはDATA EVENTとして返す
+    async fn data_error_event() {
+        let data_connection_id =
+            DataConnectionId::try_create("dc-8bdef7a1-65c8-46be-a82e-37d51c776309").unwrap();
+        let event = ResponseResult::Success(Response::Data(DataResponse::Event(
+            DataConnectionEventEnum::ERROR((data_connection_id, "error".to_string())),
+        )));
+
+        let result = process(event, MockGlobalState::new()).await.unwrap();
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"DATA",
+                    "command":"EVENT",
+                    "event":"ERROR",
+                    "data_connection_id":"dc-8bdef7a1-65c8-46be-a82e-37d51c776309",
+                    "error":"error"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+        // C++側に返す際にシリアライズできること
+        assert!(result.to_string().is_ok());
+    }
+
+    #[tokio::test]
+    // MediaConnectionのERRORはMEDIA EVENTとして返す
+    async fn media_error_event() {
+        let media_connection_id =
+            MediaConnectionId::try_create("mc-102127d9-30de-413b-93f7-41a33e39d82b").unwrap();
+        let event = ResponseResult::Success(Response::Media(MediaResponse::Event(
+            MediaConnectionEventEnum::ERROR((media_connection_id, "error".to_string())),
+        )));
+
+        let result = process(event, MockGlobalState::new()).await.unwrap();
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"MEDIA",
+                    "command":"EVENT",
+                    "event":"ERROR",
+                    "media_connection_id":"mc-102127d9-30de-413b-93f7-41a33e39d82b",
+                    "error":"error"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+        assert!(result.to_string().is_ok());
+    }
+
+    #[tokio::test]
+    // CALL, ANSWER時の情報を保持していないMediaConnectionのREADYはエラーとして返す
+    async fn media_ready_unknown_connection() {
+        let media_connection_id =
+            MediaConnectionId::try_create("mc-102127d9-30de-413b-93f7-41a33e39d82b").unwrap();
+        let event = ResponseResult::Success(Response::Media(MediaResponse::Event(
+            MediaConnectionEventEnum::READY(MediaConnectionIdWrapper {
+                media_connection_id,
+            }),
+        )));
+        let mut state = MockGlobalState::new();
+        state.expect_find_call_response().returning(|_| None);
+
+        let result = process(event, state).await;
+        assert_eq!(
+            result,
+            Err(error::Error::UnknownConnection {
+                connection_id: "mc-102127d9-30de-413b-93f7-41a33e39d82b".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    // PeerのTIMEOUTはPEER EVENTとして返す
+    async fn peer_timeout_event() {
+        let event =
+            ResponseResult::Success(Response::Peer(PeerResponse::Event(PeerEventEnum::TIMEOUT)));
+
+        let result = process(event, MockGlobalState::new()).await.unwrap();
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"EVENT",
+                    "event":"TIMEOUT"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    // イベント以外のオブジェクトはpanicせずにエラーとして返す
+    async fn non_event_object() {
+        let event = ResponseResult::Success(Response::Data(DataResponse::Disconnect(
+            crate::domain::entity::DataConnectionIdWrapper {
+                data_connection_id: DataConnectionId::try_create(
+                    "dc-8bdef7a1-65c8-46be-a82e-37d51c776309",
+                )
+                .unwrap(),
+            },
+        )));
+
+        let result = process(event, MockGlobalState::new()).await;
+        assert!(matches!(result, Err(error::Error::Internal { .. })));
+    }
+}
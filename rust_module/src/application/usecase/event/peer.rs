@@ -1,14 +1,14 @@
 use shaku::HasComponent;
 
 use super::EventReceiveImpl;
-use crate::application::dto::request::{MediaRequestDto, RequestDto};
+use crate::application::dto::request::RequestDto;
 use crate::application::dto::response::{
     DataResponseDto, MediaResponseDto, PeerCallEventDto, PeerConnectionEventDto, PeerEventEnumDto,
     PeerResponseDto, ResponseDto, ResponseDtoResult,
 };
 use crate::di::*;
 use crate::domain::entity::response::PeerResponse;
-use crate::domain::entity::PeerEventEnum;
+use crate::domain::entity::{PeerEventEnum, PeerId};
 use crate::error;
 
 impl EventReceiveImpl {
@@ -21,11 +21,15 @@ impl EventReceiveImpl {
                 Ok(PeerResponseDto::Event(PeerEventEnumDto::OPEN(event)))
             }
             PeerResponse::Event(PeerEventEnum::CLOSE(close)) => {
+                self.state
+                    .peer_registry()
+                    .lock()
+                    .unwrap()
+                    .remove_peer(&close.params.peer_id());
+                self.start_recovery(&close.params.peer_id());
                 Ok(PeerResponseDto::Event(PeerEventEnumDto::CLOSE(close)))
             }
             PeerResponse::Event(PeerEventEnum::CONNECTION(connection)) => {
-                use serde::{Deserialize, Serialize};
-
                 use crate::application::dto::request::DataRequestDto;
                 use crate::application::Factory;
 
@@ -41,7 +45,15 @@ impl EventReceiveImpl {
                     status,
                 )))) = result
                 {
-                    let message = serde_json::to_string(&status).unwrap();
+                    // 相手側から確立されたConnectionも、終了時に切断するため登録する
+                    self.state
+                        .peer_registry()
+                        .lock()
+                        .unwrap()
+                        .insert_data_connection(
+                            &connection.params.peer_id(),
+                            connection.data_params.data_connection_id.clone(),
+                        );
                     let event_dto = PeerConnectionEventDto {
                         params: connection.params,
                         data_params: connection.data_params,
@@ -52,12 +64,10 @@ impl EventReceiveImpl {
                     )))
                 } else {
                     let message = format!("connection request is received from {}. But failed to get DataConnection Status.", connection.params.peer_id().as_str());
-                    Err(error::Error::create_local_error(&message))
+                    Err(error::Error::gateway_api_error(message))
                 }
             }
             PeerResponse::Event(PeerEventEnum::CALL(event)) => {
-                use serde::{Deserialize, Serialize};
-
                 use crate::application::dto::request::MediaRequestDto;
                 use crate::application::Factory;
 
@@ -74,7 +84,14 @@ impl EventReceiveImpl {
                     MediaResponseDto::Status(status),
                 ))) = result
                 {
-                    let message = serde_json::to_string(&status).unwrap();
+                    self.state
+                        .peer_registry()
+                        .lock()
+                        .unwrap()
+                        .insert_media_connection(
+                            &event.params.peer_id(),
+                            event.call_params.media_connection_id.clone(),
+                        );
                     let event_dto = PeerCallEventDto {
                         params: event.params,
                         call_params: event.call_params,
@@ -83,15 +100,50 @@ impl EventReceiveImpl {
                     Ok(PeerResponseDto::Event(PeerEventEnumDto::CALL(event_dto)))
                 } else {
                     let message = format!("call request is received from {}. But failed to get MediaConnection Status.", event.params.peer_id().as_str());
-                    Err(error::Error::create_local_error(&message))
+                    Err(error::Error::gateway_api_error(message))
                 }
             }
-            PeerResponse::Event(PeerEventEnum::TIMEOUT) => unreachable!(),
-            _ => {
-                let message = format!("Non-Event object is processed in EventReceiveImpl as Peer");
+            PeerResponse::Event(PeerEventEnum::ERROR(event)) => {
+                Ok(PeerResponseDto::Event(PeerEventEnumDto::ERROR(event)))
+            }
+            PeerResponse::Event(PeerEventEnum::TIMEOUT) => {
+                Ok(PeerResponseDto::Event(PeerEventEnumDto::TIMEOUT))
+            }
+            response => {
+                let message = format!(
+                    "Non-Event object is processed in EventReceiveImpl as Peer: {:?}",
+                    response
+                );
                 self.logger.error(&message);
-                unreachable!()
+                Err(error::Error::internal(message))
             }
         }
     }
 }
+
+impl EventReceiveImpl {
+    // 自動復旧が有効な場合、PEER CLOSE時点で保持しているパラメータを取り出し、復旧処理を開始する
+    // 復旧処理はreceive_eventsの呼び出しをまたいで継続するため、rust_mainのruntimeで実行する
+    fn start_recovery(&self, peer_id: &PeerId) {
+        use crate::application::usecase::peer::recovery::PeerRecovery;
+
+        let config = &self.state.config().peer_recovery;
+        if !config.enabled {
+            return;
+        }
+
+        // PEER DELETEで明示的に削除した場合は、パラメータは既に破棄されている
+        let taken = self.state.recovery_state().lock().unwrap().take(
+            peer_id,
+            std::time::Duration::from_millis(config.close_grace_ms),
+        );
+
+        if let Some((params, targets)) = taken {
+            self.state.runtime().spawn(async move {
+                let module = PeerRecoveryService::builder().build();
+                let service: &dyn PeerRecovery = module.resolve_ref();
+                service.execute(params, targets).await;
+            });
+        }
+    }
+}
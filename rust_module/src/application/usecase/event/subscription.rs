@@ -0,0 +1,464 @@
+// 複数のクライアントが、それぞれ全てのイベントを受け取れるようにするためのモジュール
+// WebRTC Gatewayのイベントはdispatch_eventsが1度だけ処理し、全ての購読(subscription)に複製して配信する
+// 各購読はフィルタを持ち、条件に合致するイベントのみを受け取る
+// 購読を指定しないreceive_eventsはDEFAULT_SUBSCRIPTIONを利用する
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use shaku::Component;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::application::dto::request::{EventFilterDto, EventRequestDto, RequestDto};
+use crate::application::dto::response::{
+    EventResponseDto, ResponseDto, ResponseDtoResult, SystemResponseDto,
+};
+use crate::application::usecase::Service;
+use crate::domain::entity::event::SystemEvent;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+/// 起動時から存在し、削除できない購読
+pub(crate) const DEFAULT_SUBSCRIPTION: &str = "default";
+
+// 配信するイベント。フィルタの判定のためにパース済みのJSONも保持する
+#[derive(Debug)]
+struct PublishedEvent {
+    message: String,
+    value: Value,
+}
+
+struct Subscription {
+    filter: EventFilterDto,
+    // 同じ購読を複数のクライアントが待った場合は、先にlockできた方が受け取る
+    receiver: tokio::sync::Mutex<broadcast::Receiver<Arc<PublishedEvent>>>,
+}
+
+/// 購読の一覧
+pub(crate) struct Subscriptions {
+    sender: broadcast::Sender<Arc<PublishedEvent>>,
+    subscriptions: std::sync::Mutex<HashMap<String, Arc<Subscription>>>,
+}
+
+impl Subscriptions {
+    /// capacityは1つの購読が受け取らずに溜めておけるイベントの数
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        let subscriptions = Subscriptions {
+            sender,
+            subscriptions: std::sync::Mutex::new(HashMap::new()),
+        };
+        subscriptions
+            .subscribe(DEFAULT_SUBSCRIPTION, EventFilterDto::default())
+            .expect("default subscription must be created");
+        subscriptions
+    }
+
+    /// 購読を開始する。開始以前に発生したイベントは受け取れない
+    pub fn subscribe(
+        &self,
+        subscription_id: &str,
+        filter: EventFilterDto,
+    ) -> Result<(), error::Error> {
+        if subscription_id.is_empty() {
+            return Err(error::Error::invalid_request(
+                "subscription_id must not be empty",
+            ));
+        }
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if subscriptions.contains_key(subscription_id) {
+            return Err(error::Error::invalid_request(format!(
+                "subscription {} already exists",
+                subscription_id
+            )));
+        }
+        let subscription = Subscription {
+            filter,
+            receiver: tokio::sync::Mutex::new(self.sender.subscribe()),
+        };
+        subscriptions.insert(subscription_id.to_string(), Arc::new(subscription));
+        Ok(())
+    }
+
+    /// 購読を終了する。受け取られていないイベントは破棄される
+    pub fn unsubscribe(&self, subscription_id: &str) -> Result<(), error::Error> {
+        if subscription_id == DEFAULT_SUBSCRIPTION {
+            return Err(error::Error::invalid_request(
+                "default subscription cannot be removed",
+            ));
+        }
+
+        match self.subscriptions.lock().unwrap().remove(subscription_id) {
+            Some(_) => Ok(()),
+            None => Err(unknown_subscription(subscription_id)),
+        }
+    }
+
+    /// 全ての購読にイベントを配信する
+    pub fn publish(&self, message: String) {
+        // パースできないメッセージは生成していないが、念の為全ての購読に配信されるようにしておく
+        let value = serde_json::from_str(&message).unwrap_or(Value::Null);
+        // 購読が1つもない場合のみエラーになるが、DEFAULT_SUBSCRIPTIONは常に存在する
+        let _ = self
+            .sender
+            .send(Arc::new(PublishedEvent { message, value }));
+    }
+
+    /// 購読のフィルタに合致するイベントを1つ受け取る
+    /// timeoutごとにis_shutting_downを確認し、終了処理中であればエラーを返す
+    pub async fn receive(
+        &self,
+        subscription_id: &str,
+        timeout: Duration,
+        is_shutting_down: impl Fn() -> bool,
+    ) -> Result<String, error::Error> {
+        let subscription = self.find(subscription_id)?;
+        let mut receiver = subscription.receiver.lock().await;
+
+        while !is_shutting_down() {
+            match tokio::time::timeout(timeout, receiver.recv()).await {
+                Ok(Ok(event)) => {
+                    if subscription.filter.matches(&event.value) {
+                        return Ok(event.message.clone());
+                    }
+                }
+                // 受け取りが遅れて溢れたイベントは破棄されるので、その件数をユーザに通知する
+                Ok(Err(RecvError::Lagged(count))) => {
+                    let event = SystemEvent::EventsDropped {
+                        subscription_id: subscription_id.to_string(),
+                        count,
+                    };
+                    return ResponseDtoResult::Success(ResponseDto::System(
+                        SystemResponseDto::Event(event),
+                    ))
+                    .to_string();
+                }
+                Ok(Err(RecvError::Closed)) => {
+                    return Err(error::Error::internal("event dispatcher is stopped"));
+                }
+                Err(_) => {
+                    // 待機中に購読が終了された場合
+                    if self.find(subscription_id).is_err() {
+                        return Err(unknown_subscription(subscription_id));
+                    }
+                }
+            }
+        }
+
+        Err(error::Error::internal("ros has been shut down"))
+    }
+
+    fn find(&self, subscription_id: &str) -> Result<Arc<Subscription>, error::Error> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .get(subscription_id)
+            .cloned()
+            .ok_or_else(|| unknown_subscription(subscription_id))
+    }
+}
+
+fn unknown_subscription(subscription_id: &str) -> error::Error {
+    error::Error::invalid_request(format!("subscription {} is not found", subscription_id))
+}
+
+impl EventFilterDto {
+    // receive_eventsが返すJSONに対して判定する
+    fn matches(&self, value: &Value) -> bool {
+        // エラーは特定のイベントに紐づかないので、全ての購読に配信する
+        if value["is_success"] != Value::Bool(true) {
+            return true;
+        }
+
+        let result = &value["result"];
+        let field_matches = |key: &str, expected: &Option<String>| match expected {
+            Some(expected) => result[key].as_str() == Some(expected.as_str()),
+            None => true,
+        };
+        let id_matches = |key: &str, expected: Option<&str>| match expected {
+            Some(expected) => contains(result, key, expected),
+            None => true,
+        };
+
+        field_matches("request_type", &self.request_type)
+            && field_matches("event", &self.event)
+            && id_matches("peer_id", self.peer_id.as_ref().map(|id| id.as_str()))
+            && id_matches(
+                "data_connection_id",
+                self.data_connection_id.as_ref().map(|id| id.as_str()),
+            )
+            && id_matches(
+                "media_connection_id",
+                self.media_connection_id.as_ref().map(|id| id.as_str()),
+            )
+    }
+}
+
+// イベントごとにIDが格納される階層が異なるので、全ての階層からkeyを探す
+fn contains(value: &Value, key: &str, expected: &str) -> bool {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .any(|(k, v)| (k == key && v.as_str() == Some(expected)) || contains(v, key, expected)),
+        Value::Array(array) => array.iter().any(|v| contains(v, key, expected)),
+        _ => false,
+    }
+}
+
+/// EVENT SUBSCRIBE, UNSUBSCRIBEを処理する
+#[derive(Component)]
+#[shaku(interface = Service)]
+pub(crate) struct EventSubscription {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+}
+
+#[async_trait]
+impl Service for EventSubscription {
+    async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
+        let subscriptions = self.state.subscriptions();
+        let response = match request {
+            RequestDto::Event(EventRequestDto::Subscribe { params }) => {
+                subscriptions.subscribe(&params.subscription_id, params.filter.clone())?;
+                EventResponseDto::Subscribe(params)
+            }
+            RequestDto::Event(EventRequestDto::Unsubscribe { params }) => {
+                subscriptions.unsubscribe(&params.subscription_id)?;
+                EventResponseDto::Unsubscribe(params)
+            }
+            _ => {
+                return Err(error::Error::internal(
+                    "invalid message in event subscription service",
+                ))
+            }
+        };
+        Ok(ResponseDtoResult::Success(ResponseDto::Event(response)))
+    }
+}
+
+#[cfg(test)]
+mod subscription_test {
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::request::{SubscribeParams, SubscriptionIdWrapper};
+    use crate::di::EventSubscriptionService;
+    use crate::domain::entity::{DataConnectionId, PeerId};
+    use crate::ffi::rust_to_c_bridge::state_objects::MockGlobalState;
+
+    const TIMEOUT: Duration = Duration::from_millis(10);
+
+    fn peer_open() -> String {
+        r#"{"is_success":true,"result":{"request_type":"PEER","command":"EVENT","event":"OPEN","params":{"peer_id":"peer_id","token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}}"#.to_string()
+    }
+
+    fn data_close() -> String {
+        r#"{"is_success":true,"result":{"request_type":"DATA","command":"EVENT","event":"CLOSE","data_connection_id":"dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c"}}"#.to_string()
+    }
+
+    fn error_message() -> String {
+        r#"{"is_success":false,"result":{"request_type":null,"command":null,"code":"INTERNAL","error":"error","details":{}}}"#.to_string()
+    }
+
+    async fn receive(
+        subscriptions: &Subscriptions,
+        subscription_id: &str,
+    ) -> Result<String, error::Error> {
+        subscriptions
+            .receive(subscription_id, TIMEOUT, || false)
+            .await
+    }
+
+    #[tokio::test]
+    // 全ての購読が同じイベントの複製を受け取る
+    async fn fan_out() {
+        let subscriptions = Subscriptions::new(10);
+        subscriptions
+            .subscribe("node_a", EventFilterDto::default())
+            .unwrap();
+        subscriptions.publish(peer_open());
+
+        assert_eq!(receive(&subscriptions, "node_a").await, Ok(peer_open()));
+        assert_eq!(
+            receive(&subscriptions, DEFAULT_SUBSCRIPTION).await,
+            Ok(peer_open())
+        );
+    }
+
+    #[tokio::test]
+    // フィルタに合致しないイベントは読み飛ばす
+    async fn filter() {
+        let subscriptions = Subscriptions::new(10);
+        let filter = EventFilterDto {
+            request_type: Some("DATA".to_string()),
+            event: Some("CLOSE".to_string()),
+            data_connection_id: Some(
+                DataConnectionId::try_create("dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c").unwrap(),
+            ),
+            ..Default::default()
+        };
+        subscriptions.subscribe("data", filter).unwrap();
+        let filter = EventFilterDto {
+            peer_id: Some(PeerId::new("peer_id")),
+            ..Default::default()
+        };
+        subscriptions.subscribe("peer", filter).unwrap();
+
+        subscriptions.publish(peer_open());
+        subscriptions.publish(data_close());
+
+        assert_eq!(receive(&subscriptions, "data").await, Ok(data_close()));
+        assert_eq!(receive(&subscriptions, "peer").await, Ok(peer_open()));
+        // peer_idを含むイベントはもう無いので、待機を続ける
+        let result =
+            tokio::time::timeout(Duration::from_millis(50), receive(&subscriptions, "peer")).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    // エラーはフィルタによらず全ての購読に配信する
+    async fn error_is_delivered_to_all() {
+        let subscriptions = Subscriptions::new(10);
+        let filter = EventFilterDto {
+            request_type: Some("MEDIA".to_string()),
+            ..Default::default()
+        };
+        subscriptions.subscribe("media", filter).unwrap();
+        subscriptions.publish(error_message());
+
+        assert_eq!(receive(&subscriptions, "media").await, Ok(error_message()));
+    }
+
+    #[tokio::test]
+    // 受け取りが遅れてキューが溢れた場合は、破棄した件数を通知する
+    async fn lagged() {
+        let subscriptions = Subscriptions::new(1);
+        subscriptions.publish(peer_open());
+        subscriptions.publish(data_close());
+
+        let result = receive(&subscriptions, DEFAULT_SUBSCRIPTION).await.unwrap();
+        let expected = ResponseDtoResult::Success(ResponseDto::System(SystemResponseDto::Event(
+            SystemEvent::EventsDropped {
+                subscription_id: DEFAULT_SUBSCRIPTION.to_string(),
+                count: 1,
+            },
+        )));
+        assert_eq!(ResponseDtoResult::from_str(&result), Ok(expected));
+        // 溢れなかったイベントは引き続き受け取れる
+        assert_eq!(
+            receive(&subscriptions, DEFAULT_SUBSCRIPTION).await,
+            Ok(data_close())
+        );
+    }
+
+    #[tokio::test]
+    // 購読の生成・削除に失敗するケース
+    async fn invalid_subscription() {
+        let subscriptions = Subscriptions::new(10);
+        assert!(subscriptions
+            .subscribe(DEFAULT_SUBSCRIPTION, EventFilterDto::default())
+            .is_err());
+        assert!(subscriptions
+            .subscribe("", EventFilterDto::default())
+            .is_err());
+        assert!(subscriptions.unsubscribe(DEFAULT_SUBSCRIPTION).is_err());
+        assert_eq!(
+            subscriptions.unsubscribe("unknown"),
+            Err(error::Error::invalid_request(
+                "subscription unknown is not found"
+            ))
+        );
+        assert_eq!(
+            receive(&subscriptions, "unknown").await,
+            Err(error::Error::invalid_request(
+                "subscription unknown is not found"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    // 待機中に購読が削除された場合はエラーを返す
+    async fn unsubscribed_while_waiting() {
+        let subscriptions = Subscriptions::new(10);
+        subscriptions
+            .subscribe("node_a", EventFilterDto::default())
+            .unwrap();
+        let waiting = receive(&subscriptions, "node_a");
+        let unsubscribe = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            subscriptions.unsubscribe("node_a").unwrap();
+        };
+        let (result, _) = tokio::join!(waiting, unsubscribe);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    // 終了処理中であればエラーを返す
+    async fn shutting_down() {
+        let subscriptions = Subscriptions::new(10);
+        let result = subscriptions
+            .receive(DEFAULT_SUBSCRIPTION, TIMEOUT, || true)
+            .await;
+        assert_eq!(
+            result,
+            Err(error::Error::internal("ros has been shut down"))
+        );
+    }
+
+    #[tokio::test]
+    // EVENT SUBSCRIBE, UNSUBSCRIBEを処理する
+    async fn service() {
+        let subscriptions: &'static Subscriptions = Box::leak(Box::new(Subscriptions::new(10)));
+        let mut state = MockGlobalState::new();
+        state
+            .expect_subscriptions()
+            .returning(move || subscriptions);
+
+        let module = EventSubscriptionService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+
+        let params = SubscribeParams {
+            subscription_id: "node_a".to_string(),
+            filter: EventFilterDto {
+                request_type: Some("PEER".to_string()),
+                ..Default::default()
+            },
+        };
+        let result = service
+            .execute(RequestDto::Event(EventRequestDto::Subscribe {
+                params: params.clone(),
+            }))
+            .await;
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::Event(
+                EventResponseDto::Subscribe(params)
+            )))
+        );
+        subscriptions.publish(data_close());
+        subscriptions.publish(peer_open());
+        assert_eq!(receive(subscriptions, "node_a").await, Ok(peer_open()));
+
+        let params = SubscriptionIdWrapper {
+            subscription_id: "node_a".to_string(),
+        };
+        let result = service
+            .execute(RequestDto::Event(EventRequestDto::Unsubscribe {
+                params: params.clone(),
+            }))
+            .await;
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::Event(
+                EventResponseDto::Unsubscribe(params)
+            )))
+        );
+        assert!(receive(subscriptions, "node_a").await.is_err());
+    }
+}
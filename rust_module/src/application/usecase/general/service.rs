@@ -83,7 +83,7 @@ mod create_peer_test {
                         "token":"pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
                     }
                 }"#;
-            ResponseResult::from_str(message)
+            ResponseResult::from_str(message).map_err(crate::error::Error::from)
         });
 
         // サービスの生成
@@ -120,8 +120,8 @@ mod create_peer_test {
         // errorを返してくるケース
         let mut repository = MockRepository::new();
         repository.expect_register().times(1).returning(|_| {
-            let answer = error::Error::create_local_error("error");
-            return Err(answer);
+            let answer = error::Error::internal("error");
+            Err(answer)
         });
 
         // サービスの生成
@@ -132,7 +132,7 @@ mod create_peer_test {
 
         // 実行
         let result = service.execute(dto).await;
-        if let Err(error::Error::LocalError(message)) = result {
+        if let Err(error::Error::Internal { message }) = result {
             assert_eq!(message, "error");
         }
     }
@@ -160,7 +160,10 @@ mod create_peer_test {
         let result = service.execute(dto).await;
         // 評価
         // 間違ったパラメータである旨を返してくるはずである
-        if let Err(error::Error::LocalError(error_message)) = result {
+        if let Err(error::Error::Internal {
+            message: error_message,
+        }) = result
+        {
             assert_eq!(error_message, "invalid parameter for GeneralService Test");
         }
     }
@@ -231,8 +234,8 @@ mod create_data_test {
         // 呼び出しに成功するケース
         let mut repository = MockRepository::new();
         repository.expect_register().times(1).returning(|_| {
-            let answer = error::Error::create_local_error("error");
-            return Err(answer);
+            let answer = error::Error::internal("error");
+            Err(answer)
         });
 
         // サービスの生成
@@ -243,7 +246,7 @@ mod create_data_test {
 
         // 実行
         let result = service.execute(dto).await;
-        if let Err(error::Error::LocalError(message)) = result {
+        if let Err(error::Error::Internal { message }) = result {
             assert_eq!(message, "error");
         }
     }
@@ -257,8 +260,8 @@ mod create_data_test {
         // 呼ばれないはずである
         let mut repository = MockRepository::new();
         repository.expect_register().times(0).returning(|_| {
-            let answer = error::Error::create_local_error("error");
-            return Err(answer);
+            let answer = error::Error::internal("error");
+            Err(answer)
         });
 
         // サービスの生成
@@ -270,7 +273,10 @@ mod create_data_test {
         // 実行
         let result = service.execute(dto).await;
         // 間違ったパラメータである旨を返してくるはずである
-        if let Err(error::Error::LocalError(error_message)) = result {
+        if let Err(error::Error::Internal {
+            message: error_message,
+        }) = result
+        {
             assert_eq!(error_message, "invalid parameter for GeneralService Test");
         }
     }
@@ -1,6 +1,6 @@
 // このサービスでは、End-User-Programの指示を受けて、MediaConnectionの確立要求を行う
 // 責務は以下の通りである
-// 1. GWにMedia Portを開放させる。これはVideo, Audioともに行う
+// 1. GWにMedia Portを開放させる。これはVideo, Audioのうち送信するトラックについてのみ行う
 // 2. CALL APIをコールし、MediaConnectionの確立を開始する
 //
 // WebRTC GWの仕様により、確立は受信側でAnswerが行われたタイミングである。
@@ -12,20 +12,22 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use shaku::Component;
 
-use crate::application::dto::request::{ConstraintsDto, MediaRequestDto, RequestDto};
+use crate::application::dto::request::{CallQueryDto, MediaRequestDto, RequestDto};
 use crate::application::dto::response::{
-    CallResponseDto, MediaPair, MediaResponseDto, ResponseDto, ResponseDtoResult, SendParams,
+    CallResponseDto, MediaResponseDto, ResponseDto, ResponseDtoResult,
 };
 use crate::application::factory::Factory;
+use crate::application::usecase::media::{
+    create_constraint, create_send_params, resolve_directions,
+};
+use crate::application::usecase::rollback::Rollback;
 use crate::application::usecase::Service;
-use crate::domain::entity::request::{IsVideo, MediaRequest, Request};
+use crate::domain::entity::request::{MediaRequest, Request};
 use crate::domain::entity::response::{MediaResponse, Response, ResponseResult};
-use crate::domain::entity::{
-    CallQuery, Constraints, MediaId, MediaParams, RedirectParameters, RtcpId, SerializableSocket,
-};
+use crate::domain::entity::CallQuery;
 use crate::domain::repository::Repository;
 use crate::error;
-use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+use crate::ffi::rust_to_c_bridge::state_objects::{GlobalState, Logger};
 
 #[derive(Component)]
 #[shaku(interface = Service)]
@@ -36,236 +38,123 @@ pub(crate) struct Call {
     state: Arc<dyn GlobalState>,
     #[shaku(inject)]
     factory: Arc<dyn Factory>,
+    #[shaku(inject)]
+    logger: Arc<dyn Logger>,
 }
 
 #[async_trait]
 impl Service for Call {
     async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
         if let RequestDto::Media(MediaRequestDto::Call { params }) = request {
-            let video_socket = {
-                let param = RequestDto::Media(MediaRequestDto::ContentCreate {
-                    params: IsVideo { is_video: true },
-                });
-                let service = self.factory.create_service(&param);
-                let result = service.execute(param).await?;
-                if let ResponseDtoResult::Success(ResponseDto::Media(
-                    MediaResponseDto::ContentCreate(socket),
-                )) = result
-                {
-                    socket
-                } else {
-                    let message = format!("create data failed {:?}", result);
-                    return Err(error::Error::create_local_error(&message));
-                }
-            };
-
-            let video_rtcp_socket = {
-                let param = RequestDto::Media(MediaRequestDto::RtcpCreate { params: None });
-                let service = self.factory.create_service(&param);
-                let result = service.execute(param).await?;
-                if let ResponseDtoResult::Success(ResponseDto::Media(
-                    MediaResponseDto::RtcpCreate(socket),
-                )) = result
-                {
-                    socket
-                } else {
-                    let message = format!("create data failed {:?}", result);
-                    return Err(error::Error::create_local_error(&message));
-                }
-            };
-
-            let audio_socket = {
-                let param = RequestDto::Media(MediaRequestDto::ContentCreate {
-                    params: IsVideo { is_video: false },
-                });
-                let service = self.factory.create_service(&param);
-                let result = service.execute(param).await?;
-                if let ResponseDtoResult::Success(ResponseDto::Media(
-                    MediaResponseDto::ContentCreate(socket),
-                )) = result
-                {
-                    socket
-                } else {
-                    let message = format!("create data failed {:?}", result);
-                    return Err(error::Error::create_local_error(&message));
-                }
-            };
-
-            let audio_rtcp_socket = {
-                let param = RequestDto::Media(MediaRequestDto::RtcpCreate { params: None });
-                let service = self.factory.create_service(&param);
-                let result = service.execute(param).await?;
-                if let ResponseDtoResult::Success(ResponseDto::Media(
-                    MediaResponseDto::RtcpCreate(socket),
-                )) = result
-                {
-                    socket
-                } else {
-                    let message = format!("create data failed {:?}", result);
-                    return Err(error::Error::create_local_error(&message));
-                }
-            };
-
-            // Readyイベントでユーザに返すために保持
-            let send_params = SendParams {
-                video: MediaPair {
-                    media: video_socket.clone(),
-                    rtcp: video_rtcp_socket.clone(),
-                },
-                audio: MediaPair {
-                    media: audio_socket.clone(),
-                    rtcp: audio_rtcp_socket.clone(),
-                },
-            };
-            let redirect_params = params.redirect_params.clone();
-            let constraints = create_constraint(
-                video_socket.get_id().unwrap(),
-                video_rtcp_socket.get_id().unwrap(),
-                audio_socket.get_id().unwrap(),
-                audio_rtcp_socket.get_id().unwrap(),
-                &params.constraints,
-                &params.redirect_params,
-            );
-
-            let params = CallQuery {
-                peer_id: params.peer_id,
-                token: params.token,
-                target_id: params.target_id,
-                constraints: Some(constraints),
-                redirect_params: params.redirect_params,
-            };
-            let request = Request::Media(MediaRequest::Call { params });
-            let result = self.repository.register(request).await?;
-            match result {
-                ResponseResult::Success(Response::Media(MediaResponse::Call(call_result))) => {
-                    let call_response = CallResponseDto {
-                        send_params,
-                        redirect_params,
-                        media_connection_id: call_result.media_connection_id.clone(),
-                    };
-                    self.state.store_call_response(
-                        call_response.media_connection_id.clone(),
-                        call_response,
-                    );
-
-                    return Ok(ResponseDtoResult::Success(ResponseDto::Media(
-                        MediaResponseDto::Call(call_result),
-                    )));
-                }
-                ResponseResult::Error(message) => return Ok(ResponseDtoResult::Error(message)),
-                _ => {
-                    unreachable!()
+            // 途中で失敗した場合は、それまでに開放させたポートを閉じる
+            let mut rollback = Rollback::new(self.factory.clone());
+            let result = self.call(params, &mut rollback).await;
+            if result.is_err() {
+                for (resource, e) in rollback.release().await {
+                    let message = format!("failed to release {:?}: {}", resource, e);
+                    self.logger.warn(&message);
                 }
             }
+            return result;
         }
 
-        return Err(error::Error::create_local_error(
-            "invalid message in call service",
-        ));
+        return Err(error::Error::internal("invalid message in call service"));
     }
 }
 
-pub(crate) fn create_constraint(
-    video_id: MediaId,
-    video_rtcp_id: RtcpId,
-    audio_id: MediaId,
-    audio_rtcp_id: RtcpId,
-    constraint_dto: &Option<ConstraintsDto>,
-    redirect_params: &Option<RedirectParameters>,
-) -> Constraints {
-    let video_receive_enabled = if let Some(RedirectParameters {
-        video: Some(ref _video),
-        ..
-    }) = redirect_params
-    {
-        Some(true)
-    } else {
-        None
-    };
-
-    let audio_receive_enabled = if let Some(RedirectParameters {
-        audio: Some(ref _audio),
-        ..
-    }) = redirect_params
-    {
-        Some(true)
-    } else {
-        None
-    };
-
-    let video_params = if let Some(ConstraintsDto {
-        video_params: Some(ref params),
-        ..
-    }) = constraint_dto
-    {
-        Some(MediaParams {
-            band_width: params.band_width,
-            codec: params.codec.clone(),
-            media_id: video_id,
-            rtcp_id: Some(video_rtcp_id),
-            payload_type: params.payload_type,
-            sampling_rate: params.sampling_rate,
-        })
-    } else {
-        None
-    };
-
-    let audio_params = if let Some(ConstraintsDto {
-        audio_params: Some(ref params),
-        ..
-    }) = constraint_dto
-    {
-        Some(MediaParams {
-            band_width: params.band_width,
-            codec: params.codec.clone(),
-            media_id: audio_id,
-            rtcp_id: Some(audio_rtcp_id),
-            payload_type: params.payload_type,
-            sampling_rate: params.sampling_rate,
-        })
-    } else {
-        None
-    };
-
-    let metadata = if let Some(ConstraintsDto {
-        metadata: Some(ref metadata),
-        ..
-    }) = constraint_dto
-    {
-        Some(metadata.clone())
-    } else {
-        None
-    };
+impl Call {
+    async fn call(
+        &self,
+        params: CallQueryDto,
+        rollback: &mut Rollback,
+    ) -> Result<ResponseDtoResult, error::Error> {
+        let (video, audio) = resolve_directions(
+            params.constraints.as_ref(),
+            params.redirect_params.as_ref(),
+            params.video_direction,
+            params.audio_direction,
+        )?;
+        // Readyイベントでユーザに返すために保持
+        let send_params = create_send_params(self.factory.as_ref(), video, audio, rollback).await?;
+        let redirect_params = params.redirect_params.clone();
+        let constraints =
+            create_constraint(&send_params, video, audio, params.constraints.as_ref());
+
+        let remote_peer_id = params.target_id.clone();
+        let params = CallQuery {
+            peer_id: params.peer_id,
+            token: params.token,
+            target_id: params.target_id,
+            constraints: Some(constraints),
+            redirect_params: params.redirect_params,
+        };
+        let request = Request::Media(MediaRequest::Call { params });
+        let result = self.repository.register(request).await?;
+        match result {
+            ResponseResult::Success(Response::Media(MediaResponse::Call(call_result))) => {
+                let call_response = CallResponseDto {
+                    send_params,
+                    redirect_params,
+                    media_connection_id: call_result.media_connection_id.clone(),
+                    remote_peer_id: Some(remote_peer_id),
+                };
+                self.state
+                    .store_call_response(call_response.media_connection_id.clone(), call_response);
 
-    Constraints {
-        video: true,
-        videoReceiveEnabled: video_receive_enabled,
-        audio: true,
-        audioReceiveEnabled: audio_receive_enabled,
-        video_params,
-        audio_params,
-        metadata: metadata,
+                Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                    MediaResponseDto::Call(call_result),
+                )))
+            }
+            ResponseResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+            response => Err(error::Error::internal(format!(
+                "unexpected response {:?}",
+                response
+            ))),
+        }
     }
 }
 
 #[cfg(test)]
 mod call_media_test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
     use shaku::HasComponent;
 
     use super::*;
-    use crate::application::dto::request::{CallQueryDto, MediaRequestDto};
-    use crate::application::dto::response::CallResponseDto;
+    use crate::application::dto::request::{
+        CallQueryDto, ConstraintsDto, MediaDirection, MediaParamsDto, MediaRequestDto,
+    };
+    use crate::application::dto::response::{CallResponseDto, MediaPair, SendParams};
     use crate::application::factory::MockFactory;
+    use crate::application::usecase::rollback::Resource;
     use crate::application::usecase::MockService;
     use crate::di::MediaCallService;
     use crate::domain::entity::request::{MediaRequest, Request};
     use crate::domain::entity::response::ResponseResult;
+    use crate::domain::entity::SerializableId;
     use crate::domain::entity::{
-        MediaConnectionId, MediaConnectionIdWrapper, PeerId, SocketInfo, Token,
+        MediaConnectionId, MediaConnectionIdWrapper, MediaId, PeerId, RtcpId, SerializableSocket,
+        SocketInfo, Token,
     };
     use crate::domain::repository::MockRepository;
     use crate::ffi::rust_to_c_bridge::state_objects::MockGlobalState;
 
+    // video, audioともに送信するパラメータ
+    fn constraints() -> ConstraintsDto {
+        let params = |codec: &str| MediaParamsDto {
+            band_width: 1500,
+            codec: codec.to_string(),
+            payload_type: None,
+            sampling_rate: None,
+        };
+        ConstraintsDto {
+            video_params: Some(params("H264")),
+            audio_params: Some(params("OPUS")),
+            metadata: None,
+        }
+    }
+
     #[tokio::test]
     async fn success() {
         // 正解データの生成
@@ -304,7 +193,10 @@ mod call_media_test {
                     MediaPair { media, rtcp }
                 };
 
-                SendParams { video, audio }
+                SendParams {
+                    video: Some(video),
+                    audio: Some(audio),
+                }
             };
 
             let media_connection_id =
@@ -314,6 +206,7 @@ mod call_media_test {
                 send_params: send,
                 redirect_params: None,
                 media_connection_id,
+                remote_peer_id: Some(PeerId::new("target_id")),
             }
         };
 
@@ -342,13 +235,17 @@ mod call_media_test {
             peer_id: PeerId::new("peer_id"),
             token: Token::try_create("pt-06cf1d26-0ef0-4b03-aca6-933027d434c2").unwrap(),
             target_id: PeerId::new("target_id"),
-            constraints: None,
+            constraints: Some(constraints()),
             redirect_params: None,
+            video_direction: None,
+            audio_direction: None,
         };
 
+        // 接続先とともに記録する
         let mut state = MockGlobalState::new();
         state
             .expect_store_call_response()
+            .withf(|_, call| call.remote_peer_id == Some(PeerId::new("target_id")))
             .times(1)
             .returning(|_, _| ());
 
@@ -417,4 +314,259 @@ mod call_media_test {
 
         assert_eq!(result.unwrap(), expected);
     }
+
+    // ソケット生成の順序(video, video rtcp, audio, audio rtcp)に対応するリソース
+    fn created_resource(index: usize) -> Resource {
+        let suffix = format!("4d053831-5dc2-461b-a358-d062d611521{}", index);
+        match index {
+            0 => Resource::Media(MediaId::try_create(format!("vi-{}", suffix)).unwrap()),
+            2 => Resource::Media(MediaId::try_create(format!("au-{}", suffix)).unwrap()),
+            _ => Resource::Rtcp(RtcpId::try_create(format!("rc-{}", suffix)).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    // 各ステップで失敗させ、それまでに開放させたポートが逆順に閉じられることを確認する
+    // fail_atが0-3の場合はfail_at番目のソケット生成が、4の場合はCALL APIが失敗する
+    async fn rollback_on_failure() {
+        for fail_at in 0..=4usize {
+            let created = Arc::new(AtomicUsize::new(0));
+            let deleted = Arc::new(Mutex::new(vec![]));
+
+            let mut factory = MockFactory::new();
+            let created_ref = created.clone();
+            let deleted_ref = deleted.clone();
+            factory.expect_create_service().returning(move |_| {
+                let created_ref = created_ref.clone();
+                let deleted_ref = deleted_ref.clone();
+                let mut mock_service = MockService::new();
+                mock_service
+                    .expect_execute()
+                    .returning(move |request| match request {
+                        RequestDto::Media(MediaRequestDto::ContentCreate { .. })
+                        | RequestDto::Media(MediaRequestDto::RtcpCreate { .. }) => {
+                            let index = created_ref.fetch_add(1, Ordering::SeqCst);
+                            if index == fail_at {
+                                return Err(error::Error::gateway_unreachable("error"));
+                            }
+                            let port = 10000 + index as u16;
+                            let response = match created_resource(index) {
+                                Resource::Media(media_id) => MediaResponseDto::ContentCreate(
+                                    SocketInfo::<MediaId>::try_create(
+                                        Some(media_id.as_str().to_string()),
+                                        "127.0.0.1",
+                                        port,
+                                    )
+                                    .unwrap(),
+                                ),
+                                Resource::Rtcp(rtcp_id) => MediaResponseDto::RtcpCreate(
+                                    SocketInfo::<RtcpId>::try_create(
+                                        Some(rtcp_id.as_str().to_string()),
+                                        "127.0.0.1",
+                                        port,
+                                    )
+                                    .unwrap(),
+                                ),
+                                _ => unreachable!(),
+                            };
+                            Ok(ResponseDtoResult::Success(ResponseDto::Media(response)))
+                        }
+                        RequestDto::Media(MediaRequestDto::ContentDelete { params }) => {
+                            deleted_ref
+                                .lock()
+                                .unwrap()
+                                .push(Resource::Media(params.media_id.clone()));
+                            Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                                MediaResponseDto::ContentDelete(params),
+                            )))
+                        }
+                        RequestDto::Media(MediaRequestDto::RtcpDelete { params }) => {
+                            deleted_ref
+                                .lock()
+                                .unwrap()
+                                .push(Resource::Rtcp(params.rtcp_id.clone()));
+                            Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                                MediaResponseDto::RtcpDelete(params),
+                            )))
+                        }
+                        _ => unreachable!(),
+                    });
+                Arc::new(mock_service)
+            });
+
+            // CALL APIはソケットを全て開放できた場合のみ呼ばれ、エラーを返す
+            let mut repository = MockRepository::new();
+            repository
+                .expect_register()
+                .times(if fail_at == 4 { 1 } else { 0 })
+                .returning(|_| Ok(ResponseResult::Error("error".to_string())));
+            // 失敗した場合は情報を保持しない
+            let mut state = MockGlobalState::new();
+            state.expect_store_call_response().times(0);
+
+            let module = MediaCallService::builder()
+                .with_component_override::<dyn Factory>(Box::new(factory))
+                .with_component_override::<dyn Repository>(Box::new(repository))
+                .with_component_override::<dyn GlobalState>(Box::new(state))
+                .build();
+            let service: &dyn Service = module.resolve_ref();
+            let result = service
+                .execute(RequestDto::Media(MediaRequestDto::Call {
+                    params: CallQueryDto {
+                        peer_id: PeerId::new("peer_id"),
+                        token: Token::try_create("pt-06cf1d26-0ef0-4b03-aca6-933027d434c2")
+                            .unwrap(),
+                        target_id: PeerId::new("target_id"),
+                        constraints: Some(constraints()),
+                        redirect_params: None,
+                        video_direction: None,
+                        audio_direction: None,
+                    },
+                }))
+                .await;
+
+            let expected_error = if fail_at == 4 {
+                error::Error::gateway_api_error("error")
+            } else {
+                error::Error::gateway_unreachable("error")
+            };
+            assert_eq!(result, Err(expected_error));
+            let expected_deleted: Vec<Resource> =
+                (0..fail_at.min(4)).rev().map(created_resource).collect();
+            assert_eq!(*deleted.lock().unwrap(), expected_deleted);
+        }
+    }
+
+    #[tokio::test]
+    // videoのみ送信する場合は、audioのポートを開放させない
+    async fn video_only() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().times(2).returning(|_| {
+            let mut mock_service = MockService::new();
+            mock_service
+                .expect_execute()
+                .returning(|request| match request {
+                    RequestDto::Media(MediaRequestDto::ContentCreate { params }) => {
+                        assert!(params.is_video);
+                        let socket = SocketInfo::<MediaId>::try_create(
+                            Some("vi-06cf1d26-0ef0-4b03-aca6-933027d434c2".to_string()),
+                            "127.0.0.1",
+                            10000,
+                        )
+                        .unwrap();
+                        Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                            MediaResponseDto::ContentCreate(socket),
+                        )))
+                    }
+                    RequestDto::Media(MediaRequestDto::RtcpCreate { params: _ }) => {
+                        let socket = SocketInfo::<RtcpId>::try_create(
+                            Some("rc-06cf1d26-0ef0-4b03-aca6-933027d434c2".to_string()),
+                            "127.0.0.1",
+                            10001,
+                        )
+                        .unwrap();
+                        Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                            MediaResponseDto::RtcpCreate(socket),
+                        )))
+                    }
+                    _ => unreachable!(),
+                });
+            Arc::new(mock_service)
+        });
+
+        // CALL APIにはvideoのみ送信する設定が渡される
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(1)
+            .returning(|request| match request {
+                Request::Media(MediaRequest::Call { params }) => {
+                    let constraints = params.constraints.unwrap();
+                    assert!(constraints.video);
+                    assert!(!constraints.audio);
+                    assert!(constraints.video_params.is_some());
+                    assert!(constraints.audio_params.is_none());
+                    Ok(ResponseResult::Success(Response::Media(
+                        MediaResponse::Call(MediaConnectionIdWrapper {
+                            media_connection_id: MediaConnectionId::try_create(
+                                "mc-102127d9-30de-413b-93f7-41a33e39d82b",
+                            )
+                            .unwrap(),
+                        }),
+                    )))
+                }
+                _ => unreachable!(),
+            });
+
+        let mut state = MockGlobalState::new();
+        state
+            .expect_store_call_response()
+            .times(1)
+            .returning(|_, response| {
+                assert!(response.send_params.video.is_some());
+                assert!(response.send_params.audio.is_none());
+            });
+
+        let mut constraints = constraints();
+        constraints.audio_params = None;
+        let params = CallQueryDto {
+            peer_id: PeerId::new("peer_id"),
+            token: Token::try_create("pt-06cf1d26-0ef0-4b03-aca6-933027d434c2").unwrap(),
+            target_id: PeerId::new("target_id"),
+            constraints: Some(constraints),
+            redirect_params: None,
+            video_direction: None,
+            audio_direction: None,
+        };
+
+        let module = MediaCallService::builder()
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let result = service
+            .execute(RequestDto::Media(MediaRequestDto::Call { params }))
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    // 送信を指定したトラックのパラメータが無い場合は、ポートを開放させずにエラーを返す
+    async fn direction_without_params() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().times(0);
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(0);
+        let mut state = MockGlobalState::new();
+        state.expect_store_call_response().times(0);
+
+        let mut constraints = constraints();
+        constraints.audio_params = None;
+        let params = CallQueryDto {
+            peer_id: PeerId::new("peer_id"),
+            token: Token::try_create("pt-06cf1d26-0ef0-4b03-aca6-933027d434c2").unwrap(),
+            target_id: PeerId::new("target_id"),
+            constraints: Some(constraints),
+            redirect_params: None,
+            video_direction: None,
+            audio_direction: Some(MediaDirection::SendOnly),
+        };
+
+        let module = MediaCallService::builder()
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let result = service
+            .execute(RequestDto::Media(MediaRequestDto::Call { params }))
+            .await;
+        assert_eq!(
+            result,
+            Err(error::Error::invalid_request(
+                "audio_params is required to send audio"
+            ))
+        );
+    }
 }
@@ -0,0 +1,228 @@
+/// MEDIA LISTは、このノードがCALL, ANSWERしたMediaConnectionと、その転送設定の一覧を返す
+/// with_statusが指定された場合は、各MediaConnectionのSTATUSをWebRTC Gatewayに問い合わせて付加する
+/// 接続先はCALL時のパラメータから、ANSWERしたMediaConnectionについてはSTATUSから取得する
+/// 問い合わせに失敗したMediaConnectionはstatus_errorに理由を格納し、一覧からは除外しない
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shaku::Component;
+
+use crate::application::dto::request::{MediaRequestDto, RequestDto};
+use crate::application::dto::response::{
+    MediaConnectionEntryDto, MediaResponseDto, ResponseDto, ResponseDtoResult,
+};
+use crate::application::usecase::Service;
+use crate::domain::entity::request::{MediaRequest, Request};
+use crate::domain::entity::response::{MediaResponse, Response, ResponseResult};
+use crate::domain::entity::{MediaConnectionId, MediaConnectionIdWrapper, MediaConnectionStatus};
+use crate::domain::repository::Repository;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+#[derive(Component)]
+#[shaku(interface = Service)]
+pub(crate) struct MediaList {
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+}
+
+#[async_trait]
+impl Service for MediaList {
+    async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
+        if let RequestDto::Media(MediaRequestDto::List { params }) = request {
+            let mut calls = self.state.list_call_responses();
+            calls.sort_by(|a, b| a.media_connection_id.cmp(&b.media_connection_id));
+
+            let mut media_connections = vec![];
+            for call in calls {
+                let peer_id = self
+                    .state
+                    .peer_registry()
+                    .lock()
+                    .unwrap()
+                    .find_media_connection_owner(&call.media_connection_id);
+                let mut entry = MediaConnectionEntryDto {
+                    media_connection_id: call.media_connection_id,
+                    peer_id,
+                    remote_peer_id: call.remote_peer_id,
+                    send_params: call.send_params,
+                    redirect_params: call.redirect_params,
+                    status: None,
+                    status_error: None,
+                };
+
+                if params.with_status {
+                    match self.status(&entry.media_connection_id).await {
+                        Ok(status) => {
+                            if entry.remote_peer_id.is_none() {
+                                entry.remote_peer_id = Some(status.remote_id.clone());
+                            }
+                            entry.status = Some(status);
+                        }
+                        Err(e) => entry.status_error = Some(e),
+                    }
+                }
+                media_connections.push(entry);
+            }
+
+            return Ok(ResponseDtoResult::Success(ResponseDto::Media(
+                MediaResponseDto::List { media_connections },
+            )));
+        }
+
+        return Err(error::Error::internal(
+            "invalid message in media list service",
+        ));
+    }
+}
+
+impl MediaList {
+    async fn status(
+        &self,
+        media_connection_id: &MediaConnectionId,
+    ) -> Result<MediaConnectionStatus, error::Error> {
+        let request = Request::Media(MediaRequest::Status {
+            params: MediaConnectionIdWrapper {
+                media_connection_id: media_connection_id.clone(),
+            },
+        });
+        match self.repository.register(request).await? {
+            ResponseResult::Success(Response::Media(MediaResponse::Status(status))) => Ok(status),
+            ResponseResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+            response => Err(error::Error::internal(format!(
+                "unexpected response {:?}",
+                response
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod list_media_test {
+    use std::sync::Mutex;
+
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::request::ListParamsDto;
+    use crate::application::dto::response::{CallResponseDto, SendParams};
+    use crate::di::MediaListService;
+    use crate::domain::entity::{PeerId, PeerInfo};
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    const MEDIA_CONNECTION_ID: &str = "mc-102127d9-30de-413b-93f7-41a33e39d82b";
+
+    // MediaConnectionを1つ保持している状態
+    // CALLした場合はremote_peer_idを、ANSWERした場合はNoneを与える
+    fn state(remote_peer_id: Option<PeerId>) -> MockGlobalState {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info);
+        registry.insert_media_connection(
+            &peer_info.peer_id(),
+            MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap(),
+        );
+        let registry: &'static Mutex<PeerRegistry> = Box::leak(Box::new(Mutex::new(registry)));
+
+        let call = CallResponseDto {
+            send_params: SendParams {
+                video: None,
+                audio: None,
+            },
+            redirect_params: None,
+            media_connection_id: MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap(),
+            remote_peer_id,
+        };
+
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        state
+            .expect_list_call_responses()
+            .times(1)
+            .returning(move || vec![call.clone()]);
+        state
+    }
+
+    #[tokio::test]
+    // with_statusを指定しない場合は、WebRTC Gatewayに問い合わせない
+    // CALLしたMediaConnectionのremote_peer_idは、CALL時のパラメータから取得する
+    async fn without_status() {
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(0);
+
+        let module = MediaListService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state(Some(PeerId::new(
+                "target_id",
+            )))))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::from_str(r#"{"request_type":"MEDIA","command":"LIST"}"#);
+        let result = service.execute(request.unwrap()).await.unwrap();
+
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"MEDIA",
+                    "command":"LIST",
+                    "media_connections":[{
+                        "media_connection_id":"mc-102127d9-30de-413b-93f7-41a33e39d82b",
+                        "peer_id":"peer_id",
+                        "remote_peer_id":"target_id",
+                        "send_params":{}
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    // ANSWERしたMediaConnectionのremote_peer_idはSTATUSから取得する
+    async fn with_status() {
+        let status = MediaConnectionStatus {
+            metadata: "".to_string(),
+            open: true,
+            remote_id: PeerId::new("remote_id"),
+            ssrc: None,
+        };
+        let status_ref = status.clone();
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(1).returning(move |_| {
+            Ok(ResponseResult::Success(Response::Media(
+                MediaResponse::Status(status_ref.clone()),
+            )))
+        });
+
+        let module = MediaListService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state(None)))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::Media(MediaRequestDto::List {
+            params: ListParamsDto { with_status: true },
+        });
+        let result = service.execute(request).await.unwrap();
+
+        if let ResponseDtoResult::Success(ResponseDto::Media(MediaResponseDto::List {
+            media_connections,
+        })) = result
+        {
+            assert_eq!(media_connections.len(), 1);
+            assert_eq!(
+                media_connections[0].remote_peer_id,
+                Some(PeerId::new("remote_id"))
+            );
+            assert_eq!(media_connections[0].status, Some(status));
+            assert_eq!(media_connections[0].status_error, None);
+        } else {
+            unreachable!();
+        }
+    }
+}
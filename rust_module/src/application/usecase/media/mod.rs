@@ -1,3 +1,348 @@
 /// /media系のAPIのうち、特別な内部処理を必要とするものはここで実装する
 pub(crate) mod answer;
 pub(crate) mod call;
+pub(crate) mod list;
+
+use crate::application::dto::request::{
+    ConstraintsDto, MediaDirection, MediaParamsDto, MediaRequestDto, RequestDto,
+};
+use crate::application::dto::response::{
+    MediaPair, MediaResponseDto, ResponseDto, ResponseDtoResult, SendParams,
+};
+use crate::application::factory::Factory;
+use crate::application::usecase::rollback::{Resource, Rollback};
+use crate::domain::entity::request::IsVideo;
+use crate::domain::entity::{
+    Constraints, MediaId, MediaParams, RedirectParameters, RtcpId, SerializableSocket, SocketInfo,
+};
+use crate::error;
+
+/// 1トラック分の送受信の有無
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TrackDirection {
+    pub send: bool,
+    pub receive: bool,
+    // ユーザが明示的に指定したかどうか
+    explicit: bool,
+}
+
+impl TrackDirection {
+    // directionが指定されていない場合は、送信用のパラメータとリダイレクト先の有無から判断する
+    // 指定されている場合は、送受信に必要なパラメータが揃っているか確認する
+    pub fn resolve(
+        track: &str,
+        direction: Option<MediaDirection>,
+        has_params: bool,
+        has_redirect: bool,
+    ) -> Result<Self, error::Error> {
+        match direction {
+            None => Ok(TrackDirection {
+                send: has_params,
+                receive: has_redirect,
+                explicit: false,
+            }),
+            Some(direction) if direction.sends() && !has_params => {
+                Err(error::Error::invalid_request(format!(
+                    "{}_params is required to send {}",
+                    track, track
+                )))
+            }
+            Some(direction) if direction.receives() && !has_redirect => {
+                Err(error::Error::invalid_request(format!(
+                    "redirect_params.{} is required to receive {}",
+                    track, track
+                )))
+            }
+            Some(direction) => Ok(TrackDirection {
+                send: direction.sends(),
+                receive: direction.receives(),
+                explicit: true,
+            }),
+        }
+    }
+
+    // 明示的に指定されていない場合は、従来通りWebRTC Gatewayの既定値に任せる
+    fn receive_enabled(&self) -> Option<bool> {
+        match (self.receive, self.explicit) {
+            (true, _) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        }
+    }
+}
+
+/// CALL, ANSWERのパラメータから、video, audioそれぞれの送受信の有無を決定する
+pub(crate) fn resolve_directions(
+    constraints: Option<&ConstraintsDto>,
+    redirect_params: Option<&RedirectParameters>,
+    video_direction: Option<MediaDirection>,
+    audio_direction: Option<MediaDirection>,
+) -> Result<(TrackDirection, TrackDirection), error::Error> {
+    let video = TrackDirection::resolve(
+        "video",
+        video_direction,
+        constraints.and_then(|c| c.video_params.as_ref()).is_some(),
+        redirect_params.and_then(|r| r.video.as_ref()).is_some(),
+    )?;
+    let audio = TrackDirection::resolve(
+        "audio",
+        audio_direction,
+        constraints.and_then(|c| c.audio_params.as_ref()).is_some(),
+        redirect_params.and_then(|r| r.audio.as_ref()).is_some(),
+    )?;
+    Ok((video, audio))
+}
+
+/// 送信するトラックについてのみ、Media PortとRTCP Portを開放させる
+pub(crate) async fn create_send_params(
+    factory: &dyn Factory,
+    video: TrackDirection,
+    audio: TrackDirection,
+    rollback: &mut Rollback,
+) -> Result<SendParams, error::Error> {
+    let video = if video.send {
+        let media = create_media_socket(factory, true, rollback).await?;
+        let rtcp = create_rtcp_socket(factory, rollback).await?;
+        Some(MediaPair { media, rtcp })
+    } else {
+        None
+    };
+    let audio = if audio.send {
+        let media = create_media_socket(factory, false, rollback).await?;
+        let rtcp = create_rtcp_socket(factory, rollback).await?;
+        Some(MediaPair { media, rtcp })
+    } else {
+        None
+    };
+    Ok(SendParams { video, audio })
+}
+
+// 開放させたソケットとユーザが指定したパラメータから、送信用のパラメータを生成する
+fn create_media_params(
+    pair: &Option<MediaPair<MediaId, RtcpId>>,
+    params: &Option<MediaParamsDto>,
+) -> Option<MediaParams> {
+    match (pair, params) {
+        (Some(pair), Some(params)) => Some(MediaParams {
+            band_width: params.band_width,
+            codec: params.codec.clone(),
+            // create_send_paramsで開放に成功したソケットはidを持つ
+            media_id: pair.media.get_id().unwrap(),
+            rtcp_id: pair.rtcp.get_id(),
+            payload_type: params.payload_type,
+            sampling_rate: params.sampling_rate,
+        }),
+        _ => None,
+    }
+}
+
+/// CALL, ANSWER APIに与えるConstraintsを生成する
+pub(crate) fn create_constraint(
+    send_params: &SendParams,
+    video: TrackDirection,
+    audio: TrackDirection,
+    constraint_dto: Option<&ConstraintsDto>,
+) -> Constraints {
+    let video_params = create_media_params(
+        &send_params.video,
+        &constraint_dto.and_then(|c| c.video_params.clone()),
+    );
+    let audio_params = create_media_params(
+        &send_params.audio,
+        &constraint_dto.and_then(|c| c.audio_params.clone()),
+    );
+
+    Constraints {
+        video: video.send,
+        videoReceiveEnabled: video.receive_enabled(),
+        audio: audio.send,
+        audioReceiveEnabled: audio.receive_enabled(),
+        video_params,
+        audio_params,
+        metadata: constraint_dto.and_then(|c| c.metadata.clone()),
+    }
+}
+
+// CALL, ANSWERで利用するMedia Portを開放させ、rollbackに記録する
+#[tracing::instrument(
+    name = "create_socket",
+    skip_all,
+    fields(kind = if is_video { "video" } else { "audio" })
+)]
+pub(crate) async fn create_media_socket(
+    factory: &dyn Factory,
+    is_video: bool,
+    rollback: &mut Rollback,
+) -> Result<SocketInfo<MediaId>, error::Error> {
+    let param = RequestDto::Media(MediaRequestDto::ContentCreate {
+        params: IsVideo { is_video },
+    });
+    let service = factory.create_service(&param);
+    let result = service.execute(param).await?;
+    match result {
+        ResponseDtoResult::Success(ResponseDto::Media(MediaResponseDto::ContentCreate(socket))) => {
+            let media_id = socket
+                .get_id()
+                .ok_or_else(|| error::Error::gateway_api_error("media_id is not returned"))?;
+            rollback.push(Resource::Media(media_id));
+            Ok(socket)
+        }
+        _ => {
+            let message = format!("create media failed {:?}", result);
+            Err(error::Error::gateway_api_error(message))
+        }
+    }
+}
+
+// CALL, ANSWERで利用するRTCP Portを開放させ、rollbackに記録する
+#[tracing::instrument(name = "create_socket", skip_all, fields(kind = "rtcp"))]
+pub(crate) async fn create_rtcp_socket(
+    factory: &dyn Factory,
+    rollback: &mut Rollback,
+) -> Result<SocketInfo<RtcpId>, error::Error> {
+    let param = RequestDto::Media(MediaRequestDto::RtcpCreate { params: None });
+    let service = factory.create_service(&param);
+    let result = service.execute(param).await?;
+    match result {
+        ResponseDtoResult::Success(ResponseDto::Media(MediaResponseDto::RtcpCreate(socket))) => {
+            let rtcp_id = socket
+                .get_id()
+                .ok_or_else(|| error::Error::gateway_api_error("rtcp_id is not returned"))?;
+            rollback.push(Resource::Rtcp(rtcp_id));
+            Ok(socket)
+        }
+        _ => {
+            let message = format!("create rtcp failed {:?}", result);
+            Err(error::Error::gateway_api_error(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod media_test {
+    use super::*;
+    use crate::domain::entity::{PhantomId, SerializableId};
+
+    fn media_params(codec: &str) -> MediaParamsDto {
+        MediaParamsDto {
+            band_width: 1500,
+            codec: codec.to_string(),
+            payload_type: None,
+            sampling_rate: None,
+        }
+    }
+
+    fn redirect_video() -> RedirectParameters {
+        RedirectParameters {
+            video: Some(SocketInfo::<PhantomId>::try_create(None, "127.0.0.1", 20000).unwrap()),
+            video_rtcp: None,
+            audio: None,
+            audio_rtcp: None,
+        }
+    }
+
+    #[test]
+    // directionを指定しない場合は、送信用パラメータとリダイレクト先の有無で決まる
+    fn resolve_implicit() {
+        let constraints = ConstraintsDto {
+            video_params: Some(media_params("H264")),
+            audio_params: None,
+            metadata: None,
+        };
+        let (video, audio) =
+            resolve_directions(Some(&constraints), Some(&redirect_video()), None, None).unwrap();
+        assert!(video.send && video.receive);
+        assert!(!audio.send && !audio.receive);
+        assert_eq!(audio.receive_enabled(), None);
+    }
+
+    #[test]
+    // 明示的にrecvonlyを指定した場合は、送信用パラメータがあっても送信しない
+    fn resolve_explicit() {
+        let constraints = ConstraintsDto {
+            video_params: Some(media_params("H264")),
+            audio_params: None,
+            metadata: None,
+        };
+        let (video, _) = resolve_directions(
+            Some(&constraints),
+            Some(&redirect_video()),
+            Some(MediaDirection::RecvOnly),
+            None,
+        )
+        .unwrap();
+        assert!(!video.send && video.receive);
+
+        let (video, _) = resolve_directions(
+            Some(&constraints),
+            Some(&redirect_video()),
+            Some(MediaDirection::SendOnly),
+            None,
+        )
+        .unwrap();
+        assert!(video.send && !video.receive);
+        assert_eq!(video.receive_enabled(), Some(false));
+    }
+
+    #[test]
+    // 送受信に必要なパラメータが無い場合はエラーを返す
+    fn resolve_missing_params() {
+        let result = resolve_directions(None, None, None, Some(MediaDirection::SendOnly));
+        assert_eq!(
+            result,
+            Err(error::Error::invalid_request(
+                "audio_params is required to send audio"
+            ))
+        );
+
+        let result = resolve_directions(None, None, Some(MediaDirection::RecvOnly), None);
+        assert_eq!(
+            result,
+            Err(error::Error::invalid_request(
+                "redirect_params.video is required to receive video"
+            ))
+        );
+    }
+
+    #[test]
+    // 送信しないトラックのパラメータは含まない
+    fn constraint_video_only() {
+        let constraints = ConstraintsDto {
+            video_params: Some(media_params("H264")),
+            audio_params: None,
+            metadata: Some("metadata".to_string()),
+        };
+        let send_params = SendParams {
+            video: Some(MediaPair {
+                media: SocketInfo::<MediaId>::try_create(
+                    Some("vi-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+                    "127.0.0.1",
+                    10000,
+                )
+                .unwrap(),
+                rtcp: SocketInfo::<RtcpId>::try_create(
+                    Some("rc-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+                    "127.0.0.1",
+                    10001,
+                )
+                .unwrap(),
+            }),
+            audio: None,
+        };
+        let video = TrackDirection::resolve("video", None, true, false).unwrap();
+        let audio =
+            TrackDirection::resolve("audio", Some(MediaDirection::RecvOnly), false, true).unwrap();
+
+        let constraint = create_constraint(&send_params, video, audio, Some(&constraints));
+        assert!(constraint.video);
+        assert_eq!(constraint.videoReceiveEnabled, None);
+        assert!(!constraint.audio);
+        assert_eq!(constraint.audioReceiveEnabled, Some(true));
+        assert_eq!(
+            constraint.video_params.unwrap().media_id,
+            MediaId::try_create("vi-4d053831-5dc2-461b-a358-d062d6115216").unwrap()
+        );
+        assert!(constraint.audio_params.is_none());
+        assert_eq!(constraint.metadata, Some("metadata".to_string()));
+    }
+}
@@ -1,8 +1,10 @@
+pub(crate) mod batch;
 pub(crate) mod data;
 pub(crate) mod event;
 pub(crate) mod general;
 pub(crate) mod media;
 pub(crate) mod peer;
+pub(crate) mod rollback;
 pub(crate) mod system;
 
 use async_trait::async_trait;
@@ -3,20 +3,20 @@
 /// ユーザにとってはPeer Objectは生成に完了して然るべきもので、Eventの監視をする積極的理由がないので、
 /// このUseCase内でEventの監視まで自動的に行い、Open完了時に結果を返す
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use shaku::Component;
 
-use crate::application::dto::request::RequestDto;
+use crate::application::dto;
+use crate::application::dto::request::{PeerRequestDto, RequestDto};
 use crate::application::dto::response::{PeerResponseDto, ResponseDto, ResponseDtoResult};
 use crate::application::usecase::Service;
-use crate::domain::entity::request::Request;
 use crate::domain::entity::response::{PeerResponse, Response, ResponseResult};
-use crate::domain::repository::Repository;
+use crate::domain::repository::{Repository, REQUEST_TIMEOUT};
 use crate::error;
 use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState};
 
-#[allow(unused)]
 #[derive(Component)]
 #[shaku(interface = Service)]
 pub(crate) struct Create {
@@ -31,13 +31,26 @@ pub(crate) struct Create {
 #[async_trait]
 impl Service for Create {
     async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
-        if let RequestDto::Peer(ref inner) = request {
-            let request = Request::Peer(inner.clone());
-            let result = self.repository.register(request).await?;
+        if let RequestDto::Peer(PeerRequestDto::Create { .. }) = request {
+            let request = dto::dto_to_request(request)?;
+            // SkyWay CrateはOPENイベントを受信してからCREATEの応答を返すので、
+            // 応答までの期限をOPENまでの期限とする
+            // リクエスト単位で期限が指定されている場合はそちらを優先する
+            let timeout = REQUEST_TIMEOUT
+                .try_with(|timeout| *timeout)
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| Duration::from_millis(self.state.config().peer_open_timeout_ms));
+            let result = REQUEST_TIMEOUT
+                .scope(Some(timeout), async {
+                    self.repository.register(request).await
+                })
+                .await?;
 
-            // 成功した場合はC++側にpeer_id, tokenを渡す
             match result {
-                ResponseResult::Success(Response::Peer(PeerResponse::Create(ref peer_info))) => {
+                // 成功の応答が得られた時点でPeer ObjectはOPENしている
+                ResponseResult::Success(Response::Peer(PeerResponse::Create(peer_info))) => {
+                    // 成功した場合はC++側にpeer_id, tokenを渡す
                     let peer_id = peer_info.peer_id();
                     let token = peer_info.token();
                     // shutdown処理のためにpeer_id, tokenをC++側に通知
@@ -45,10 +58,10 @@ impl Service for Create {
                         .create_peer_callback(peer_id.as_str(), token.as_str());
 
                     return Ok(ResponseDtoResult::Success(ResponseDto::Peer(
-                        PeerResponseDto::Create(peer_info.clone()),
+                        PeerResponseDto::Create(peer_info),
                     )));
                 }
-                // API Callには成功したが、内部処理に失敗したケース
+                // API Callには成功したが、OPENの代わりにERRORを受信したなど、内部処理に失敗したケース
                 ResponseResult::Error(message) => {
                     return Ok(ResponseDtoResult::Error(message));
                 }
@@ -59,7 +72,7 @@ impl Service for Create {
         }
 
         let error_message = format!("wrong parameter {:?}", request);
-        return Err(error::Error::create_local_error(&error_message));
+        return Err(error::Error::internal(error_message));
     }
 }
 
@@ -70,10 +83,67 @@ mod create_peer_test {
     use super::*;
     use crate::application::dto::request::RequestDto;
     use crate::application::dto::response::ResponseDtoResult;
+    use crate::config::Config;
     use crate::di::PeerCreateService;
+    use crate::domain::entity::request::{PeerRequest, Request};
     use crate::domain::entity::response::ResponseResult;
     use crate::domain::repository::MockRepository;
-    use crate::ffi::rust_to_c_bridge::state_objects::MockCallbackFunctions;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockCallbackFunctions, MockGlobalState};
+
+    fn create_request() -> RequestDto {
+        let message = r#"{
+            "request_type": "PEER",
+            "command": "CREATE",
+            "params": {
+                "key": "API_KEY",
+                "domain": "localhost",
+                "peer_id": "peer_id",
+                "turn": true
+            }
+        }"#;
+        RequestDto::from_str(message).unwrap()
+    }
+
+    fn create_response() -> Result<ResponseResult, error::Error> {
+        let message = r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"CREATE",
+                    "peer_id":"peer_id",
+                    "token":"pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
+                }
+            }"#;
+        ResponseResult::from_str(message).map_err(crate::error::Error::from)
+    }
+
+    fn state() -> MockGlobalState {
+        static CONFIG: once_cell::sync::Lazy<Config> = once_cell::sync::Lazy::new(|| Config {
+            peer_open_timeout_ms: 50,
+            ..Config::default()
+        });
+
+        let mut state = MockGlobalState::new();
+        state.expect_config().returning(|| &CONFIG);
+        state
+    }
+
+    // CREATEの応答を返し、その際に設定されていた期限を記録するMockRepository
+    fn repository(
+        response: ResponseResult,
+        timeout: Arc<std::sync::Mutex<Option<Duration>>>,
+    ) -> MockRepository {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(1)
+            .return_once(move |request| {
+                assert!(matches!(request, Request::Peer(PeerRequest::Create { .. })));
+                *timeout.lock().unwrap() = REQUEST_TIMEOUT.try_with(|timeout| *timeout).unwrap();
+                Ok(response)
+            });
+        repository
+    }
 
     #[tokio::test]
     async fn success() {
@@ -91,36 +161,9 @@ mod create_peer_test {
             ResponseDtoResult::from_str(message).unwrap()
         };
 
-        // CreatePeerのパラメータ生成
-        let dto = {
-            let message = r#"{
-                "request_type": "PEER",
-                "command": "CREATE",
-                "params": {
-                    "key": "API_KEY",
-                    "domain": "localhost",
-                    "peer_id": "peer_id",
-                    "turn": true
-                }
-            }"#;
-            RequestDto::from_str(message).unwrap()
-        };
-
-        // repositoryのMockを生成
-        // 呼び出しに成功するケース
-        let mut repository = MockRepository::new();
-        repository.expect_register().times(1).returning(|_| {
-            let message = r#"{
-                    "is_success":true,
-                    "result":{
-                        "request_type":"PEER",
-                        "command":"CREATE",
-                        "peer_id":"peer_id",
-                        "token":"pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
-                    }
-                }"#;
-            ResponseResult::from_str(message)
-        });
+        // OPENイベントを受信し、SkyWay Crateが成功を返すケース
+        let timeout = Arc::new(std::sync::Mutex::new(None));
+        let repository = repository(create_response().unwrap(), timeout.clone());
 
         let mut caller = MockCallbackFunctions::new();
         caller
@@ -131,53 +174,96 @@ mod create_peer_test {
         // サービスの生成
         let module = PeerCreateService::builder()
             .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
             .with_component_override::<dyn CallbackFunctions>(Box::new(caller))
             .build();
         let service: &dyn Service = module.resolve_ref();
 
         // 実行
-        let result = service.execute(dto).await;
+        let result = service.execute(create_request()).await;
         assert_eq!(result.unwrap(), expected);
+        // OPENまでの期限で応答を待つはずである
+        assert_eq!(*timeout.lock().unwrap(), Some(Duration::from_millis(50)));
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        // リクエスト単位で期限が指定された場合はそちらを優先するはずである
+        let timeout = Arc::new(std::sync::Mutex::new(None));
+        let repository = repository(create_response().unwrap(), timeout.clone());
+
+        let mut caller = MockCallbackFunctions::new();
+        caller
+            .expect_create_peer_callback()
+            .times(1)
+            .returning(|_, _| ());
+
+        let module = PeerCreateService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(caller))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+
+        let result = REQUEST_TIMEOUT
+            .scope(
+                Some(Duration::from_millis(300)),
+                service.execute(create_request()),
+            )
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(*timeout.lock().unwrap(), Some(Duration::from_millis(300)));
+    }
+
+    #[tokio::test]
+    async fn error_response() {
+        // OPENの代わりにERRORを受信し、SkyWay Crateがエラーを返すケース
+        // C++側には通知しないはずである
+        let timeout = Arc::new(std::sync::Mutex::new(None));
+        let response = ResponseResult::Error("not receiving OPEN event".to_string());
+        let repository = repository(response, timeout);
+
+        let mut caller = MockCallbackFunctions::new();
+        caller.expect_create_peer_callback().times(0);
+
+        let module = PeerCreateService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(caller))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+
+        let result = service.execute(create_request()).await;
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Error(
+                "not receiving OPEN event".to_string()
+            ))
+        );
     }
 
     #[tokio::test]
     async fn fail() {
         // APIがエラーを返してくるケース
 
-        // CreatePeerのパラメータ生成
-        let dto = {
-            let message = r#"{
-                "request_type": "PEER",
-                "command": "CREATE",
-                "params": {
-                    "key": "API_KEY",
-                    "domain": "localhost",
-                    "peer_id": "peer_id",
-                    "turn": true
-                }
-            }"#;
-            RequestDto::from_str(message).unwrap()
-        };
-
         // repositoryのMockを生成
         // errorを返してくるケース
         let mut repository = MockRepository::new();
         repository.expect_register().times(1).returning(|_| {
-            let answer = error::Error::create_local_error("error");
-            return Err(answer);
+            let answer = error::Error::internal("error");
+            Err(answer)
         });
 
         // サービスの生成
         let module = PeerCreateService::builder()
             .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
             .build();
         let service: &dyn Service = module.resolve_ref();
 
         // 実行
-        let result = service.execute(dto).await;
-        if let Err(error::Error::LocalError(message)) = result {
-            assert_eq!(message, "error");
-        }
+        let result = service.execute(create_request()).await;
+        assert_eq!(result, Err(error::Error::internal("error")));
     }
 
     #[tokio::test]
@@ -203,7 +289,10 @@ mod create_peer_test {
         let result = service.execute(dto).await;
         // 評価
         // 間違ったパラメータである旨を返してくるはずである
-        if let Err(error::Error::LocalError(error_message)) = result {
+        if let Err(error::Error::Internal {
+            message: error_message,
+        }) = result
+        {
             assert_eq!(error_message, "wrong parameter Test");
         }
     }
@@ -0,0 +1,87 @@
+/// PEER LISTは、WebRTC GatewayのAPIを呼ばず、このノードが生成したPeer Objectの一覧を返す
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shaku::Component;
+
+use crate::application::dto::request::{PeerRequestDto, RequestDto};
+use crate::application::dto::response::{PeerResponseDto, ResponseDto, ResponseDtoResult};
+use crate::application::usecase::Service;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+#[derive(Component)]
+#[shaku(interface = Service)]
+pub(crate) struct List {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+}
+
+#[async_trait]
+impl Service for List {
+    async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
+        if let RequestDto::Peer(PeerRequestDto::List) = request {
+            let peers = self.state.peer_registry().lock().unwrap().list();
+            return Ok(ResponseDtoResult::Success(ResponseDto::Peer(
+                PeerResponseDto::List { peers },
+            )));
+        }
+
+        return Err(error::Error::internal(
+            "invalid message in peer list service",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod list_peer_test {
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::di::PeerListService;
+    use crate::domain::entity::PeerInfo;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    #[tokio::test]
+    async fn success() {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info);
+        let registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(registry)));
+
+        let mut state = MockGlobalState::new();
+        state
+            .expect_peer_registry()
+            .times(1)
+            .returning(move || registry);
+
+        let module = PeerListService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let result = service
+            .execute(RequestDto::Peer(PeerRequestDto::List))
+            .await
+            .unwrap();
+
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"LIST",
+                    "peers":[{
+                        "peer_id":"peer_id",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308",
+                        "data_connection_ids":[],
+                        "media_connection_ids":[]
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+    }
+}
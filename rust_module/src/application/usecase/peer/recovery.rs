@@ -0,0 +1,988 @@
+// PEER CLOSEを受信した際に、Peer Objectを再生成し、確立していたDataConnection, MediaConnectionを張り直す
+// Configのpeer_recovery.enabledがtrueの場合のみ動作する
+//
+// 復旧に必要なパラメータは、call_serviceで成功したリクエストから集めておく
+// - PEER CREATEのパラメータ
+// - 自身から確立を要求したDATA CONNECT, MEDIA CALLのパラメータ
+// 相手側から確立されたDataConnection, MediaConnectionは、相手側が張り直すべきものなので対象としない
+// ユーザがDISCONNECT, PEER DELETEを明示的に行った場合は、パラメータを破棄し復旧の対象から外す
+//
+// 復旧の各段階はSYSTEMのEVENTとしてreceive_events経由でユーザに通知される
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use shaku::{Component, Interface};
+
+use crate::application::dto::request::{
+    CallQueryDto, ConnectDtoParams, DataRequestDto, MediaRequestDto, PeerRequestDto, RequestDto,
+};
+use crate::application::dto::response::{
+    DataResponseDto, MediaResponseDto, PeerResponseDto, ResponseDto, ResponseDtoResult,
+};
+use crate::application::factory::Factory;
+use crate::domain::entity::event::SystemEvent;
+use crate::domain::entity::{CreatePeerParams, DataConnectionId, MediaConnectionId, PeerId};
+use crate::domain::repository::Repository;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::snapshot;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+use crate::utils::Backoff;
+
+#[cfg(test)]
+use mockall::automock;
+
+// 復旧対象のConnectionのパラメータと、CLOSEイベントを受信した時刻
+struct Session<T> {
+    params: T,
+    closed_at: Option<Instant>,
+}
+
+impl<T> Session<T> {
+    fn new(params: T) -> Self {
+        Session {
+            params,
+            closed_at: None,
+        }
+    }
+
+    // PEER CLOSEの時点で確立していたとみなせるか
+    fn is_alive(&self, now: Instant, grace: Duration) -> bool {
+        match self.closed_at {
+            None => true,
+            Some(closed_at) => now.duration_since(closed_at) <= grace,
+        }
+    }
+}
+
+// Peer Objectごとに保持する、再生成のパラメータと復旧対象のConnection
+struct PeerSessions {
+    params: CreatePeerParams,
+    data: HashMap<DataConnectionId, Session<ConnectDtoParams>>,
+    media: HashMap<MediaConnectionId, Session<CallQueryDto>>,
+}
+
+impl PeerSessions {
+    fn new(params: CreatePeerParams) -> Self {
+        PeerSessions {
+            params,
+            data: HashMap::new(),
+            media: HashMap::new(),
+        }
+    }
+}
+
+/// 自動復旧のために保持しておくパラメータ
+/// 複数のPeer Objectを扱えるよう、PeerIdごとに管理する
+#[derive(Default)]
+pub(crate) struct RecoveryState {
+    peers: HashMap<PeerId, PeerSessions>,
+}
+
+/// PEER CLOSE時点で確立していたConnectionの一覧
+/// 復旧処理に渡される
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct RecoveryTargets {
+    pub data: Vec<(DataConnectionId, ConnectDtoParams)>,
+    pub media: Vec<(MediaConnectionId, CallQueryDto)>,
+}
+
+impl RecoveryState {
+    /// 成功したリクエストから、復旧に必要なパラメータを記録・破棄する
+    pub fn record(&mut self, request: &RequestDto, response: &ResponseDtoResult) {
+        let response = match response {
+            ResponseDtoResult::Success(response) => response,
+            ResponseDtoResult::Error(_) => return,
+        };
+
+        match (request, response) {
+            (
+                RequestDto::Peer(PeerRequestDto::Create { params }),
+                ResponseDto::Peer(PeerResponseDto::Create(_)),
+            ) => {
+                // 新しいPeer Objectには以前のConnectionは属さない
+                // 他のPeer Objectのパラメータには影響しない
+                self.peers
+                    .insert(params.peer_id.clone(), PeerSessions::new(params.clone()));
+            }
+            (
+                RequestDto::Peer(PeerRequestDto::Delete { params }),
+                ResponseDto::Peer(PeerResponseDto::Delete(_)),
+            ) => {
+                self.peers.remove(&params.peer_id());
+            }
+            (
+                RequestDto::Data(DataRequestDto::Connect { params }),
+                ResponseDto::Data(DataResponseDto::Connect(wrapper)),
+            ) => {
+                // PEER CREATEを記録していないPeer Objectは再生成できないので対象としない
+                if let Some(peer) = self.peers.get_mut(&params.peer_id) {
+                    peer.data.insert(
+                        wrapper.data_connection_id.clone(),
+                        Session::new(params.clone()),
+                    );
+                }
+            }
+            (
+                RequestDto::Data(DataRequestDto::Disconnect { params }),
+                ResponseDto::Data(DataResponseDto::Disconnect(_)),
+            ) => {
+                for peer in self.peers.values_mut() {
+                    peer.data.remove(&params.data_connection_id);
+                }
+            }
+            (
+                RequestDto::Media(MediaRequestDto::Call { params }),
+                ResponseDto::Media(MediaResponseDto::Call(wrapper)),
+            ) => {
+                if let Some(peer) = self.peers.get_mut(&params.peer_id) {
+                    peer.media.insert(
+                        wrapper.media_connection_id.clone(),
+                        Session::new(params.clone()),
+                    );
+                }
+            }
+            (
+                RequestDto::Media(MediaRequestDto::Disconnect { params }),
+                ResponseDto::Media(MediaResponseDto::Disconnect(_)),
+            ) => {
+                for peer in self.peers.values_mut() {
+                    peer.media.remove(&params.media_connection_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// DataConnectionのCLOSEイベントを受信した時刻を記録する
+    /// PEER CLOSEの直前に閉じたものは、Peer Objectとともに閉じたものとみなして復旧の対象にするため、
+    /// この時点では破棄しない
+    pub fn mark_data_closed(&mut self, data_connection_id: &DataConnectionId) {
+        let session = self
+            .peers
+            .values_mut()
+            .find_map(|peer| peer.data.get_mut(data_connection_id));
+        if let Some(session) = session {
+            session.closed_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// MediaConnectionのCLOSEイベントを受信した時刻を記録する
+    pub fn mark_media_closed(&mut self, media_connection_id: &MediaConnectionId) {
+        let session = self
+            .peers
+            .values_mut()
+            .find_map(|peer| peer.media.get_mut(media_connection_id));
+        if let Some(session) = session {
+            session.closed_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// PEER CLOSE受信時に呼ばれ、閉じたPeer Objectのパラメータのみを取り出す
+    /// graceより前に閉じていたConnectionは、Peer ObjectのCLOSEとは無関係に閉じたものとして除外する
+    pub fn take(
+        &mut self,
+        peer_id: &PeerId,
+        grace: Duration,
+    ) -> Option<(CreatePeerParams, RecoveryTargets)> {
+        let state = self.peers.remove(peer_id)?;
+        let now = Instant::now();
+
+        let data = state
+            .data
+            .into_iter()
+            .filter(|(_, session)| session.is_alive(now, grace))
+            .map(|(id, session)| (id, session.params))
+            .collect();
+        let media = state
+            .media
+            .into_iter()
+            .filter(|(_, session)| session.is_alive(now, grace))
+            .map(|(id, session)| (id, session.params))
+            .collect();
+
+        Some((state.params, RecoveryTargets { data, media }))
+    }
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub(crate) trait PeerRecovery: Interface {
+    async fn execute(&self, params: CreatePeerParams, targets: RecoveryTargets);
+}
+
+#[derive(Component)]
+#[shaku(interface = PeerRecovery)]
+pub(crate) struct Recovery {
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    factory: Arc<dyn Factory>,
+}
+
+#[async_trait]
+impl PeerRecovery for Recovery {
+    async fn execute(&self, params: CreatePeerParams, targets: RecoveryTargets) {
+        let peer_id = params.peer_id.clone();
+        self.publish(SystemEvent::PeerRecoveryStarted {
+            peer_id: peer_id.clone(),
+        })
+        .await;
+
+        // 1. Peer Objectを再生成する
+        let config = &self.state.config().peer_recovery;
+        let backoff = Backoff {
+            initial: Duration::from_millis(config.initial_backoff_ms),
+            max: Duration::from_millis(config.max_backoff_ms),
+        };
+        let mut attempts = 0u32;
+        let peer_info = loop {
+            let request = RequestDto::Peer(PeerRequestDto::Create {
+                params: params.clone(),
+            });
+            let error = match self.request(request).await {
+                // 別のPeerIdで生成されたPeer Objectに、元のPeer ObjectのConnectionを張り直してはならない
+                Ok(ResponseDto::Peer(PeerResponseDto::Create(peer_info)))
+                    if peer_info.peer_id() != peer_id =>
+                {
+                    self.publish(SystemEvent::PeerRecoveryFailed {
+                        peer_id,
+                        error: error::Error::internal(format!(
+                            "recovered peer_id {} does not match",
+                            peer_info.peer_id().as_str()
+                        )),
+                    })
+                    .await;
+                    return;
+                }
+                Ok(ResponseDto::Peer(PeerResponseDto::Create(peer_info))) => break peer_info,
+                Ok(response) => unexpected_response(response),
+                Err(e) => e,
+            };
+
+            attempts += 1;
+            if attempts >= config.max_attempts {
+                self.publish(SystemEvent::PeerRecoveryFailed { peer_id, error })
+                    .await;
+                return;
+            }
+            self.publish(SystemEvent::PeerRecoveryRetry { attempts, error })
+                .await;
+            tokio::time::sleep(backoff.delay(attempts)).await;
+        };
+        let token = peer_info.token();
+        self.publish(SystemEvent::PeerRecoveryPeerCreated { params: peer_info })
+            .await;
+
+        // 2. DataConnectionを張り直す
+        // 失敗したものがあっても、残りの復旧は継続する
+        for (previous_data_connection_id, mut params) in targets.data {
+            // 他のPeer Objectから確立したConnectionは対象外である
+            if params.peer_id != peer_id {
+                self.publish(SystemEvent::PeerRecoveryDataConnectFailed {
+                    previous_data_connection_id,
+                    error: peer_mismatch(&params.peer_id),
+                })
+                .await;
+                continue;
+            }
+            params.token = token.clone();
+            let request = RequestDto::Data(DataRequestDto::Connect { params });
+            let event = match self.request(request).await {
+                Ok(ResponseDto::Data(DataResponseDto::Connect(wrapper))) => {
+                    SystemEvent::PeerRecoveryDataConnected {
+                        previous_data_connection_id,
+                        data_connection_id: wrapper.data_connection_id,
+                    }
+                }
+                Ok(response) => SystemEvent::PeerRecoveryDataConnectFailed {
+                    previous_data_connection_id,
+                    error: unexpected_response(response),
+                },
+                Err(error) => SystemEvent::PeerRecoveryDataConnectFailed {
+                    previous_data_connection_id,
+                    error,
+                },
+            };
+            self.publish(event).await;
+        }
+
+        // 3. MediaConnectionを張り直す
+        for (previous_media_connection_id, mut params) in targets.media {
+            if params.peer_id != peer_id {
+                self.publish(SystemEvent::PeerRecoveryMediaCallFailed {
+                    previous_media_connection_id,
+                    error: peer_mismatch(&params.peer_id),
+                })
+                .await;
+                continue;
+            }
+            params.token = token.clone();
+            let request = RequestDto::Media(MediaRequestDto::Call { params });
+            let event = match self.request(request).await {
+                Ok(ResponseDto::Media(MediaResponseDto::Call(wrapper))) => {
+                    SystemEvent::PeerRecoveryMediaCalled {
+                        previous_media_connection_id,
+                        media_connection_id: wrapper.media_connection_id,
+                    }
+                }
+                Ok(response) => SystemEvent::PeerRecoveryMediaCallFailed {
+                    previous_media_connection_id,
+                    error: unexpected_response(response),
+                },
+                Err(error) => SystemEvent::PeerRecoveryMediaCallFailed {
+                    previous_media_connection_id,
+                    error,
+                },
+            };
+            self.publish(event).await;
+        }
+
+        self.publish(SystemEvent::PeerRecoveryCompleted { peer_id })
+            .await;
+    }
+}
+
+// 復旧中のPeer Objectに属さないパラメータが渡されたケース
+fn peer_mismatch(peer_id: &PeerId) -> error::Error {
+    error::Error::internal(format!(
+        "connection belongs to another peer: {}",
+        peer_id.as_str()
+    ))
+}
+
+// Serviceはリクエストに対応するResponseDtoを返すので、通常は到達しない
+fn unexpected_response(response: ResponseDto) -> error::Error {
+    error::Error::internal(format!("unexpected response: {:?}", response))
+}
+
+impl Recovery {
+    // 通常のリクエストと同じServiceで処理し、次回の復旧のためにパラメータを記録する
+    // WebRTC Gatewayが返したエラーもErrとして扱う
+    async fn request(&self, request: RequestDto) -> Result<ResponseDto, error::Error> {
+        let service = self.factory.create_service(&request);
+        let result = service.execute(request.clone()).await?;
+        self.state
+            .recovery_state()
+            .lock()
+            .unwrap()
+            .record(&request, &result);
+        self.state
+            .peer_registry()
+            .lock()
+            .unwrap()
+            .record(&request, &result);
+        snapshot::save();
+        match result {
+            ResponseDtoResult::Success(response) => Ok(response),
+            ResponseDtoResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+        }
+    }
+
+    async fn publish(&self, event: SystemEvent) {
+        // イベントキューが閉じられているのは終了処理中のみなので、通知できなくても復旧は継続する
+        let _ = self.repository.publish_event(event).await;
+    }
+}
+
+#[cfg(test)]
+mod recovery_test {
+    use std::sync::Mutex;
+
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::factory::MockFactory;
+    use crate::application::usecase::MockService;
+    use crate::config::{Config, PeerRecoveryConfig};
+    use crate::di::PeerRecoveryService;
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    fn create_peer_request() -> RequestDto {
+        let message = r#"{
+            "request_type": "PEER",
+            "command": "CREATE",
+            "params": {
+                "key": "API_KEY",
+                "domain": "localhost",
+                "peer_id": "peer_id",
+                "turn": true
+            }
+        }"#;
+        RequestDto::from_str(message).unwrap()
+    }
+
+    fn create_peer_response() -> ResponseDtoResult {
+        let message = r#"{
+            "is_success": true,
+            "result": {
+                "request_type": "PEER",
+                "command": "CREATE",
+                "peer_id": "peer_id",
+                "token": "pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
+            }
+        }"#;
+        ResponseDtoResult::from_str(message).unwrap()
+    }
+
+    fn create_other_peer_request() -> RequestDto {
+        let message = r#"{
+            "request_type": "PEER",
+            "command": "CREATE",
+            "params": {
+                "key": "API_KEY",
+                "domain": "localhost",
+                "peer_id": "other_peer_id",
+                "turn": true
+            }
+        }"#;
+        RequestDto::from_str(message).unwrap()
+    }
+
+    fn create_other_peer_response() -> ResponseDtoResult {
+        let message = r#"{
+            "is_success": true,
+            "result": {
+                "request_type": "PEER",
+                "command": "CREATE",
+                "peer_id": "other_peer_id",
+                "token": "pt-06cf1d26-0ef0-4b03-aca6-933027d434c3"
+            }
+        }"#;
+        ResponseDtoResult::from_str(message).unwrap()
+    }
+
+    fn delete_peer_request() -> RequestDto {
+        let message = r#"{
+            "request_type": "PEER",
+            "command": "DELETE",
+            "params": {
+                "peer_id": "peer_id",
+                "token": "pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
+            }
+        }"#;
+        RequestDto::from_str(message).unwrap()
+    }
+
+    fn delete_peer_response() -> ResponseDtoResult {
+        let message = r#"{
+            "is_success": true,
+            "result": {
+                "request_type": "PEER",
+                "command": "DELETE",
+                "peer_id": "peer_id",
+                "token": "pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
+            }
+        }"#;
+        ResponseDtoResult::from_str(message).unwrap()
+    }
+
+    fn connect_request() -> RequestDto {
+        connect_request_from("peer_id")
+    }
+
+    fn connect_request_from(peer_id: &str) -> RequestDto {
+        let message = format!(
+            r#"{{
+            "request_type": "DATA",
+            "command": "CONNECT",
+            "params": {{
+                "peer_id": "{}",
+                "token": "pt-9749250e-d157-4f80-9ee2-359ce8524308",
+                "target_id": "target_id",
+                "plugin_info": {{
+                    "type": "string",
+                    "plugins": []
+                }}
+            }}
+        }}"#,
+            peer_id
+        );
+        RequestDto::from_str(&message).unwrap()
+    }
+
+    fn connect_response(data_connection_id: &str) -> ResponseDtoResult {
+        let message = format!(
+            r#"{{
+                "is_success": true,
+                "result": {{
+                    "request_type": "DATA",
+                    "command": "CONNECT",
+                    "data_connection_id": "{}"
+                }}
+            }}"#,
+            data_connection_id
+        );
+        ResponseDtoResult::from_str(&message).unwrap()
+    }
+
+    fn disconnect_request(data_connection_id: &str) -> RequestDto {
+        let message = format!(
+            r#"{{
+                "request_type": "DATA",
+                "command": "DISCONNECT",
+                "params": {{
+                    "data_connection_id": "{}"
+                }}
+            }}"#,
+            data_connection_id
+        );
+        RequestDto::from_str(&message).unwrap()
+    }
+
+    fn disconnect_response(data_connection_id: &str) -> ResponseDtoResult {
+        let message = format!(
+            r#"{{
+                "is_success": true,
+                "result": {{
+                    "request_type": "DATA",
+                    "command": "DISCONNECT",
+                    "data_connection_id": "{}"
+                }}
+            }}"#,
+            data_connection_id
+        );
+        ResponseDtoResult::from_str(&message).unwrap()
+    }
+
+    fn data_connection_id(id: &str) -> DataConnectionId {
+        DataConnectionId::try_create(id).unwrap()
+    }
+
+    fn state(recovery_state: &'static std::sync::Mutex<RecoveryState>) -> MockGlobalState {
+        static CONFIG: once_cell::sync::Lazy<Config> = once_cell::sync::Lazy::new(|| Config {
+            peer_recovery: PeerRecoveryConfig {
+                enabled: true,
+                initial_backoff_ms: 1,
+                max_backoff_ms: 1,
+                max_attempts: 2,
+                ..PeerRecoveryConfig::default()
+            },
+            ..Config::default()
+        });
+
+        let mut state = MockGlobalState::new();
+        state.expect_config().returning(|| &CONFIG);
+        state
+            .expect_recovery_state()
+            .returning(move || recovery_state);
+        let peer_registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(PeerRegistry::default())));
+        state
+            .expect_peer_registry()
+            .returning(move || peer_registry);
+        state
+    }
+
+    // publish_eventで通知されたイベントを記録するMockRepository
+    fn repository(events: Arc<Mutex<Vec<SystemEvent>>>) -> MockRepository {
+        let mut repository = MockRepository::new();
+        repository.expect_publish_event().returning(move |event| {
+            events.lock().unwrap().push(event);
+            Ok(())
+        });
+        repository
+    }
+
+    #[test]
+    // 成功したリクエストのパラメータを記録し、DISCONNECTで破棄する
+    fn record() {
+        let mut state = RecoveryState::default();
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(
+            &connect_request(),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+        state.record(
+            &connect_request(),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776302"),
+        );
+        state.record(
+            &disconnect_request("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+            &disconnect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+
+        let (peer, targets) = state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(peer.peer_id, PeerId::new("peer_id"));
+        assert_eq!(targets.data.len(), 1);
+        assert_eq!(
+            targets.data[0].0,
+            data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776302")
+        );
+        assert!(targets.media.is_empty());
+
+        // 取り出した後は空になる
+        assert!(state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .is_none());
+    }
+
+    #[test]
+    // 失敗したリクエストは記録しない
+    fn record_error() {
+        let mut state = RecoveryState::default();
+        state.record(
+            &create_peer_request(),
+            &ResponseDtoResult::Error("error".to_string()),
+        );
+        assert!(state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .is_none());
+    }
+
+    #[test]
+    // 複数のPeer Objectのパラメータは独立して記録・破棄される
+    fn record_multiple_peers() {
+        let mut state = RecoveryState::default();
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(&create_other_peer_request(), &create_other_peer_response());
+        state.record(
+            &connect_request_from("peer_id"),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+        state.record(
+            &connect_request_from("other_peer_id"),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776302"),
+        );
+
+        // 別のPeer ObjectのCREATEで、既存のPeer Objectのパラメータは破棄されない
+        state.record(&create_other_peer_request(), &create_other_peer_response());
+        let (_, targets) = state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(targets.data.len(), 1);
+
+        // PEER DELETEは対象のPeer Objectのパラメータのみを破棄する
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(&delete_peer_request(), &delete_peer_response());
+        assert!(state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .is_none());
+
+        // 再度CREATEしたother_peer_idには以前のConnectionは属さない
+        let (peer, targets) = state
+            .take(&PeerId::new("other_peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(peer.peer_id, PeerId::new("other_peer_id"));
+        assert!(targets.data.is_empty());
+    }
+
+    #[test]
+    // PEER CLOSEで取り出すのは閉じたPeer Objectのパラメータのみである
+    fn take_one_of_multiple_peers() {
+        let mut state = RecoveryState::default();
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(&create_other_peer_request(), &create_other_peer_response());
+        state.record(
+            &connect_request_from("peer_id"),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+        state.record(
+            &connect_request_from("other_peer_id"),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776302"),
+        );
+
+        let (peer, targets) = state
+            .take(&PeerId::new("other_peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(peer.peer_id, PeerId::new("other_peer_id"));
+        assert_eq!(
+            targets.data,
+            vec![(
+                data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776302"),
+                match connect_request_from("other_peer_id") {
+                    RequestDto::Data(DataRequestDto::Connect { params }) => params,
+                    _ => unreachable!(),
+                }
+            )]
+        );
+
+        // peer_idのパラメータは残っている
+        let (_, targets) = state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            targets.data[0].0,
+            data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776301")
+        );
+    }
+
+    #[test]
+    // PEER CLOSEより十分前に閉じていたConnectionは復旧の対象外とする
+    fn take_closed_sessions() {
+        let mut state = RecoveryState::default();
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(
+            &connect_request(),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+        state.mark_data_closed(&data_connection_id(
+            "dc-8bdef7a1-65c8-46be-a82e-37d51c776301",
+        ));
+
+        std::thread::sleep(Duration::from_millis(20));
+        let (_, targets) = state
+            .take(&PeerId::new("peer_id"), Duration::from_millis(10))
+            .unwrap();
+        assert!(targets.data.is_empty());
+    }
+
+    #[test]
+    // PEER CLOSEの直前に閉じたConnectionは復旧の対象とする
+    fn take_recently_closed_sessions() {
+        let mut state = RecoveryState::default();
+        state.record(&create_peer_request(), &create_peer_response());
+        state.record(
+            &connect_request(),
+            &connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776301"),
+        );
+        state.mark_data_closed(&data_connection_id(
+            "dc-8bdef7a1-65c8-46be-a82e-37d51c776301",
+        ));
+
+        let (_, targets) = state
+            .take(&PeerId::new("peer_id"), Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(targets.data.len(), 1);
+    }
+
+    #[tokio::test]
+    // Peer Objectを再生成し、DataConnectionを張り直すケース
+    async fn recover() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().returning(|request| {
+            let mut service = MockService::new();
+            match request {
+                RequestDto::Peer(_) => {
+                    service
+                        .expect_execute()
+                        .returning(|_| Ok(create_peer_response()));
+                }
+                RequestDto::Data(DataRequestDto::Connect { params }) => {
+                    // 再生成したPeer Objectのtokenが使われる
+                    assert_eq!(
+                        params.token.as_str(),
+                        "pt-06cf1d26-0ef0-4b03-aca6-933027d434c2"
+                    );
+                    service.expect_execute().returning(|_| {
+                        Ok(connect_response("dc-8bdef7a1-65c8-46be-a82e-37d51c776304"))
+                    });
+                }
+                _ => unreachable!(),
+            }
+            Arc::new(service)
+        });
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recovery_state: &'static std::sync::Mutex<RecoveryState> =
+            Box::leak(Box::new(std::sync::Mutex::new(RecoveryState::default())));
+        let module = PeerRecoveryService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository(events.clone())))
+            .with_component_override::<dyn GlobalState>(Box::new(state(recovery_state)))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let service: &dyn PeerRecovery = module.resolve_ref();
+
+        let params = match create_peer_request() {
+            RequestDto::Peer(PeerRequestDto::Create { params }) => params,
+            _ => unreachable!(),
+        };
+        let connect_params = match connect_request() {
+            RequestDto::Data(DataRequestDto::Connect { params }) => params,
+            _ => unreachable!(),
+        };
+        let targets = RecoveryTargets {
+            data: vec![(
+                data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776303"),
+                connect_params,
+            )],
+            media: vec![],
+        };
+        service.execute(params, targets).await;
+
+        let peer_info = match create_peer_response() {
+            ResponseDtoResult::Success(ResponseDto::Peer(PeerResponseDto::Create(info))) => info,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                SystemEvent::PeerRecoveryStarted {
+                    peer_id: PeerId::new("peer_id")
+                },
+                SystemEvent::PeerRecoveryPeerCreated { params: peer_info },
+                SystemEvent::PeerRecoveryDataConnected {
+                    previous_data_connection_id: data_connection_id(
+                        "dc-8bdef7a1-65c8-46be-a82e-37d51c776303"
+                    ),
+                    data_connection_id: data_connection_id(
+                        "dc-8bdef7a1-65c8-46be-a82e-37d51c776304"
+                    ),
+                },
+                SystemEvent::PeerRecoveryCompleted {
+                    peer_id: PeerId::new("peer_id")
+                },
+            ]
+        );
+
+        // 次回の復旧のため、張り直したパラメータが記録されている
+        let (_, targets) = recovery_state
+            .lock()
+            .unwrap()
+            .take(&PeerId::new("peer_id"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            targets.data[0].0,
+            data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776304")
+        );
+    }
+
+    #[tokio::test]
+    // Peer Objectの再生成に上限回数まで失敗した場合は復旧を断念する
+    async fn recover_fail() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().times(2).returning(|_| {
+            let mut service = MockService::new();
+            service
+                .expect_execute()
+                .returning(|_| Err(error::Error::internal("error")));
+            Arc::new(service)
+        });
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recovery_state: &'static std::sync::Mutex<RecoveryState> =
+            Box::leak(Box::new(std::sync::Mutex::new(RecoveryState::default())));
+        let module = PeerRecoveryService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository(events.clone())))
+            .with_component_override::<dyn GlobalState>(Box::new(state(recovery_state)))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let service: &dyn PeerRecovery = module.resolve_ref();
+
+        let params = match create_peer_request() {
+            RequestDto::Peer(PeerRequestDto::Create { params }) => params,
+            _ => unreachable!(),
+        };
+        service.execute(params, RecoveryTargets::default()).await;
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[1],
+            SystemEvent::PeerRecoveryRetry { attempts: 1, .. }
+        ));
+        assert!(matches!(events[2], SystemEvent::PeerRecoveryFailed { .. }));
+    }
+
+    #[tokio::test]
+    // 再生成したPeer ObjectのPeerIdが異なる場合は、Connectionを張り直さずに復旧を断念する
+    async fn recover_peer_id_mismatch() {
+        let mut factory = MockFactory::new();
+        factory
+            .expect_create_service()
+            .times(1)
+            .returning(|request| {
+                assert!(matches!(request, RequestDto::Peer(_)));
+                let mut service = MockService::new();
+                service
+                    .expect_execute()
+                    .returning(|_| Ok(create_other_peer_response()));
+                Arc::new(service)
+            });
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recovery_state: &'static std::sync::Mutex<RecoveryState> =
+            Box::leak(Box::new(std::sync::Mutex::new(RecoveryState::default())));
+        let module = PeerRecoveryService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository(events.clone())))
+            .with_component_override::<dyn GlobalState>(Box::new(state(recovery_state)))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let service: &dyn PeerRecovery = module.resolve_ref();
+
+        let params = match create_peer_request() {
+            RequestDto::Peer(PeerRequestDto::Create { params }) => params,
+            _ => unreachable!(),
+        };
+        let connect_params = match connect_request() {
+            RequestDto::Data(DataRequestDto::Connect { params }) => params,
+            _ => unreachable!(),
+        };
+        let targets = RecoveryTargets {
+            data: vec![(
+                data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776303"),
+                connect_params,
+            )],
+            media: vec![],
+        };
+        service.execute(params, targets).await;
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[1],
+            SystemEvent::PeerRecoveryFailed { ref peer_id, .. } if peer_id.as_str() == "peer_id"
+        ));
+    }
+
+    #[tokio::test]
+    // 復旧中のPeer Objectに属さないConnectionは張り直さない
+    async fn recover_skips_other_peer_connection() {
+        let mut factory = MockFactory::new();
+        factory
+            .expect_create_service()
+            .times(1)
+            .returning(|request| {
+                assert!(matches!(request, RequestDto::Peer(_)));
+                let mut service = MockService::new();
+                service
+                    .expect_execute()
+                    .returning(|_| Ok(create_peer_response()));
+                Arc::new(service)
+            });
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recovery_state: &'static std::sync::Mutex<RecoveryState> =
+            Box::leak(Box::new(std::sync::Mutex::new(RecoveryState::default())));
+        let module = PeerRecoveryService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository(events.clone())))
+            .with_component_override::<dyn GlobalState>(Box::new(state(recovery_state)))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let service: &dyn PeerRecovery = module.resolve_ref();
+
+        let params = match create_peer_request() {
+            RequestDto::Peer(PeerRequestDto::Create { params }) => params,
+            _ => unreachable!(),
+        };
+        let connect_params = match connect_request_from("other_peer_id") {
+            RequestDto::Data(DataRequestDto::Connect { params }) => params,
+            _ => unreachable!(),
+        };
+        let targets = RecoveryTargets {
+            data: vec![(
+                data_connection_id("dc-8bdef7a1-65c8-46be-a82e-37d51c776303"),
+                connect_params,
+            )],
+            media: vec![],
+        };
+        service.execute(params, targets).await;
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            events[2],
+            SystemEvent::PeerRecoveryDataConnectFailed { .. }
+        ));
+        assert!(matches!(
+            events[3],
+            SystemEvent::PeerRecoveryCompleted { .. }
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+// 複数のAPIを順に呼び出すusecase(CONNECT, REDIRECT, CALL, ANSWER)の途中で失敗した場合に、
+// それまでにWebRTC Gatewayに確保させたリソースを解放するためのモジュール
+// usecaseはリソースを確保するたびにpushし、失敗時にreleaseを呼ぶ
+// 解放は確保した順序の逆順に行う
+use std::sync::Arc;
+
+use crate::application::dto::request::{DataRequestDto, MediaRequestDto, RequestDto};
+use crate::application::dto::response::ResponseDtoResult;
+use crate::application::factory::Factory;
+use crate::domain::entity::{
+    DataId, DataIdWrapper, MediaId, MediaIdWrapper, RtcpId, RtcpIdWrapper, SerializableId,
+};
+use crate::error;
+
+/// usecaseの途中でWebRTC Gatewayに確保させたリソース
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Resource {
+    Data(DataId),
+    Media(MediaId),
+    Rtcp(RtcpId),
+}
+
+impl Resource {
+    pub fn id(&self) -> String {
+        match self {
+            Resource::Data(data_id) => data_id.as_str().to_string(),
+            Resource::Media(media_id) => media_id.as_str().to_string(),
+            Resource::Rtcp(rtcp_id) => rtcp_id.as_str().to_string(),
+        }
+    }
+
+    /// リソースを解放するためのリクエスト
+    pub fn delete_request(&self) -> RequestDto {
+        match self {
+            Resource::Data(data_id) => RequestDto::Data(DataRequestDto::Delete {
+                params: DataIdWrapper {
+                    data_id: data_id.clone(),
+                },
+            }),
+            Resource::Media(media_id) => RequestDto::Media(MediaRequestDto::ContentDelete {
+                params: MediaIdWrapper {
+                    media_id: media_id.clone(),
+                },
+            }),
+            Resource::Rtcp(rtcp_id) => RequestDto::Media(MediaRequestDto::RtcpDelete {
+                params: RtcpIdWrapper {
+                    rtcp_id: rtcp_id.clone(),
+                },
+            }),
+        }
+    }
+}
+
+pub(crate) struct Rollback {
+    factory: Arc<dyn Factory>,
+    resources: Vec<Resource>,
+}
+
+impl Rollback {
+    pub fn new(factory: Arc<dyn Factory>) -> Self {
+        Rollback {
+            factory,
+            resources: vec![],
+        }
+    }
+
+    pub fn push(&mut self, resource: Resource) {
+        self.resources.push(resource);
+    }
+
+    /// 確保したリソースを逆順に解放する
+    /// 1つの解放に失敗しても残りの解放は継続し、失敗したリソースとエラーを返す
+    pub async fn release(self) -> Vec<(Resource, error::Error)> {
+        let mut failures = vec![];
+        for resource in self.resources.into_iter().rev() {
+            let request = resource.delete_request();
+            let service = self.factory.create_service(&request);
+            match service.execute(request).await {
+                Ok(ResponseDtoResult::Success(_)) => {}
+                Ok(ResponseDtoResult::Error(message)) => {
+                    failures.push((resource, error::Error::gateway_api_error(message)))
+                }
+                Err(e) => failures.push((resource, e)),
+            }
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod rollback_test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::application::dto::response::{DataResponseDto, ResponseDto};
+    use crate::application::factory::MockFactory;
+    use crate::application::usecase::MockService;
+    use crate::domain::entity::SerializableId;
+
+    fn data_id() -> DataId {
+        DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap()
+    }
+
+    fn media_id() -> MediaId {
+        MediaId::try_create("vi-4d053831-5dc2-461b-a358-d062d6115216").unwrap()
+    }
+
+    fn rtcp_id() -> RtcpId {
+        RtcpId::try_create("rc-4d053831-5dc2-461b-a358-d062d6115216").unwrap()
+    }
+
+    fn deleted() -> Result<ResponseDtoResult, error::Error> {
+        Ok(ResponseDtoResult::Success(ResponseDto::Data(
+            DataResponseDto::Delete(DataIdWrapper { data_id: data_id() }),
+        )))
+    }
+
+    #[tokio::test]
+    // 確保した順序の逆順に解放する
+    async fn release_in_reverse_order() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let mut factory = MockFactory::new();
+        let requests_ref = requests.clone();
+        factory
+            .expect_create_service()
+            .times(3)
+            .returning(move |_| {
+                let requests_ref = requests_ref.clone();
+                let mut service = MockService::new();
+                service.expect_execute().returning(move |request| {
+                    requests_ref.lock().unwrap().push(request);
+                    deleted()
+                });
+                Arc::new(service)
+            });
+
+        let mut rollback = Rollback::new(Arc::new(factory));
+        rollback.push(Resource::Data(data_id()));
+        rollback.push(Resource::Media(media_id()));
+        rollback.push(Resource::Rtcp(rtcp_id()));
+        let failures = rollback.release().await;
+
+        assert!(failures.is_empty());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                Resource::Rtcp(rtcp_id()).delete_request(),
+                Resource::Media(media_id()).delete_request(),
+                Resource::Data(data_id()).delete_request(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    // WebRTC Gatewayが解放を拒否した場合も失敗として返す
+    async fn gateway_error() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().times(1).returning(|_| {
+            let mut service = MockService::new();
+            service
+                .expect_execute()
+                .returning(|_| Ok(ResponseDtoResult::Error("error".to_string())));
+            Arc::new(service)
+        });
+
+        let mut rollback = Rollback::new(Arc::new(factory));
+        rollback.push(Resource::Data(data_id()));
+        let failures = rollback.release().await;
+
+        assert_eq!(
+            failures,
+            vec![(
+                Resource::Data(data_id()),
+                error::Error::gateway_api_error("error")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    // 解放に失敗したリソースがあっても、残りの解放は継続する
+    async fn continue_after_failure() {
+        let mut factory = MockFactory::new();
+        factory
+            .expect_create_service()
+            .times(2)
+            .returning(|request| {
+                let mut service = MockService::new();
+                match request {
+                    RequestDto::Media(_) => service
+                        .expect_execute()
+                        .returning(|_| Err(error::Error::gateway_unreachable("error"))),
+                    _ => service.expect_execute().returning(|_| deleted()),
+                };
+                Arc::new(service)
+            });
+
+        let mut rollback = Rollback::new(Arc::new(factory));
+        rollback.push(Resource::Data(data_id()));
+        rollback.push(Resource::Media(media_id()));
+        let failures = rollback.release().await;
+
+        assert_eq!(
+            failures,
+            vec![(
+                Resource::Media(media_id()),
+                error::Error::gateway_unreachable("error")
+            )]
+        );
+    }
+}
@@ -0,0 +1,407 @@
+// リクエスト、イベント、WebRTC Gatewayの呼び出しの統計を集めるためのモジュール
+// call_service, dispatch_events, Repositoryがそれぞれの処理の度に記録し、
+// SYSTEM METRICSと、設定で有効にした場合はPrometheusのテキスト形式で読み出す
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::application::dto::response::{
+    EventMetricsDto, GatewayCallMetricsDto, LatencyBucketDto, LatencyHistogramDto, MetricsDto,
+    RequestMetricsDto,
+};
+
+/// 応答時間の分布を集計する区切り(ms)
+const LATENCY_BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Default)]
+struct Histogram {
+    // LATENCY_BUCKETS_MSの各区切り以下だったものの数。累計ではない
+    buckets: [u64; LATENCY_BUCKETS_MS.len()],
+    count: u64,
+    sum_ms: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        if let Some(index) = LATENCY_BUCKETS_MS.iter().position(|le| elapsed_ms <= *le) {
+            self.buckets[index] += 1;
+        }
+        self.count += 1;
+        self.sum_ms += elapsed_ms;
+    }
+
+    fn to_dto(&self) -> LatencyHistogramDto {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS_MS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(le_ms, count)| {
+                cumulative += count;
+                LatencyBucketDto {
+                    le_ms: *le_ms,
+                    count: cumulative,
+                }
+            })
+            .collect();
+        LatencyHistogramDto {
+            buckets,
+            count: self.count,
+            sum_ms: self.sum_ms,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RequestStats {
+    error_count: u64,
+    latency: Histogram,
+}
+
+/// 起動時からの統計
+/// Peer Object, Connectionの数は記録せず、読み出す時点でGlobalStateから数える
+#[derive(Default)]
+pub(crate) struct Metrics {
+    // keyは(request_type, command)
+    requests: std::sync::Mutex<BTreeMap<(String, String), RequestStats>>,
+    // keyは(request_type, event)
+    events: std::sync::Mutex<BTreeMap<(String, String), u64>>,
+    // keyはrequest_type
+    gateway_calls: std::sync::Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    /// call_serviceで処理したリクエストの結果と、処理にかかった時間を記録する
+    pub fn record_request(
+        &self,
+        request_type: &str,
+        command: &str,
+        elapsed: Duration,
+        is_success: bool,
+    ) {
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests
+            .entry((request_type.to_string(), command.to_string()))
+            .or_default();
+        stats.latency.observe(elapsed);
+        if !is_success {
+            stats.error_count += 1;
+        }
+    }
+
+    /// 配信したイベントを記録する
+    /// イベントの取得に失敗した場合など、eventを持たないものはERRORとして数える
+    pub fn record_event(&self, event: &Value) {
+        let field = |key: &str| {
+            event
+                .pointer(&format!("/result/{}", key))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let request_type = field("request_type").unwrap_or_else(|| "UNKNOWN".to_string());
+        let event = field("event").unwrap_or_else(|| "ERROR".to_string());
+        *self
+            .events
+            .lock()
+            .unwrap()
+            .entry((request_type, event))
+            .or_default() += 1;
+    }
+
+    /// WebRTC Gatewayの呼び出しにかかった時間を記録する
+    pub fn record_gateway_call(&self, request_type: &str, elapsed: Duration) {
+        self.gateway_calls
+            .lock()
+            .unwrap()
+            .entry(request_type.to_string())
+            .or_default()
+            .observe(elapsed);
+    }
+
+    /// 記録した統計を読み出す。uptime_ms, peers, data_connections, media_connectionsは0のまま返すので、呼び出し側で補う
+    pub fn snapshot(&self) -> MetricsDto {
+        let requests = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((request_type, command), stats)| RequestMetricsDto {
+                request_type: request_type.clone(),
+                command: command.clone(),
+                count: stats.latency.count,
+                error_count: stats.error_count,
+                latency: stats.latency.to_dto(),
+            })
+            .collect();
+        let events = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((request_type, event), count)| EventMetricsDto {
+                request_type: request_type.clone(),
+                event: event.clone(),
+                count: *count,
+            })
+            .collect();
+        let gateway_calls = self
+            .gateway_calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(request_type, latency)| GatewayCallMetricsDto {
+                request_type: request_type.clone(),
+                latency: latency.to_dto(),
+            })
+            .collect();
+
+        MetricsDto {
+            uptime_ms: 0,
+            requests,
+            events,
+            gateway_calls,
+            peers: 0,
+            data_connections: 0,
+            media_connections: 0,
+        }
+    }
+}
+
+/// SYSTEM METRICSの結果をPrometheusのテキスト形式(0.0.4)に変換する
+/// 応答時間は秒単位に換算する
+pub(crate) fn to_prometheus(metrics: &MetricsDto) -> String {
+    let mut text = String::new();
+
+    let _ = writeln!(text, "# TYPE skyway_uptime_seconds gauge");
+    let _ = writeln!(text, "skyway_uptime_seconds {}", seconds(metrics.uptime_ms));
+
+    let _ = writeln!(text, "# TYPE skyway_requests_total counter");
+    for request in &metrics.requests {
+        let _ = writeln!(
+            text,
+            "skyway_requests_total{{{}}} {}",
+            request_labels(request),
+            request.count
+        );
+    }
+    let _ = writeln!(text, "# TYPE skyway_request_errors_total counter");
+    for request in &metrics.requests {
+        let _ = writeln!(
+            text,
+            "skyway_request_errors_total{{{}}} {}",
+            request_labels(request),
+            request.error_count
+        );
+    }
+    let _ = writeln!(text, "# TYPE skyway_request_duration_seconds histogram");
+    for request in &metrics.requests {
+        write_histogram(
+            &mut text,
+            "skyway_request_duration_seconds",
+            &request_labels(request),
+            &request.latency,
+        );
+    }
+
+    let _ = writeln!(text, "# TYPE skyway_events_total counter");
+    for event in &metrics.events {
+        let _ = writeln!(
+            text,
+            "skyway_events_total{{request_type=\"{}\",event=\"{}\"}} {}",
+            escape(&event.request_type),
+            escape(&event.event),
+            event.count
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "# TYPE skyway_gateway_call_duration_seconds histogram"
+    );
+    for call in &metrics.gateway_calls {
+        let labels = format!("request_type=\"{}\"", escape(&call.request_type));
+        write_histogram(
+            &mut text,
+            "skyway_gateway_call_duration_seconds",
+            &labels,
+            &call.latency,
+        );
+    }
+
+    for (name, value) in [
+        ("skyway_peers", metrics.peers),
+        ("skyway_data_connections", metrics.data_connections),
+        ("skyway_media_connections", metrics.media_connections),
+    ] {
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        let _ = writeln!(text, "{} {}", name, value);
+    }
+    text
+}
+
+fn request_labels(request: &RequestMetricsDto) -> String {
+    format!(
+        "request_type=\"{}\",command=\"{}\"",
+        escape(&request.request_type),
+        escape(&request.command)
+    )
+}
+
+fn write_histogram(text: &mut String, name: &str, labels: &str, histogram: &LatencyHistogramDto) {
+    for bucket in &histogram.buckets {
+        let _ = writeln!(
+            text,
+            "{}_bucket{{{},le=\"{}\"}} {}",
+            name,
+            labels,
+            seconds(bucket.le_ms),
+            bucket.count
+        );
+    }
+    let _ = writeln!(
+        text,
+        "{}_bucket{{{},le=\"+Inf\"}} {}",
+        name, labels, histogram.count
+    );
+    let _ = writeln!(
+        text,
+        "{}_sum{{{}}} {}",
+        name,
+        labels,
+        seconds(histogram.sum_ms)
+    );
+    let _ = writeln!(text, "{}_count{{{}}} {}", name, labels, histogram.count);
+}
+
+fn seconds(ms: u64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+// ラベルの値にはクライアントが送った文字列が入り得るので、Prometheusの規則に従ってエスケープする
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod metrics_test {
+    use super::*;
+
+    #[test]
+    // bucketsは各区切り以下だったものの累計を返し、区切りを超えたものはcountにのみ含める
+    fn histogram() {
+        let metrics = Metrics::default();
+        for elapsed_ms in [3, 40, 40, 20000] {
+            metrics.record_gateway_call("PEER", Duration::from_millis(elapsed_ms));
+        }
+        let snapshot = metrics.snapshot();
+        let latency = &snapshot.gateway_calls[0].latency;
+        assert_eq!(snapshot.gateway_calls[0].request_type, "PEER");
+        assert_eq!(latency.count, 4);
+        assert_eq!(latency.sum_ms, 20083);
+        let counts = latency
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.le_ms, bucket.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                (5, 1),
+                (10, 1),
+                (25, 1),
+                (50, 3),
+                (100, 3),
+                (250, 3),
+                (500, 3),
+                (1000, 3),
+                (2500, 3),
+                (5000, 3),
+                (10000, 3),
+            ]
+        );
+    }
+
+    #[test]
+    // request_type, commandごとに件数と失敗数を数える
+    fn requests() {
+        let metrics = Metrics::default();
+        metrics.record_request("DATA", "CONNECT", Duration::from_millis(120), true);
+        metrics.record_request("DATA", "CONNECT", Duration::from_millis(80), false);
+        metrics.record_request("PEER", "CREATE", Duration::from_millis(300), true);
+
+        let requests = metrics.snapshot().requests;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            (
+                requests[0].request_type.as_str(),
+                requests[0].command.as_str(),
+                requests[0].count,
+                requests[0].error_count,
+                requests[0].latency.sum_ms
+            ),
+            ("DATA", "CONNECT", 2, 1, 200)
+        );
+        assert_eq!(
+            (requests[1].command.as_str(), requests[1].error_count),
+            ("CREATE", 0)
+        );
+    }
+
+    #[test]
+    // 配信したイベントをrequest_type, eventごとに数え、eventを持たないものはERRORとする
+    fn events() {
+        let metrics = Metrics::default();
+        let open = serde_json::json!({
+            "is_success": true,
+            "result": {"request_type": "PEER", "command": "EVENT", "event": "OPEN"}
+        });
+        metrics.record_event(&open);
+        metrics.record_event(&open);
+        metrics.record_event(&serde_json::json!({
+            "is_success": false,
+            "result": {"request_type": null, "command": null, "code": "INTERNAL_ERROR"}
+        }));
+
+        let events = metrics
+            .snapshot()
+            .events
+            .into_iter()
+            .map(|event| (event.request_type, event.event, event.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ("PEER".to_string(), "OPEN".to_string(), 2),
+                ("UNKNOWN".to_string(), "ERROR".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn prometheus() {
+        let metrics = Metrics::default();
+        metrics.record_request("MEDIA", "CALL", Duration::from_millis(1500), false);
+        let mut snapshot = metrics.snapshot();
+        snapshot.peers = 1;
+        let text = to_prometheus(&snapshot);
+
+        assert!(text.contains("skyway_requests_total{request_type=\"MEDIA\",command=\"CALL\"} 1\n"));
+        assert!(text
+            .contains("skyway_request_errors_total{request_type=\"MEDIA\",command=\"CALL\"} 1\n"));
+        assert!(text.contains(
+            "skyway_request_duration_seconds_bucket{request_type=\"MEDIA\",command=\"CALL\",le=\"1\"} 0\n"
+        ));
+        assert!(text.contains(
+            "skyway_request_duration_seconds_bucket{request_type=\"MEDIA\",command=\"CALL\",le=\"2.5\"} 1\n"
+        ));
+        assert!(text.contains(
+            "skyway_request_duration_seconds_sum{request_type=\"MEDIA\",command=\"CALL\"} 1.5\n"
+        ));
+        assert!(text.contains("# TYPE skyway_peers gauge\nskyway_peers 1\n"));
+    }
+}
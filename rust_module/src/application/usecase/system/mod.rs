@@ -1,38 +1,361 @@
 /// 終了命令など、WebRTC Gateway自体の操作に関係ない指示がClientから来たときに呼ばれる
-use std::thread::sleep;
+pub(crate) mod metrics;
+pub(crate) mod ping;
+pub(crate) mod reconcile;
+pub(crate) mod resume;
+pub(crate) mod shutdown;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use shaku::*;
 
+use crate::application::dto::request::{LogLevelDto, SystemRequestDto};
 use crate::application::dto::response::ResponseDto;
 use crate::application::dto::response::SystemResponseDto;
+use crate::application::usecase::system::shutdown::ShutdownSequence;
 use crate::application::usecase::ResponseDtoResult;
 use crate::application::usecase::Service;
 use crate::application::RequestDto;
-use crate::di::CppObjctsModule;
+use crate::domain::entity::{DataConnectionId, MediaConnectionId};
+use crate::domain::repository::Repository;
 use crate::error;
-use crate::ffi::rust_to_c_bridge::state_objects::ProgramState;
+use crate::ffi::rust_to_c_bridge::state_objects::{GlobalState, Logger, ProgramState};
+use crate::version;
+
+// SHUTDOWNの応答をClientに返してから、ROSを終了させるまでの猶予
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(100);
 
 #[derive(Component)]
 #[shaku(interface = Service)]
-pub(crate) struct System {}
+pub(crate) struct System {
+    #[shaku(inject)]
+    shutdown: Arc<dyn ShutdownSequence>,
+    #[shaku(inject)]
+    program_state: Arc<dyn ProgramState>,
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+    #[shaku(inject)]
+    logger: Arc<dyn Logger>,
+}
 
 #[async_trait]
 impl Service for System {
     async fn execute(&self, request: RequestDto) -> Result<ResponseDtoResult, error::Error> {
-        if let RequestDto::System(_system) = request {
-            std::thread::spawn(|| {
-                sleep(Duration::from_millis(100));
-                let module = CppObjctsModule::builder().build();
-                let state: &dyn ProgramState = module.resolve_ref();
-                state.shutdown();
-            });
-            return Ok(ResponseDtoResult::Success(ResponseDto::System(
-                SystemResponseDto { is_success: true },
-            )));
+        let response = match request {
+            RequestDto::System(SystemRequestDto::Shutdown) => self.shutdown().await,
+            RequestDto::System(SystemRequestDto::Status) => SystemResponseDto::Status {
+                uptime_ms: self.state.uptime().as_millis() as u64,
+                gateway: ping::ping(self.repository.as_ref()).await,
+                peer_count: self.state.peer_registry().lock().unwrap().list().len(),
+            },
+            RequestDto::System(SystemRequestDto::Version) => SystemResponseDto::Version {
+                crate_version: version::CRATE_VERSION.to_string(),
+                protocol_version: version::PROTOCOL_VERSION.to_string(),
+                gateway_caller_version: version::GATEWAY_CALLER_VERSION.to_string(),
+            },
+            RequestDto::System(SystemRequestDto::ListConnections) => self.list_connections(),
+            RequestDto::System(SystemRequestDto::PingGateway) => {
+                SystemResponseDto::PingGateway(ping::ping(self.repository.as_ref()).await)
+            }
+            RequestDto::System(SystemRequestDto::Metrics) => self.metrics(),
+            RequestDto::System(SystemRequestDto::LogLevel { params }) => {
+                if let Some(params) = params {
+                    self.logger.set_level(params.level.to_filter())?;
+                }
+                SystemResponseDto::LogLevel {
+                    level: LogLevelDto::from_filter(self.logger.level()),
+                }
+            }
+            _ => return Err(error::Error::internal("invalid parameters")),
+        };
+
+        Ok(ResponseDtoResult::Success(ResponseDto::System(response)))
+    }
+}
+
+impl System {
+    async fn shutdown(&self) -> SystemResponseDto {
+        // WebRTC Gateway上のオブジェクトを全て解放してからROSを終了させる
+        let steps = self.shutdown.execute().await;
+        let is_success = steps.iter().all(|step| step.is_success);
+
+        // このレスポンスをClientに返す猶予を与えてから終了させる
+        // スレッドを占有しないよう、runtime上のタスクとしてtokioのタイマーで待機する
+        let program_state = self.program_state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(SHUTDOWN_GRACE).await;
+            program_state.shutdown();
+        });
+        SystemResponseDto::Shutdown { is_success, steps }
+    }
+
+    fn list_connections(&self) -> SystemResponseDto {
+        let (data_connection_ids, media_connection_ids) = self.connection_ids();
+        SystemResponseDto::ListConnections {
+            data_connection_ids: data_connection_ids.into_iter().collect(),
+            media_connection_ids: media_connection_ids.into_iter().collect(),
+        }
+    }
+
+    // 記録した統計に、現時点のPeer Object, Connectionの数を加える
+    fn metrics(&self) -> SystemResponseDto {
+        let (data_connection_ids, media_connection_ids) = self.connection_ids();
+        let mut metrics = self.state.metrics().snapshot();
+        metrics.uptime_ms = self.state.uptime().as_millis() as u64;
+        metrics.peers = self.state.peer_registry().lock().unwrap().list().len();
+        metrics.data_connections = data_connection_ids.len();
+        metrics.media_connections = media_connection_ids.len();
+        SystemResponseDto::Metrics(metrics)
+    }
+
+    // Peer Objectごとの記録と、Plugin, 転送先の記録の両方から集める
+    fn connection_ids(&self) -> (BTreeSet<DataConnectionId>, BTreeSet<MediaConnectionId>) {
+        let mut data_connection_ids = BTreeSet::new();
+        let mut media_connection_ids = BTreeSet::new();
+        for peer in self.state.peer_registry().lock().unwrap().list() {
+            data_connection_ids.extend(peer.data_connection_ids);
+            media_connection_ids.extend(peer.media_connection_ids);
+        }
+        for topic in self.state.list_topics() {
+            data_connection_ids.insert(topic.data_connection_id);
+        }
+        for call in self.state.list_call_responses() {
+            media_connection_ids.insert(call.media_connection_id);
+        }
+        (data_connection_ids, media_connection_ids)
+    }
+}
+
+#[cfg(test)]
+mod system_test {
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::response::{ShutdownStepDto, ShutdownStepKind};
+    use crate::application::usecase::system::metrics::Metrics;
+    use crate::application::usecase::system::shutdown::MockShutdownSequence;
+    use crate::di::SystemService;
+    use crate::domain::entity::{
+        DataConnectionId, DataId, MediaConnectionId, PeerInfo, SerializableId,
+    };
+    use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
+    use crate::ffi::rust_to_c_bridge::state_objects::{
+        MockGlobalState, MockLogger, MockProgramState, PeerRegistry,
+    };
+    use tracing::level_filters::LevelFilter;
+
+    #[tokio::test]
+    // 全ての解放を終えてからROSを終了させ、各段階の結果を返す
+    async fn shutdown() {
+        let step = ShutdownStepDto {
+            step: ShutdownStepKind::PeerDelete,
+            target: "peer_id".to_string(),
+            is_success: false,
+            error: Some(error::Error::Timeout { timeout_ms: 100 }),
+        };
+        let mut shutdown = MockShutdownSequence::new();
+        let step_ref = step.clone();
+        shutdown
+            .expect_execute()
+            .times(1)
+            .returning(move || vec![step_ref.clone()]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut program_state = MockProgramState::new();
+        program_state.expect_shutdown().times(1).returning(move || {
+            tx.send(()).unwrap();
+        });
+
+        let module = SystemService::builder()
+            .with_component_override::<dyn ShutdownSequence>(Box::new(shutdown))
+            .with_component_override::<dyn ProgramState>(Box::new(program_state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::System(SystemRequestDto::Shutdown);
+        let result = service.execute(request).await;
+
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::System(
+                SystemResponseDto::Shutdown {
+                    is_success: false,
+                    steps: vec![step],
+                }
+            )))
+        );
+        // 応答を返した時点ではまだ終了させず、猶予の後に終了させる
+        assert!(rx.try_recv().is_err());
+        let shutdown = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(shutdown, Ok(Some(())));
+    }
+
+    #[tokio::test]
+    async fn version() {
+        let module = SystemService::builder().build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::from_str(r#"{"request_type":"SYSTEM","command":"VERSION"}"#);
+        let result = service.execute(request.unwrap()).await;
+
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::System(
+                SystemResponseDto::Version {
+                    crate_version: env!("CARGO_PKG_VERSION").to_string(),
+                    protocol_version: version::PROTOCOL_VERSION.to_string(),
+                    gateway_caller_version: version::GATEWAY_CALLER_VERSION.to_string(),
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    // Peer Objectごとの記録とPlugin, 転送先の記録を重複なく合わせる
+    async fn list_connections() {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let data_connection_id =
+            DataConnectionId::try_create("dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c").unwrap();
+        let media_connection_id =
+            MediaConnectionId::try_create("mc-102127d9-30de-413b-93f7-41a33e39d82b").unwrap();
+
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info);
+        registry.insert_data_connection(&peer_info.peer_id(), data_connection_id.clone());
+        registry.insert_media_connection(&peer_info.peer_id(), media_connection_id.clone());
+        let registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(registry)));
+
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        let topic = DataPipeInfo {
+            data_connection_id: data_connection_id.clone(),
+            data_pipe_port_num: 60000,
+            data_id: DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+            plugin_type: "binary".to_string(),
+            plugins: vec![],
+            remote_peer_id: None,
+        };
+        state
+            .expect_list_topics()
+            .returning(move || vec![topic.clone()]);
+        state.expect_list_call_responses().returning(Vec::new);
+
+        let module = SystemService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let result = service
+            .execute(RequestDto::System(SystemRequestDto::ListConnections))
+            .await;
+
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::System(
+                SystemResponseDto::ListConnections {
+                    data_connection_ids: vec![data_connection_id],
+                    media_connection_ids: vec![media_connection_id],
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    // 記録した統計に、稼働時間とPeer Object, Connectionの数を加えて返す
+    async fn metrics() {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info);
+        let registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(registry)));
+        let metrics: &'static Metrics = Box::leak(Box::new(Metrics::default()));
+        metrics.record_request("PEER", "CREATE", Duration::from_millis(30), true);
+
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        state.expect_metrics().returning(move || metrics);
+        state
+            .expect_uptime()
+            .returning(|| Duration::from_millis(5000));
+        state.expect_list_topics().returning(Vec::new);
+        state.expect_list_call_responses().returning(Vec::new);
+
+        let module = SystemService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+        let request = RequestDto::from_str(r#"{"request_type":"SYSTEM","command":"METRICS"}"#);
+        let result = service.execute(request.unwrap()).await;
+
+        if let Ok(ResponseDtoResult::Success(ResponseDto::System(SystemResponseDto::Metrics(
+            metrics,
+        )))) = result
+        {
+            assert_eq!(metrics.uptime_ms, 5000);
+            assert_eq!(metrics.peers, 1);
+            assert_eq!(metrics.data_connections, 0);
+            assert_eq!(metrics.requests.len(), 1);
+            assert_eq!(metrics.requests[0].count, 1);
+        } else {
+            unreachable!();
         }
+    }
+
+    #[tokio::test]
+    // paramsを指定した場合はレベルを変更し、変更後のレベルを返す
+    async fn log_level() {
+        let current = Arc::new(std::sync::Mutex::new(LevelFilter::INFO));
+        let mut logger = MockLogger::new();
+        let level = current.clone();
+        logger.expect_set_level().times(1).returning(move |filter| {
+            *level.lock().unwrap() = filter;
+            Ok(())
+        });
+        let level = current.clone();
+        logger
+            .expect_level()
+            .returning(move || *level.lock().unwrap());
+
+        let module = SystemService::builder()
+            .with_component_override::<dyn Logger>(Box::new(logger))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+
+        let request = RequestDto::from_str(r#"{"request_type":"SYSTEM","command":"LOG_LEVEL"}"#);
+        let result = service.execute(request.unwrap()).await;
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::System(
+                SystemResponseDto::LogLevel {
+                    level: LogLevelDto::Info
+                }
+            )))
+        );
+
+        let request = RequestDto::from_str(
+            r#"{"request_type":"SYSTEM","command":"LOG_LEVEL","params":{"level":"DEBUG"}}"#,
+        );
+        let result = service.execute(request.unwrap()).await;
+        assert_eq!(
+            result,
+            Ok(ResponseDtoResult::Success(ResponseDto::System(
+                SystemResponseDto::LogLevel {
+                    level: LogLevelDto::Debug
+                }
+            )))
+        );
+        assert_eq!(*current.lock().unwrap(), LevelFilter::DEBUG);
+    }
 
-        return Err(error::Error::create_local_error("invalid parameters"));
+    #[test]
+    // 未知のcommandはパースの時点で拒否する
+    fn unknown_command() {
+        let result = RequestDto::from_str(r#"{"request_type":"SYSTEM","command":"REBOOT"}"#);
+        assert!(matches!(result, Err(error::Error::InvalidRequest { .. })));
     }
 }
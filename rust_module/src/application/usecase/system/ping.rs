@@ -0,0 +1,84 @@
+// WebRTC Gatewayに到達できるかを確認する
+// 存在しないDataConnectionのSTATUSを問い合わせ、応答までの時間を測る
+// WebRTC GatewayからHTTPの応答があれば、404などのエラーであっても到達可能とみなす
+use std::time::Instant;
+
+use crate::application::dto::response::GatewayPingDto;
+use crate::domain::entity::request::{DataRequest, Request};
+use crate::domain::entity::response::{is_network_error, ResponseResult};
+use crate::domain::entity::{DataConnectionId, DataConnectionIdWrapper};
+use crate::domain::repository::Repository;
+use crate::error;
+
+// WebRTC Gatewayが払い出すことのないDataConnectionId
+const PING_DATA_CONNECTION_ID: &str = "dc-00000000-0000-0000-0000-000000000000";
+
+pub(crate) async fn ping(repository: &dyn Repository) -> GatewayPingDto {
+    let request = Request::Data(DataRequest::Status {
+        params: DataConnectionIdWrapper {
+            data_connection_id: DataConnectionId::try_create(PING_DATA_CONNECTION_ID).unwrap(),
+        },
+    });
+
+    let started_at = Instant::now();
+    let result = repository.register(request).await;
+    let latency_ms = started_at.elapsed().as_millis() as u64;
+
+    let error = match result {
+        Ok(ResponseResult::Success(_)) => None,
+        Ok(ResponseResult::Error(message)) if is_network_error(&message) => {
+            Some(error::Error::gateway_unreachable(message))
+        }
+        Ok(ResponseResult::Error(_)) => None,
+        Err(e) => Some(e),
+    };
+
+    GatewayPingDto {
+        reachable: error.is_none(),
+        latency_ms,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod ping_test {
+    use super::*;
+    use crate::domain::repository::MockRepository;
+
+    fn repository(result: Result<ResponseResult, error::Error>) -> MockRepository {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(1)
+            .returning(move |_| result.clone());
+        repository
+    }
+
+    #[tokio::test]
+    // 存在しないDataConnectionに対するエラーは、WebRTC Gatewayが応答したことを示す
+    async fn gateway_error_is_reachable() {
+        let message = r#"{"reason":"InternalError","message":"recv NotFound"}"#.to_string();
+        let result = ping(&repository(Ok(ResponseResult::Error(message)))).await;
+        assert!(result.reachable);
+        assert_eq!(result.error, None);
+    }
+
+    #[tokio::test]
+    async fn network_error() {
+        let message = r#"{"reason":"NetworkError","message":"connection refused"}"#.to_string();
+        let result = ping(&repository(Ok(ResponseResult::Error(message.clone())))).await;
+        assert!(!result.reachable);
+        assert_eq!(
+            result.error,
+            Some(error::Error::gateway_unreachable(message))
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let error = error::Error::Timeout { timeout_ms: 100 };
+        let result = ping(&repository(Err(error.clone()))).await;
+        assert!(!result.reachable);
+        assert_eq!(result.error, Some(error));
+    }
+}
@@ -0,0 +1,641 @@
+// 異常終了後の再起動時に、前回書き出したスナップショットとWebRTC Gateway上のオブジェクトを突き合わせるモジュール
+// STATUSを問い合わせ、まだ利用できるものはこのノードの管理下に戻し、利用できないものは残っているリソースを削除する
+// SkyWay Crateは引き継いだオブジェクトのイベントを監視しないので、管理下に戻したものは監視を開始し直す
+// Peer Object → DataConnection → MediaConnectionの順に処理するので、
+// 削除したPeer Objectに属していたConnectionは、次の段階で利用できないものとして扱われる
+// WebRTC Gatewayがエラーを返した場合は、そのオブジェクトは既に存在しないとみなす
+// 通信に失敗した場合は判断できないので、そのオブジェクトには何もせず失敗として扱う
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shaku::{Component, Interface};
+
+use crate::application::dto::request::{
+    DataRequestDto, MediaRequestDto, PeerRequestDto, PluginInfo, RedirectDtoParams, RequestDto,
+};
+use crate::application::dto::response::{
+    CallResponseDto, DataResponseDto, MediaResponseDto, PeerEntryDto, PeerResponseDto, ResponseDto,
+    ResponseDtoResult,
+};
+use crate::application::factory::Factory;
+use crate::application::usecase::rollback::Resource;
+use crate::domain::entity::event::EventSource;
+use crate::domain::entity::response::is_network_error;
+use crate::domain::entity::{
+    DataConnectionIdWrapper, MediaConnectionIdWrapper, PeerId, PeerInfo, SerializableSocket,
+};
+use crate::domain::repository::Repository;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
+use crate::ffi::rust_to_c_bridge::snapshot::StateSnapshot;
+use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState};
+
+#[cfg(test)]
+use mockall::automock;
+
+/// 突き合わせの結果、オブジェクトに対して行ったこと
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Reconciled {
+    /// まだ利用できたので、このノードの管理下に戻した
+    Adopted,
+    /// 利用できなくなっていたので、残っていたリソースを削除した
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ReconcileStep {
+    pub target: String,
+    pub result: Result<Reconciled, error::Error>,
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub(crate) trait ReconcileSequence: Interface {
+    /// 処理した順に、オブジェクトごとの結果を返す
+    async fn execute(&self, snapshot: StateSnapshot) -> Vec<ReconcileStep>;
+}
+
+#[derive(Component)]
+#[shaku(interface = ReconcileSequence)]
+pub(crate) struct Reconcile {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    factory: Arc<dyn Factory>,
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+    #[shaku(inject)]
+    callback: Arc<dyn CallbackFunctions>,
+}
+
+#[async_trait]
+impl ReconcileSequence for Reconcile {
+    async fn execute(&self, snapshot: StateSnapshot) -> Vec<ReconcileStep> {
+        let mut steps = vec![];
+
+        // 1. Peer Object
+        for peer in &snapshot.peers {
+            let peer_info = PeerInfo::new(peer.peer_id.clone(), peer.token.clone());
+            let request = RequestDto::Peer(PeerRequestDto::Status {
+                params: peer_info.clone(),
+            });
+            let result = match self.request(request).await {
+                Ok(ResponseDto::Peer(PeerResponseDto::Status(status))) if !status.disconnected => {
+                    self.adopt_peer(peer_info).await
+                }
+                // SkyWayサーバとの接続を失っているPeer Objectは削除する
+                Ok(ResponseDto::Peer(PeerResponseDto::Status(_))) => {
+                    let request = RequestDto::Peer(PeerRequestDto::Delete { params: peer_info });
+                    self.delete(request).await
+                }
+                Ok(response) => Err(unexpected_response(response)),
+                Err(error::Error::GatewayApi { .. }) => Ok(Reconciled::Deleted),
+                Err(e) => Err(e),
+            };
+            steps.push(ReconcileStep {
+                target: peer.peer_id.as_str().to_string(),
+                result,
+            });
+        }
+
+        // 2. DataConnection
+        for topic in snapshot.data_connections {
+            let owner = find_owner(&snapshot.peers, |peer| {
+                peer.data_connection_ids.contains(&topic.data_connection_id)
+            });
+            steps.extend(self.data_connection(topic, owner).await);
+        }
+
+        // 3. MediaConnection
+        for call in snapshot.media_connections {
+            let owner = find_owner(&snapshot.peers, |peer| {
+                peer.media_connection_ids
+                    .contains(&call.media_connection_id)
+            });
+            steps.extend(self.media_connection(call, owner).await);
+        }
+
+        steps
+    }
+}
+
+impl Reconcile {
+    // PEER CREATEに成功した場合と同様に、イベントの監視を開始してC++側にも通知する
+    async fn adopt_peer(&self, peer_info: PeerInfo) -> Result<Reconciled, error::Error> {
+        self.repository
+            .listen(EventSource::Peer(peer_info.clone()))
+            .await?;
+        self.state
+            .peer_registry()
+            .lock()
+            .unwrap()
+            .insert_peer(&peer_info);
+        self.callback
+            .create_peer_callback(peer_info.peer_id().as_str(), peer_info.token().as_str());
+        Ok(Reconciled::Adopted)
+    }
+
+    // 開いているDataConnectionは、新しいData Socketを確保してPluginをロードし直し、転送先を差し替える
+    // イベントの監視は、REDIRECTの応答を受けたSkyWay Crateが開始する
+    // 前回のData Socketは、引き継いだ場合も新しいものに置き換わるので削除する
+    async fn data_connection(
+        &self,
+        topic: DataPipeInfo,
+        owner: Option<PeerId>,
+    ) -> Vec<ReconcileStep> {
+        let target = topic.data_connection_id.as_str().to_string();
+        let request = RequestDto::Data(DataRequestDto::Status {
+            params: DataConnectionIdWrapper {
+                data_connection_id: topic.data_connection_id.clone(),
+            },
+        });
+        let status = match self.request(request).await {
+            Ok(ResponseDto::Data(DataResponseDto::Status(status))) => Some(status),
+            Ok(response) => return vec![step(target, Err(unexpected_response(response)))],
+            Err(error::Error::GatewayApi { .. }) => None,
+            Err(e) => return vec![step(target, Err(e))],
+        };
+
+        let disconnect = RequestDto::Data(DataRequestDto::Disconnect {
+            params: DataConnectionIdWrapper {
+                data_connection_id: topic.data_connection_id.clone(),
+            },
+        });
+        let result = match status {
+            Some(status) if status.open => {
+                let request = RequestDto::Data(DataRequestDto::Redirect {
+                    params: RedirectDtoParams {
+                        data_connection_id: topic.data_connection_id.clone(),
+                        plugin_info: PluginInfo {
+                            r#type: topic.plugin_type.clone(),
+                            plugins: topic.plugins.clone(),
+                        },
+                    },
+                });
+                match self.request(request).await {
+                    Ok(_) => {
+                        // REDIRECTでは接続先が分からないので、STATUSの内容で補う
+                        if let Some(mut info) = self.state.find_topic(&topic.data_connection_id) {
+                            info.remote_peer_id = Some(PeerId::new(status.remote_id));
+                            self.state
+                                .store_topic(topic.data_connection_id.clone(), info);
+                        }
+                        if let Some(owner) = owner {
+                            self.state
+                                .peer_registry()
+                                .lock()
+                                .unwrap()
+                                .insert_data_connection(&owner, topic.data_connection_id.clone());
+                        }
+                        Ok(Reconciled::Adopted)
+                    }
+                    // Pluginをロードし直せない場合は引き継げないので切断する
+                    Err(e) => {
+                        let _ = self.delete(disconnect).await;
+                        Err(e)
+                    }
+                }
+            }
+            Some(_) => self.delete(disconnect).await,
+            None => Ok(Reconciled::Deleted),
+        };
+
+        let resource = Resource::Data(topic.data_id);
+        vec![
+            step(target, result),
+            step(resource.id(), self.delete(resource.delete_request()).await),
+        ]
+    }
+
+    // 開いているMediaConnectionは、転送設定をそのまま引き継ぎ、イベントの監視を開始する
+    // 引き継がない場合は、送信に利用していたMedia, Rtcp Socketも削除する
+    async fn media_connection(
+        &self,
+        mut call: CallResponseDto,
+        owner: Option<PeerId>,
+    ) -> Vec<ReconcileStep> {
+        let target = call.media_connection_id.as_str().to_string();
+        let request = RequestDto::Media(MediaRequestDto::Status {
+            params: MediaConnectionIdWrapper {
+                media_connection_id: call.media_connection_id.clone(),
+            },
+        });
+        let status = match self.request(request).await {
+            Ok(ResponseDto::Media(MediaResponseDto::Status(status))) => Some(status),
+            Ok(response) => return vec![step(target, Err(unexpected_response(response)))],
+            Err(error::Error::GatewayApi { .. }) => None,
+            Err(e) => return vec![step(target, Err(e))],
+        };
+
+        let result = match status {
+            Some(status) if status.open => {
+                let source = EventSource::Media(call.media_connection_id.clone());
+                if let Err(e) = self.repository.listen(source).await {
+                    return vec![step(target, Err(e))];
+                }
+                // ANSWERしたMediaConnectionの接続先は、ここで判明する
+                if call.remote_peer_id.is_none() {
+                    call.remote_peer_id = Some(status.remote_id.clone());
+                }
+                if let Some(owner) = owner {
+                    self.state
+                        .peer_registry()
+                        .lock()
+                        .unwrap()
+                        .insert_media_connection(&owner, call.media_connection_id.clone());
+                }
+                self.state
+                    .store_call_response(call.media_connection_id.clone(), call);
+                return vec![step(target, Ok(Reconciled::Adopted))];
+            }
+            Some(_) => {
+                let request = RequestDto::Media(MediaRequestDto::Disconnect {
+                    params: MediaConnectionIdWrapper {
+                        media_connection_id: call.media_connection_id.clone(),
+                    },
+                });
+                self.delete(request).await
+            }
+            None => Ok(Reconciled::Deleted),
+        };
+
+        let mut steps = vec![step(target, result)];
+        let send_params = &call.send_params;
+        for pair in [&send_params.video, &send_params.audio]
+            .into_iter()
+            .flatten()
+        {
+            let resources = [
+                pair.media.get_id().map(Resource::Media),
+                pair.rtcp.get_id().map(Resource::Rtcp),
+            ];
+            for resource in resources.into_iter().flatten() {
+                let result = self.delete(resource.delete_request()).await;
+                steps.push(step(resource.id(), result));
+            }
+        }
+        steps
+    }
+
+    // WebRTC Gatewayが返したエラーもErrとして扱う
+    async fn request(&self, request: RequestDto) -> Result<ResponseDto, error::Error> {
+        let service = self.factory.create_service(&request);
+        match service.execute(request).await? {
+            ResponseDtoResult::Success(response) => Ok(response),
+            ResponseDtoResult::Error(message) if is_network_error(&message) => {
+                Err(error::Error::gateway_unreachable(message))
+            }
+            ResponseDtoResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+        }
+    }
+
+    // WebRTC Gatewayがエラーを返した場合は、既に削除されていたとみなす
+    async fn delete(&self, request: RequestDto) -> Result<Reconciled, error::Error> {
+        match self.request(request).await {
+            Ok(_) | Err(error::Error::GatewayApi { .. }) => Ok(Reconciled::Deleted),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn step(target: String, result: Result<Reconciled, error::Error>) -> ReconcileStep {
+    ReconcileStep { target, result }
+}
+
+fn find_owner(peers: &[PeerEntryDto], predicate: impl Fn(&PeerEntryDto) -> bool) -> Option<PeerId> {
+    peers
+        .iter()
+        .find(|peer| predicate(peer))
+        .map(|peer| peer.peer_id.clone())
+}
+
+// Serviceはリクエストに対応するResponseDtoを返すので、通常は到達しない
+fn unexpected_response(response: ResponseDto) -> error::Error {
+    error::Error::internal(format!("unexpected response: {:?}", response))
+}
+
+#[cfg(test)]
+mod reconcile_test {
+    use std::sync::Mutex;
+
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::response::{MediaPair, SendParams};
+    use crate::application::factory::MockFactory;
+    use crate::application::usecase::MockService;
+    use crate::di::ReconcileService;
+    use crate::domain::entity::{
+        DataConnectionId, DataConnectionStatus, DataId, MediaConnectionId, MediaConnectionStatus,
+        MediaId, PeerStatusMessage, RtcpId, SerializableId, SocketInfo,
+    };
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::state_objects::{
+        MockCallbackFunctions, MockGlobalState, PeerRegistry,
+    };
+
+    const DATA_CONNECTION_ID: &str = "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c";
+    const MEDIA_CONNECTION_ID: &str = "mc-102127d9-30de-413b-93f7-41a33e39d82b";
+
+    fn peer_info() -> PeerInfo {
+        PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap()
+    }
+
+    // Peer Objectと、それに属するDataConnection, 映像のみを送信しているMediaConnection
+    fn snapshot() -> StateSnapshot {
+        let media = SocketInfo::<MediaId>::try_create(
+            Some("vi-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+            "127.0.0.1",
+            10000,
+        )
+        .unwrap();
+        let rtcp = SocketInfo::<RtcpId>::try_create(
+            Some("rc-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+            "127.0.0.1",
+            10001,
+        )
+        .unwrap();
+
+        StateSnapshot {
+            peers: vec![PeerEntryDto {
+                peer_id: peer_info().peer_id(),
+                token: peer_info().token(),
+                data_connection_ids: vec![DataConnectionId::try_create(DATA_CONNECTION_ID).unwrap()],
+                media_connection_ids: vec![
+                    MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap()
+                ],
+            }],
+            data_connections: vec![DataPipeInfo {
+                data_connection_id: DataConnectionId::try_create(DATA_CONNECTION_ID).unwrap(),
+                data_pipe_port_num: 60000,
+                data_id: DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+                plugin_type: "string".to_string(),
+                plugins: vec![
+                    serde_json::json!({"plugin_name": "string_loopback::StringLoopback"}),
+                ],
+                remote_peer_id: Some(PeerId::new("target_id")),
+            }],
+            media_connections: vec![CallResponseDto {
+                send_params: SendParams {
+                    video: Some(MediaPair { media, rtcp }),
+                    audio: None,
+                },
+                redirect_params: None,
+                media_connection_id: MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap(),
+                remote_peer_id: None,
+            }],
+        }
+    }
+
+    fn data_status(open: bool) -> ResponseDtoResult {
+        ResponseDtoResult::Success(ResponseDto::Data(DataResponseDto::Status(
+            DataConnectionStatus {
+                remote_id: "remote_id".to_string(),
+                buffersize: 0,
+                label: "".to_string(),
+                metadata: "".to_string(),
+                open,
+                reliable: true,
+                serialization: "NONE".to_string(),
+                r#type: "DATA".to_string(),
+            },
+        )))
+    }
+
+    fn media_status(open: bool) -> ResponseDtoResult {
+        ResponseDtoResult::Success(ResponseDto::Media(MediaResponseDto::Status(
+            MediaConnectionStatus {
+                metadata: "".to_string(),
+                open,
+                remote_id: PeerId::new("remote_id"),
+                ssrc: None,
+            },
+        )))
+    }
+
+    // リクエストごとにhandlerの結果を返し、受け取ったリクエストを記録するFactory
+    fn factory(
+        handler: fn(&RequestDto) -> Result<ResponseDtoResult, error::Error>,
+    ) -> (MockFactory, Arc<Mutex<Vec<RequestDto>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requests_ref = requests.clone();
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().returning(move |_| {
+            let requests_ref = requests_ref.clone();
+            let mut service = MockService::new();
+            service.expect_execute().returning(move |request| {
+                requests_ref.lock().unwrap().push(request.clone());
+                handler(&request)
+            });
+            Arc::new(service)
+        });
+        (factory, requests)
+    }
+
+    fn registry() -> &'static Mutex<PeerRegistry> {
+        Box::leak(Box::new(Mutex::new(PeerRegistry::default())))
+    }
+
+    #[tokio::test]
+    // 利用できるオブジェクトは全て引き継ぎ、DataConnectionはPluginをロードし直して転送先を差し替える
+    async fn adopt() {
+        let (factory, requests) = factory(|request| match request {
+            RequestDto::Peer(PeerRequestDto::Status { params }) => Ok(ResponseDtoResult::Success(
+                ResponseDto::Peer(PeerResponseDto::Status(PeerStatusMessage {
+                    peer_id: params.peer_id(),
+                    disconnected: false,
+                })),
+            )),
+            RequestDto::Data(DataRequestDto::Status { .. }) => Ok(data_status(true)),
+            RequestDto::Data(DataRequestDto::Redirect { params }) => {
+                Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                    DataResponseDto::Redirect(DataConnectionIdWrapper {
+                        data_connection_id: params.data_connection_id.clone(),
+                    }),
+                )))
+            }
+            RequestDto::Data(DataRequestDto::Delete { params }) => Ok(ResponseDtoResult::Success(
+                ResponseDto::Data(DataResponseDto::Delete(params.clone())),
+            )),
+            RequestDto::Media(MediaRequestDto::Status { .. }) => Ok(media_status(true)),
+            _ => unreachable!(),
+        });
+
+        let registry = registry();
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        // REDIRECTで保存された情報に、STATUSから得た接続先を補う
+        state
+            .expect_find_topic()
+            .times(1)
+            .returning(|_| Some(snapshot().data_connections[0].clone()));
+        state.expect_store_topic().times(1).returning(|_, info| {
+            assert_eq!(info.remote_peer_id, Some(PeerId::new("remote_id")));
+        });
+        state
+            .expect_store_call_response()
+            .times(1)
+            .returning(|_, _| ());
+        // 引き継いだPeer Object, MediaConnectionのイベントの監視を開始し直す
+        // DataConnectionの監視は、REDIRECTの応答を受けたSkyWay Crateが開始する
+        let mut repository = MockRepository::new();
+        repository
+            .expect_listen()
+            .with(mockall::predicate::eq(EventSource::Peer(peer_info())))
+            .times(1)
+            .returning(|_| Ok(()));
+        repository
+            .expect_listen()
+            .with(mockall::predicate::eq(EventSource::Media(
+                MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap(),
+            )))
+            .times(1)
+            .returning(|_| Ok(()));
+        // C++側にもPEER CREATEの成功時と同様に通知する
+        let mut callback = MockCallbackFunctions::new();
+        callback
+            .expect_create_peer_callback()
+            .withf(|peer_id, token| {
+                peer_id == "peer_id" && token == "pt-9749250e-d157-4f80-9ee2-359ce8524308"
+            })
+            .times(1)
+            .returning(|_, _| ());
+
+        let module = ReconcileService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(callback))
+            .build();
+        let reconcile: &dyn ReconcileSequence = module.resolve_ref();
+        let steps = reconcile.execute(snapshot()).await;
+
+        assert_eq!(
+            steps,
+            vec![
+                step("peer_id".to_string(), Ok(Reconciled::Adopted)),
+                step(DATA_CONNECTION_ID.to_string(), Ok(Reconciled::Adopted)),
+                step(
+                    "da-50a32bab-b3d9-4913-8e20-f79c90a6a211".to_string(),
+                    Ok(Reconciled::Deleted)
+                ),
+                step(MEDIA_CONNECTION_ID.to_string(), Ok(Reconciled::Adopted)),
+            ]
+        );
+        assert!(requests
+            .lock()
+            .unwrap()
+            .contains(&RequestDto::Data(DataRequestDto::Redirect {
+                params: RedirectDtoParams {
+                    data_connection_id: DataConnectionId::try_create(DATA_CONNECTION_ID).unwrap(),
+                    plugin_info: PluginInfo {
+                        r#type: "string".to_string(),
+                        plugins: vec![
+                            serde_json::json!({"plugin_name": "string_loopback::StringLoopback"})
+                        ],
+                    },
+                },
+            })));
+        let peers = registry.lock().unwrap().list();
+        assert_eq!(peers, snapshot().peers);
+    }
+
+    #[tokio::test]
+    // 利用できないオブジェクトは、残っているリソースとともに削除する
+    async fn delete_orphans() {
+        let (factory, requests) = factory(|request| match request {
+            // 既に存在しないPeer Object, MediaConnection
+            RequestDto::Peer(PeerRequestDto::Status { .. })
+            | RequestDto::Media(MediaRequestDto::Status { .. }) => {
+                Ok(ResponseDtoResult::Error("recv NotFound".to_string()))
+            }
+            // 閉じているDataConnection
+            RequestDto::Data(DataRequestDto::Status { .. }) => Ok(data_status(false)),
+            RequestDto::Data(DataRequestDto::Disconnect { params }) => {
+                Ok(ResponseDtoResult::Success(ResponseDto::Data(
+                    DataResponseDto::Disconnect(params.clone()),
+                )))
+            }
+            // 既に削除されていたSocket
+            RequestDto::Data(DataRequestDto::Delete { .. })
+            | RequestDto::Media(MediaRequestDto::ContentDelete { .. })
+            | RequestDto::Media(MediaRequestDto::RtcpDelete { .. }) => {
+                Ok(ResponseDtoResult::Error("recv NotFound".to_string()))
+            }
+            _ => unreachable!(),
+        });
+
+        let registry = registry();
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        state.expect_store_topic().times(0);
+        state.expect_store_call_response().times(0);
+        let mut repository = MockRepository::new();
+        repository.expect_listen().times(0);
+        let mut callback = MockCallbackFunctions::new();
+        callback.expect_create_peer_callback().times(0);
+
+        let module = ReconcileService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(callback))
+            .build();
+        let reconcile: &dyn ReconcileSequence = module.resolve_ref();
+        let steps = reconcile.execute(snapshot()).await;
+
+        assert_eq!(
+            steps,
+            vec![
+                step("peer_id".to_string(), Ok(Reconciled::Deleted)),
+                step(DATA_CONNECTION_ID.to_string(), Ok(Reconciled::Deleted)),
+                step(
+                    "da-50a32bab-b3d9-4913-8e20-f79c90a6a211".to_string(),
+                    Ok(Reconciled::Deleted)
+                ),
+                step(MEDIA_CONNECTION_ID.to_string(), Ok(Reconciled::Deleted)),
+                step(
+                    "vi-4d053831-5dc2-461b-a358-d062d6115216".to_string(),
+                    Ok(Reconciled::Deleted)
+                ),
+                step(
+                    "rc-4d053831-5dc2-461b-a358-d062d6115216".to_string(),
+                    Ok(Reconciled::Deleted)
+                ),
+            ]
+        );
+        assert_eq!(requests.lock().unwrap().len(), 7);
+        assert!(registry.lock().unwrap().list().is_empty());
+    }
+
+    #[tokio::test]
+    // WebRTC Gatewayと通信できない場合は、存在するかどうか分からないので何もしない
+    async fn gateway_unreachable() {
+        let (factory, requests) = factory(|_| {
+            Ok(ResponseDtoResult::Error(
+                r#"{"reason":"NetworkError","message":"connection refused"}"#.to_string(),
+            ))
+        });
+
+        let registry = registry();
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+
+        let module = ReconcileService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .build();
+        let reconcile: &dyn ReconcileSequence = module.resolve_ref();
+        let steps = reconcile.execute(snapshot()).await;
+
+        assert_eq!(steps.len(), 3);
+        for step in steps {
+            assert!(matches!(
+                step.result,
+                Err(error::Error::GatewayUnreachable { .. })
+            ));
+        }
+        // STATUSの問い合わせのみ行う
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,443 @@
+// SkyWay Crateの再起動後に、このノードが管理しているオブジェクトのイベントの監視を再開するモジュール
+// SkyWay Crateは再起動前に開始したイベントの監視を引き継がないので、
+// STATUSを問い合わせ、まだ存在するPeer Object, DataConnection, MediaConnectionは監視を開始し直す
+// 既に存在しないものは、受け取れなかったCLOSEイベントを補い、通常のCLOSEと同様に後始末と自動復旧を行わせる
+// SkyWayサーバとの接続を失っているPeer Objectは、PEER DELETEを行った上で閉じたものとして扱う
+// 通信に失敗した場合は判断できないので、そのオブジェクトには何もせず失敗として扱う
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shaku::{Component, Interface};
+
+use crate::domain::entity::event::EventSource;
+use crate::domain::entity::request::{DataRequest, MediaRequest, PeerRequest, Request};
+use crate::domain::entity::response::{
+    is_network_error, DataResponse, MediaResponse, PeerResponse, Response, ResponseResult,
+};
+use crate::domain::entity::{
+    DataConnectionEventEnum, DataConnectionId, DataConnectionIdWrapper, MediaConnectionEventEnum,
+    MediaConnectionId, MediaConnectionIdWrapper, PeerCloseEvent, PeerEventEnum, PeerInfo,
+};
+use crate::domain::repository::Repository;
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+
+#[cfg(test)]
+use mockall::automock;
+
+/// 監視の再開を試みた結果、オブジェクトに対して行ったこと
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Resumed {
+    /// まだ存在したので、イベントの監視を再開した
+    Listening,
+    /// 既に閉じていたので、CLOSEイベントを補った
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ResumeStep {
+    pub target: String,
+    pub result: Result<Resumed, error::Error>,
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub(crate) trait ResumeSequence: Interface {
+    /// 処理した順に、オブジェクトごとの結果を返す
+    async fn execute(&self) -> Vec<ResumeStep>;
+}
+
+#[derive(Component)]
+#[shaku(interface = ResumeSequence)]
+pub(crate) struct Resume {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    repository: Arc<dyn Repository>,
+}
+
+#[async_trait]
+impl ResumeSequence for Resume {
+    async fn execute(&self) -> Vec<ResumeStep> {
+        let peers = self.state.peer_registry().lock().unwrap().list();
+        let mut steps = vec![];
+
+        for peer in peers {
+            let target = peer.peer_id.as_str().to_string();
+            let peer_info = PeerInfo::new(peer.peer_id, peer.token);
+            let request = Request::Peer(PeerRequest::Status {
+                params: peer_info.clone(),
+            });
+            let is_alive = match self.request(request).await {
+                Ok(Response::Peer(PeerResponse::Status(status))) if !status.disconnected => true,
+                Ok(Response::Peer(PeerResponse::Status(_))) => {
+                    let request = Request::Peer(PeerRequest::Delete {
+                        params: peer_info.clone(),
+                    });
+                    match self.request(request).await {
+                        Ok(_) | Err(error::Error::GatewayApi { .. }) => false,
+                        Err(e) => {
+                            steps.push(step(target, Err(e)));
+                            continue;
+                        }
+                    }
+                }
+                Ok(response) => {
+                    steps.push(step(target, Err(unexpected_response(response))));
+                    continue;
+                }
+                Err(error::Error::GatewayApi { .. }) => false,
+                Err(e) => {
+                    steps.push(step(target, Err(e)));
+                    continue;
+                }
+            };
+
+            if is_alive {
+                let result = self.listen(EventSource::Peer(peer_info)).await;
+                steps.push(step(target, result));
+                for data_connection_id in peer.data_connection_ids {
+                    steps.push(self.data_connection(data_connection_id).await);
+                }
+                for media_connection_id in peer.media_connection_ids {
+                    steps.push(self.media_connection(media_connection_id).await);
+                }
+                continue;
+            }
+
+            // Peer Objectが閉じていれば、属していたConnectionも閉じている
+            // PEER CLOSEの時点で確立していたConnectionを自動復旧の対象にするため、Connectionを先に閉じる
+            for data_connection_id in peer.data_connection_ids {
+                let target = data_connection_id.as_str().to_string();
+                let result = self.close_data_connection(data_connection_id).await;
+                steps.push(step(target, result));
+            }
+            for media_connection_id in peer.media_connection_ids {
+                let target = media_connection_id.as_str().to_string();
+                let result = self.close_media_connection(media_connection_id).await;
+                steps.push(step(target, result));
+            }
+            let event =
+                PeerResponse::Event(PeerEventEnum::CLOSE(PeerCloseEvent { params: peer_info }));
+            let result = self.supplement(Response::Peer(event)).await;
+            steps.push(step(target, result));
+        }
+
+        steps
+    }
+}
+
+impl Resume {
+    async fn data_connection(&self, data_connection_id: DataConnectionId) -> ResumeStep {
+        let target = data_connection_id.as_str().to_string();
+        let request = Request::Data(DataRequest::Status {
+            params: DataConnectionIdWrapper {
+                data_connection_id: data_connection_id.clone(),
+            },
+        });
+        // 確立中のものもあるので、存在していれば監視を再開する
+        let result = match self.request(request).await {
+            Ok(Response::Data(DataResponse::Status(_))) => {
+                self.listen(EventSource::Data(data_connection_id)).await
+            }
+            Ok(response) => Err(unexpected_response(response)),
+            Err(error::Error::GatewayApi { .. }) => {
+                self.close_data_connection(data_connection_id).await
+            }
+            Err(e) => Err(e),
+        };
+        step(target, result)
+    }
+
+    async fn media_connection(&self, media_connection_id: MediaConnectionId) -> ResumeStep {
+        let target = media_connection_id.as_str().to_string();
+        let request = Request::Media(MediaRequest::Status {
+            params: MediaConnectionIdWrapper {
+                media_connection_id: media_connection_id.clone(),
+            },
+        });
+        let result = match self.request(request).await {
+            Ok(Response::Media(MediaResponse::Status(_))) => {
+                self.listen(EventSource::Media(media_connection_id)).await
+            }
+            Ok(response) => Err(unexpected_response(response)),
+            Err(error::Error::GatewayApi { .. }) => {
+                self.close_media_connection(media_connection_id).await
+            }
+            Err(e) => Err(e),
+        };
+        step(target, result)
+    }
+
+    async fn close_data_connection(
+        &self,
+        data_connection_id: DataConnectionId,
+    ) -> Result<Resumed, error::Error> {
+        let event = DataResponse::Event(DataConnectionEventEnum::CLOSE(DataConnectionIdWrapper {
+            data_connection_id,
+        }));
+        self.supplement(Response::Data(event)).await
+    }
+
+    async fn close_media_connection(
+        &self,
+        media_connection_id: MediaConnectionId,
+    ) -> Result<Resumed, error::Error> {
+        let event =
+            MediaResponse::Event(MediaConnectionEventEnum::CLOSE(MediaConnectionIdWrapper {
+                media_connection_id,
+            }));
+        self.supplement(Response::Media(event)).await
+    }
+
+    async fn listen(&self, source: EventSource) -> Result<Resumed, error::Error> {
+        self.repository.listen(source).await?;
+        Ok(Resumed::Listening)
+    }
+
+    async fn supplement(&self, event: Response) -> Result<Resumed, error::Error> {
+        self.repository
+            .supplement_event(ResponseResult::Success(event))
+            .await?;
+        Ok(Resumed::Closed)
+    }
+
+    // WebRTC Gatewayが返したエラーもErrとして扱う
+    async fn request(&self, request: Request) -> Result<Response, error::Error> {
+        match self.repository.register(request).await? {
+            ResponseResult::Success(response) => Ok(response),
+            ResponseResult::Error(message) if is_network_error(&message) => {
+                Err(error::Error::gateway_unreachable(message))
+            }
+            ResponseResult::Error(message) => Err(error::Error::gateway_api_error(message)),
+        }
+    }
+}
+
+fn step(target: String, result: Result<Resumed, error::Error>) -> ResumeStep {
+    ResumeStep { target, result }
+}
+
+// Repositoryはリクエストに対応するResponseを返すので、通常は到達しない
+fn unexpected_response(response: Response) -> error::Error {
+    error::Error::internal(format!("unexpected response: {:?}", response))
+}
+
+#[cfg(test)]
+mod resume_test {
+    use std::sync::Mutex;
+
+    use mockall::predicate::eq;
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::di::ResumeService;
+    use crate::domain::entity::{
+        DataConnectionStatus, MediaConnectionStatus, PeerId, PeerStatusMessage,
+    };
+    use crate::domain::repository::MockRepository;
+    use crate::ffi::rust_to_c_bridge::state_objects::{MockGlobalState, PeerRegistry};
+
+    const DATA_CONNECTION_ID: &str = "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c";
+    const MEDIA_CONNECTION_ID: &str = "mc-102127d9-30de-413b-93f7-41a33e39d82b";
+
+    fn peer_info() -> PeerInfo {
+        PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap()
+    }
+
+    fn data_connection_id() -> DataConnectionId {
+        DataConnectionId::try_create(DATA_CONNECTION_ID).unwrap()
+    }
+
+    fn media_connection_id() -> MediaConnectionId {
+        MediaConnectionId::try_create(MEDIA_CONNECTION_ID).unwrap()
+    }
+
+    // DataConnectionとMediaConnectionを1つずつ持つPeer Objectを登録したGlobalState
+    fn state() -> MockGlobalState {
+        let registry: &'static Mutex<PeerRegistry> =
+            Box::leak(Box::new(Mutex::new(PeerRegistry::default())));
+        {
+            let mut registry = registry.lock().unwrap();
+            registry.insert_peer(&peer_info());
+            registry.insert_data_connection(&peer_info().peer_id(), data_connection_id());
+            registry.insert_media_connection(&peer_info().peer_id(), media_connection_id());
+        }
+        let mut state = MockGlobalState::new();
+        state.expect_peer_registry().returning(move || registry);
+        state
+    }
+
+    fn peer_status(disconnected: bool) -> ResponseResult {
+        ResponseResult::Success(Response::Peer(PeerResponse::Status(PeerStatusMessage {
+            peer_id: peer_info().peer_id(),
+            disconnected,
+        })))
+    }
+
+    fn api_error() -> ResponseResult {
+        ResponseResult::Error(r#"{"reason":"RecvError","message":"404 Not Found"}"#.to_string())
+    }
+
+    fn network_error() -> ResponseResult {
+        ResponseResult::Error(r#"{"reason":"NetworkError","message":"refused"}"#.to_string())
+    }
+
+    async fn resume(repository: MockRepository) -> Vec<ResumeStep> {
+        let module = ResumeService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let resume: &dyn ResumeSequence = module.resolve_ref();
+        resume.execute().await
+    }
+
+    #[tokio::test]
+    // 存在するPeer Object, Connectionは、全てイベントの監視を開始し直す
+    async fn alive() {
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(3).returning(|request| {
+            Ok(match request {
+                Request::Peer(PeerRequest::Status { .. }) => peer_status(false),
+                Request::Data(DataRequest::Status { .. }) => ResponseResult::Success(
+                    Response::Data(DataResponse::Status(DataConnectionStatus {
+                        remote_id: "remote_id".to_string(),
+                        buffersize: 0,
+                        label: "".to_string(),
+                        metadata: "".to_string(),
+                        open: true,
+                        reliable: true,
+                        serialization: "NONE".to_string(),
+                        r#type: "DATA".to_string(),
+                    })),
+                ),
+                Request::Media(MediaRequest::Status { .. }) => ResponseResult::Success(
+                    Response::Media(MediaResponse::Status(MediaConnectionStatus {
+                        metadata: "".to_string(),
+                        open: true,
+                        remote_id: PeerId::new("remote_id"),
+                        ssrc: None,
+                    })),
+                ),
+                _ => unreachable!(),
+            })
+        });
+        repository
+            .expect_listen()
+            .with(eq(EventSource::Peer(peer_info())))
+            .times(1)
+            .returning(|_| Ok(()));
+        repository
+            .expect_listen()
+            .with(eq(EventSource::Data(data_connection_id())))
+            .times(1)
+            .returning(|_| Ok(()));
+        repository
+            .expect_listen()
+            .with(eq(EventSource::Media(media_connection_id())))
+            .times(1)
+            .returning(|_| Ok(()));
+        repository.expect_supplement_event().never();
+
+        assert_eq!(
+            resume(repository).await,
+            vec![
+                step("peer_id".to_string(), Ok(Resumed::Listening)),
+                step(DATA_CONNECTION_ID.to_string(), Ok(Resumed::Listening)),
+                step(MEDIA_CONNECTION_ID.to_string(), Ok(Resumed::Listening)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    // 存在しないPeer Objectは、属していたConnectionのCLOSEを補ってから、PEER CLOSEを補う
+    async fn closed() {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(1)
+            .returning(|_| Ok(api_error()));
+        repository.expect_listen().never();
+        let events = Arc::new(Mutex::new(vec![]));
+        let events_ref = events.clone();
+        repository
+            .expect_supplement_event()
+            .returning(move |event| {
+                events_ref.lock().unwrap().push(event);
+                Ok(())
+            });
+
+        assert_eq!(
+            resume(repository).await,
+            vec![
+                step(DATA_CONNECTION_ID.to_string(), Ok(Resumed::Closed)),
+                step(MEDIA_CONNECTION_ID.to_string(), Ok(Resumed::Closed)),
+                step("peer_id".to_string(), Ok(Resumed::Closed)),
+            ]
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ResponseResult::Success(Response::Data(DataResponse::Event(
+                    DataConnectionEventEnum::CLOSE(DataConnectionIdWrapper {
+                        data_connection_id: data_connection_id(),
+                    })
+                ))),
+                ResponseResult::Success(Response::Media(MediaResponse::Event(
+                    MediaConnectionEventEnum::CLOSE(MediaConnectionIdWrapper {
+                        media_connection_id: media_connection_id(),
+                    })
+                ))),
+                ResponseResult::Success(Response::Peer(PeerResponse::Event(PeerEventEnum::CLOSE(
+                    PeerCloseEvent {
+                        params: peer_info()
+                    }
+                )))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    // SkyWayサーバとの接続を失っているPeer Objectは、削除してから閉じたものとして扱う
+    async fn disconnected() {
+        let mut repository = MockRepository::new();
+        repository.expect_register().times(2).returning(|request| {
+            Ok(match request {
+                Request::Peer(PeerRequest::Status { .. }) => peer_status(true),
+                Request::Peer(PeerRequest::Delete { params }) => {
+                    ResponseResult::Success(Response::Peer(PeerResponse::Delete(params)))
+                }
+                _ => unreachable!(),
+            })
+        });
+        repository.expect_listen().never();
+        repository
+            .expect_supplement_event()
+            .times(3)
+            .returning(|_| Ok(()));
+
+        let steps = resume(repository).await;
+        assert_eq!(steps.len(), 3);
+        assert!(steps.iter().all(|s| s.result == Ok(Resumed::Closed)));
+    }
+
+    #[tokio::test]
+    // WebRTC Gatewayと通信できない場合は、判断できないので何もしない
+    async fn unreachable() {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_register()
+            .times(1)
+            .returning(|_| Ok(network_error()));
+        repository.expect_listen().never();
+        repository.expect_supplement_event().never();
+
+        let steps = resume(repository).await;
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].target, "peer_id");
+        assert!(matches!(
+            steps[0].result,
+            Err(error::Error::GatewayUnreachable { .. })
+        ));
+    }
+}
@@ -0,0 +1,408 @@
+// SYSTEM SHUTDOWNで、ROSを終了させる前にWebRTC Gateway上のオブジェクトを順に解放するモジュール
+// DataConnection(とPlugin) → MediaConnection → Media, Rtcp, Data Socket → Peer Objectの順に解放する
+// 全体でshutdown_timeout_msの期限を持ち、期限を過ぎてから行うはずだった解放はTIMEOUTとして失敗扱いにする
+// 1つの解放に失敗しても、残りの解放は継続する
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use shaku::{Component, Interface};
+use tokio::time::Instant;
+
+use crate::application::dto::request::{
+    DataRequestDto, MediaRequestDto, PeerRequestDto, RequestDto,
+};
+use crate::application::dto::response::{ResponseDtoResult, ShutdownStepDto, ShutdownStepKind};
+use crate::application::factory::Factory;
+use crate::application::usecase::rollback::Resource;
+use crate::domain::entity::{
+    DataConnectionIdWrapper, MediaConnectionIdWrapper, PeerInfo, SerializableSocket,
+};
+use crate::error;
+use crate::ffi::rust_to_c_bridge::state_objects::{CallbackFunctions, GlobalState};
+
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub(crate) trait ShutdownSequence: Interface {
+    /// 解放を試みた順に、オブジェクトごとの結果を返す
+    async fn execute(&self) -> Vec<ShutdownStepDto>;
+}
+
+#[derive(Component)]
+#[shaku(interface = ShutdownSequence)]
+pub(crate) struct Shutdown {
+    #[shaku(inject)]
+    state: Arc<dyn GlobalState>,
+    #[shaku(inject)]
+    factory: Arc<dyn Factory>,
+    #[shaku(inject)]
+    callback: Arc<dyn CallbackFunctions>,
+}
+
+#[async_trait]
+impl ShutdownSequence for Shutdown {
+    async fn execute(&self) -> Vec<ShutdownStepDto> {
+        let timeout_ms = self.state.config().shutdown_timeout_ms;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        let topics = self.state.list_topics();
+        let calls = self.state.list_call_responses();
+        let mut steps = vec![];
+
+        // 1. DataConnectionを切断し、C++側にPluginを破棄させる
+        for topic in &topics {
+            let request = RequestDto::Data(DataRequestDto::Disconnect {
+                params: DataConnectionIdWrapper {
+                    data_connection_id: topic.data_connection_id.clone(),
+                },
+            });
+            let target = topic.data_connection_id.as_str();
+            steps.push(
+                self.step(ShutdownStepKind::DataDisconnect, target, request, deadline)
+                    .await,
+            );
+            // 切断に失敗した場合も、終了するのでPluginは破棄する
+            if self.state.remove_topic(&topic.data_connection_id).is_some() {
+                self.callback
+                    .data_connection_deleted_callback(topic.data_pipe_port_num);
+            }
+        }
+
+        // 2. MediaConnectionを切断する
+        for call in &calls {
+            let request = RequestDto::Media(MediaRequestDto::Disconnect {
+                params: MediaConnectionIdWrapper {
+                    media_connection_id: call.media_connection_id.clone(),
+                },
+            });
+            let target = call.media_connection_id.as_str();
+            steps.push(
+                self.step(ShutdownStepKind::MediaDisconnect, target, request, deadline)
+                    .await,
+            );
+            self.state.remove_call_response(&call.media_connection_id);
+        }
+
+        // 3. Connectionに紐づけていたSocketを解放する
+        let mut resources = vec![];
+        for call in &calls {
+            let send_params = &call.send_params;
+            for pair in [&send_params.video, &send_params.audio]
+                .into_iter()
+                .flatten()
+            {
+                if let Some(media_id) = pair.media.get_id() {
+                    resources.push((ShutdownStepKind::MediaDelete, Resource::Media(media_id)));
+                }
+                if let Some(rtcp_id) = pair.rtcp.get_id() {
+                    resources.push((ShutdownStepKind::RtcpDelete, Resource::Rtcp(rtcp_id)));
+                }
+            }
+        }
+        for topic in &topics {
+            resources.push((
+                ShutdownStepKind::DataDelete,
+                Resource::Data(topic.data_id.clone()),
+            ));
+        }
+        for (kind, resource) in resources {
+            let target = resource.id();
+            let request = resource.delete_request();
+            steps.push(self.step(kind, &target, request, deadline).await);
+        }
+
+        // 4. Peer Objectを削除する
+        let peers = self.state.peer_registry().lock().unwrap().list();
+        for peer in peers {
+            let target = peer.peer_id.as_str().to_string();
+            let request = RequestDto::Peer(PeerRequestDto::Delete {
+                params: PeerInfo::new(peer.peer_id, peer.token),
+            });
+            steps.push(
+                self.step(ShutdownStepKind::PeerDelete, &target, request, deadline)
+                    .await,
+            );
+        }
+
+        steps
+    }
+}
+
+impl Shutdown {
+    // 1つのオブジェクトを解放する。成功した場合は、call_serviceと同様に状態の記録に反映する
+    async fn step(
+        &self,
+        step: ShutdownStepKind,
+        target: &str,
+        request: RequestDto,
+        deadline: Instant,
+    ) -> ShutdownStepDto {
+        let timeout = error::Error::Timeout {
+            timeout_ms: self.state.config().shutdown_timeout_ms,
+        };
+        // 期限を過ぎた後は、WebRTC Gatewayにリクエストを送らずに失敗とする
+        let result = if Instant::now() >= deadline {
+            Err(timeout)
+        } else {
+            let service = self.factory.create_service(&request);
+            match tokio::time::timeout_at(deadline, service.execute(request.clone())).await {
+                Ok(Ok(ResponseDtoResult::Error(message))) => {
+                    Err(error::Error::gateway_api_error(message))
+                }
+                Ok(Ok(response)) => {
+                    // PEER CLOSEによる自動復旧が始まらないよう、明示的に削除したことを記録する
+                    self.state
+                        .recovery_state()
+                        .lock()
+                        .unwrap()
+                        .record(&request, &response);
+                    self.state
+                        .peer_registry()
+                        .lock()
+                        .unwrap()
+                        .record(&request, &response);
+                    Ok(())
+                }
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(timeout),
+            }
+        };
+
+        ShutdownStepDto {
+            step,
+            target: target.to_string(),
+            is_success: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod shutdown_test {
+    use std::sync::Mutex;
+
+    use once_cell::sync::Lazy;
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::response::{
+        CallResponseDto, MediaPair, PeerResponseDto, ResponseDto, SendParams,
+    };
+    use crate::application::factory::MockFactory;
+    use crate::application::usecase::peer::recovery::RecoveryState;
+    use crate::application::usecase::MockService;
+    use crate::config::Config;
+    use crate::di::ShutdownService;
+    use crate::domain::entity::SerializableId;
+    use crate::domain::entity::{
+        DataConnectionId, DataId, MediaConnectionId, MediaId, RtcpId, SocketInfo,
+    };
+    use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
+    use crate::ffi::rust_to_c_bridge::state_objects::{
+        MockCallbackFunctions, MockGlobalState, PeerRegistry,
+    };
+
+    static CONFIG: Lazy<Config> = Lazy::new(|| Config {
+        shutdown_timeout_ms: 50,
+        ..Config::default()
+    });
+
+    fn peer_info() -> PeerInfo {
+        PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap()
+    }
+
+    fn topic() -> DataPipeInfo {
+        DataPipeInfo {
+            data_connection_id: DataConnectionId::try_create(
+                "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+            )
+            .unwrap(),
+            data_pipe_port_num: 60000,
+            data_id: DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+            plugin_type: "binary".to_string(),
+            plugins: vec![],
+            remote_peer_id: None,
+        }
+    }
+
+    // 映像のみを送信しているMediaConnection
+    fn call_response() -> CallResponseDto {
+        let media = SocketInfo::<MediaId>::try_create(
+            Some("vi-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+            "127.0.0.1",
+            10000,
+        )
+        .unwrap();
+        let rtcp = SocketInfo::<RtcpId>::try_create(
+            Some("rc-4d053831-5dc2-461b-a358-d062d6115216".to_string()),
+            "127.0.0.1",
+            10001,
+        )
+        .unwrap();
+        CallResponseDto {
+            send_params: SendParams {
+                video: Some(MediaPair { media, rtcp }),
+                audio: None,
+            },
+            redirect_params: None,
+            media_connection_id: MediaConnectionId::try_create(
+                "mc-102127d9-30de-413b-93f7-41a33e39d82b",
+            )
+            .unwrap(),
+            remote_peer_id: None,
+        }
+    }
+
+    fn state() -> MockGlobalState {
+        let mut registry = PeerRegistry::default();
+        registry.insert_peer(&peer_info());
+        let registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(registry)));
+        let recovery_state: &'static std::sync::Mutex<RecoveryState> =
+            Box::leak(Box::new(std::sync::Mutex::new(RecoveryState::default())));
+
+        let mut state = MockGlobalState::new();
+        state.expect_config().returning(|| &CONFIG);
+        state.expect_list_topics().returning(|| vec![topic()]);
+        state
+            .expect_list_call_responses()
+            .returning(|| vec![call_response()]);
+        state
+            .expect_remove_topic()
+            .times(1)
+            .returning(|_| Some(topic()));
+        state
+            .expect_remove_call_response()
+            .times(1)
+            .returning(|_| Some(call_response()));
+        state.expect_peer_registry().returning(move || registry);
+        state
+            .expect_recovery_state()
+            .returning(move || recovery_state);
+        state
+    }
+
+    fn step(kind: ShutdownStepKind, target: &str, error: Option<error::Error>) -> ShutdownStepDto {
+        ShutdownStepDto {
+            step: kind,
+            target: target.to_string(),
+            is_success: error.is_none(),
+            error,
+        }
+    }
+
+    #[tokio::test]
+    // Connection, Socket, Peer Objectの順に解放し、Pluginを破棄させる
+    async fn release_in_order() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requests_ref = requests.clone();
+        let mut factory = MockFactory::new();
+        factory
+            .expect_create_service()
+            .times(6)
+            .returning(move |_| {
+                let requests_ref = requests_ref.clone();
+                let mut service = MockService::new();
+                service.expect_execute().returning(move |request| {
+                    requests_ref.lock().unwrap().push(request);
+                    // 記録の更新に利用されるだけなので、内容は問わない
+                    Ok(ResponseDtoResult::Success(ResponseDto::Peer(
+                        PeerResponseDto::Delete(peer_info()),
+                    )))
+                });
+                Arc::new(service)
+            });
+
+        let mut callback = MockCallbackFunctions::new();
+        callback
+            .expect_data_connection_deleted_callback()
+            .withf(|port| *port == 60000)
+            .times(1)
+            .returning(|_| ());
+
+        let module = ShutdownService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(callback))
+            .build();
+        let shutdown: &dyn ShutdownSequence = module.resolve_ref();
+        let steps = shutdown.execute().await;
+
+        assert_eq!(
+            steps,
+            vec![
+                step(
+                    ShutdownStepKind::DataDisconnect,
+                    "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+                    None
+                ),
+                step(
+                    ShutdownStepKind::MediaDisconnect,
+                    "mc-102127d9-30de-413b-93f7-41a33e39d82b",
+                    None
+                ),
+                step(
+                    ShutdownStepKind::MediaDelete,
+                    "vi-4d053831-5dc2-461b-a358-d062d6115216",
+                    None
+                ),
+                step(
+                    ShutdownStepKind::RtcpDelete,
+                    "rc-4d053831-5dc2-461b-a358-d062d6115216",
+                    None
+                ),
+                step(
+                    ShutdownStepKind::DataDelete,
+                    "da-50a32bab-b3d9-4913-8e20-f79c90a6a211",
+                    None
+                ),
+                step(ShutdownStepKind::PeerDelete, "peer_id", None),
+            ]
+        );
+        assert_eq!(
+            requests.lock().unwrap().last(),
+            Some(&RequestDto::Peer(PeerRequestDto::Delete {
+                params: peer_info()
+            }))
+        );
+    }
+
+    #[tokio::test]
+    // 期限を過ぎた後の解放は行わずに失敗として扱い、Pluginの破棄は継続する
+    async fn deadline() {
+        let mut factory = MockFactory::new();
+        factory.expect_create_service().times(1).returning(|_| {
+            let mut service = MockService::new();
+            service.expect_execute().returning(|_| {
+                std::thread::sleep(Duration::from_millis(60));
+                Ok(ResponseDtoResult::Error("error".to_string()))
+            });
+            Arc::new(service)
+        });
+
+        let mut callback = MockCallbackFunctions::new();
+        callback
+            .expect_data_connection_deleted_callback()
+            .times(1)
+            .returning(|_| ());
+
+        let module = ShutdownService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .with_component_override::<dyn Factory>(Box::new(factory))
+            .with_component_override::<dyn CallbackFunctions>(Box::new(callback))
+            .build();
+        let shutdown: &dyn ShutdownSequence = module.resolve_ref();
+        let steps = shutdown.execute().await;
+
+        assert_eq!(steps.len(), 6);
+        assert_eq!(
+            steps[0].error,
+            Some(error::Error::gateway_api_error("error"))
+        );
+        for step in &steps[1..] {
+            assert_eq!(step.error, Some(error::Error::Timeout { timeout_ms: 50 }));
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// WebRTC GatewayのREST APIを模擬するHTTPサーバを起動する
+// WebRTC GatewayやSkyWayのシグナリングサーバに接続できない環境で、rust_moduleを動作させるために利用する
+// 振る舞いはScenarioのJSONファイルで指定し、省略した場合は全ての接続が成功する
+//
+// $ cargo run --bin fake_gateway -- [port] [scenario.json]
+use skyway::fake_gateway::{FakeGateway, Scenario};
+
+const DEFAULT_PORT: u16 = 8000;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let port = match args.next() {
+        Some(port) => port.parse().expect("port must be a number"),
+        None => DEFAULT_PORT,
+    };
+    let scenario = match args.next() {
+        Some(path) => {
+            let json = std::fs::read_to_string(&path).expect("failed to read scenario");
+            serde_json::from_str::<Scenario>(&json).expect("invalid scenario")
+        }
+        None => Scenario::default(),
+    };
+
+    let gateway = FakeGateway::start(port, scenario)
+        .await
+        .expect("failed to start fake gateway");
+    println!("fake gateway is listening on {}", gateway.base_url());
+
+    let _ = tokio::signal::ctrl_c().await;
+}
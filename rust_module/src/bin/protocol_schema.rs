@@ -0,0 +1,7 @@
+// SkyWayControl, SkyWayEventsで送受信するJSONのSchemaを標準出力に書き出す
+// ROSを起動せずに、クライアントの実装やドキュメントの確認に利用できる
+//
+// $ cargo run --bin protocol_schema > protocol_schema.json
+fn main() {
+    println!("{}", skyway::protocol_schema());
+}
@@ -0,0 +1,27 @@
+// ROSを使わずにSkyWay for ROSを起動する
+// SkyWayControlへのリクエストを1行ずつ受け取ってレスポンスを返し、SkyWayEventsのイベントを1行ずつ書き出す
+// ログは標準エラー出力に書き出し、SIGINTまたはSIGTERMを受けるとPeer Objectを削除してから終了する
+//
+// $ cargo run --bin skyway_host -- [--config <json>] [--socket <path>]
+use skyway::host::{self, Transport};
+
+fn main() {
+    let mut config = None;
+    let mut transport = Transport::Stdio;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = Some(args.next().expect("--config requires a json string")),
+            "--socket" => {
+                let path = args.next().expect("--socket requires a path");
+                transport = Transport::UnixSocket(path.into());
+            }
+            _ => panic!("unknown argument: {}", arg),
+        }
+    }
+
+    if let Err(e) = host::run(config.as_deref(), transport) {
+        eprintln!("[ERROR] {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,397 @@
+// rust_module全体の起動パラメータ
+// C++側から`crate::ffi::c_to_rust_bridge::run_with_config`経由でJSONとして与えられる
+// 起動前に全ての値をチェックし、不正な値が含まれる場合は起動させない
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+
+const DEFAULT_GATEWAY_URL: &str = "http://localhost:8000";
+const DEFAULT_REQUEST_QUEUE_SIZE: usize = 10;
+const DEFAULT_EVENT_QUEUE_SIZE: usize = 1000;
+const DEFAULT_EVENT_TIMEOUT_MS: u64 = 1000;
+const DEFAULT_RECONNECT_INITIAL_BACKOFF_MS: u64 = 500;
+const DEFAULT_RECONNECT_MAX_BACKOFF_MS: u64 = 30000;
+const DEFAULT_RECONNECT_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 10000;
+const DEFAULT_PEER_OPEN_TIMEOUT_MS: u64 = 10000;
+const DEFAULT_SHUTDOWN_TIMEOUT_MS: u64 = 10000;
+const DEFAULT_PEER_RECOVERY_INITIAL_BACKOFF_MS: u64 = 1000;
+const DEFAULT_PEER_RECOVERY_MAX_BACKOFF_MS: u64 = 30000;
+const DEFAULT_PEER_RECOVERY_MAX_ATTEMPTS: u32 = 10;
+const DEFAULT_PEER_RECOVERY_CLOSE_GRACE_MS: u64 = 3000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// WebRTC GatewayのURL。`http://`または`https://`で始まる必要がある
+    pub gateway_url: String,
+    /// WebRTC Gatewayへの操作要求を溜めておくキューの長さ
+    pub request_queue_size: usize,
+    /// WebRTC Gatewayから受け取ったイベントを溜めておくキューの長さ
+    /// 購読ごとに受け取られていないイベントを溜めておける数でもある
+    pub event_queue_size: usize,
+    /// receive_eventsでイベントを待つ際に、ROSの終了を確認する間隔
+    pub event_timeout_ms: u64,
+    /// SkyWay Crateとの通信が途絶えた際に、再起動を試みるまでの待ち時間の初期値
+    /// 再起動に失敗するたびに倍になる
+    pub reconnect_initial_backoff_ms: u64,
+    /// 再起動を試みるまでの待ち時間の上限
+    pub reconnect_max_backoff_ms: u64,
+    /// WebRTC Gatewayに到達できない応答や、イベント取得の失敗がこの回数連続した場合もSkyWay Crateを再起動する
+    pub reconnect_failure_threshold: u32,
+    /// WebRTC Gatewayへの操作要求の応答を待つ期限
+    pub request_timeout: RequestTimeoutConfig,
+    /// PEER CREATEで、Peer ObjectのOPENイベントを受信して応答するまでの期限
+    pub peer_open_timeout_ms: u64,
+    /// SYSTEM SHUTDOWNで、WebRTC Gateway上のオブジェクトの解放に費やせる時間の上限
+    /// 期限を過ぎた場合は残りの解放を諦めてROSを終了させる
+    pub shutdown_timeout_ms: u64,
+    /// PEER CLOSE時の自動復旧の設定
+    pub peer_recovery: PeerRecoveryConfig,
+    /// 状態のスナップショットを書き出すJSONファイルのパス
+    /// 指定した場合のみ、起動時に前回の状態を読み込み、WebRTC Gateway上に残っているオブジェクトと突き合わせる
+    pub snapshot_path: Option<String>,
+    /// SYSTEM METRICSの内容をPrometheusのテキスト形式で公開するポート
+    /// 指定した場合のみ、127.0.0.1のこのポートで`GET /metrics`を受け付ける
+    pub metrics_port: Option<u16>,
+    /// WebRTC Gatewayとのやり取りを記録するJSON Linesファイルのパス
+    /// 指定した場合のみ、全てのリクエスト、応答、イベントを時刻とともに追記する
+    pub record_path: Option<String>,
+}
+
+/// WebRTC Gatewayへの操作要求の応答を待つ期限をrequest_typeごとに設定する
+/// リクエストに`timeout_ms`が含まれている場合は、そちらが優先される
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RequestTimeoutConfig {
+    pub peer_ms: u64,
+    pub data_ms: u64,
+    pub media_ms: u64,
+}
+
+impl Default for RequestTimeoutConfig {
+    fn default() -> Self {
+        RequestTimeoutConfig {
+            peer_ms: DEFAULT_REQUEST_TIMEOUT_MS,
+            data_ms: DEFAULT_REQUEST_TIMEOUT_MS,
+            media_ms: DEFAULT_REQUEST_TIMEOUT_MS,
+        }
+    }
+}
+
+/// PEER CLOSEを受信した際に、Peer Objectを再生成し、DataConnection, MediaConnectionを張り直す機能の設定
+/// 明示的に有効化した場合のみ動作する
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PeerRecoveryConfig {
+    /// trueの場合のみ自動復旧を行う
+    pub enabled: bool,
+    /// Peer Objectの再生成を試みるまでの待ち時間の初期値
+    /// 再生成に失敗するたびに倍になる
+    pub initial_backoff_ms: u64,
+    /// 再生成を試みるまでの待ち時間の上限
+    pub max_backoff_ms: u64,
+    /// Peer Objectの再生成を試みる回数の上限
+    pub max_attempts: u32,
+    /// PEER CLOSEのこの時間前までに閉じたDataConnection, MediaConnectionは、
+    /// Peer ObjectのCLOSEに伴って閉じたものとみなして張り直す
+    pub close_grace_ms: u64,
+}
+
+impl Default for PeerRecoveryConfig {
+    fn default() -> Self {
+        PeerRecoveryConfig {
+            enabled: false,
+            initial_backoff_ms: DEFAULT_PEER_RECOVERY_INITIAL_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_PEER_RECOVERY_MAX_BACKOFF_MS,
+            max_attempts: DEFAULT_PEER_RECOVERY_MAX_ATTEMPTS,
+            close_grace_ms: DEFAULT_PEER_RECOVERY_CLOSE_GRACE_MS,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            gateway_url: DEFAULT_GATEWAY_URL.to_string(),
+            request_queue_size: DEFAULT_REQUEST_QUEUE_SIZE,
+            event_queue_size: DEFAULT_EVENT_QUEUE_SIZE,
+            event_timeout_ms: DEFAULT_EVENT_TIMEOUT_MS,
+            reconnect_initial_backoff_ms: DEFAULT_RECONNECT_INITIAL_BACKOFF_MS,
+            reconnect_max_backoff_ms: DEFAULT_RECONNECT_MAX_BACKOFF_MS,
+            reconnect_failure_threshold: DEFAULT_RECONNECT_FAILURE_THRESHOLD,
+            request_timeout: RequestTimeoutConfig::default(),
+            peer_open_timeout_ms: DEFAULT_PEER_OPEN_TIMEOUT_MS,
+            shutdown_timeout_ms: DEFAULT_SHUTDOWN_TIMEOUT_MS,
+            peer_recovery: PeerRecoveryConfig::default(),
+            snapshot_path: None,
+            metrics_port: None,
+            record_path: None,
+        }
+    }
+}
+
+impl Config {
+    /// JSONからConfigを生成する
+    /// 省略された項目はデフォルト値で補い、不正な値が含まれている場合は理由を示すエラーを返す
+    pub fn try_create(json: &str) -> Result<Self, error::Error> {
+        let config = serde_json::from_str::<Config>(json)
+            .map_err(|e| error::Error::invalid_request(format!("invalid config: {}", e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), error::Error> {
+        let host = self
+            .gateway_url
+            .strip_prefix("http://")
+            .or_else(|| self.gateway_url.strip_prefix("https://"));
+        match host {
+            Some(host) if !host.is_empty() && !host.starts_with('/') => {}
+            _ => {
+                let message = format!(
+                    "invalid config: gateway_url must be http://host:port or https://host:port, but got {:?}",
+                    self.gateway_url
+                );
+                return Err(error::Error::invalid_request(message));
+            }
+        }
+
+        if self.request_queue_size == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: request_queue_size must be greater than 0",
+            ));
+        }
+        if self.event_queue_size == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: event_queue_size must be greater than 0",
+            ));
+        }
+        if self.event_timeout_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: event_timeout_ms must be greater than 0",
+            ));
+        }
+        if self.reconnect_initial_backoff_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: reconnect_initial_backoff_ms must be greater than 0",
+            ));
+        }
+        if self.reconnect_max_backoff_ms < self.reconnect_initial_backoff_ms {
+            return Err(error::Error::invalid_request(
+                "invalid config: reconnect_max_backoff_ms must not be less than reconnect_initial_backoff_ms",
+            ));
+        }
+        if self.reconnect_failure_threshold == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: reconnect_failure_threshold must be greater than 0",
+            ));
+        }
+        let timeout = &self.request_timeout;
+        if timeout.peer_ms == 0 || timeout.data_ms == 0 || timeout.media_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: request_timeout values must be greater than 0",
+            ));
+        }
+        if self.peer_open_timeout_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: peer_open_timeout_ms must be greater than 0",
+            ));
+        }
+        if self.shutdown_timeout_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: shutdown_timeout_ms must be greater than 0",
+            ));
+        }
+        if self.peer_recovery.initial_backoff_ms == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: peer_recovery.initial_backoff_ms must be greater than 0",
+            ));
+        }
+        if self.peer_recovery.max_backoff_ms < self.peer_recovery.initial_backoff_ms {
+            return Err(error::Error::invalid_request(
+                "invalid config: peer_recovery.max_backoff_ms must not be less than peer_recovery.initial_backoff_ms",
+            ));
+        }
+        if self.peer_recovery.max_attempts == 0 {
+            return Err(error::Error::invalid_request(
+                "invalid config: peer_recovery.max_attempts must be greater than 0",
+            ));
+        }
+        if self.snapshot_path.as_deref() == Some("") {
+            return Err(error::Error::invalid_request(
+                "invalid config: snapshot_path must not be empty",
+            ));
+        }
+        if self.record_path.as_deref() == Some("") {
+            return Err(error::Error::invalid_request(
+                "invalid config: record_path must not be empty",
+            ));
+        }
+        if self.metrics_port == Some(0) {
+            return Err(error::Error::invalid_request(
+                "invalid config: metrics_port must be greater than 0",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+
+    #[test]
+    fn empty_json() {
+        // 全ての項目を省略した場合はデフォルト値になる
+        let config = Config::try_create("{}").unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn override_values() {
+        let message = r#"{
+            "gateway_url": "http://192.168.0.10:8001",
+            "request_queue_size": 20,
+            "event_queue_size": 50,
+            "event_timeout_ms": 300,
+            "reconnect_initial_backoff_ms": 100,
+            "reconnect_max_backoff_ms": 1000,
+            "reconnect_failure_threshold": 5,
+            "peer_open_timeout_ms": 5000,
+            "snapshot_path": "/tmp/skyway_state.json",
+            "metrics_port": 9464,
+            "record_path": "/tmp/skyway_record.jsonl"
+        }"#;
+        let config = Config::try_create(message).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                gateway_url: "http://192.168.0.10:8001".to_string(),
+                request_queue_size: 20,
+                event_queue_size: 50,
+                event_timeout_ms: 300,
+                reconnect_initial_backoff_ms: 100,
+                reconnect_max_backoff_ms: 1000,
+                reconnect_failure_threshold: 5,
+                request_timeout: RequestTimeoutConfig::default(),
+                peer_open_timeout_ms: 5000,
+                shutdown_timeout_ms: DEFAULT_SHUTDOWN_TIMEOUT_MS,
+                peer_recovery: PeerRecoveryConfig::default(),
+                snapshot_path: Some("/tmp/skyway_state.json".to_string()),
+                metrics_port: Some(9464),
+                record_path: Some("/tmp/skyway_record.jsonl".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn peer_recovery() {
+        // 省略した項目はデフォルト値で補われる
+        let config =
+            Config::try_create(r#"{"peer_recovery": {"enabled": true, "max_attempts": 3}}"#)
+                .unwrap();
+        assert_eq!(
+            config.peer_recovery,
+            PeerRecoveryConfig {
+                enabled: true,
+                max_attempts: 3,
+                ..PeerRecoveryConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn request_timeout() {
+        let config = Config::try_create(r#"{"request_timeout": {"peer_ms": 30000}}"#).unwrap();
+        assert_eq!(
+            config.request_timeout,
+            RequestTimeoutConfig {
+                peer_ms: 30000,
+                ..RequestTimeoutConfig::default()
+            }
+        );
+
+        let result = Config::try_create(r#"{"request_timeout": {"data_ms": 0}}"#);
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert_eq!(
+                message,
+                "invalid config: request_timeout values must be greater than 0"
+            );
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn invalid_peer_recovery() {
+        let result = Config::try_create(r#"{"peer_recovery": {"max_attempts": 0}}"#);
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert_eq!(
+                message,
+                "invalid config: peer_recovery.max_attempts must be greater than 0"
+            );
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn invalid_json() {
+        let result = Config::try_create("gateway_url");
+        assert!(matches!(result, Err(error::Error::InvalidRequest { .. })));
+    }
+
+    #[test]
+    fn unknown_field() {
+        // typoに気づけるよう、未知の項目はエラーにする
+        let result = Config::try_create(r#"{"gateway": "http://localhost:8000"}"#);
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert!(message.starts_with("invalid config: unknown field `gateway`"));
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn invalid_url() {
+        let result = Config::try_create(r#"{"gateway_url": "localhost:8000"}"#);
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert_eq!(
+                message,
+                "invalid config: gateway_url must be http://host:port or https://host:port, but got \"localhost:8000\""
+            );
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn invalid_backoff() {
+        let result = Config::try_create(
+            r#"{"reconnect_initial_backoff_ms": 1000, "reconnect_max_backoff_ms": 500}"#,
+        );
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert_eq!(
+                message,
+                "invalid config: reconnect_max_backoff_ms must not be less than reconnect_initial_backoff_ms"
+            );
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn zero_queue_size() {
+        let result = Config::try_create(r#"{"event_queue_size": 0}"#);
+        if let Err(error::Error::InvalidRequest { message }) = result {
+            assert_eq!(
+                message,
+                "invalid config: event_queue_size must be greater than 0"
+            );
+        } else {
+            unreachable!();
+        }
+    }
+}
@@ -1,13 +1,21 @@
 use shaku::module;
 
 use crate::application::factory::FactoryImpl;
+use crate::application::usecase::batch::Batch;
 use crate::application::usecase::data::connect::Connect;
+use crate::application::usecase::data::list::DataList;
 use crate::application::usecase::data::redirect::Redirect;
 use crate::application::usecase::event;
 use crate::application::usecase::general::service::General;
 use crate::application::usecase::media::answer::AnswerService;
 use crate::application::usecase::media::call::Call;
+use crate::application::usecase::media::list::MediaList;
 use crate::application::usecase::peer::create::Create;
+use crate::application::usecase::peer::list::List;
+use crate::application::usecase::peer::recovery::Recovery;
+use crate::application::usecase::system::reconcile::Reconcile;
+use crate::application::usecase::system::resume::Resume;
+use crate::application::usecase::system::shutdown::Shutdown;
 use crate::application::usecase::system::System;
 use crate::ffi::rust_to_c_bridge::state_objects::{
     CallbackFunctionsImpl, GlobalStateImpl, LoggerImpl, ProgramStateImpl,
@@ -44,7 +52,35 @@ module! {
 
 module! {
     pub(crate) SystemService {
-        components = [System],
+        components = [System, Shutdown, ProgramStateImpl, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl, LoggerImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) ShutdownService {
+        components = [Shutdown, GlobalStateImpl, FactoryImpl, CallbackFunctionsImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) BatchService {
+        components = [Batch, GlobalStateImpl, FactoryImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) ReconcileService {
+        components = [Reconcile, GlobalStateImpl, FactoryImpl, RepositoryImpl, CallbackFunctionsImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) ResumeService {
+        components = [Resume, GlobalStateImpl, RepositoryImpl],
         providers = []
     }
 }
@@ -56,37 +92,72 @@ module! {
     }
 }
 
+module! {
+    pub(crate) PeerListService {
+        components = [List, GlobalStateImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) PeerRecoveryService {
+        components = [Recovery, GlobalStateImpl, RepositoryImpl, FactoryImpl],
+        providers = []
+    }
+}
+
 module! {
     pub(crate) DataConnectService {
-        components = [Connect, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl],
+        components = [Connect, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl, LoggerImpl],
         providers = []
     }
 }
 
 module! {
     pub(crate) DataRedirectService {
-        components = [Redirect, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl],
+        components = [Redirect, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl, LoggerImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) DataListService {
+        components = [DataList, GlobalStateImpl, RepositoryImpl],
         providers = []
     }
 }
 
 module! {
     pub(crate) MediaCallService {
-        components = [Call, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl],
+        components = [Call, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl, LoggerImpl],
         providers = []
     }
 }
 
 module! {
     pub(crate) MediaAnswerService {
-        components = [AnswerService, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl],
+        components = [AnswerService, GlobalStateImpl, RepositoryImpl, FactoryImpl, CallbackFunctionsImpl, LoggerImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) MediaListService {
+        components = [MediaList, GlobalStateImpl, RepositoryImpl],
         providers = []
     }
 }
 
 module! {
     pub(crate) EventReceiveService {
-        components = [event::EventReceiveImpl, CallbackFunctionsImpl, GlobalStateImpl, RepositoryImpl, LoggerImpl],
+        components = [event::EventReceiveImpl, Resume, CallbackFunctionsImpl, GlobalStateImpl, RepositoryImpl, LoggerImpl],
+        providers = []
+    }
+}
+
+module! {
+    pub(crate) EventSubscriptionService {
+        components = [event::subscription::EventSubscription, GlobalStateImpl],
         providers = []
     }
 }
@@ -0,0 +1,107 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::application::dto::schema;
+use crate::domain::entity::response::ResponseResult;
+use crate::domain::entity::{DataConnectionId, MediaConnectionId, PeerId, PeerInfo};
+use crate::error;
+
+/// WebRTC Gatewayではなく、rust_module自身が生成するイベント
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "event")]
+pub(crate) enum SystemEvent {
+    /// SkyWay Crateとの通信が途絶えた
+    #[serde(rename = "GATEWAY_DISCONNECTED")]
+    GatewayDisconnected,
+    /// SkyWay Crateを再起動し、通信が再開された
+    /// attemptsは再起動を試みた回数
+    #[serde(rename = "GATEWAY_RECONNECTED")]
+    GatewayReconnected { attempts: u32 },
+    /// PEER CLOSEを受信したので、自動復旧を開始した
+    #[serde(rename = "PEER_RECOVERY_STARTED")]
+    PeerRecoveryStarted {
+        #[schemars(with = "schema::PeerId")]
+        peer_id: PeerId,
+    },
+    /// Peer Objectの再生成に失敗したので、待ち時間をおいて再試行する
+    /// attemptsは失敗した回数
+    #[serde(rename = "PEER_RECOVERY_RETRY")]
+    PeerRecoveryRetry { attempts: u32, error: error::Error },
+    /// Peer Objectを再生成した。以降は新しいtokenを利用する必要がある
+    #[serde(rename = "PEER_RECOVERY_PEER_CREATED")]
+    PeerRecoveryPeerCreated {
+        #[schemars(with = "schema::PeerInfo")]
+        params: PeerInfo,
+    },
+    /// 復旧前に確立していたDataConnectionを張り直した
+    #[serde(rename = "PEER_RECOVERY_DATA_CONNECTED")]
+    PeerRecoveryDataConnected {
+        #[schemars(with = "schema::DataConnectionId")]
+        previous_data_connection_id: DataConnectionId,
+        #[schemars(with = "schema::DataConnectionId")]
+        data_connection_id: DataConnectionId,
+    },
+    /// DataConnectionの張り直しに失敗した
+    #[serde(rename = "PEER_RECOVERY_DATA_CONNECT_FAILED")]
+    PeerRecoveryDataConnectFailed {
+        #[schemars(with = "schema::DataConnectionId")]
+        previous_data_connection_id: DataConnectionId,
+        error: error::Error,
+    },
+    /// 復旧前に確立していたMediaConnectionを張り直した
+    #[serde(rename = "PEER_RECOVERY_MEDIA_CALLED")]
+    PeerRecoveryMediaCalled {
+        #[schemars(with = "schema::MediaConnectionId")]
+        previous_media_connection_id: MediaConnectionId,
+        #[schemars(with = "schema::MediaConnectionId")]
+        media_connection_id: MediaConnectionId,
+    },
+    /// MediaConnectionの張り直しに失敗した
+    #[serde(rename = "PEER_RECOVERY_MEDIA_CALL_FAILED")]
+    PeerRecoveryMediaCallFailed {
+        #[schemars(with = "schema::MediaConnectionId")]
+        previous_media_connection_id: MediaConnectionId,
+        error: error::Error,
+    },
+    /// 全ての復旧処理を終えた
+    #[serde(rename = "PEER_RECOVERY_COMPLETED")]
+    PeerRecoveryCompleted {
+        #[schemars(with = "schema::PeerId")]
+        peer_id: PeerId,
+    },
+    /// Peer Objectを再生成できなかったので、復旧を断念した
+    #[serde(rename = "PEER_RECOVERY_FAILED")]
+    PeerRecoveryFailed {
+        #[schemars(with = "schema::PeerId")]
+        peer_id: PeerId,
+        error: error::Error,
+    },
+    /// 購読がイベントを受け取らないうちにキューが溢れたので、古いイベントを破棄した
+    /// countは破棄したイベントの数
+    #[serde(rename = "EVENTS_DROPPED")]
+    EventsDropped { subscription_id: String, count: u64 },
+}
+
+/// Channels経由で受け取るイベント
+/// WebRTC Gatewayのイベントはパース前のJSON文字列のまま中継する
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EventMessage {
+    Gateway(String),
+    System(SystemEvent),
+}
+
+/// Repository::listenでイベントの監視を開始するオブジェクト
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EventSource {
+    Peer(PeerInfo),
+    Data(DataConnectionId),
+    Media(MediaConnectionId),
+}
+
+/// Repository::receive_eventの戻り値
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Event {
+    Gateway(ResponseResult),
+    System(SystemEvent),
+}
@@ -1,3 +1,4 @@
+pub(crate) mod event;
 pub(crate) mod request;
 pub(crate) mod response;
 
@@ -21,6 +22,7 @@ pub(crate) trait Stringify {
 }
 
 // 自然にStrからメッセージに変換できるようにする
+#[allow(dead_code)]
 pub(crate) trait FromStr: Sized {
     fn from_str(raw_message: &str) -> Result<Self, error::Error>;
 }
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -35,7 +36,7 @@ pub(crate) enum DataRequest {
     Status { params: DataConnectionIdWrapper },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub(crate) struct IsVideo {
     pub(crate) is_video: bool,
 }
@@ -69,6 +70,7 @@ pub(crate) enum MediaRequest {
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum Request {
     #[serde(rename = "PEER")]
     Peer(PeerRequest),
@@ -81,13 +83,13 @@ pub(crate) enum Request {
 #[allow(dead_code)]
 impl Stringify for Request {
     fn to_string(&self) -> Result<String, error::Error> {
-        return serde_json::to_string(self).map_err(|e| error::Error::SerdeError { error: e });
+        serde_json::to_string(self).map_err(|e| error::Error::internal(e.to_string()))
     }
 }
 
 #[allow(dead_code)]
 impl FromStr for Request {
     fn from_str(raw_message: &str) -> Result<Self, error::Error> {
-        serde_json::from_str(raw_message).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::from_str(raw_message).map_err(|e| error::Error::invalid_request(e.to_string()))
     }
 }
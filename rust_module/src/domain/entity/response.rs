@@ -8,16 +8,26 @@ pub(crate) use skyway_webrtc_gateway_caller::prelude::response_parser::{
 use crate::domain::entity::{FromStr, Stringify};
 use crate::error;
 
+/// WebRTC Gatewayが返したエラーではなく、HTTPの通信自体に失敗したことを示すエラーメッセージかどうか
+/// SkyWay CrateがHTTPの通信自体に失敗した場合はreasonがNetworkErrorになる
+pub(crate) fn is_network_error(message: &str) -> bool {
+    let reason = serde_json::from_str::<serde_json::Value>(message)
+        .ok()
+        .and_then(|value| value["reason"].as_str().map(|s| s.to_string()));
+    reason.as_deref() == Some("NetworkError")
+}
+
 #[allow(dead_code)]
 impl Stringify for ResponseResult {
     fn to_string(&self) -> Result<String, error::Error> {
-        return serde_json::to_string(self).map_err(|e| error::Error::SerdeError { error: e });
+        serde_json::to_string(self).map_err(|e| error::Error::internal(e.to_string()))
     }
 }
 
 #[allow(dead_code)]
 impl FromStr for ResponseResult {
     fn from_str(raw_message: &str) -> Result<Self, error::Error> {
-        serde_json::from_str(raw_message).map_err(|e| error::Error::SerdeError { error: e })
+        serde_json::from_str(raw_message)
+            .map_err(|e| error::Error::gateway_api_error(e.to_string()))
     }
 }
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use shaku::Interface;
 
+use crate::domain::entity::event::{Event, EventSource, SystemEvent};
 use crate::domain::entity::request::Request;
 use crate::domain::entity::response::ResponseResult;
 use crate::error;
@@ -8,12 +9,31 @@ use crate::error;
 #[cfg(test)]
 use mockall::automock;
 
+tokio::task_local! {
+    /// call_serviceで受け取ったリクエストに`timeout_ms`が指定されていた場合、
+    /// その処理の中で呼ばれるregisterは、設定値の代わりにこの期限を用いる
+    pub(crate) static REQUEST_TIMEOUT: Option<std::time::Duration>;
+    /// call_serviceで処理中のリクエストのrequest_id
+    /// この処理の中で出力するログと、registerで生成されたオブジェクトのイベントに付与する
+    pub(crate) static REQUEST_ID: String;
+}
+
 /// skyway_webrtc_gateway_callerを利用するためのtrait定義
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub(crate) trait Repository: Interface {
     /// APIを能動的に呼ぶためのメソッド
+    /// 期限内に応答が得られない場合は`error::Error::Timeout`を返す
     async fn register(&self, params: Request) -> Result<ResponseResult, error::Error>;
     /// イベントを監視するためのメソッド
-    async fn receive_event(&self) -> Result<ResponseResult, error::Error>;
+    async fn receive_event(&self) -> Result<Event, error::Error>;
+    /// rust_module自身が生成したイベントを、receive_eventで受け取れるようにするためのメソッド
+    async fn publish_event(&self, event: SystemEvent) -> Result<(), error::Error>;
+    /// 既に存在するオブジェクトのイベントの監視を開始し、receive_eventで受け取れるようにするためのメソッド
+    /// SkyWay CrateはCREATE, CONNECTなどの応答を受けた時にしか監視を開始しないので、
+    /// 引き継いだオブジェクトや、SkyWay Crateの再起動前から存在するオブジェクトに対して用いる
+    async fn listen(&self, source: EventSource) -> Result<(), error::Error>;
+    /// 監視が途切れていた間にWebRTC Gatewayから受け取れなかったイベントを補い、
+    /// receive_eventで受け取れるようにするためのメソッド
+    async fn supplement_event(&self, event: ResponseResult) -> Result<(), error::Error>;
 }
@@ -1 +1,206 @@
-pub(crate) use skyway_webrtc_gateway_caller::error::Error;
+// rust_module内で扱うエラーの定義
+// C++側やEnd User Programが文字列の照合なしにエラーの種類を判別できるよう、
+// variantごとに固定のコードを割り当て、付随する情報は構造化したまま保持する
+// SkyWay Crateが返すエラーもFromで変換し、このErrorとして扱う
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "code")]
+pub(crate) enum Error {
+    /// リクエストのJSONやパラメータ、設定値が不正
+    #[serde(rename = "INVALID_REQUEST")]
+    InvalidRequest { message: String },
+    /// SkyWay Crate, WebRTC Gatewayと通信できない
+    #[serde(rename = "GATEWAY_UNREACHABLE")]
+    GatewayUnreachable { message: String },
+    /// WebRTC Gatewayがエラーを返した、または解釈できない応答を返した
+    #[serde(rename = "GATEWAY_API_ERROR")]
+    GatewayApi { message: String },
+    /// C++側でPluginのロードに失敗した
+    #[serde(rename = "PLUGIN_LOAD_FAILED")]
+    PluginLoadFailed {
+        plugin_type: String,
+        message: String,
+    },
+    /// 指定されたDataConnection, MediaConnectionの情報を保持していない
+    #[serde(rename = "UNKNOWN_CONNECTION")]
+    UnknownConnection { connection_id: String },
+    /// WebRTC Gatewayからの応答が期限内に得られなかった
+    #[serde(rename = "TIMEOUT")]
+    Timeout { timeout_ms: u64 },
+    /// リクエストのprotocol_versionに対応していない
+    #[serde(rename = "UNSUPPORTED_PROTOCOL_VERSION")]
+    UnsupportedProtocolVersion { requested: String, current: String },
+    /// rust_module内部の不整合など、上記以外のエラー
+    #[serde(rename = "INTERNAL")]
+    Internal { message: String },
+}
+
+impl Error {
+    pub fn invalid_request(message: impl Into<String>) -> Error {
+        Error::InvalidRequest {
+            message: message.into(),
+        }
+    }
+
+    pub fn gateway_unreachable(message: impl Into<String>) -> Error {
+        Error::GatewayUnreachable {
+            message: message.into(),
+        }
+    }
+
+    pub fn gateway_api_error(message: impl Into<String>) -> Error {
+        Error::GatewayApi {
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Error {
+        Error::Internal {
+            message: message.into(),
+        }
+    }
+
+    /// エラーの種類を示す固定のコード
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidRequest { .. } => "INVALID_REQUEST",
+            Error::GatewayUnreachable { .. } => "GATEWAY_UNREACHABLE",
+            Error::GatewayApi { .. } => "GATEWAY_API_ERROR",
+            Error::PluginLoadFailed { .. } => "PLUGIN_LOAD_FAILED",
+            Error::UnknownConnection { .. } => "UNKNOWN_CONNECTION",
+            Error::Timeout { .. } => "TIMEOUT",
+            Error::UnsupportedProtocolVersion { .. } => "UNSUPPORTED_PROTOCOL_VERSION",
+            Error::Internal { .. } => "INTERNAL",
+        }
+    }
+
+    /// codeを除いた、エラーに付随する情報
+    pub fn details(&self) -> serde_json::Value {
+        // 全てのvariantは文字列と数値のみを持つので、変換には失敗しない
+        let mut value = serde_json::to_value(self).unwrap();
+        if let Some(map) = value.as_object_mut() {
+            map.remove("code");
+        }
+        value
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidRequest { message } => write!(f, "invalid request: {}", message),
+            Error::GatewayUnreachable { message } => {
+                write!(f, "WebRTC Gateway is unreachable: {}", message)
+            }
+            Error::GatewayApi { message } => {
+                write!(f, "WebRTC Gateway returned an error: {}", message)
+            }
+            Error::PluginLoadFailed {
+                plugin_type,
+                message,
+            } => write!(f, "failed to load {} plugin: {}", plugin_type, message),
+            Error::UnknownConnection { connection_id } => {
+                write!(f, "no info about {}", connection_id)
+            }
+            Error::Timeout { timeout_ms } => {
+                write!(f, "no response from WebRTC Gateway in {}ms", timeout_ms)
+            }
+            Error::UnsupportedProtocolVersion { requested, current } => write!(
+                f,
+                "unsupported protocol version {} (current version is {})",
+                requested, current
+            ),
+            Error::Internal { message } => write!(f, "internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<skyway_webrtc_gateway_caller::error::Error> for Error {
+    fn from(error: skyway_webrtc_gateway_caller::error::Error) -> Self {
+        use skyway_webrtc_gateway_caller::error::Error as CrateError;
+
+        // SkyWay CrateのErrorのDisplayは内容を出力しないので、Debugを利用する
+        match error {
+            CrateError::LocalError(message) => Error::GatewayApi { message },
+            CrateError::SerdeError { error } => Error::gateway_api_error(error.to_string()),
+            CrateError::ReqwestError(error) => Error::gateway_unreachable(format!("{:?}", error)),
+            CrateError::IOError { error } => Error::gateway_unreachable(format!("{:?}", error)),
+            CrateError::AddrParseError(error) => Error::invalid_request(error.to_string()),
+            CrateError::Utf8Error { error } => Error::internal(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use super::*;
+
+    #[test]
+    fn from_crate_error() {
+        let error: Error =
+            skyway_webrtc_gateway_caller::error::Error::create_local_error("error").into();
+        assert_eq!(error, Error::gateway_api_error("error"));
+        assert_eq!(error.code(), "GATEWAY_API_ERROR");
+    }
+
+    #[test]
+    fn timeout() {
+        let error = Error::Timeout { timeout_ms: 100 };
+        assert_eq!(error.code(), "TIMEOUT");
+        assert_eq!(
+            error.to_string(),
+            "no response from WebRTC Gateway in 100ms"
+        );
+    }
+
+    #[test]
+    fn serialize() {
+        // codeとvariantの持つ値がフラットに並ぶ
+        let error = Error::PluginLoadFailed {
+            plugin_type: "string".to_string(),
+            message: "not found".to_string(),
+        };
+        let expected = serde_json::json!({
+            "code": "PLUGIN_LOAD_FAILED",
+            "plugin_type": "string",
+            "message": "not found"
+        });
+        assert_eq!(serde_json::to_value(&error).unwrap(), expected);
+        assert_eq!(
+            error.details(),
+            serde_json::json!({"plugin_type": "string", "message": "not found"})
+        );
+    }
+
+    #[test]
+    fn code_matches_serialized_tag() {
+        let errors = vec![
+            Error::invalid_request(""),
+            Error::gateway_unreachable(""),
+            Error::gateway_api_error(""),
+            Error::PluginLoadFailed {
+                plugin_type: "".to_string(),
+                message: "".to_string(),
+            },
+            Error::UnknownConnection {
+                connection_id: "".to_string(),
+            },
+            Error::Timeout { timeout_ms: 0 },
+            Error::UnsupportedProtocolVersion {
+                requested: "".to_string(),
+                current: "".to_string(),
+            },
+            Error::internal(""),
+        ];
+        for error in errors {
+            let value = serde_json::to_value(&error).unwrap();
+            assert_eq!(value["code"], error.code());
+        }
+    }
+}
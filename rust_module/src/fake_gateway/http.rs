@@ -0,0 +1,156 @@
+// WebRTC GatewayのREST APIを模擬するための、最小限のHTTP/1.1の読み書き
+// SkyWay Crateはリクエストごとに接続を張り直すので、1つの接続では1つのリクエストにのみ応答する
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+// リクエストボディとして受け付ける長さの上限
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HttpRequest {
+    pub method: String,
+    /// クエリ文字列を除いたパス
+    pub path: String,
+    /// クエリ文字列のうち、`token`の値
+    pub token: Option<String>,
+    /// JSONとして解釈できない場合はNull
+    pub body: Value,
+}
+
+impl HttpRequest {
+    /// ログに残すための"METHOD /path"形式の文字列
+    pub fn line(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub body: Option<Value>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: Value) -> Self {
+        HttpResponse {
+            status,
+            body: Some(body),
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        HttpResponse { status, body: None }
+    }
+
+    /// SkyWay Crateが400のボディとして解釈するエラーメッセージ
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(
+            400,
+            serde_json::json!({
+                "command_type": "ERROR",
+                "params": {
+                    "errors": [{ "field": "", "message": message }]
+                }
+            }),
+        )
+    }
+}
+
+/// リクエストを1つ読み込む。接続が途中で閉じられた場合はNoneを返す
+pub(crate) async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<HttpRequest>> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(None),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Ok(None);
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query)),
+        None => (target, None),
+    };
+    let token = query.and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "token")
+            .map(|(_, value)| value.to_string())
+    });
+
+    Ok(Some(HttpRequest {
+        method,
+        path,
+        token,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    }))
+}
+
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    response: &HttpResponse,
+) -> std::io::Result<()> {
+    let body = response
+        .body
+        .as_ref()
+        .map(|body| body.to_string())
+        .unwrap_or_default();
+    let content_type = match response.body {
+        Some(_) => "Content-Type: application/json\r\n",
+        None => "",
+    };
+    let message = format!(
+        "HTTP/1.1 {} {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(message.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        408 => "Request Timeout",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,417 @@
+// WebRTC GatewayのREST APIを模擬するHTTPサーバ
+// WebRTC GatewayやSkyWayのシグナリングサーバを用意せずに、rust_moduleをEnd-to-Endで動作させるために利用する
+// Peer, Data, Media, RTCPの各エンドポイントと、/eventsのlong pollに応答する
+// OPENなどのイベントの発火や、相手側からの接続、失敗の注入はScenarioで指定する
+//
+// テストからはFakeGateway::startで起動し、fake_gatewayバイナリからはScenarioのJSONファイルを与えて起動する
+mod http;
+mod routes;
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::net::{TcpListener, TcpStream};
+
+use self::routes::Gateway;
+
+const DEFAULT_LONG_POLL_TIMEOUT_MS: u64 = 1000;
+
+/// 模擬するWebRTC Gatewayの振る舞い
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// /eventsへのlong pollで、イベントがない場合に408を返すまでの時間
+    pub long_poll_timeout_ms: u64,
+    /// Peer Objectの生成後にOPENイベントを発火させる
+    pub open_peer: bool,
+    /// CONNECT, REDIRECTの後にDataConnectionのOPENイベントを発火させる
+    pub open_data_connection: bool,
+    /// CALL, ANSWERの後にMediaConnectionのREADY, STREAMイベントを発火させる
+    pub start_stream: bool,
+    /// Peer ObjectのOPEN後に、相手側から受ける接続要求
+    pub incoming: Vec<Incoming>,
+    /// 該当するリクエストに対して、指定したステータスコードを返す
+    pub failures: Vec<Failure>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            long_poll_timeout_ms: DEFAULT_LONG_POLL_TIMEOUT_MS,
+            open_peer: true,
+            open_data_connection: true,
+            start_stream: true,
+            incoming: vec![],
+            failures: vec![],
+        }
+    }
+}
+
+/// 相手側からの接続要求
+/// peer_idのPeer ObjectにCONNECTION, CALLイベントを発火させる
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", deny_unknown_fields)]
+pub enum Incoming {
+    #[serde(rename = "CONNECTION")]
+    Connection { peer_id: String, remote_id: String },
+    #[serde(rename = "CALL")]
+    Call { peer_id: String, remote_id: String },
+}
+
+impl Incoming {
+    fn peer_id(&self) -> &str {
+        match self {
+            Incoming::Connection { peer_id, .. } | Incoming::Call { peer_id, .. } => peer_id,
+        }
+    }
+}
+
+/// 注入する失敗
+/// pathはクエリ文字列を除いて比較し、`*`はidなど任意の1要素に一致する
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Failure {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// 失敗させる回数。省略した場合は常に失敗させる
+    #[serde(default)]
+    pub times: Option<u32>,
+}
+
+impl Failure {
+    fn matches(&self, method: &str, path: &str) -> bool {
+        let expected = self.path.trim_start_matches('/').split('/');
+        let actual = path.trim_start_matches('/').split('/');
+        self.method.eq_ignore_ascii_case(method)
+            && expected.clone().count() == actual.clone().count()
+            && expected
+                .zip(actual)
+                .all(|(expected, actual)| expected == "*" || expected == actual)
+    }
+}
+
+/// 起動中の模擬WebRTC Gateway
+/// dropすると待ち受けを終了する
+pub struct FakeGateway {
+    base_url: String,
+    gateway: Arc<Gateway>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl FakeGateway {
+    /// 127.0.0.1のportで待ち受けを開始する。0を指定した場合は空いているポートを用いる
+    pub async fn start(port: u16, scenario: Scenario) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let gateway = Arc::new(Gateway::new(scenario));
+        let server = tokio::spawn(serve(listener, gateway.clone()));
+        Ok(FakeGateway {
+            base_url,
+            gateway,
+            server,
+        })
+    }
+
+    /// Configのgateway_urlに指定するURL
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// 受け付けたリクエストを"METHOD /path"の形式で、受け付けた順に返す
+    pub fn requests(&self) -> Vec<String> {
+        self.gateway.requests()
+    }
+
+    /// 起動後に失敗を注入する
+    pub fn fail(&self, failure: Failure) {
+        self.gateway.fail(failure)
+    }
+
+    /// 相手側からのDataConnectionの確立要求を模擬する
+    /// 生成したdata_connection_idを返し、peer_idのPeer Objectが存在しない場合はNoneを返す
+    pub fn connect_from(&self, peer_id: &str, remote_id: &str) -> Option<String> {
+        self.gateway.connect_from(peer_id, remote_id)
+    }
+
+    /// 相手側からのMediaConnectionの確立要求を模擬する
+    /// 生成したmedia_connection_idを返し、peer_idのPeer Objectが存在しない場合はNoneを返す
+    pub fn call_from(&self, peer_id: &str, remote_id: &str) -> Option<String> {
+        self.gateway.call_from(peer_id, remote_id)
+    }
+
+    /// Peer ObjectのCLOSEを模擬する
+    pub fn close_peer(&self, peer_id: &str) -> bool {
+        self.gateway.close_peer(peer_id)
+    }
+
+    /// 相手側からのDataConnectionの切断を模擬する
+    pub fn close_data_connection(&self, data_connection_id: &str) -> bool {
+        self.gateway.close_data_connection(data_connection_id)
+    }
+
+    /// 相手側からのMediaConnectionの切断を模擬する
+    pub fn close_media_connection(&self, media_connection_id: &str) -> bool {
+        self.gateway.close_media_connection(media_connection_id)
+    }
+}
+
+impl Drop for FakeGateway {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn serve(listener: TcpListener, gateway: Arc<Gateway>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("fake gateway: failed to accept: {}", e);
+                continue;
+            }
+        };
+        let gateway = gateway.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, gateway).await {
+                tracing::debug!("fake gateway: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, gateway: Arc<Gateway>) -> std::io::Result<()> {
+    let request = match http::read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    let response = gateway.handle(request).await;
+    http::write_response(&mut stream, &response).await
+}
+
+#[cfg(test)]
+mod fake_gateway_test {
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    // 1つのリクエストを送り、ステータスコードとJSONのボディを返す
+    async fn send(gateway: &FakeGateway, method: &str, path: &str, body: Value) -> (u16, Value) {
+        let address = gateway.base_url().trim_start_matches("http://");
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let body = body.to_string();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            address,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    async fn create_peer(gateway: &FakeGateway, peer_id: &str) -> String {
+        let (status, body) = send(
+            gateway,
+            "POST",
+            "/peers",
+            json!({ "key": "API_KEY", "domain": "localhost", "peer_id": peer_id, "turn": false }),
+        )
+        .await;
+        assert_eq!(status, 201);
+        body["params"]["token"].as_str().unwrap().to_string()
+    }
+
+    fn scenario() -> Scenario {
+        Scenario {
+            long_poll_timeout_ms: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_scenario() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{
+                "open_data_connection": false,
+                "incoming": [{ "event": "CALL", "peer_id": "robot", "remote_id": "operator" }],
+                "failures": [{ "method": "POST", "path": "/media/connections/*/answer", "status": 400, "times": 1 }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(scenario.long_poll_timeout_ms, DEFAULT_LONG_POLL_TIMEOUT_MS);
+        assert!(scenario.open_peer);
+        assert!(!scenario.open_data_connection);
+        assert_eq!(
+            scenario.incoming,
+            vec![Incoming::Call {
+                peer_id: "robot".to_string(),
+                remote_id: "operator".to_string()
+            }]
+        );
+        assert!(scenario.failures[0].matches(
+            "POST",
+            "/media/connections/mc-102127d9-30de-413b-93f7-41a33e39d82b/answer"
+        ));
+        assert!(!scenario.failures[0].matches("POST", "/media/connections"));
+    }
+
+    #[tokio::test]
+    // Peer Objectの生成後、OPENイベントをlong pollで1度だけ返し、以降は408を返す
+    async fn peer_events() {
+        let gateway = FakeGateway::start(0, scenario()).await.unwrap();
+        let token = create_peer(&gateway, "robot").await;
+        assert!(token.starts_with("pt-"));
+        assert_eq!(token.len(), 39);
+
+        let path = format!("/peers/robot/events?token={}", token);
+        let (status, body) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            json!({ "event": "OPEN", "params": { "peer_id": "robot", "token": token } })
+        );
+        let (status, _) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(status, 408);
+
+        // 相手側からの接続要求はCONNECTIONイベントとして通知される
+        let data_connection_id = gateway.connect_from("robot", "operator").unwrap();
+        let (_, body) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(body["event"], "CONNECTION");
+        assert_eq!(
+            body["data_params"]["data_connection_id"],
+            data_connection_id.as_str()
+        );
+
+        // 削除するとCLOSEイベントが発火する
+        let (status, _) = send(
+            &gateway,
+            "DELETE",
+            &format!("/peers/robot?token={}", token),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, 204);
+        let (_, body) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(body["event"], "CLOSE");
+    }
+
+    #[tokio::test]
+    // tokenが一致しない場合は403を返す
+    async fn invalid_token() {
+        let gateway = FakeGateway::start(0, scenario()).await.unwrap();
+        create_peer(&gateway, "robot").await;
+        let (status, _) = send(
+            &gateway,
+            "GET",
+            "/peers/robot/status?token=pt-invalid",
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, 403);
+    }
+
+    #[tokio::test]
+    // CONNECTに応答し、DataConnectionのOPENイベントを発火させる
+    async fn data_connection() {
+        let gateway = FakeGateway::start(0, scenario()).await.unwrap();
+        let token = create_peer(&gateway, "robot").await;
+        let (status, socket) = send(&gateway, "POST", "/data", json!({})).await;
+        assert_eq!(status, 201);
+        assert!(socket["data_id"].as_str().unwrap().starts_with("da-"));
+
+        let (status, body) = send(
+            &gateway,
+            "POST",
+            "/data/connections",
+            json!({
+                "peer_id": "robot",
+                "token": token,
+                "target_id": "operator",
+                "params": { "data_id": socket["data_id"] }
+            }),
+        )
+        .await;
+        assert_eq!(status, 202);
+        let data_connection_id = body["params"]["data_connection_id"].as_str().unwrap();
+
+        let path = format!("/data/connections/{}/events", data_connection_id);
+        let (_, body) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(body, json!({ "event": "OPEN" }));
+
+        let (_, status) = send(
+            &gateway,
+            "GET",
+            &format!("/data/connections/{}/status", data_connection_id),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status["remote_id"], "operator");
+        assert_eq!(status["open"], true);
+
+        assert!(gateway.close_data_connection(data_connection_id));
+        let (_, body) = send(&gateway, "GET", &path, Value::Null).await;
+        assert_eq!(body, json!({ "event": "CLOSE" }));
+    }
+
+    #[tokio::test]
+    // 相手側からのCALLにANSWERすると、送信用のSocketを生成してREADY, STREAMイベントを発火させる
+    async fn answer() {
+        let gateway = FakeGateway::start(0, scenario()).await.unwrap();
+        create_peer(&gateway, "robot").await;
+        let media_connection_id = gateway.call_from("robot", "operator").unwrap();
+
+        let path = format!("/media/connections/{}/answer", media_connection_id);
+        let query = json!({ "constraints": { "video": true, "audio": false } });
+        let (status, body) = send(&gateway, "POST", &path, query.clone()).await;
+        assert_eq!(status, 202);
+        assert!(body["params"]["video_id"]
+            .as_str()
+            .unwrap()
+            .starts_with("vi-"));
+        assert!(body["params"].get("audio_id").is_none());
+
+        let events = format!("/media/connections/{}/events", media_connection_id);
+        let (_, body) = send(&gateway, "GET", &events, Value::Null).await;
+        assert_eq!(body, json!({ "event": "READY" }));
+        let (_, body) = send(&gateway, "GET", &events, Value::Null).await;
+        assert_eq!(body, json!({ "event": "STREAM" }));
+
+        // 確立済みのMediaConnectionには応答できない
+        let (status, body) = send(&gateway, "POST", &path, query).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["params"]["errors"][0]["message"], "already answered");
+    }
+
+    #[tokio::test]
+    // 注入した失敗は指定した回数だけ返す
+    async fn failure() {
+        let gateway = FakeGateway::start(0, scenario()).await.unwrap();
+        gateway.fail(Failure {
+            method: "POST".to_string(),
+            path: "/media/rtcp".to_string(),
+            status: 403,
+            times: Some(1),
+        });
+        let (status, _) = send(&gateway, "POST", "/media/rtcp", Value::Null).await;
+        assert_eq!(status, 403);
+        let (status, body) = send(&gateway, "POST", "/media/rtcp", Value::Null).await;
+        assert_eq!(status, 201);
+        assert!(body["rtcp_id"].as_str().unwrap().starts_with("rc-"));
+        assert_eq!(
+            gateway.requests(),
+            vec![
+                "POST /media/rtcp".to_string(),
+                "POST /media/rtcp".to_string()
+            ]
+        );
+    }
+}
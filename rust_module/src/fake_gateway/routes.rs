@@ -0,0 +1,655 @@
+// 模擬するWebRTC Gatewayの状態と、各エンドポイントの処理
+// Peer Object, Socket, Connectionはメモリ上でのみ管理し、実際のWebRTCの通信は行わない
+// イベントはオブジェクトごとのMailboxに積み、/eventsへのlong pollで1つずつ返す
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+
+use super::http::{HttpRequest, HttpResponse};
+use super::{Failure, Incoming, Scenario};
+
+// Socketとして返すアドレス。実際には待ち受けない
+const SOCKET_ADDRESS: &str = "127.0.0.1";
+const FIRST_PORT: u16 = 50000;
+
+// 1つのオブジェクトに対するイベントのキュー
+#[derive(Default)]
+struct Mailbox {
+    events: Mutex<VecDeque<Value>>,
+    notify: Notify,
+}
+
+impl Mailbox {
+    fn push(&self, event: Value) {
+        self.events.lock().unwrap().push_back(event);
+        self.notify.notify_one();
+    }
+}
+
+struct Connection {
+    peer_id: String,
+    remote_id: String,
+    open: bool,
+}
+
+struct Objects {
+    sequence: u64,
+    next_port: u16,
+    // peer_idとtokenの対応
+    peers: HashMap<String, String>,
+    data: HashSet<String>,
+    data_connections: HashMap<String, Connection>,
+    media: HashSet<String>,
+    rtcp: HashSet<String>,
+    media_connections: HashMap<String, Connection>,
+    failures: Vec<Failure>,
+    requests: Vec<String>,
+}
+
+impl Objects {
+    // SkyWay Crateが受け付ける"prefix-"に続く36文字の形式でidを生成する
+    fn id(&mut self, prefix: &str) -> String {
+        self.sequence += 1;
+        format!(
+            "{}-{:08x}-0000-4000-8000-{:012x}",
+            prefix,
+            std::process::id(),
+            self.sequence
+        )
+    }
+
+    fn socket(&mut self, id_key: &str, id: String) -> Value {
+        let port = self.next_port;
+        self.next_port = self.next_port.wrapping_add(1).max(FIRST_PORT);
+        json!({ id_key: id, "ip_v4": SOCKET_ADDRESS, "port": port })
+    }
+
+    // 操作要求に含まれるpeer_idとtokenを確認する
+    fn check_peer(&self, peer_id: &str, token: Option<&str>) -> Result<(), HttpResponse> {
+        match self.peers.get(peer_id) {
+            None => Err(HttpResponse::empty(404)),
+            Some(expected) if Some(expected.as_str()) != token => Err(HttpResponse::empty(403)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    // 注入された失敗に該当する場合は、その応答を返す
+    fn inject_failure(&mut self, request: &HttpRequest) -> Option<HttpResponse> {
+        let index = self
+            .failures
+            .iter()
+            .position(|failure| failure.matches(&request.method, &request.path))?;
+        let failure = &mut self.failures[index];
+        let status = failure.status;
+        match failure.times {
+            Some(1) => {
+                self.failures.remove(index);
+            }
+            Some(ref mut times) => *times -= 1,
+            None => {}
+        }
+        Some(match status {
+            400 => HttpResponse::bad_request("injected failure"),
+            status => HttpResponse::empty(status),
+        })
+    }
+}
+
+pub(crate) struct Gateway {
+    scenario: Scenario,
+    objects: Mutex<Objects>,
+    mailboxes: Mutex<HashMap<String, Arc<Mailbox>>>,
+}
+
+impl Gateway {
+    pub fn new(mut scenario: Scenario) -> Self {
+        let failures = std::mem::take(&mut scenario.failures);
+        Gateway {
+            scenario,
+            objects: Mutex::new(Objects {
+                sequence: 0,
+                next_port: FIRST_PORT,
+                peers: HashMap::new(),
+                data: HashSet::new(),
+                data_connections: HashMap::new(),
+                media: HashSet::new(),
+                rtcp: HashSet::new(),
+                media_connections: HashMap::new(),
+                failures,
+                requests: vec![],
+            }),
+            mailboxes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.objects.lock().unwrap().requests.clone()
+    }
+
+    pub fn fail(&self, failure: Failure) {
+        self.objects.lock().unwrap().failures.push(failure);
+    }
+
+    pub async fn handle(&self, request: HttpRequest) -> HttpResponse {
+        {
+            let mut objects = self.objects.lock().unwrap();
+            objects.requests.push(request.line());
+            if let Some(response) = objects.inject_failure(&request) {
+                return response;
+            }
+        }
+
+        let token = request.token.as_deref();
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["peers"]) => self.create_peer(&request.body),
+            ("GET", ["peers", peer_id, "events"]) => {
+                return self.poll(&Self::peer_key(peer_id)).await;
+            }
+            ("GET", ["peers", peer_id, "status"]) => self.peer_status(peer_id, token),
+            ("DELETE", ["peers", peer_id]) => self.delete_peer(peer_id, token),
+            ("POST", ["data"]) => Ok(self.create_socket("data_id", "da")),
+            ("DELETE", ["data", data_id]) => self.delete_socket(data_id),
+            ("POST", ["data", "connections"]) => self.connect(&request.body),
+            ("PUT", ["data", "connections", data_connection_id]) => {
+                self.redirect(data_connection_id, &request.body)
+            }
+            ("DELETE", ["data", "connections", data_connection_id]) => self
+                .close_data_connection(data_connection_id)
+                .then(|| HttpResponse::empty(204))
+                .ok_or_else(|| HttpResponse::empty(404)),
+            ("GET", ["data", "connections", data_connection_id, "status"]) => {
+                self.data_connection_status(data_connection_id)
+            }
+            ("GET", ["data", "connections", data_connection_id, "events"]) => {
+                return self.poll(data_connection_id).await;
+            }
+            ("POST", ["media"]) => {
+                let is_video = request.body["is_video"].as_bool().unwrap_or(false);
+                let prefix = if is_video { "vi" } else { "au" };
+                Ok(self.create_socket("media_id", prefix))
+            }
+            ("DELETE", ["media", media_id]) => self.delete_socket(media_id),
+            ("POST", ["media", "rtcp"]) => Ok(self.create_socket("rtcp_id", "rc")),
+            ("DELETE", ["media", "rtcp", rtcp_id]) => self.delete_socket(rtcp_id),
+            ("POST", ["media", "connections"]) => self.call(&request.body),
+            ("DELETE", ["media", "connections", media_connection_id]) => self
+                .close_media_connection(media_connection_id)
+                .then(|| HttpResponse::empty(204))
+                .ok_or_else(|| HttpResponse::empty(404)),
+            ("POST", ["media", "connections", media_connection_id, "answer"]) => {
+                self.answer(media_connection_id, &request.body)
+            }
+            ("POST", ["media", "connections", media_connection_id, "pli"]) => {
+                self.pli(media_connection_id)
+            }
+            ("GET", ["media", "connections", media_connection_id, "status"]) => {
+                self.media_connection_status(media_connection_id)
+            }
+            ("GET", ["media", "connections", media_connection_id, "events"]) => {
+                return self.poll(media_connection_id).await;
+            }
+            _ => Err(HttpResponse::empty(404)),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    /// 相手側からのDataConnectionの確立要求を模擬し、peer_idのPeer ObjectにCONNECTIONイベントを発火させる
+    pub fn connect_from(&self, peer_id: &str, remote_id: &str) -> Option<String> {
+        let (token, data_connection_id) = {
+            let mut objects = self.objects.lock().unwrap();
+            let token = objects.peers.get(peer_id)?.clone();
+            let data_connection_id = objects.id("dc");
+            objects.data_connections.insert(
+                data_connection_id.clone(),
+                Connection {
+                    peer_id: peer_id.to_string(),
+                    remote_id: remote_id.to_string(),
+                    open: false,
+                },
+            );
+            (token, data_connection_id)
+        };
+        self.mailbox(&data_connection_id);
+        self.mailbox(&Self::peer_key(peer_id)).push(json!({
+            "event": "CONNECTION",
+            "params": { "peer_id": peer_id, "token": token },
+            "data_params": { "data_connection_id": data_connection_id }
+        }));
+        Some(data_connection_id)
+    }
+
+    /// 相手側からのMediaConnectionの確立要求を模擬し、peer_idのPeer ObjectにCALLイベントを発火させる
+    pub fn call_from(&self, peer_id: &str, remote_id: &str) -> Option<String> {
+        let (token, media_connection_id) = {
+            let mut objects = self.objects.lock().unwrap();
+            let token = objects.peers.get(peer_id)?.clone();
+            let media_connection_id = objects.id("mc");
+            objects.media_connections.insert(
+                media_connection_id.clone(),
+                Connection {
+                    peer_id: peer_id.to_string(),
+                    remote_id: remote_id.to_string(),
+                    open: false,
+                },
+            );
+            (token, media_connection_id)
+        };
+        self.mailbox(&media_connection_id);
+        self.mailbox(&Self::peer_key(peer_id)).push(json!({
+            "event": "CALL",
+            "params": { "peer_id": peer_id, "token": token },
+            "call_params": { "media_connection_id": media_connection_id }
+        }));
+        Some(media_connection_id)
+    }
+
+    /// シグナリングサーバとの切断などによるPeer ObjectのCLOSEを模擬する
+    /// Peer Objectが確立したConnectionも全て閉じられる
+    pub fn close_peer(&self, peer_id: &str) -> bool {
+        let (token, data_connection_ids, media_connection_ids) = {
+            let mut objects = self.objects.lock().unwrap();
+            let token = match objects.peers.remove(peer_id) {
+                Some(token) => token,
+                None => return false,
+            };
+            let owned = |connections: &HashMap<String, Connection>| -> Vec<String> {
+                connections
+                    .iter()
+                    .filter(|(_, connection)| connection.peer_id == peer_id)
+                    .map(|(id, _)| id.clone())
+                    .collect()
+            };
+            (
+                token,
+                owned(&objects.data_connections),
+                owned(&objects.media_connections),
+            )
+        };
+        for data_connection_id in data_connection_ids {
+            self.close_data_connection(&data_connection_id);
+        }
+        for media_connection_id in media_connection_ids {
+            self.close_media_connection(&media_connection_id);
+        }
+        self.mailbox(&Self::peer_key(peer_id)).push(json!({
+            "event": "CLOSE",
+            "params": { "peer_id": peer_id, "token": token }
+        }));
+        true
+    }
+
+    /// 相手側からの切断を模擬し、DataConnectionにCLOSEイベントを発火させる
+    pub fn close_data_connection(&self, data_connection_id: &str) -> bool {
+        let removed = self
+            .objects
+            .lock()
+            .unwrap()
+            .data_connections
+            .remove(data_connection_id);
+        if removed.is_some() {
+            self.mailbox(data_connection_id)
+                .push(json!({ "event": "CLOSE" }));
+        }
+        removed.is_some()
+    }
+
+    /// 相手側からの切断を模擬し、MediaConnectionにCLOSEイベントを発火させる
+    pub fn close_media_connection(&self, media_connection_id: &str) -> bool {
+        let removed = self
+            .objects
+            .lock()
+            .unwrap()
+            .media_connections
+            .remove(media_connection_id);
+        if removed.is_some() {
+            self.mailbox(media_connection_id)
+                .push(json!({ "event": "CLOSE" }));
+        }
+        removed.is_some()
+    }
+
+    // peer_idは任意の文字列なので、Connectionのidと衝突しないよう区別する
+    fn peer_key(peer_id: &str) -> String {
+        format!("peers/{}", peer_id)
+    }
+
+    fn mailbox(&self, key: &str) -> Arc<Mailbox> {
+        self.mailboxes
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    // イベントが積まれるまで待ち、期限を過ぎた場合は408を返す
+    async fn poll(&self, key: &str) -> HttpResponse {
+        let mailbox = match self.mailboxes.lock().unwrap().get(key) {
+            Some(mailbox) => mailbox.clone(),
+            None => return HttpResponse::empty(404),
+        };
+        let timeout = Duration::from_millis(self.scenario.long_poll_timeout_ms);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(event) = mailbox.events.lock().unwrap().pop_front() {
+                return HttpResponse::new(200, event);
+            }
+            if tokio::time::timeout_at(deadline, mailbox.notify.notified())
+                .await
+                .is_err()
+            {
+                return HttpResponse::empty(408);
+            }
+        }
+    }
+
+    fn create_peer(&self, body: &Value) -> Result<HttpResponse, HttpResponse> {
+        let peer_id = body["peer_id"]
+            .as_str()
+            .filter(|peer_id| !peer_id.is_empty())
+            .ok_or_else(|| HttpResponse::bad_request("peer_id is required"))?;
+        let token = {
+            let mut objects = self.objects.lock().unwrap();
+            if objects.peers.contains_key(peer_id) {
+                return Err(HttpResponse::bad_request("peer_id is already in use"));
+            }
+            let token = objects.id("pt");
+            objects.peers.insert(peer_id.to_string(), token.clone());
+            token
+        };
+
+        let mailbox = self.mailbox(&Self::peer_key(peer_id));
+        if self.scenario.open_peer {
+            mailbox.push(json!({
+                "event": "OPEN",
+                "params": { "peer_id": peer_id, "token": token }
+            }));
+            let incoming: Vec<Incoming> = self
+                .scenario
+                .incoming
+                .iter()
+                .filter(|incoming| incoming.peer_id() == peer_id)
+                .cloned()
+                .collect();
+            for incoming in incoming {
+                match incoming {
+                    Incoming::Connection { remote_id, .. } => {
+                        self.connect_from(peer_id, &remote_id);
+                    }
+                    Incoming::Call { remote_id, .. } => {
+                        self.call_from(peer_id, &remote_id);
+                    }
+                }
+            }
+        }
+
+        Ok(HttpResponse::new(
+            201,
+            json!({
+                "command_type": "PEERS_CREATE",
+                "params": { "peer_id": peer_id, "token": token }
+            }),
+        ))
+    }
+
+    fn peer_status(
+        &self,
+        peer_id: &str,
+        token: Option<&str>,
+    ) -> Result<HttpResponse, HttpResponse> {
+        self.objects.lock().unwrap().check_peer(peer_id, token)?;
+        Ok(HttpResponse::new(
+            200,
+            json!({ "peer_id": peer_id, "disconnected": false }),
+        ))
+    }
+
+    fn delete_peer(
+        &self,
+        peer_id: &str,
+        token: Option<&str>,
+    ) -> Result<HttpResponse, HttpResponse> {
+        self.objects.lock().unwrap().check_peer(peer_id, token)?;
+        self.close_peer(peer_id);
+        Ok(HttpResponse::empty(204))
+    }
+
+    fn create_socket(&self, id_key: &str, prefix: &str) -> HttpResponse {
+        let mut objects = self.objects.lock().unwrap();
+        let id = objects.id(prefix);
+        match prefix {
+            "da" => objects.data.insert(id.clone()),
+            "rc" => objects.rtcp.insert(id.clone()),
+            _ => objects.media.insert(id.clone()),
+        };
+        HttpResponse::new(201, objects.socket(id_key, id))
+    }
+
+    fn delete_socket(&self, id: &str) -> Result<HttpResponse, HttpResponse> {
+        let mut objects = self.objects.lock().unwrap();
+        let removed = match id.get(..3) {
+            Some("da-") => objects.data.remove(id),
+            Some("rc-") => objects.rtcp.remove(id),
+            _ => objects.media.remove(id),
+        };
+        match removed {
+            true => Ok(HttpResponse::empty(204)),
+            false => Err(HttpResponse::empty(404)),
+        }
+    }
+
+    fn connect(&self, body: &Value) -> Result<HttpResponse, HttpResponse> {
+        let data_connection_id = self.open_connection(body, "dc")?;
+        if self.scenario.open_data_connection {
+            self.open_data_connection(&data_connection_id);
+        }
+        Ok(HttpResponse::new(
+            202,
+            json!({
+                "command_type": "PEERS_CONNECT",
+                "params": { "data_connection_id": data_connection_id }
+            }),
+        ))
+    }
+
+    fn redirect(
+        &self,
+        data_connection_id: &str,
+        body: &Value,
+    ) -> Result<HttpResponse, HttpResponse> {
+        let data_id = {
+            let mut objects = self.objects.lock().unwrap();
+            if !objects.data_connections.contains_key(data_connection_id) {
+                return Err(HttpResponse::empty(404));
+            }
+            match body["feed_params"]["data_id"].as_str() {
+                Some(data_id) if objects.data.contains(data_id) => data_id.to_string(),
+                Some(_) => return Err(HttpResponse::bad_request("data_id is not found")),
+                None => objects.id("da"),
+            }
+        };
+        if self.scenario.open_data_connection {
+            self.open_data_connection(data_connection_id);
+        }
+        Ok(HttpResponse::new(
+            200,
+            json!({ "command_type": "DATA_CONNECTION_PUT", "data_id": data_id }),
+        ))
+    }
+
+    fn open_data_connection(&self, data_connection_id: &str) {
+        let opened = match self
+            .objects
+            .lock()
+            .unwrap()
+            .data_connections
+            .get_mut(data_connection_id)
+        {
+            Some(connection) if !connection.open => {
+                connection.open = true;
+                true
+            }
+            _ => false,
+        };
+        if opened {
+            self.mailbox(data_connection_id)
+                .push(json!({ "event": "OPEN" }));
+        }
+    }
+
+    fn data_connection_status(
+        &self,
+        data_connection_id: &str,
+    ) -> Result<HttpResponse, HttpResponse> {
+        let objects = self.objects.lock().unwrap();
+        let connection = objects
+            .data_connections
+            .get(data_connection_id)
+            .ok_or_else(|| HttpResponse::empty(404))?;
+        Ok(HttpResponse::new(
+            200,
+            json!({
+                "remote_id": connection.remote_id,
+                "buffersize": 0,
+                "label": "",
+                "metadata": "",
+                "open": connection.open,
+                "reliable": true,
+                "serialization": "BINARY",
+                "type": "DATA"
+            }),
+        ))
+    }
+
+    fn call(&self, body: &Value) -> Result<HttpResponse, HttpResponse> {
+        let media_connection_id = self.open_connection(body, "mc")?;
+        if self.scenario.start_stream {
+            self.start_stream(&media_connection_id);
+        }
+        Ok(HttpResponse::new(
+            202,
+            json!({
+                "command_type": "PEERS_CALL",
+                "params": { "media_connection_id": media_connection_id }
+            }),
+        ))
+    }
+
+    fn answer(
+        &self,
+        media_connection_id: &str,
+        body: &Value,
+    ) -> Result<HttpResponse, HttpResponse> {
+        let params = {
+            let mut objects = self.objects.lock().unwrap();
+            match objects.media_connections.get(media_connection_id) {
+                None => return Err(HttpResponse::empty(404)),
+                Some(connection) if connection.open => {
+                    return Err(HttpResponse::bad_request("already answered"))
+                }
+                Some(_) => {}
+            }
+            // 送信用のSocketが指定されていない場合は、WebRTC Gatewayが自動で生成する
+            let constraints = &body["constraints"];
+            let mut params = serde_json::Map::new();
+            for (kind, prefix) in [("video", "vi"), ("audio", "au")] {
+                let is_enabled = constraints[kind].as_bool().unwrap_or(false);
+                if is_enabled && constraints[format!("{}_params", kind)].is_null() {
+                    let media_id = objects.id(prefix);
+                    objects.media.insert(media_id.clone());
+                    params.insert(format!("{}_id", kind), Value::String(media_id));
+                }
+            }
+            params
+        };
+        if self.scenario.start_stream {
+            self.start_stream(media_connection_id);
+        }
+        Ok(HttpResponse::new(
+            202,
+            json!({ "command_type": "MEDIA_CONNECTION_ANSWER", "params": params }),
+        ))
+    }
+
+    fn start_stream(&self, media_connection_id: &str) {
+        let started = match self
+            .objects
+            .lock()
+            .unwrap()
+            .media_connections
+            .get_mut(media_connection_id)
+        {
+            Some(connection) if !connection.open => {
+                connection.open = true;
+                true
+            }
+            _ => false,
+        };
+        if started {
+            let mailbox = self.mailbox(media_connection_id);
+            mailbox.push(json!({ "event": "READY" }));
+            mailbox.push(json!({ "event": "STREAM" }));
+        }
+    }
+
+    fn pli(&self, media_connection_id: &str) -> Result<HttpResponse, HttpResponse> {
+        match self
+            .objects
+            .lock()
+            .unwrap()
+            .media_connections
+            .contains_key(media_connection_id)
+        {
+            true => Ok(HttpResponse::empty(201)),
+            false => Err(HttpResponse::empty(404)),
+        }
+    }
+
+    fn media_connection_status(
+        &self,
+        media_connection_id: &str,
+    ) -> Result<HttpResponse, HttpResponse> {
+        let objects = self.objects.lock().unwrap();
+        let connection = objects
+            .media_connections
+            .get(media_connection_id)
+            .ok_or_else(|| HttpResponse::empty(404))?;
+        Ok(HttpResponse::new(
+            200,
+            json!({
+                "metadata": "",
+                "open": connection.open,
+                "remote_id": connection.remote_id
+            }),
+        ))
+    }
+
+    // CONNECT, CALLの操作要求から、このノードが発信したConnectionを生成する
+    fn open_connection(&self, body: &Value, prefix: &str) -> Result<String, HttpResponse> {
+        let peer_id = body["peer_id"].as_str().unwrap_or_default();
+        let remote_id = body["target_id"]
+            .as_str()
+            .ok_or_else(|| HttpResponse::bad_request("target_id is required"))?;
+        let id = {
+            let mut objects = self.objects.lock().unwrap();
+            objects.check_peer(peer_id, body["token"].as_str())?;
+            let id = objects.id(prefix);
+            let connection = Connection {
+                peer_id: peer_id.to_string(),
+                remote_id: remote_id.to_string(),
+                open: false,
+            };
+            match prefix {
+                "dc" => objects.data_connections.insert(id.clone(), connection),
+                _ => objects.media_connections.insert(id.clone(), connection),
+            };
+            id
+        };
+        self.mailbox(&id);
+        Ok(id)
+    }
+}
@@ -1,107 +1,210 @@
-// 当面はユニットテストは行わず、結合試験だけ行うことにする
-// Fixme: Unit Test
+// C++側から呼ばれる関数
+// C文字列とRust文字列の変換のみ行い、中身の処理はapplication層に任せる
+// 変換に失敗した場合もabortせず、エラーの応答を返す。この変換はユニットテストで確認し、それ以外は結合試験で確認する
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
 use std::thread::JoinHandle;
 
 use shaku::HasComponent;
 
-use crate::application::dto::request::RequestDto;
-use crate::application::usecase::Service;
-use crate::di::GeneralService;
-use crate::domain::entity::request::PeerRequest;
-use crate::domain::entity::PeerInfo;
+use crate::application::usecase::system::shutdown::ShutdownSequence;
+use crate::config::Config;
+use crate::di::ShutdownService;
+use crate::error;
 use crate::ffi::rust_to_c_bridge::c_functions_wrapper::*;
+use crate::ffi::rust_to_c_bridge::snapshot;
+use crate::ffi::rust_to_c_bridge::state_objects::{RECORDER, RUNTIME};
 
 //========== 起動時用 ==========
 // 起動に成功した場合、Rust側でWebRTC Gateawyから生じるイベントのリスナースレッドが回り続ける
 // 終了時にそれを終了するため、起動に成功したというフラグとともにhandlerを一緒に返す
 #[repr(C)]
 pub struct RunResponse {
-    flag: bool,
-    handler: *mut c_void,
+    pub(crate) flag: bool,
+    pub(crate) handler: *mut c_void,
 }
 
+impl RunResponse {
+    fn failure() -> Self {
+        RunResponse {
+            flag: false,
+            handler: std::ptr::null_mut(),
+        }
+    }
+}
+
+// デフォルトの設定値(http://localhost:8000のWebRTC Gatewayを利用する)で起動する
 #[no_mangle]
 pub extern "C" fn run() -> RunResponse {
+    start(Config::default())
+}
+
+// JSONで与えられた設定値で起動する
+// 省略された項目はデフォルト値を利用する。不正な設定値の場合はエラーをログに出力し、起動しない
+#[no_mangle]
+pub extern "C" fn run_with_config(config_char: *const c_char) -> RunResponse {
     if !LoggerHolder::is_allocated() {
-        return RunResponse {
-            flag: false,
-            handler: std::ptr::null_mut(),
-        };
+        return RunResponse::failure();
+    }
+
+    if config_char.is_null() {
+        LoggerHolder::global().error("invalid config: config is null");
+        return RunResponse::failure();
+    }
+
+    let c_str: &CStr = unsafe { CStr::from_ptr(config_char) };
+    let config = match c_str.to_str() {
+        Ok(json) => Config::try_create(json),
+        Err(e) => Err(error::Error::invalid_request(format!(
+            "invalid config: {}",
+            e
+        ))),
+    };
+
+    match config {
+        Ok(config) => start(config),
+        Err(error::Error::InvalidRequest { message }) => {
+            LoggerHolder::global().error(message);
+            RunResponse::failure()
+        }
+        Err(e) => {
+            LoggerHolder::global().error(format!("invalid config: {}", e));
+            RunResponse::failure()
+        }
+    }
+}
+
+fn start(config: Config) -> RunResponse {
+    if !LoggerHolder::is_allocated() {
+        return RunResponse::failure();
     }
 
     if !ProgramStateHolder::is_allocated() {
         LoggerHolder::global().error(
             "ProgramState object is not allocated. Please call the register_program_state function",
         );
-        return RunResponse {
-            flag: false,
-            handler: std::ptr::null_mut(),
-        };
+        return RunResponse::failure();
     }
 
     // SkyWay Crateを開始する
+    // rust_mainはROSの終了まで戻らないので、専用のスレッドから共有のruntimeを利用する
     let handle: JoinHandle<()> = std::thread::spawn(|| {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            crate::rust_main().await;
+        RUNTIME.block_on(async {
+            crate::rust_main(config).await;
         });
     });
 
     let thread_handle = Box::into_raw(Box::new(handle)) as *mut c_void;
 
-    return RunResponse {
+    RunResponse {
         flag: true,
         handler: thread_handle,
-    };
+    }
 }
 
 //========== ROS側から、WebRTC Gatewayの操作のために呼ばれる関数 ==========
 #[no_mangle]
 pub extern "C" fn call_service(message_char: *const c_char) -> *mut c_char {
     // C文字列とRust文字列の変換だけ行って、中身の処理はapplicationメソッドに任せる
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let message: String = rt.block_on(async {
-        let c_str: &CStr = unsafe { CStr::from_ptr(message_char) };
-        let message = c_str.to_str().unwrap().to_string();
+    let message: String = RUNTIME.block_on(async {
+        match to_message(message_char, "message") {
+            Ok(message) => crate::application::call_service(message).await,
+            Err(e) => crate::application::error_response(&e),
+        }
+    });
+    CString::new(message.as_str()).unwrap().into_raw()
+}
+
+// C++側から渡された文字列をRustの文字列に変換する
+// nullの場合やUTF-8として解釈できない場合は、abortせずにINVALID_REQUESTとして応答できるようエラーを返す
+fn to_message(message_char: *const c_char, name: &str) -> Result<String, error::Error> {
+    if message_char.is_null() {
+        return Err(error::Error::invalid_request(format!("{} is null", name)));
+    }
+    let c_str: &CStr = unsafe { CStr::from_ptr(message_char) };
+    c_str
+        .to_str()
+        .map(str::to_string)
+        .map_err(|e| error::Error::invalid_request(format!("{} is not valid UTF-8: {}", name, e)))
+}
 
-        crate::application::call_service(message).await
+// call_serviceの完了時に呼ばれるC++側の関数
+// request_idはcall_service_asyncに渡された値で、responseはrelease_stringで開放する必要がある
+pub type CompletionCallback = extern "C" fn(request_id: u64, response: *mut c_char);
+
+// call_serviceの非同期版。処理の完了を待たずに戻り、完了時にcompletion_cbを呼び出す
+// completion_cbはruntimeのワーカースレッドから呼ばれるので、C++側はスレッドセーフに実装する必要がある
+// また、completion_cbの中でcall_serviceなどruntimeの完了を待つ関数を呼び出してはならない
+// messageがUTF-8として解釈できない場合も、エラーの応答とともにcompletion_cbが呼ばれる
+#[no_mangle]
+pub extern "C" fn call_service_async(
+    message_char: *const c_char,
+    request_id: u64,
+    completion_cb: CompletionCallback,
+) {
+    // 呼び出し元がすぐに文字列を開放できるよう、戻る前にRust側にコピーしておく
+    let message = to_message(message_char, "message");
+
+    // 呼び出し元のスレッドでcompletion_cbを呼ぶことはなく、エラーの場合もワーカースレッドから呼ぶ
+    RUNTIME.spawn(async move {
+        let response = match message {
+            Ok(message) => crate::application::call_service(message).await,
+            Err(e) => crate::application::error_response(&e),
+        };
+        completion_cb(request_id, CString::new(response).unwrap().into_raw());
     });
-    return CString::new(message.as_str()).unwrap().into_raw();
 }
 
 #[no_mangle]
 pub extern "C" fn receive_events() -> *mut c_char {
     // C文字列とRust文字列の変換だけ行って、中身の処理はapplicationメソッドに任せる
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let result = rt.block_on(async { crate::application::receive_events().await });
-    return CString::new(result).unwrap().into_raw();
+    let result = RUNTIME.block_on(async { crate::application::receive_events().await });
+    CString::new(result).unwrap().into_raw()
+}
+
+// EVENT SUBSCRIBEで開始した購読からイベントを取得する
+#[no_mangle]
+pub extern "C" fn receive_subscription_events(subscription_id_char: *const c_char) -> *mut c_char {
+    // C文字列とRust文字列の変換だけ行って、中身の処理はapplicationメソッドに任せる
+    let result = match to_message(subscription_id_char, "subscription_id") {
+        Ok(subscription_id) => RUNTIME.block_on(async {
+            crate::application::receive_subscription_events(&subscription_id).await
+        }),
+        Err(e) => crate::application::error_response(&e),
+    };
+    CString::new(result).unwrap().into_raw()
+}
+
+// クライアントの実装やドキュメントとの照合に利用するため、プロトコルのJSON Schemaを返す
+// 起動前でも呼び出せる。戻り値はrelease_stringで開放する必要がある
+#[no_mangle]
+pub extern "C" fn skyway_protocol_schema() -> *mut c_char {
+    CString::new(crate::protocol_schema()).unwrap().into_raw()
 }
 
 //========== 開放処理 ==========
 // ros終了時にC++側から呼ばれる
 // Rust側オブジェクトの開放処理と、WebRTC Gateway上のオブジェクトの開放処理を行う
+// SYSTEM SHUTDOWNと同じ順序・期限で、このノードが生成したConnection, Socket, Peer Objectを解放する
 #[no_mangle]
-pub extern "C" fn shutdown_service(peer_id: *const c_char, token: *const c_char) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async {
-        let c_str: &CStr = unsafe { CStr::from_ptr(peer_id) };
-        let peer_id = c_str.to_str().unwrap().to_string();
-
-        let c_str: &CStr = unsafe { CStr::from_ptr(token) };
-        let token = c_str.to_str().unwrap().to_string();
+pub extern "C" fn shutdown_service() {
+    RUNTIME.block_on(async {
+        let module = ShutdownService::builder().build();
+        let shutdown: &dyn ShutdownSequence = module.resolve_ref();
 
-        let param = RequestDto::Peer(PeerRequest::Delete {
-            params: PeerInfo::try_create(peer_id, token).expect("peer_info is invalid"),
-        });
-
-        let module = GeneralService::builder().build();
-        let service: &dyn Service = module.resolve_ref();
-
-        if let Err(e) = service.execute(param).await {
-            let error_message = format!("peer close error: {:?}", e);
-            LoggerHolder::global().error(error_message);
+        for step in shutdown.execute().await {
+            if !step.is_success {
+                let error_message = format!(
+                    "shutdown error: {:?} {}: {:?}",
+                    step.step, step.target, step.error
+                );
+                LoggerHolder::global().error(error_message);
+            }
+        }
+        snapshot::save();
+        snapshot::flush();
+        if let Some(recorder) = RECORDER.get() {
+            recorder.flush();
         }
 
         CallbackFunctionsHolder::global().peer_deleted_callback();
@@ -129,3 +232,73 @@ pub extern "C" fn print_string(message: *const c_char) {
     println!("{}", str);
     CallbackFunctionsHolder::global().release_str(message);
 }
+
+#[cfg(test)]
+mod c_to_rust_bridge_test {
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    // completion_cbに渡されたrequest_idと応答
+    type Completed = Mutex<Vec<(u64, String)>>;
+
+    static COMPLETED: Lazy<(Completed, Condvar)> =
+        Lazy::new(|| (Mutex::new(vec![]), Condvar::new()));
+
+    extern "C" fn completion_cb(request_id: u64, response: *mut c_char) {
+        let response = unsafe { CString::from_raw(response) };
+        let (lock, condvar) = &*COMPLETED;
+        lock.lock()
+            .unwrap()
+            .push((request_id, response.into_string().unwrap()));
+        condvar.notify_all();
+    }
+
+    #[test]
+    // UTF-8として解釈できない文字列が渡された場合も、abortせずにエラーの応答を返す
+    fn call_service_async_invalid_utf8() {
+        let message = CString::new(vec![0x7b, 0xff, 0xfe, 0x7d]).unwrap();
+        call_service_async(message.as_ptr(), 42, completion_cb);
+
+        let (lock, condvar) = &*COMPLETED;
+        let completed = condvar
+            .wait_timeout_while(lock.lock().unwrap(), Duration::from_secs(5), |completed| {
+                !completed.iter().any(|(request_id, _)| *request_id == 42)
+            })
+            .unwrap()
+            .0;
+        let (_, response) = completed
+            .iter()
+            .find(|(request_id, _)| *request_id == 42)
+            .unwrap();
+        let response: serde_json::Value = serde_json::from_str(response).unwrap();
+        assert_eq!(response["is_success"], false);
+        assert_eq!(response["result"]["code"], "INVALID_REQUEST");
+    }
+
+    // 応答を読み取り、Rust側で開放する
+    fn take_response(response: *mut c_char) -> serde_json::Value {
+        let response = unsafe { CString::from_raw(response) };
+        serde_json::from_str(response.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    // subscription_idがnullの場合も、abortせずにエラーの応答を返す
+    fn receive_subscription_events_null() {
+        let response = take_response(receive_subscription_events(std::ptr::null()));
+        assert_eq!(response["is_success"], false);
+        assert_eq!(response["result"]["code"], "INVALID_REQUEST");
+    }
+
+    #[test]
+    // subscription_idがUTF-8として解釈できない場合も、abortせずにエラーの応答を返す
+    fn receive_subscription_events_invalid_utf8() {
+        let subscription_id = CString::new(vec![0xff, 0xfe]).unwrap();
+        let response = take_response(receive_subscription_events(subscription_id.as_ptr()));
+        assert_eq!(response["is_success"], false);
+        assert_eq!(response["result"]["code"], "INVALID_REQUEST");
+    }
+}
@@ -4,8 +4,11 @@ use std::ffi::CString;
 use std::os::raw::{c_char, c_double};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::domain::entity::DataConnectionId;
+use crate::domain::entity::{DataConnectionId, DataId, PeerId};
+use crate::domain::repository::REQUEST_ID;
+use crate::ffi::rust_to_c_bridge::log_bridge;
 use crate::ffi::rust_to_c_bridge::state_objects::{
     CALLBACK_FUNCTIONS, LOGGER_INSTANCE, PROGRAM_STATE_INSTANCE,
 };
@@ -99,9 +102,7 @@ pub extern "C" fn register_callbacks(param: &CallbackFunctionsHolder) {
         param.release_str_c,
     );
 
-    if CALLBACK_FUNCTIONS.set(functions).is_err() {
-        return;
-    }
+    let _ = CALLBACK_FUNCTIONS.set(functions);
 }
 
 // ROSの機能でロギングするための関数を保持する
@@ -138,26 +139,41 @@ impl LoggerHolder {
     }
 
     pub fn debug(&self, message: impl Into<String>) {
-        let message_raw = CString::new(message.into()).unwrap().into_raw();
+        let message_raw = CString::new(with_request_id(message.into()))
+            .unwrap()
+            .into_raw();
         (self.debug_c)(message_raw);
     }
 
     pub fn info(&self, message: impl Into<String>) {
-        let message_raw = CString::new(message.into()).unwrap().into_raw();
+        let message_raw = CString::new(with_request_id(message.into()))
+            .unwrap()
+            .into_raw();
         (self.info_c)(message_raw);
     }
 
     pub fn warn(&self, message: impl Into<String>) {
-        let message_raw = CString::new(message.into()).unwrap().into_raw();
+        let message_raw = CString::new(with_request_id(message.into()))
+            .unwrap()
+            .into_raw();
         (self.warn_c)(message_raw);
     }
 
     pub fn error(&self, message: impl Into<String>) {
-        let message_raw = CString::new(message.into()).unwrap().into_raw();
+        let message_raw = CString::new(with_request_id(message.into()))
+            .unwrap()
+            .into_raw();
         (self.error_c)(message_raw);
     }
 }
 
+// call_serviceで処理中のリクエストから出力されたログは、そのrequest_idを先頭に付与して区別できるようにする
+fn with_request_id(message: String) -> String {
+    REQUEST_ID
+        .try_with(|request_id| format!("[{}] {}", request_id, message))
+        .unwrap_or(message)
+}
+
 // ROSの機能でロギングするための関数の実体を受け取るための関数
 #[no_mangle]
 pub extern "C" fn register_logger(
@@ -174,6 +190,8 @@ pub extern "C" fn register_logger(
             error_c,
         })
         .unwrap();
+    // Rust側でtracingを用いて記録したログを、受け取った関数に転送する
+    log_bridge::init();
 }
 
 // ROSの機能を制御するための関数を保持する
@@ -260,6 +278,13 @@ pub extern "C" fn register_program_state(
 pub(crate) struct DataPipeInfo {
     pub data_connection_id: DataConnectionId,
     pub data_pipe_port_num: u16,
+    // 終了時に解放するため、DataConnectionに紐づけたData Socketを保持しておく
+    pub data_id: DataId,
+    pub plugin_type: String,
+    // 再起動時にPluginをロードし直すため、ロード時のパラメータを保持しておく
+    pub plugins: Vec<Value>,
+    // CONNECTした場合は接続先が分かっているが、REDIRECTの場合は分からない
+    pub remote_peer_id: Option<PeerId>,
 }
 
 // DataChannel <-> ROS間のデータのやり取りはC++側のPluginでハンドリングする
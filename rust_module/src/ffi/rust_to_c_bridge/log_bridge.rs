@@ -0,0 +1,251 @@
+// tracingで記録したログを、register_loggerで受け取ったC++側のLoggerに転送するためのモジュール
+// 処理中のリクエストと、その中の段階(Socketの開放、Pluginのロード、WebRTC Gatewayの呼び出し、イベントの配信)は
+// tracingのspanとして記録し、ログの先頭に"request{command=CONNECT}:plugin_load{plugin_type=string}: "の形で付与する
+// 転送するログのレベルは、SYSTEM LOG_LEVELで実行中に変更できる
+use std::fmt;
+use std::fmt::Write;
+
+use once_cell::sync::OnceCell;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+use tracing_subscriber::reload;
+
+use crate::error;
+use crate::ffi::rust_to_c_bridge::c_functions_wrapper::LoggerHolder;
+
+/// 起動時に転送するログのレベル
+pub(crate) const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+
+// 実行中にレベルを変更するためのHandle
+static LEVEL_HANDLE: OnceCell<reload::Handle<LevelFilter, Registry>> = OnceCell::new();
+
+/// tracingのログをC++側のLoggerに転送するSubscriberを登録する
+/// register_loggerから呼ばれ、2度目以降の呼び出しは何もしない
+pub(crate) fn init() {
+    let (filter, handle) = reload::Layer::new(DEFAULT_LEVEL);
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(CallbackLayer::new(forward));
+    if tracing::subscriber::set_global_default(subscriber).is_ok() {
+        let _ = LEVEL_HANDLE.set(handle);
+    }
+}
+
+/// 現在転送しているログのレベル
+/// Subscriberが登録されていない場合はログを出力しないので、OFFを返す
+pub(crate) fn level() -> LevelFilter {
+    LEVEL_HANDLE
+        .get()
+        .and_then(|handle| handle.clone_current())
+        .unwrap_or(LevelFilter::OFF)
+}
+
+pub(crate) fn set_level(level: LevelFilter) -> Result<(), error::Error> {
+    let handle = LEVEL_HANDLE
+        .get()
+        .ok_or_else(|| error::Error::internal("logger is not initialized"))?;
+    handle
+        .reload(level)
+        .map_err(|e| error::Error::internal(e.to_string()))
+}
+
+// C++側にはTRACEに相当するレベルがないので、DEBUGとして出力する
+fn forward(level: Level, message: String) {
+    if !LoggerHolder::is_allocated() {
+        return;
+    }
+    let logger = LoggerHolder::global();
+    match level {
+        Level::ERROR => logger.error(message),
+        Level::WARN => logger.warn(message),
+        Level::INFO => logger.info(message),
+        _ => logger.debug(message),
+    }
+}
+
+// spanのフィールドを"key=value"の形に整形して保持する
+struct SpanFields(String);
+
+/// ログを1行の文字列に整形し、sinkに渡すLayer
+struct CallbackLayer<F> {
+    sink: F,
+}
+
+impl<F> CallbackLayer<F>
+where
+    F: Fn(Level, String) + Send + Sync + 'static,
+{
+    fn new(sink: F) -> Self {
+        CallbackLayer { sink }
+    }
+}
+
+impl<S, F> Layer<S> for CallbackLayer<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    F: Fn(Level, String) + Send + Sync + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    // 生成後にSpan::recordで値が決まったフィールドを追加する
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = FieldVisitor {
+                fields: std::mem::take(fields),
+                ..Default::default()
+            };
+            values.record(&mut visitor);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut line = String::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                line.push_str(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    if !fields.is_empty() {
+                        let _ = write!(line, "{{{}}}", fields);
+                    }
+                }
+                line.push(':');
+            }
+            line.push(' ');
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        line.push_str(&visitor.message);
+        if !visitor.fields.is_empty() {
+            if !visitor.message.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&visitor.fields);
+        }
+        (self.sink)(*event.metadata().level(), line);
+    }
+}
+
+// messageはそのまま、それ以外のフィールドは"key=value"の形で空白区切りにする
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+}
+
+impl FieldVisitor {
+    fn push(&mut self, field: &Field, value: fmt::Arguments<'_>) {
+        if field.name() == "message" {
+            let _ = self.message.write_fmt(value);
+            return;
+        }
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{}={}", field.name(), value);
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, format_args!("{:?}", value));
+    }
+}
+
+#[cfg(test)]
+mod log_bridge_test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    // 整形したログを記録するSubscriberを有効にした状態でfを実行する
+    fn capture(f: impl FnOnce()) -> Vec<(Level, String)> {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let sink = {
+            let lines = lines.clone();
+            move |level, line| lines.lock().unwrap().push((level, line))
+        };
+        let subscriber = Registry::default().with(CallbackLayer::new(sink));
+        tracing::subscriber::with_default(subscriber, f);
+        let lines = lines.lock().unwrap().clone();
+        lines
+    }
+
+    #[test]
+    // spanの名前とフィールドを、外側から順にログの先頭に付与する
+    fn format_spans() {
+        let lines = capture(|| {
+            let request = tracing::info_span!("request", command = "CONNECT");
+            let _request = request.enter();
+            let plugin = tracing::info_span!("plugin_load", plugin_type = "string", port = 50000);
+            let _plugin = plugin.enter();
+            tracing::warn!(error = "not declared", "failed to load plugin");
+        });
+        assert_eq!(
+            lines,
+            vec![(
+                Level::WARN,
+                "request{command=CONNECT}:plugin_load{plugin_type=string port=50000}: failed to load plugin error=not declared"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    // 生成後に値を記録したフィールドも付与する
+    fn recorded_field() {
+        let lines = capture(|| {
+            let span = tracing::debug_span!(
+                "gateway_call",
+                request_type = "PEER",
+                elapsed_ms = tracing::field::Empty
+            );
+            span.record("elapsed_ms", 12);
+            let _span = span.enter();
+            tracing::debug!("received response");
+        });
+        assert_eq!(
+            lines,
+            vec![(
+                Level::DEBUG,
+                "gateway_call{request_type=PEER elapsed_ms=12}: received response".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    // spanの外では、メッセージとフィールドのみを出力する
+    fn without_span() {
+        let lines = capture(|| {
+            tracing::info!("snapshot: adopted {}", "robot");
+            tracing::error!(code = "INTERNAL_ERROR");
+        });
+        assert_eq!(
+            lines,
+            vec![
+                (Level::INFO, "snapshot: adopted robot".to_string()),
+                (Level::ERROR, "code=INTERNAL_ERROR".to_string()),
+            ]
+        );
+    }
+}
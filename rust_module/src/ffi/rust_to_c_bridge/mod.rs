@@ -1,2 +1,4 @@
 pub(crate) mod c_functions_wrapper;
+pub(crate) mod log_bridge;
+pub(crate) mod snapshot;
 pub(crate) mod state_objects;
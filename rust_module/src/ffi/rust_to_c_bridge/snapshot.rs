@@ -0,0 +1,298 @@
+// ノードが異常終了しても、WebRTC Gateway上のPeer Object, Data Socket, Media Socketは残り続ける
+// 再起動後にそれらを引き継ぐか削除できるよう、GlobalStateの内容をJSONファイルに書き出しておく
+// 書き出しは一時ファイルに書いてからrenameするので、書き出し中に終了してもファイルが壊れることはない
+// リクエストやイベントを処理するスレッドを止めないよう、書き出しは専用のスレッドで行い、
+// 前回から内容が変化していない場合は書き出さない
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::application::dto::response::{CallResponseDto, PeerEntryDto};
+use crate::error;
+use crate::ffi::rust_to_c_bridge::c_functions_wrapper::DataPipeInfo;
+use crate::ffi::rust_to_c_bridge::state_objects::{
+    DATA_CONNECTION_STATE_INSTANCE, MEDIA_CONNECTION_STATE_INSTANCE, PEER_REGISTRY_INSTANCE,
+};
+
+// 書き出しを担当するスレッド
+// 起動時の突き合わせが終わるまでは、前回の内容を上書きしないよう生成しない
+static WRITER: OnceCell<Writer> = OnceCell::new();
+// 終了時に、書き出しが終わるのを待つ時間の上限
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 再起動時に引き継ぐ状態
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct StateSnapshot {
+    pub peers: Vec<PeerEntryDto>,
+    pub data_connections: Vec<DataPipeInfo>,
+    pub media_connections: Vec<CallResponseDto>,
+}
+
+impl StateSnapshot {
+    /// 現在のGlobalStateの内容を集める
+    /// 初期化されていない項目は空として扱う
+    pub fn capture() -> Self {
+        let peers = PEER_REGISTRY_INSTANCE
+            .get()
+            .map(|registry| registry.lock().unwrap().list())
+            .unwrap_or_default();
+        let mut data_connections: Vec<DataPipeInfo> = DATA_CONNECTION_STATE_INSTANCE
+            .get()
+            .map(|hash| hash.lock().unwrap().values().cloned().collect())
+            .unwrap_or_default();
+        data_connections.sort_by(|a, b| a.data_connection_id.cmp(&b.data_connection_id));
+        let mut media_connections: Vec<CallResponseDto> = MEDIA_CONNECTION_STATE_INSTANCE
+            .get()
+            .map(|hash| hash.lock().unwrap().values().cloned().collect())
+            .unwrap_or_default();
+        media_connections.sort_by(|a, b| a.media_connection_id.cmp(&b.media_connection_id));
+
+        StateSnapshot {
+            peers,
+            data_connections,
+            media_connections,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+            && self.data_connections.is_empty()
+            && self.media_connections.is_empty()
+    }
+
+    /// ファイルから読み込む。ファイルが存在しない場合はNoneを返す
+    pub fn read(path: &Path) -> Result<Option<Self>, error::Error> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                let message = format!("failed to read {}: {}", path.display(), e);
+                return Err(error::Error::internal(message));
+            }
+        };
+        serde_json::from_str::<StateSnapshot>(&json)
+            .map(Some)
+            .map_err(|e| {
+                let message = format!("invalid snapshot {}: {}", path.display(), e);
+                error::Error::internal(message)
+            })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), error::Error> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        // StateSnapshotのserializeが失敗するケースはRustの型システムにより発生しない
+        let json = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(&temp, json)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| {
+                let message = format!("failed to write {}: {}", path.display(), e);
+                error::Error::internal(message)
+            })
+    }
+}
+
+// 書き出しを依頼されたStateSnapshotを、専用のスレッドで順にファイルへ書き出す
+// 書き出しは1つのスレッドのみで行うので、一時ファイルを取り合うことはない
+struct Writer {
+    sender: Mutex<mpsc::Sender<StateSnapshot>>,
+    // 最後に書き出しを依頼した内容
+    last: Mutex<Option<StateSnapshot>>,
+    // 書き出しを依頼し、まだ書き出していない件数
+    pending: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl Writer {
+    fn spawn(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel::<StateSnapshot>();
+        let pending = Arc::new((Mutex::new(0usize), Condvar::new()));
+
+        let counter = pending.clone();
+        std::thread::spawn(move || {
+            while let Ok(mut snapshot) = receiver.recv() {
+                // 書き出している間に溜まった依頼は、最新の内容だけを書き出せば十分である
+                let mut count = 1;
+                while let Ok(next) = receiver.try_recv() {
+                    snapshot = next;
+                    count += 1;
+                }
+                if let Err(e) = snapshot.write(&path) {
+                    tracing::warn!("snapshot error: {}", e);
+                }
+
+                let (lock, condvar) = &*counter;
+                *lock.lock().unwrap() -= count;
+                condvar.notify_all();
+            }
+        });
+
+        Writer {
+            sender: Mutex::new(sender),
+            last: Mutex::new(None),
+            pending,
+        }
+    }
+
+    // 前回の依頼から内容が変化している場合のみ書き出しを依頼し、依頼したかどうかを返す
+    fn submit(&self, snapshot: StateSnapshot) -> bool {
+        // 依頼した順に書き出されるよう、lastのlockを保持したまま送信する
+        let mut last = self.last.lock().unwrap();
+        if last.as_ref() == Some(&snapshot) {
+            return false;
+        }
+
+        *self.pending.0.lock().unwrap() += 1;
+        // 書き出しスレッドはプロセスの終了まで受信を続けるので、送信は失敗しない
+        let _ = self.sender.lock().unwrap().send(snapshot.clone());
+        *last = Some(snapshot);
+        true
+    }
+
+    // 依頼済みの内容が全て書き出されるまで、timeoutを上限に待機する
+    fn flush(&self, timeout: Duration) {
+        let (lock, condvar) = &*self.pending;
+        let pending = lock.lock().unwrap();
+        let _ = condvar
+            .wait_timeout_while(pending, timeout, |pending| *pending > 0)
+            .unwrap();
+    }
+}
+
+/// 以降の状態の変化を書き出すようにし、現在の状態を書き出す
+pub(crate) fn enable(path: PathBuf) {
+    let _ = WRITER.set(Writer::spawn(path));
+    save();
+}
+
+/// 現在の状態が前回から変化していれば、書き出しを依頼する
+/// 書き出しは専用のスレッドで行うので、呼び出し元は待たされない
+/// 有効化されていない場合は何もしない。書き出しに失敗してもノードの動作は継続する
+pub(crate) fn save() {
+    if let Some(writer) = WRITER.get() {
+        writer.submit(StateSnapshot::capture());
+    }
+}
+
+/// 依頼済みの書き出しが終わるまで待機する
+/// 終了処理の最後に呼び、最終的な状態をファイルに残す
+pub(crate) fn flush() {
+    if let Some(writer) = WRITER.get() {
+        writer.flush(FLUSH_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod snapshot_test {
+    use super::*;
+    use crate::application::dto::response::SendParams;
+    use crate::domain::entity::{
+        DataConnectionId, DataId, MediaConnectionId, PeerId, SerializableId, Token,
+    };
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "skyway_snapshot_{}_{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn write_and_read() {
+        let snapshot = StateSnapshot {
+            peers: vec![PeerEntryDto {
+                peer_id: PeerId::new("peer_id"),
+                token: Token::try_create("pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap(),
+                data_connection_ids: vec![DataConnectionId::try_create(
+                    "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+                )
+                .unwrap()],
+                media_connection_ids: vec![],
+            }],
+            data_connections: vec![DataPipeInfo {
+                data_connection_id: DataConnectionId::try_create(
+                    "dc-4995f372-fb6a-4196-b30a-ce11e5c7f56c",
+                )
+                .unwrap(),
+                data_pipe_port_num: 60000,
+                data_id: DataId::try_create("da-50a32bab-b3d9-4913-8e20-f79c90a6a211").unwrap(),
+                plugin_type: "string".to_string(),
+                plugins: vec![
+                    serde_json::json!({"plugin_name": "string_loopback::StringLoopback"}),
+                ],
+                remote_peer_id: Some(PeerId::new("target_id")),
+            }],
+            media_connections: vec![CallResponseDto {
+                send_params: SendParams {
+                    video: None,
+                    audio: None,
+                },
+                redirect_params: None,
+                media_connection_id: MediaConnectionId::try_create(
+                    "mc-102127d9-30de-413b-93f7-41a33e39d82b",
+                )
+                .unwrap(),
+                remote_peer_id: Some(PeerId::new("target_id")),
+            }],
+        };
+
+        let path = path("write_and_read");
+        snapshot.write(&path).unwrap();
+        let result = StateSnapshot::read(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(result, Ok(Some(snapshot)));
+    }
+
+    #[test]
+    // 初回起動時など、ファイルが存在しない場合は引き継ぐものがない
+    fn not_found() {
+        let result = StateSnapshot::read(&path("not_found"));
+        assert_eq!(result, Ok(None));
+    }
+
+    #[test]
+    fn broken_file() {
+        let path = path("broken_file");
+        std::fs::write(&path, "{").unwrap();
+        let result = StateSnapshot::read(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(result, Err(error::Error::Internal { .. })));
+    }
+
+    #[test]
+    // 内容が変化した場合のみ書き出しを依頼し、最新の内容がファイルに残る
+    fn writer() {
+        let path = path("writer");
+        let writer = Writer::spawn(path.clone());
+
+        let empty = StateSnapshot::default();
+        let snapshot = StateSnapshot {
+            peers: vec![PeerEntryDto {
+                peer_id: PeerId::new("peer_id"),
+                token: Token::try_create("pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap(),
+                data_connection_ids: vec![],
+                media_connection_ids: vec![],
+            }],
+            ..StateSnapshot::default()
+        };
+        assert!(writer.submit(empty.clone()));
+        assert!(!writer.submit(empty));
+        assert!(writer.submit(snapshot.clone()));
+        assert!(!writer.submit(snapshot.clone()));
+
+        writer.flush(Duration::from_secs(5));
+        let result = StateSnapshot::read(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(result, Ok(Some(snapshot)));
+        assert_eq!(*writer.pending.0.lock().unwrap(), 0);
+    }
+}
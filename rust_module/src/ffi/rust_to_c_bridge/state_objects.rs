@@ -1,17 +1,34 @@
 // C++側とオブジェクトのやり取りをする回数を最低限にするため、C++側のモジュールで本来所持するべきオブジェクトはOnceCellで保持する
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::c_char;
 use std::sync::Arc;
 
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use shaku::{Component, Interface};
 use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::level_filters::LevelFilter;
 
-use crate::application::dto::response::CallResponseDto;
-use crate::domain::entity::{DataConnectionId, MediaConnectionId};
+use crate::application::dto::request::{
+    DataRequestDto, MediaRequestDto, PeerRequestDto, RequestDto,
+};
+use crate::application::dto::response::{
+    CallResponseDto, DataResponseDto, MediaResponseDto, PeerEntryDto, PeerResponseDto, ResponseDto,
+    ResponseDtoResult,
+};
+use crate::application::usecase::event::correlation::Correlations;
+use crate::application::usecase::event::subscription::Subscriptions;
+use crate::application::usecase::peer::recovery::RecoveryState;
+use crate::application::usecase::system::metrics::Metrics;
+use crate::config::Config;
+use crate::domain::entity::event::EventMessage;
+use crate::domain::entity::{DataConnectionId, MediaConnectionId, PeerId, PeerInfo, Token};
+use crate::error;
 use crate::ffi::rust_to_c_bridge::c_functions_wrapper::{
     CallbackFunctionsHolder, DataPipeInfo, LoggerHolder, PluginLoadResult, ProgramStateHolder,
 };
+use crate::ffi::rust_to_c_bridge::log_bridge;
+use crate::ffi::rust_to_c_bridge::snapshot;
+use crate::infra::recording::Recorder;
 
 #[cfg(test)]
 use mockall::automock;
@@ -22,7 +39,10 @@ pub(crate) static CALLBACK_FUNCTIONS: OnceCell<CallbackFunctionsHolder> = OnceCe
 pub(crate) static LOGGER_INSTANCE: OnceCell<LoggerHolder> = OnceCell::new();
 // Programの状態を取得・操作するために必要なC++側の関数を保持する
 pub(crate) static PROGRAM_STATE_INSTANCE: OnceCell<ProgramStateHolder> = OnceCell::new();
+// 起動時に与えられた設定値を保持する
+pub(crate) static CONFIG: OnceCell<Config> = OnceCell::new();
 // WebRTC Crate起動時に生成されたSender, Receiverを破棄すると通信できなくなるので、保持し続ける
+// WebRTC Crateが再起動された場合もこのSender, Receiverは変わらず、中継先のみが差し替えられる
 pub(crate) static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
 // Event処理やDisconnect時に利用するため、DataConnection確立時に
 // Source Topic とDestination Topicの情報を集めておく
@@ -34,8 +54,31 @@ pub(crate) static DATA_CONNECTION_STATE_INSTANCE: OnceCell<
 pub(crate) static MEDIA_CONNECTION_STATE_INSTANCE: OnceCell<
     std::sync::Mutex<HashMap<MediaConnectionId, CallResponseDto>>,
 > = OnceCell::new();
+// PEER CLOSE時の自動復旧に利用するため、Peer Object生成時のパラメータと、
+// 自身から確立を要求したDataConnection, MediaConnectionのパラメータを集めておく
+pub(crate) static RECOVERY_STATE_INSTANCE: OnceCell<std::sync::Mutex<RecoveryState>> =
+    OnceCell::new();
+// 複数のPeer Objectを扱えるよう、このノードが生成したPeer Objectと、それに属するConnectionを集めておく
+// 終了時には、ここに含まれる全てのPeer Objectを削除する
+pub(crate) static PEER_REGISTRY_INSTANCE: OnceCell<std::sync::Mutex<PeerRegistry>> =
+    OnceCell::new();
+// SYSTEM STATUSで稼働時間を返すため、起動時刻を保持する
+pub(crate) static STARTED_AT: OnceCell<std::time::Instant> = OnceCell::new();
+// receive_eventsで複数のクライアントにイベントを配信するため、購読の一覧を保持する
+pub(crate) static SUBSCRIPTIONS_INSTANCE: OnceCell<Subscriptions> = OnceCell::new();
+// イベントにその原因となったリクエストのrequest_idを付与するため、生成したオブジェクトとの対応を保持する
+pub(crate) static CORRELATIONS_INSTANCE: Lazy<Correlations> = Lazy::new(Correlations::default);
+// SYSTEM METRICSで返すため、リクエスト、イベント、WebRTC Gatewayの呼び出しの統計を集めておく
+pub(crate) static METRICS_INSTANCE: Lazy<Metrics> = Lazy::new(Metrics::default);
+// WebRTC Gatewayとのやり取りの記録先。設定でrecord_pathを指定した場合のみ保持する
+pub(crate) static RECORDER: OnceCell<Recorder> = OnceCell::new();
+// Rust側の非同期処理は全てこのruntime上で実行する
+// FFIの呼び出しごとにruntimeを生成せず、プログラムの終了まで同じものを使い続ける
+pub(crate) static RUNTIME: Lazy<tokio::runtime::Runtime> =
+    Lazy::new(|| tokio::runtime::Runtime::new().expect("failed to create tokio runtime"));
 
 #[cfg_attr(test, automock)]
+#[allow(dead_code)]
 pub(crate) trait CallbackFunctions: Interface {
     fn create_peer_callback(&self, peer_id: &str, token: &str);
     fn peer_deleted_callback(&self);
@@ -87,11 +130,17 @@ impl CallbackFunctions for CallbackFunctionsImpl {
 #[shaku(interface = CallbackFunctions)]
 pub(crate) struct CallbackFunctionsImpl {}
 
+/// ログはtracingを経由してC++側のLoggerに転送されるので、処理中のspanが付与される
+#[allow(dead_code)]
+#[cfg_attr(test, automock)]
 pub(crate) trait Logger: Interface {
     fn debug(&self, message: &str);
     fn info(&self, message: &str);
     fn warn(&self, message: &str);
     fn error(&self, message: &str);
+    /// C++側に転送しているログのレベル
+    fn level(&self) -> LevelFilter;
+    fn set_level(&self, level: LevelFilter) -> Result<(), error::Error>;
 }
 
 #[derive(Component)]
@@ -100,22 +149,32 @@ pub(crate) struct LoggerImpl {}
 
 impl Logger for LoggerImpl {
     fn debug(&self, message: &str) {
-        LoggerHolder::global().debug(message)
+        tracing::debug!("{}", message)
     }
 
     fn info(&self, message: &str) {
-        LoggerHolder::global().info(message)
+        tracing::info!("{}", message)
     }
 
     fn warn(&self, message: &str) {
-        LoggerHolder::global().warn(message)
+        tracing::warn!("{}", message)
     }
 
     fn error(&self, message: &str) {
-        LoggerHolder::global().error(message)
+        tracing::error!("{}", message)
+    }
+
+    fn level(&self) -> LevelFilter {
+        log_bridge::level()
+    }
+
+    fn set_level(&self, level: LevelFilter) -> Result<(), error::Error> {
+        log_bridge::set_level(level)
     }
 }
 
+#[allow(dead_code)]
+#[cfg_attr(test, automock)]
 pub(crate) trait ProgramState: Interface {
     fn is_running(&self) -> bool;
     fn is_shutting_down(&self) -> bool;
@@ -152,20 +211,28 @@ impl ProgramState for ProgramStateImpl {
 
 pub(crate) trait Channels: Interface {
     fn sender(&self) -> &mpsc::Sender<(oneshot::Sender<String>, String)>;
-    fn receiver(&self) -> &Mutex<mpsc::Receiver<String>>;
+    fn receiver(&self) -> &Mutex<mpsc::Receiver<EventMessage>>;
+    // rust_module自身が生成したイベントを、receiverに届けるためのSender
+    fn event_sender(&self) -> &mpsc::Sender<EventMessage>;
 }
 
 pub(crate) struct ChannelsImpl {
     sender: mpsc::Sender<(oneshot::Sender<String>, String)>,
-    receiver: Mutex<mpsc::Receiver<String>>,
+    event_sender: mpsc::Sender<EventMessage>,
+    receiver: Mutex<mpsc::Receiver<EventMessage>>,
 }
 
 impl ChannelsImpl {
     pub fn new(
         sender: mpsc::Sender<(oneshot::Sender<String>, String)>,
-        receiver: Mutex<mpsc::Receiver<String>>,
+        event_sender: mpsc::Sender<EventMessage>,
+        receiver: Mutex<mpsc::Receiver<EventMessage>>,
     ) -> Self {
-        Self { sender, receiver }
+        Self {
+            sender,
+            event_sender,
+            receiver,
+        }
     }
 }
 
@@ -174,18 +241,32 @@ impl Channels for ChannelsImpl {
         &self.sender
     }
 
-    fn receiver(&self) -> &Mutex<mpsc::Receiver<String>> {
+    fn receiver(&self) -> &Mutex<mpsc::Receiver<EventMessage>> {
         &self.receiver
     }
+
+    fn event_sender(&self) -> &mpsc::Sender<EventMessage> {
+        &self.event_sender
+    }
 }
 
 #[cfg_attr(test, automock)]
 pub(crate) trait GlobalState: Interface {
+    fn config(&self) -> &'static Config;
     fn channels(&self) -> &'static Arc<dyn Channels>;
     fn program_state(&self) -> &'static ProgramStateHolder;
+    fn runtime(&self) -> &'static tokio::runtime::Handle;
+    fn recovery_state(&self) -> &'static std::sync::Mutex<RecoveryState>;
+    fn subscriptions(&self) -> &'static Subscriptions;
+    fn correlations(&self) -> &'static Correlations;
+    fn metrics(&self) -> &'static Metrics;
+    fn recorder(&self) -> Option<&'static Recorder>;
+    fn peer_registry(&self) -> &'static std::sync::Mutex<PeerRegistry>;
+    fn uptime(&self) -> std::time::Duration;
     fn store_topic(&self, data_connection_id: DataConnectionId, response: DataPipeInfo);
     fn find_topic(&self, data_connection_id: &DataConnectionId) -> Option<DataPipeInfo>;
     fn remove_topic(&self, data_connection_id: &DataConnectionId) -> Option<DataPipeInfo>;
+    fn list_topics(&self) -> Vec<DataPipeInfo>;
     fn store_call_response(
         &self,
         media_connection_id: MediaConnectionId,
@@ -195,6 +276,11 @@ pub(crate) trait GlobalState: Interface {
         &self,
         media_connection_id: &MediaConnectionId,
     ) -> Option<CallResponseDto>;
+    fn remove_call_response(
+        &self,
+        media_connection_id: &MediaConnectionId,
+    ) -> Option<CallResponseDto>;
+    fn list_call_responses(&self) -> Vec<CallResponseDto>;
 }
 
 #[derive(Component)]
@@ -202,6 +288,10 @@ pub(crate) trait GlobalState: Interface {
 pub(crate) struct GlobalStateImpl {}
 
 impl GlobalState for GlobalStateImpl {
+    fn config(&self) -> &'static Config {
+        CONFIG.get().expect("CONFIG is not initialized")
+    }
+
     fn channels(&self) -> &'static Arc<dyn Channels> {
         CHANNELS.get().expect("CHANNELS is not initialized")
     }
@@ -212,9 +302,51 @@ impl GlobalState for GlobalStateImpl {
             .expect("PROGRAM_STATE is not initialized")
     }
 
+    fn runtime(&self) -> &'static tokio::runtime::Handle {
+        RUNTIME.handle()
+    }
+
+    fn recovery_state(&self) -> &'static std::sync::Mutex<RecoveryState> {
+        RECOVERY_STATE_INSTANCE
+            .get()
+            .expect("RECOVERY_STATE is not initialized")
+    }
+
+    fn subscriptions(&self) -> &'static Subscriptions {
+        SUBSCRIPTIONS_INSTANCE
+            .get()
+            .expect("SUBSCRIPTIONS is not initialized")
+    }
+
+    fn correlations(&self) -> &'static Correlations {
+        &CORRELATIONS_INSTANCE
+    }
+
+    fn metrics(&self) -> &'static Metrics {
+        &METRICS_INSTANCE
+    }
+
+    fn recorder(&self) -> Option<&'static Recorder> {
+        RECORDER.get()
+    }
+
+    fn peer_registry(&self) -> &'static std::sync::Mutex<PeerRegistry> {
+        PEER_REGISTRY_INSTANCE
+            .get()
+            .expect("PEER_REGISTRY is not initialized")
+    }
+
+    fn uptime(&self) -> std::time::Duration {
+        STARTED_AT
+            .get()
+            .expect("STARTED_AT is not initialized")
+            .elapsed()
+    }
+
     fn store_topic(&self, data_connection_id: DataConnectionId, response: DataPipeInfo) {
         let hash = DATA_CONNECTION_STATE_INSTANCE.get().unwrap();
         hash.lock().unwrap().insert(data_connection_id, response);
+        snapshot::save();
     }
 
     fn find_topic(&self, data_connection_id: &DataConnectionId) -> Option<DataPipeInfo> {
@@ -224,16 +356,27 @@ impl GlobalState for GlobalStateImpl {
             .lock()
             .unwrap();
         let item = hash.get(data_connection_id);
-        item.map(|item| item.clone())
+        item.cloned()
     }
 
     fn remove_topic(&self, data_connection_id: &DataConnectionId) -> Option<DataPipeInfo> {
-        let mut hash = DATA_CONNECTION_STATE_INSTANCE
+        let item = DATA_CONNECTION_STATE_INSTANCE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .remove(data_connection_id);
+        snapshot::save();
+        item
+    }
+
+    fn list_topics(&self) -> Vec<DataPipeInfo> {
+        let hash = DATA_CONNECTION_STATE_INSTANCE
             .get()
             .unwrap()
             .lock()
             .unwrap();
-        return hash.remove(data_connection_id);
+        hash.values().cloned().collect()
     }
 
     fn store_call_response(
@@ -243,6 +386,7 @@ impl GlobalState for GlobalStateImpl {
     ) {
         let hash = MEDIA_CONNECTION_STATE_INSTANCE.get().unwrap();
         hash.lock().unwrap().insert(media_connection_id, response);
+        snapshot::save();
     }
 
     fn find_call_response(
@@ -255,6 +399,173 @@ impl GlobalState for GlobalStateImpl {
             .lock()
             .unwrap();
         let item = hash.get(media_connection_id);
-        item.map(|item| item.clone())
+        item.cloned()
+    }
+
+    fn remove_call_response(
+        &self,
+        media_connection_id: &MediaConnectionId,
+    ) -> Option<CallResponseDto> {
+        let item = MEDIA_CONNECTION_STATE_INSTANCE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .remove(media_connection_id);
+        snapshot::save();
+        item
+    }
+
+    fn list_call_responses(&self) -> Vec<CallResponseDto> {
+        let hash = MEDIA_CONNECTION_STATE_INSTANCE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap();
+        hash.values().cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PeerEntry {
+    token: Token,
+    data_connections: BTreeSet<DataConnectionId>,
+    media_connections: BTreeSet<MediaConnectionId>,
+}
+
+/// このノードが生成したPeer Objectと、それに属するDataConnection, MediaConnectionの一覧
+/// 自身から確立したものはリクエストの成功時に、相手側から確立されたものはPEER CONNECTION, CALLイベントの受信時に登録する
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct PeerRegistry {
+    peers: BTreeMap<PeerId, PeerEntry>,
+}
+
+impl PeerRegistry {
+    pub fn insert_peer(&mut self, peer_info: &PeerInfo) {
+        self.peers.insert(
+            peer_info.peer_id(),
+            PeerEntry {
+                token: peer_info.token(),
+                data_connections: BTreeSet::new(),
+                media_connections: BTreeSet::new(),
+            },
+        );
+    }
+
+    /// Peer Objectを登録から外し、属していたConnectionとともに返す
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> Option<PeerEntryDto> {
+        self.peers
+            .remove(peer_id)
+            .map(|entry| to_dto(peer_id, &entry))
+    }
+
+    // 登録されていないPeer Objectに属するConnectionは無視する
+    pub fn insert_data_connection(
+        &mut self,
+        peer_id: &PeerId,
+        data_connection_id: DataConnectionId,
+    ) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.data_connections.insert(data_connection_id);
+        }
+    }
+
+    pub fn insert_media_connection(
+        &mut self,
+        peer_id: &PeerId,
+        media_connection_id: MediaConnectionId,
+    ) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.media_connections.insert(media_connection_id);
+        }
+    }
+
+    pub fn remove_data_connection(&mut self, data_connection_id: &DataConnectionId) {
+        for entry in self.peers.values_mut() {
+            entry.data_connections.remove(data_connection_id);
+        }
+    }
+
+    pub fn remove_media_connection(&mut self, media_connection_id: &MediaConnectionId) {
+        for entry in self.peers.values_mut() {
+            entry.media_connections.remove(media_connection_id);
+        }
+    }
+
+    /// DataConnectionが属するPeer Object
+    pub fn find_data_connection_owner(
+        &self,
+        data_connection_id: &DataConnectionId,
+    ) -> Option<PeerId> {
+        self.peers
+            .iter()
+            .find(|(_, entry)| entry.data_connections.contains(data_connection_id))
+            .map(|(peer_id, _)| peer_id.clone())
+    }
+
+    /// MediaConnectionが属するPeer Object
+    pub fn find_media_connection_owner(
+        &self,
+        media_connection_id: &MediaConnectionId,
+    ) -> Option<PeerId> {
+        self.peers
+            .iter()
+            .find(|(_, entry)| entry.media_connections.contains(media_connection_id))
+            .map(|(peer_id, _)| peer_id.clone())
+    }
+
+    /// PeerId順の一覧
+    pub fn list(&self) -> Vec<PeerEntryDto> {
+        self.peers
+            .iter()
+            .map(|(peer_id, entry)| to_dto(peer_id, entry))
+            .collect()
+    }
+
+    /// 成功したリクエストから、Peer Object, Connectionを登録・削除する
+    pub fn record(&mut self, request: &RequestDto, response: &ResponseDtoResult) {
+        let response = match response {
+            ResponseDtoResult::Success(response) => response,
+            ResponseDtoResult::Error(_) => return,
+        };
+
+        match (request, response) {
+            (
+                RequestDto::Peer(PeerRequestDto::Create { .. }),
+                ResponseDto::Peer(PeerResponseDto::Create(peer_info)),
+            ) => self.insert_peer(peer_info),
+            (
+                RequestDto::Peer(PeerRequestDto::Delete { params }),
+                ResponseDto::Peer(PeerResponseDto::Delete(_)),
+            ) => {
+                self.remove_peer(&params.peer_id());
+            }
+            (
+                RequestDto::Data(DataRequestDto::Connect { params }),
+                ResponseDto::Data(DataResponseDto::Connect(wrapper)),
+            ) => self.insert_data_connection(&params.peer_id, wrapper.data_connection_id.clone()),
+            (
+                RequestDto::Data(DataRequestDto::Disconnect { params }),
+                ResponseDto::Data(DataResponseDto::Disconnect(_)),
+            ) => self.remove_data_connection(&params.data_connection_id),
+            (
+                RequestDto::Media(MediaRequestDto::Call { params }),
+                ResponseDto::Media(MediaResponseDto::Call(wrapper)),
+            ) => self.insert_media_connection(&params.peer_id, wrapper.media_connection_id.clone()),
+            (
+                RequestDto::Media(MediaRequestDto::Disconnect { params }),
+                ResponseDto::Media(MediaResponseDto::Disconnect(_)),
+            ) => self.remove_media_connection(&params.media_connection_id),
+            _ => {}
+        }
+    }
+}
+
+fn to_dto(peer_id: &PeerId, entry: &PeerEntry) -> PeerEntryDto {
+    PeerEntryDto {
+        peer_id: peer_id.clone(),
+        token: entry.token.clone(),
+        data_connection_ids: entry.data_connections.iter().cloned().collect(),
+        media_connection_ids: entry.media_connections.iter().cloned().collect(),
     }
 }
@@ -0,0 +1,81 @@
+// C++側のPluginの代わりに、DataConnectionで受け取ったデータをそのまま相手側に送り返すUDP echo
+// WebRTC Gatewayは127.0.0.1の返したポートにデータを転送するので、受け取ったデータをtargetのData Socketに送る
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+// 停止を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+// 待ち受けているポートごとに、停止させるためのフラグを保持する
+static ECHOES: Lazy<Mutex<HashMap<u16, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// targetに送り返すUDP echoを開始し、待ち受けるポートを返す
+pub(crate) fn start(target_ip: &str, target_port: u16) -> std::io::Result<u16> {
+    let target_ip: IpAddr = target_ip
+        .parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let target = SocketAddr::new(target_ip, target_port);
+
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let port = socket.local_addr()?.port();
+
+    let is_stopped = Arc::new(AtomicBool::new(false));
+    ECHOES.lock().unwrap().insert(port, is_stopped.clone());
+    std::thread::spawn(move || {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        while !is_stopped.load(Ordering::Relaxed) {
+            // タイムアウトした場合は停止を確認してから待ち直す
+            if let Ok((len, _)) = socket.recv_from(&mut buffer) {
+                let _ = socket.send_to(&buffer[..len], target);
+            }
+        }
+    });
+
+    Ok(port)
+}
+
+/// 指定したポートのUDP echoを停止する。該当するものがなければfalseを返す
+pub(crate) fn stop(port: u16) -> bool {
+    match ECHOES.lock().unwrap().remove(&port) {
+        Some(is_stopped) => {
+            is_stopped.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod echo_test {
+    use super::*;
+
+    #[test]
+    // 受け取ったデータをtargetに送り、停止後は送らない
+    fn echo() {
+        let target = UdpSocket::bind("127.0.0.1:0").unwrap();
+        target
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let port = start("127.0.0.1", target.local_addr().unwrap().port()).unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"hello", ("127.0.0.1", port)).unwrap();
+        let mut buffer = [0u8; 16];
+        let (len, _) = target.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"hello");
+
+        assert!(stop(port));
+        assert!(!stop(port));
+        std::thread::sleep(POLL_INTERVAL * 2);
+        sender.send_to(b"world", ("127.0.0.1", port)).unwrap();
+        assert!(target.recv_from(&mut buffer).is_err());
+    }
+}
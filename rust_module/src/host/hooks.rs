@@ -0,0 +1,204 @@
+// C++側(src/ffi.cpp, src/ffi_bridge.cpp)がROSの機能で実装している関数の、ROSを使わない実装
+// ログは標準エラー出力に書き出し、プログラムの状態はシグナルやSYSTEM SHUTDOWNで終了に遷移させる
+// Rust側から受け取った文字列は、C++側と同様にこちらで開放する
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_double};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use super::echo;
+use crate::ffi::rust_to_c_bridge::c_functions_wrapper::{
+    register_callbacks, register_logger, register_program_state, CallbackFunctionsHolder,
+    PluginLoadResult,
+};
+
+// 終了に遷移したかどうかと、その待機に利用するCondvar
+static SHUTDOWN: Lazy<(Mutex<bool>, Condvar)> = Lazy::new(|| (Mutex::new(false), Condvar::new()));
+
+/// logger, program state, callbackを全てRust側に登録する
+/// run, run_with_configより先に呼ぶ必要がある
+pub(crate) fn register() {
+    register_logger(log_debug, log_info, log_warn, log_error);
+    register_program_state(
+        is_running,
+        is_shutting_down,
+        sleep,
+        wait_for_shutdown,
+        shutdown,
+    );
+    register_callbacks(&CallbackFunctionsHolder::new(
+        create_peer_callback,
+        peer_deleted_callback,
+        data_callback,
+        data_connection_deleted_callback,
+        release_str,
+    ));
+}
+
+// Rust側でCString::into_rawされた文字列を受け取り、開放する
+fn take(message: *const c_char) -> String {
+    if message.is_null() {
+        return String::new();
+    }
+    let message = unsafe { CString::from_raw(message as *mut c_char) };
+    message.to_string_lossy().into_owned()
+}
+
+pub(crate) fn log(level: &str, message: &str) {
+    eprintln!("[{}] {}", level, message);
+}
+
+//========== logger ==========
+extern "C" fn log_debug(message: *const c_char) {
+    log("DEBUG", &take(message));
+}
+
+extern "C" fn log_info(message: *const c_char) {
+    log("INFO", &take(message));
+}
+
+extern "C" fn log_warn(message: *const c_char) {
+    log("WARN", &take(message));
+}
+
+extern "C" fn log_error(message: *const c_char) {
+    log("ERROR", &take(message));
+}
+
+//========== program state ==========
+pub(crate) extern "C" fn is_running() -> bool {
+    !is_shutting_down()
+}
+
+pub(crate) extern "C" fn is_shutting_down() -> bool {
+    *SHUTDOWN.0.lock().unwrap()
+}
+
+extern "C" fn sleep(duration: c_double) {
+    std::thread::sleep(Duration::from_secs_f64(duration.max(0.0)));
+}
+
+pub(crate) extern "C" fn wait_for_shutdown() {
+    let (lock, condvar) = &*SHUTDOWN;
+    let mut is_shutting_down = lock.lock().unwrap();
+    while !*is_shutting_down {
+        is_shutting_down = condvar.wait(is_shutting_down).unwrap();
+    }
+}
+
+pub(crate) extern "C" fn shutdown() {
+    let (lock, condvar) = &*SHUTDOWN;
+    *lock.lock().unwrap() = true;
+    condvar.notify_all();
+}
+
+//========== callbacks ==========
+extern "C" fn create_peer_callback(peer_id: *mut c_char, token: *mut c_char) {
+    let peer_id = take(peer_id);
+    let _ = take(token);
+    log("INFO", &format!("peer object is created: {}", peer_id));
+}
+
+// C++側と同様に、Peer Objectが削除された場合はプログラム全体を終了する
+extern "C" fn peer_deleted_callback() {
+    log("INFO", "peer object is deleted");
+    shutdown();
+}
+
+// Pluginの種別やパラメータによらず、受け取ったデータをそのまま相手側に送り返す
+extern "C" fn data_callback(
+    target_ip: *mut c_char,
+    target_port: u16,
+    plugin_type: *mut c_char,
+    plugin_param: *mut c_char,
+) -> PluginLoadResult {
+    let target_ip = take(target_ip);
+    let plugin_type = take(plugin_type);
+    let _ = take(plugin_param);
+
+    match echo::start(&target_ip, target_port) {
+        Ok(port) => {
+            log(
+                "INFO",
+                &format!(
+                    "{} plugin is replaced with udp echo on port {}",
+                    plugin_type, port
+                ),
+            );
+            PluginLoadResult {
+                is_success: true,
+                port,
+                error_message: std::ptr::null_mut(),
+            }
+        }
+        Err(e) => PluginLoadResult {
+            is_success: false,
+            port: 0,
+            error_message: CString::new(format!("udp echo error: {}", e))
+                .unwrap()
+                .into_raw(),
+        },
+    }
+}
+
+extern "C" fn data_connection_deleted_callback(port: u16) {
+    if !echo::stop(port) {
+        log(
+            "WARN",
+            &format!("udp echo is not found on port {} at data deletion", port),
+        );
+    }
+}
+
+// Rust側に渡した文字列(PluginLoadResultのerror_message)を開放する
+extern "C" fn release_str(message: *const c_char) {
+    let _ = take(message);
+}
+
+// Rust側から返された文字列をStringにコピーし、Rust側で開放させる
+pub(crate) fn copy_and_release(message: *mut c_char) -> String {
+    let copied = unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned();
+    crate::ffi::c_to_rust_bridge::release_string(message);
+    copied
+}
+
+#[cfg(test)]
+mod hooks_test {
+    use super::*;
+
+    #[test]
+    // Pluginのロードに成功した場合は、UDP echoのポートを返す
+    fn load_plugin() {
+        let result = data_callback(
+            CString::new("127.0.0.1").unwrap().into_raw(),
+            10000,
+            CString::new("string").unwrap().into_raw(),
+            CString::new("[]").unwrap().into_raw(),
+        );
+        assert!(result.is_success);
+        assert!(result.error_message.is_null());
+        assert!(echo::stop(result.port));
+    }
+
+    #[test]
+    // 送り返す先が不正な場合はエラーメッセージを返し、それはrelease_strで開放できる
+    fn load_plugin_error() {
+        let result = data_callback(
+            CString::new("not an address").unwrap().into_raw(),
+            10000,
+            CString::new("string").unwrap().into_raw(),
+            CString::new("[]").unwrap().into_raw(),
+        );
+        assert!(!result.is_success);
+        let message = unsafe { CStr::from_ptr(result.error_message) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(message.starts_with("udp echo error"));
+        release_str(result.error_message);
+    }
+}
@@ -0,0 +1,249 @@
+// ROSを使わずにrust_moduleを動作させるためのホスト
+// src/main.cppの代わりにlogger, program state, callbackを登録し、
+// SkyWayControl, SkyWayEventsの代わりに標準入出力またはUnix domain socketでJSON Linesを送受信する
+//
+// 1行のリクエストに対して1行のレスポンスを返し、SkyWayEventsに配信されるイベントも同じ出力に1行ずつ書き出す
+// イベントは"command":"EVENT"で、レスポンスはrequest_idでリクエストと対応付けられる
+// skyway_hostバイナリから利用する
+mod echo;
+mod hooks;
+
+use std::ffi::CString;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::ffi::c_to_rust_bridge;
+use crate::ffi::rust_to_c_bridge::state_objects::{CHANNELS, RUNTIME};
+
+// 終了処理を一度だけ行うためのフラグ
+static STOPPING: AtomicBool = AtomicBool::new(false);
+// rust_mainの初期化の完了を確認する間隔
+const START_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// リクエストを受け付け、レスポンスとイベントを返す経路
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transport {
+    /// 標準入力からリクエストを読み、標準出力に書き出す。標準入力が閉じられた場合は終了する
+    Stdio,
+    /// 指定したパスで待ち受け、接続したクライアントごとにリクエストを受け付ける
+    /// イベントは接続中の全てのクライアントに書き出す
+    UnixSocket(PathBuf),
+}
+
+type Writer = Arc<Mutex<dyn Write + Send>>;
+
+// イベントを書き出す先
+#[derive(Default)]
+struct Broadcast {
+    writers: Mutex<Vec<Writer>>,
+}
+
+impl Broadcast {
+    fn add(&self, writer: Writer) {
+        self.writers.lock().unwrap().push(writer);
+    }
+
+    // 書き込めなくなった出力先は、切断されたとみなして取り除く
+    fn send(&self, message: &str) {
+        self.writers
+            .lock()
+            .unwrap()
+            .retain(|writer| write_line(writer, message).is_ok());
+    }
+}
+
+fn write_line(writer: &Writer, message: &str) -> std::io::Result<()> {
+    let mut writer = writer.lock().unwrap();
+    writeln!(writer, "{}", message)?;
+    writer.flush()
+}
+
+/// SkyWay for ROSを起動し、終了するまで待機する
+/// configはrun_with_configに与えるJSON文字列で、省略した場合はデフォルトの設定値で起動する
+pub fn run(config: Option<&str>, transport: Transport) -> std::io::Result<()> {
+    // 起動後に待ち受けられないことがないよう、先にbindしておく
+    let listener = match transport {
+        Transport::Stdio => None,
+        Transport::UnixSocket(ref path) => {
+            let _ = std::fs::remove_file(path);
+            Some(UnixListener::bind(path)?)
+        }
+    };
+
+    hooks::register();
+    let response = match config {
+        Some(config) => {
+            let config = CString::new(config)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            c_to_rust_bridge::run_with_config(config.as_ptr())
+        }
+        None => c_to_rust_bridge::run(),
+    };
+    if !response.flag {
+        return Err(std::io::Error::other("failed to start skyway"));
+    }
+    // ROSとは異なり起動直後からリクエストを受け付けるので、rust_mainの初期化を待つ
+    while CHANNELS.get().is_none() && !hooks::is_shutting_down() {
+        std::thread::sleep(START_POLL_INTERVAL);
+    }
+
+    std::thread::spawn(wait_for_signal);
+    let broadcast = Arc::new(Broadcast::default());
+    let events = broadcast.clone();
+    std::thread::spawn(move || forward_events(&events));
+
+    match listener {
+        None => {
+            let writer: Writer = Arc::new(Mutex::new(std::io::stdout()));
+            broadcast.add(writer.clone());
+            std::thread::spawn(move || {
+                serve(BufReader::new(std::io::stdin()), &writer, call_service);
+                stop();
+            });
+        }
+        Some(listener) => {
+            std::thread::spawn(move || accept(listener, &broadcast));
+        }
+    }
+
+    hooks::wait_for_shutdown();
+    c_to_rust_bridge::join_handler(response.handler);
+    if let Transport::UnixSocket(path) = transport {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+// SIGINTまたはSIGTERMを受けた場合は、WebRTC Gateway上のオブジェクトを開放してから終了する
+fn wait_for_signal() {
+    let result = RUNTIME.block_on(async {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    });
+    match result {
+        Ok(_) => stop(),
+        Err(e) => hooks::log("ERROR", &format!("signal handler error: {}", e)),
+    }
+}
+
+// C++側のSIGINTハンドラと同様にshutdown_serviceを呼び、このノードが生成したオブジェクトを全て解放してから終了する
+fn stop() {
+    if STOPPING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    c_to_rust_bridge::shutdown_service();
+    hooks::shutdown();
+}
+
+fn accept(listener: UnixListener, broadcast: &Broadcast) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                hooks::log("WARN", &format!("unix socket accept error: {}", e));
+                continue;
+            }
+        };
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(e) => {
+                hooks::log("WARN", &format!("unix socket error: {}", e));
+                continue;
+            }
+        };
+        let writer: Writer = Arc::new(Mutex::new(stream));
+        broadcast.add(writer.clone());
+        // クライアントの切断ではSkyWay for ROSを終了しない
+        std::thread::spawn(move || serve(reader, &writer, call_service));
+    }
+}
+
+// 1行を1つのリクエストとして処理し、レスポンスを1行で書き出す
+fn serve(reader: impl BufRead, writer: &Writer, call: impl Fn(&str) -> String) {
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if write_line(writer, &call(&line)).is_err() {
+            return;
+        }
+    }
+}
+
+fn forward_events(broadcast: &Broadcast) {
+    while !hooks::is_shutting_down() {
+        let message = hooks::copy_and_release(c_to_rust_bridge::receive_events());
+        // 終了時には、終了したことを示すエラーが返される
+        if hooks::is_shutting_down() {
+            return;
+        }
+        broadcast.send(&message);
+    }
+}
+
+fn call_service(message: &str) -> String {
+    // NUL文字を含む場合は、空のリクエストとしてエラーを返させる
+    let message = CString::new(message).unwrap_or_default();
+    hooks::copy_and_release(c_to_rust_bridge::call_service(message.as_ptr()))
+}
+
+#[cfg(test)]
+mod host_test {
+    use super::*;
+
+    fn written(buffer: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(buffer.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    // 空行は読み飛ばし、リクエストごとに1行のレスポンスを書き出す
+    fn serve_lines() {
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let writer: Writer = buffer.clone();
+        let input = "{\"request\":1}\n\n{\"request\":2}\n";
+        serve(input.as_bytes(), &writer, |line| {
+            format!("response to {}", line)
+        });
+        assert_eq!(
+            written(&buffer),
+            "response to {\"request\":1}\nresponse to {\"request\":2}\n"
+        );
+    }
+
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    // イベントは全ての出力先に書き出し、書き込めなくなった出力先は取り除く
+    fn broadcast() {
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let broadcast = Broadcast::default();
+        broadcast.add(buffer.clone());
+        broadcast.add(Arc::new(Mutex::new(Closed)));
+
+        broadcast.send("event 1");
+        assert_eq!(broadcast.writers.lock().unwrap().len(), 1);
+        broadcast.send("event 2");
+        assert_eq!(written(&buffer), "event 1\nevent 2\n");
+    }
+}
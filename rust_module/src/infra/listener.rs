@@ -0,0 +1,104 @@
+// SkyWay Crateを介さずに、既に存在するオブジェクトのイベントをWebRTC Gatewayのlong pollで取得し続けるモジュール
+// SkyWay Crateのイベントと同じ形式のJSONに変換して中継するので、受け取る側は区別しなくてよい
+// SkyWay Crateと同様に、CLOSEを受け取るか、取得に失敗した時点で監視を終える
+//
+// 1つのオブジェクトを重ねて監視するとイベントを取り合うので、監視中のオブジェクトには新たな監視を開始しない
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use skyway_webrtc_gateway_api::{data, media, peer};
+use tokio::sync::mpsc;
+
+use crate::domain::entity::event::{EventMessage, EventSource};
+use crate::domain::entity::response::{DataResponse, MediaResponse, PeerResponse, ResponseResult};
+use crate::domain::entity::{
+    DataConnectionEventEnum, MediaConnectionEventEnum, PeerEventEnum, Stringify,
+};
+
+// 監視中のオブジェクトのID
+static LISTENING: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+/// sourceの監視を開始する。既に監視中の場合は何もせずfalseを返す
+pub(crate) fn spawn(source: EventSource, event_tx: mpsc::Sender<EventMessage>) -> bool {
+    let id = id(&source);
+    if !LISTENING.lock().unwrap().insert(id.clone()) {
+        return false;
+    }
+    tokio::spawn(async move {
+        listen(&source, event_tx).await;
+        LISTENING.lock().unwrap().remove(&id);
+    });
+    true
+}
+
+fn id(source: &EventSource) -> String {
+    match source {
+        EventSource::Peer(peer_info) => peer_info.peer_id().as_str().to_string(),
+        EventSource::Data(data_connection_id) => data_connection_id.as_str().to_string(),
+        EventSource::Media(media_connection_id) => media_connection_id.as_str().to_string(),
+    }
+}
+
+async fn listen(source: &EventSource, event_tx: mpsc::Sender<EventMessage>) {
+    loop {
+        let (event, is_last) = match poll(source).await {
+            Some(result) => result,
+            None => continue,
+        };
+        // ResponseResultのシリアライズには失敗しない
+        let message = event.to_string().unwrap();
+        if event_tx.send(EventMessage::Gateway(message)).await.is_err() || is_last {
+            return;
+        }
+    }
+}
+
+// 1回のlong pollの結果を、SkyWay Crateが返すイベントに変換する
+// 通知の必要がないTIMEOUTはNoneとし、CLOSEと取得の失敗は最後のイベントとしてtrueを添える
+async fn poll(source: &EventSource) -> Option<(ResponseResult, bool)> {
+    match source {
+        EventSource::Peer(peer_info) => match peer::event(peer_info.clone()).await {
+            Ok(PeerEventEnum::TIMEOUT) => None,
+            Ok(event) => {
+                let is_last = matches!(event, PeerEventEnum::CLOSE(_));
+                Some((
+                    PeerResponse::Event(event).create_response_message(),
+                    is_last,
+                ))
+            }
+            Err(e) => Some((
+                ResponseResult::Error(format!("error in EventService for Peer {:?}", e)),
+                true,
+            )),
+        },
+        EventSource::Data(data_connection_id) => match data::event(data_connection_id).await {
+            Ok(DataConnectionEventEnum::TIMEOUT) => None,
+            Ok(event) => {
+                let is_last = matches!(event, DataConnectionEventEnum::CLOSE(_));
+                Some((
+                    DataResponse::Event(event).create_response_message(),
+                    is_last,
+                ))
+            }
+            Err(e) => Some((
+                ResponseResult::Error(format!("error in EventListener for data. {:?}", e)),
+                true,
+            )),
+        },
+        EventSource::Media(media_connection_id) => match media::event(media_connection_id).await {
+            Ok(MediaConnectionEventEnum::TIMEOUT) => None,
+            Ok(event) => {
+                let is_last = matches!(event, MediaConnectionEventEnum::CLOSE(_));
+                Some((
+                    MediaResponse::Event(event).create_response_message(),
+                    is_last,
+                ))
+            }
+            Err(e) => Some((
+                ResponseResult::Error(format!("error in EventListener for Media {:?}", e)),
+                true,
+            )),
+        },
+    }
+}
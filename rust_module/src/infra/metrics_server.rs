@@ -0,0 +1,101 @@
+// SYSTEM METRICSの内容をPrometheusから収集できるよう、HTTPで公開する
+// 設定でmetrics_portを指定した場合のみ、localhostでのみ待ち受ける
+// 1つのリクエストに応答したら接続を閉じる、最小限のHTTP/1.1サーバとして動作する
+use std::future::Future;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// リクエストヘッダとして受け付ける長さの上限
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// 127.0.0.1のportで待ち受ける
+pub(crate) async fn bind(port: u16) -> std::io::Result<TcpListener> {
+    TcpListener::bind(("127.0.0.1", port)).await
+}
+
+/// GET /metricsに対し、renderが生成したテキストを返し続ける
+/// それ以外のパスには404を返す
+pub(crate) async fn serve<F, Fut>(listener: TcpListener, render: F)
+where
+    F: Fn() -> Fut + Send + Sync + Clone + 'static,
+    Fut: Future<Output = String> + Send,
+{
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("metrics server: failed to accept: {}", e);
+                continue;
+            }
+        };
+        let render = render.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, render).await {
+                tracing::debug!("metrics server: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond<F, Fut>(mut stream: TcpStream, render: F) -> std::io::Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = String>,
+{
+    // リクエストボディは使わないので、ヘッダの終端まで読めば十分
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let size = stream.read(&mut buffer).await?;
+        if size == 0 || request.len() + size > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..size]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render().await;
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod metrics_server_test {
+    use super::*;
+
+    async fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn metrics() {
+        let listener = bind(0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, || async { "skyway_peers 1\n".to_string() }));
+
+        let response = get(port, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.ends_with("\r\n\r\nskyway_peers 1\n"));
+
+        let response = get(port, "/").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -1,15 +1,152 @@
 // skyway_webrtc_gateway_callerをInfra層として利用するための薄いラッパー
+pub(crate) mod listener;
+pub(crate) mod metrics_server;
+pub(crate) mod recording;
+#[cfg(test)]
+pub(crate) mod replay;
+pub(crate) mod supervisor;
+
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use shaku::Component;
+use tokio::sync::{mpsc, oneshot};
+use tracing::Instrument;
 
-use crate::domain::entity::request::Request;
-use crate::domain::entity::response::ResponseResult;
-use crate::domain::entity::Stringify;
-use crate::domain::repository::Repository;
+use crate::config::Config;
+use crate::domain::entity::event::{Event, EventMessage, EventSource, SystemEvent};
+use crate::domain::entity::request::{DataRequest, MediaRequest, PeerRequest, Request};
+use crate::domain::entity::response::{
+    DataResponse, MediaResponse, PeerResponse, Response, ResponseResult,
+};
+use crate::domain::entity::{
+    DataIdWrapper, MediaIdWrapper, RtcpIdWrapper, SerializableSocket, Stringify,
+};
+use crate::domain::repository::{Repository, REQUEST_ID, REQUEST_TIMEOUT};
 use crate::error;
-use crate::ffi::rust_to_c_bridge::state_objects::GlobalState;
+use crate::ffi::rust_to_c_bridge::state_objects::{ChannelsImpl, GlobalState};
+
+/// SkyWay Crateを起動し、操作要求を送るSenderとイベントを受け取るReceiverをChannelsとして返す
+/// SkyWay Crate内部のキューの長さは固定なので、設定値の長さのキューを間に挟んで中継する
+/// 中継はsupervisorが行い、SkyWay Crateとの通信が途絶えた場合は再起動する
+pub(crate) fn run(config: &Config) -> ChannelsImpl {
+    let (request_tx, request_rx) =
+        mpsc::channel::<(oneshot::Sender<String>, String)>(config.request_queue_size);
+    let (event_tx, event_rx) = mpsc::channel::<EventMessage>(config.event_queue_size);
+
+    let gateway_url = config.gateway_url.clone();
+    let backoff = crate::utils::Backoff {
+        initial: Duration::from_millis(config.reconnect_initial_backoff_ms),
+        max: Duration::from_millis(config.reconnect_max_backoff_ms),
+    };
+    tokio::spawn(supervisor::supervise(
+        move || {
+            let gateway_url = gateway_url.clone();
+            async move { skyway_webrtc_gateway_caller::run(&gateway_url).await }
+        },
+        backoff,
+        config.reconnect_failure_threshold,
+        request_rx,
+        event_tx.clone(),
+    ));
+
+    ChannelsImpl::new(request_tx, event_tx, tokio::sync::Mutex::new(event_rx))
+}
+
+// リクエストで指定された期限を優先し、指定がなければrequest_typeごとの設定値を用いる
+fn request_timeout(config: &Config, params: &Request) -> Duration {
+    if let Ok(Some(timeout)) = REQUEST_TIMEOUT.try_with(|timeout| *timeout) {
+        return timeout;
+    }
+
+    let timeout_ms = match params {
+        Request::Peer(_) => config.request_timeout.peer_ms,
+        Request::Data(_) => config.request_timeout.data_ms,
+        Request::Media(_) => config.request_timeout.media_ms,
+    };
+    Duration::from_millis(timeout_ms)
+}
+
+fn request_type(params: &Request) -> &'static str {
+    match params {
+        Request::Peer(_) => "PEER",
+        Request::Data(_) => "DATA",
+        Request::Media(_) => "MEDIA",
+    }
+}
+
+// 生成したオブジェクトを削除するためのリクエスト
+// 既存のオブジェクトを操作するだけのリクエストや、取得・削除のリクエストはNoneを返す
+fn release_request(response: &Response) -> Option<Request> {
+    match response {
+        Response::Peer(PeerResponse::Create(peer_info)) => {
+            Some(Request::Peer(PeerRequest::Delete {
+                params: peer_info.clone(),
+            }))
+        }
+        Response::Data(DataResponse::Create(socket)) => socket.get_id().map(|data_id| {
+            Request::Data(DataRequest::Delete {
+                params: DataIdWrapper { data_id },
+            })
+        }),
+        Response::Data(DataResponse::Connect(wrapper)) => {
+            Some(Request::Data(DataRequest::Disconnect {
+                params: wrapper.clone(),
+            }))
+        }
+        Response::Media(MediaResponse::ContentCreate(socket)) => socket.get_id().map(|media_id| {
+            Request::Media(MediaRequest::ContentDelete {
+                params: MediaIdWrapper { media_id },
+            })
+        }),
+        Response::Media(MediaResponse::RtcpCreate(socket)) => socket.get_id().map(|rtcp_id| {
+            Request::Media(MediaRequest::RtcpDelete {
+                params: RtcpIdWrapper { rtcp_id },
+            })
+        }),
+        Response::Media(MediaResponse::Call(wrapper)) => {
+            Some(Request::Media(MediaRequest::Disconnect {
+                params: wrapper.clone(),
+            }))
+        }
+        _ => None,
+    }
+}
+
+// 期限を過ぎた後も応答を待ち続け、成功していた場合は生成されたオブジェクトを削除する
+// 呼び出し元には既にTIMEOUTを返しており、オブジェクトはどこにも記録されないので、削除しなければ管理外として残ってしまう
+async fn release_late_success(
+    response: impl std::future::Future<Output = Result<ResponseResult, error::Error>>,
+    sender: mpsc::Sender<(oneshot::Sender<String>, String)>,
+) {
+    let request = match response.await {
+        Ok(ResponseResult::Success(ref response)) => release_request(response),
+        _ => None,
+    };
+    let request = match request {
+        Some(request) => request,
+        None => return,
+    };
+
+    tracing::warn!(
+        request_type = request_type(&request),
+        "releasing an object created after the request timed out"
+    );
+    let (tx, rx) = oneshot::channel();
+    // Request型である時点でto_stringには失敗しない
+    if sender
+        .send((tx, request.to_string().unwrap()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    match rx.await.map(|message| ResponseResult::from_str(&message)) {
+        Ok(Ok(ResponseResult::Success(_))) => {}
+        _ => tracing::warn!("failed to release an object created after the request timed out"),
+    }
+}
 
 #[derive(Component)]
 #[shaku(interface = Repository)]
@@ -18,8 +155,49 @@ pub(crate) struct RepositoryImpl {
     state: Arc<dyn GlobalState>,
 }
 
+impl RepositoryImpl {
+    // 設定でrecord_pathが指定されている場合は、WebRTC Gatewayとのやり取りを記録する
+    fn repository(&self) -> Box<dyn Repository> {
+        let gateway = GatewayRepository {
+            state: self.state.clone(),
+        };
+        match self.state.recorder() {
+            Some(recorder) => Box::new(recording::RecordingRepository::new(gateway, recorder)),
+            None => Box::new(gateway),
+        }
+    }
+}
+
 #[async_trait]
 impl Repository for RepositoryImpl {
+    async fn register(&self, params: Request) -> Result<ResponseResult, error::Error> {
+        self.repository().register(params).await
+    }
+
+    async fn receive_event(&self) -> Result<Event, error::Error> {
+        self.repository().receive_event().await
+    }
+
+    async fn publish_event(&self, event: SystemEvent) -> Result<(), error::Error> {
+        self.repository().publish_event(event).await
+    }
+
+    async fn listen(&self, source: EventSource) -> Result<(), error::Error> {
+        self.repository().listen(source).await
+    }
+
+    async fn supplement_event(&self, event: ResponseResult) -> Result<(), error::Error> {
+        self.repository().supplement_event(event).await
+    }
+}
+
+/// SkyWay Crateとchannelで通信し、WebRTC Gatewayを操作する
+struct GatewayRepository {
+    state: Arc<dyn GlobalState>,
+}
+
+#[async_trait]
+impl Repository for GatewayRepository {
     async fn register(&self, params: Request) -> Result<ResponseResult, error::Error> {
         // SkyWay Crateからの戻り値を得るためのoneshot channelを生成
         let (channel_message_tx, channel_message_rx) = tokio::sync::oneshot::channel();
@@ -27,42 +205,95 @@ impl Repository for RepositoryImpl {
         // Request型である時点でto_stringには失敗しない
         let message = params.to_string().unwrap();
 
-        let sender = self.state.channels().sender();
+        let sender = self.state.channels().sender().clone();
+        let timeout = request_timeout(self.state.config(), &params);
+        // API keyやtokenを含むので、メッセージ自体はログに出力しない
+        let span = tracing::debug_span!(
+            "gateway_call",
+            request_type = request_type(&params),
+            elapsed_ms = tracing::field::Empty
+        );
+        let started_at = std::time::Instant::now();
 
-        // SkyWay Crateへメッセージを送る
-        // 失敗した場合はエラーメッセージを返す
-        if let Err(_) = sender.send((channel_message_tx, message)).await {
-            return Err(error::Error::create_local_error(
-                "could not send request to skyway crate",
-            ));
-        }
+        let late_sender = sender.clone();
+        let response = async move {
+            tracing::debug!("sending request");
+            // SkyWay Crateへメッセージを送る
+            // 失敗した場合はエラーメッセージを返す
+            if sender.send((channel_message_tx, message)).await.is_err() {
+                return Err(error::Error::gateway_unreachable(
+                    "could not send request to skyway crate",
+                ));
+            }
+
+            // SkyWay Crateからのメッセージを処理する
+            match channel_message_rx.await {
+                Ok(message) => Ok(ResponseResult::from_str(&message)?),
+                Err(_) => Err(error::Error::gateway_unreachable(
+                    "could not receive response from skyway crate",
+                )),
+            }
+        };
+
+        // 期限を過ぎた場合は呼び出し元にはTIMEOUTを返す
+        // WebRTC Gatewayに送信済みの操作要求は取り消されないので、応答は別のtaskで待ち続け、
+        // 後から成功した場合は生成されたオブジェクトを削除する
+        let mut response = Box::pin(response);
+        let result = match tokio::time::timeout(timeout, &mut response)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(result) => result,
+            Err(_) => {
+                tokio::spawn(release_late_success(response, late_sender).instrument(span.clone()));
+                Err(error::Error::Timeout {
+                    timeout_ms: timeout.as_millis() as u64,
+                })
+            }
+        };
+        let elapsed = started_at.elapsed();
+        span.record("elapsed_ms", elapsed.as_millis() as u64);
+        self.state
+            .metrics()
+            .record_gateway_call(request_type(&params), elapsed);
+        span.in_scope(|| match result {
+            Ok(_) => tracing::debug!("received response"),
+            Err(ref e) => tracing::warn!(error = %e, "gateway call failed"),
+        });
 
-        // SkyWay Crateからのメッセージを処理する
-        match channel_message_rx.await {
-            Ok(message) => Ok(ResponseResult::from_str(&message)?),
-            Err(_) => Err(error::Error::create_local_error(
-                "could not receive response from skyway crate",
-            )),
+        // 生成されたオブジェクトのイベントを、このリクエストと結びつけられるようにする
+        // OPENなどのイベントはcall_serviceの応答より先に配信される場合があるので、ここで記録する
+        if let Ok(ResponseResult::Success(ref response)) = result {
+            let _ = REQUEST_ID.try_with(|request_id| {
+                self.state
+                    .correlations()
+                    .record(request_id, &params, response)
+            });
         }
+        result
     }
 
-    async fn receive_event(&self) -> Result<ResponseResult, error::Error> {
-        use std::time::Duration;
-
+    async fn receive_event(&self) -> Result<Event, error::Error> {
         use tokio::time;
         let state = self.state.program_state();
         let channels = self.state.channels();
         let receiver = channels.receiver();
+        let timeout = Duration::from_millis(self.state.config().event_timeout_ms);
 
         while !state.is_shutting_down() {
             let mut rx = receiver.lock().await;
-            match time::timeout(Duration::from_millis(1000), rx.recv()).await {
-                Ok(Some(response_string)) => {
-                    return ResponseResult::from_str(&response_string);
+            match time::timeout(timeout, rx.recv()).await {
+                Ok(Some(EventMessage::Gateway(response_string))) => {
+                    return ResponseResult::from_str(&response_string)
+                        .map(Event::Gateway)
+                        .map_err(error::Error::from);
+                }
+                Ok(Some(EventMessage::System(event))) => {
+                    return Ok(Event::System(event));
                 }
                 Ok(None) => {
                     // closed
-                    return Err(error::Error::create_local_error("receiver is closed"));
+                    return Err(error::Error::gateway_unreachable("receiver is closed"));
                 }
                 Err(_) => {
                     //timeout
@@ -71,22 +302,51 @@ impl Repository for RepositoryImpl {
             }
         }
 
-        return Err(error::Error::create_local_error("ros has been shut down"));
+        return Err(error::Error::internal("ros has been shut down"));
+    }
+
+    async fn publish_event(&self, event: SystemEvent) -> Result<(), error::Error> {
+        let sender = self.state.channels().event_sender();
+        sender
+            .send(EventMessage::System(event))
+            .await
+            .map_err(|_| error::Error::internal("event queue is closed"))
+    }
+
+    // 既に監視中の場合も、イベントは受け取れるので成功とする
+    async fn listen(&self, source: EventSource) -> Result<(), error::Error> {
+        let sender = self.state.channels().event_sender().clone();
+        listener::spawn(source, sender);
+        Ok(())
+    }
+
+    // SkyWay Crateから受け取ったイベントと同様に、JSON文字列として中継する
+    async fn supplement_event(&self, event: ResponseResult) -> Result<(), error::Error> {
+        let sender = self.state.channels().event_sender();
+        sender
+            .send(EventMessage::Gateway(event.to_string()?))
+            .await
+            .map_err(|_| error::Error::internal("event queue is closed"))
     }
 }
 
 #[cfg(test)]
 mod infra_send_message_test {
-    use once_cell::sync::OnceCell;
+    use once_cell::sync::{Lazy, OnceCell};
     use shaku::HasComponent;
     use tokio::sync::{mpsc, oneshot, Mutex};
 
     use super::*;
+    use crate::application::dto::response::ResponseDtoResult;
+    use crate::application::usecase::event::correlation::Correlations;
+    use crate::application::usecase::system::metrics::Metrics;
     use crate::di::RepositoryModule;
     use crate::domain::entity::request::PeerRequest;
-    use crate::domain::entity::{CreatePeerParams, FromStr, PeerId};
+    use crate::domain::entity::{CreatePeerParams, FromStr, PeerId, PeerInfo};
     use crate::ffi::rust_to_c_bridge::state_objects::{Channels, ChannelsImpl, MockGlobalState};
 
+    static CONFIG: OnceCell<Config> = OnceCell::new();
+
     fn create_request() -> Request {
         let inner = PeerRequest::Create {
             params: CreatePeerParams {
@@ -96,7 +356,7 @@ mod infra_send_message_test {
                 turn: false,
             },
         };
-        return Request::Peer(inner);
+        Request::Peer(inner)
     }
 
     #[tokio::test]
@@ -106,20 +366,29 @@ mod infra_send_message_test {
 
         let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
         // eventのtestは他でやるので、txは使わない
-        let (_event_tx, event_rx) = mpsc::channel::<String>(1000);
+        let (_event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
         static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
         let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
             message_tx,
+            _event_tx.clone(),
             Mutex::new(event_rx),
         )));
 
         // GlobalStateのMockを生成
         // message_txを返すために使う
         let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
         state
             .expect_channels()
             .times(1)
             .returning(move || CHANNELS.get().unwrap());
+        state
+            .expect_config()
+            .times(1)
+            .returning(move || CONFIG.get_or_init(Config::default));
 
         // サービスを生成
         let module = RepositoryModule::builder()
@@ -133,14 +402,7 @@ mod infra_send_message_test {
             let (response_message_tx, request_message) = message_rx.recv().await.unwrap();
 
             let request = Request::from_str(&request_message);
-            match request {
-                Ok(Request::Peer(_)) => {
-                    assert!(true)
-                }
-                _ => {
-                    assert!(false)
-                }
-            }
+            assert!(matches!(request, Ok(Request::Peer(_))));
 
             let response_str = r#"{
                 "is_success":true,
@@ -168,20 +430,29 @@ mod infra_send_message_test {
         // WebRTC Gatewayが生成するSenderとReceiver相当のものを作成
         let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
         // eventのtestは他でやるので、txは使わない
-        let (_event_tx, event_rx) = mpsc::channel::<String>(1000);
+        let (_event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
         static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
         let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
             message_tx,
+            _event_tx.clone(),
             Mutex::new(event_rx),
         )));
 
         // GlobalStateのMockを生成
         // message_txを返すために使う
         let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
         state
             .expect_channels()
             .times(1)
             .returning(move || CHANNELS.get().unwrap());
+        state
+            .expect_config()
+            .times(1)
+            .returning(move || CONFIG.get_or_init(Config::default));
 
         // サービスを生成
         let module = RepositoryModule::builder()
@@ -194,23 +465,16 @@ mod infra_send_message_test {
             let (_response_message_tx, request_message) = message_rx.recv().await.unwrap();
 
             let request = Request::from_str(&request_message);
-            match request {
-                Ok(Request::Peer(_)) => {
-                    assert!(true)
-                }
-                _ => {
-                    assert!(false)
-                }
-            }
+            assert!(matches!(request, Ok(Request::Peer(_))));
         });
 
         // 実行
         let result = repository_impl.register(message).await;
         match result {
-            Err(error::Error::LocalError(message)) => {
+            Err(error::Error::GatewayUnreachable { message }) => {
                 assert_eq!(message, "could not receive response from skyway crate");
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
 
@@ -223,20 +487,29 @@ mod infra_send_message_test {
         // WebRTC Gatewayが生成するSenderとReceiver相当のものを作成
         let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
         // eventのtestは他でやるので、txは使わない
-        let (_event_tx, event_rx) = mpsc::channel::<String>(1000);
+        let (_event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
         static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
         let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
             message_tx,
+            _event_tx.clone(),
             Mutex::new(event_rx),
         )));
 
         // GlobalStateのMockを生成
         // message_txを返すために使う
         let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
         state
             .expect_channels()
             .times(1)
             .returning(move || CHANNELS.get().unwrap());
+        state
+            .expect_config()
+            .times(1)
+            .returning(move || CONFIG.get_or_init(Config::default));
 
         // サービスを生成
         let module = RepositoryModule::builder()
@@ -248,14 +521,7 @@ mod infra_send_message_test {
             let (response_message_tx, request_message) = message_rx.recv().await.unwrap();
 
             let request = Request::from_str(&request_message);
-            match request {
-                Ok(Request::Peer(_)) => {
-                    assert!(true)
-                }
-                _ => {
-                    assert!(false)
-                }
-            }
+            assert!(matches!(request, Ok(Request::Peer(_))));
 
             let response_str = r#"{
                 "is_success":true,
@@ -270,12 +536,231 @@ mod infra_send_message_test {
 
         // 実行
         let result = repository_impl.register(message).await;
-        match result {
-            Err(error::Error::SerdeError { error: _ }) => {
-                assert!(true)
+        assert!(matches!(result, Err(error::Error::GatewayApi { .. })));
+    }
+
+    // WebRTC Gatewayが応答を返さないままregisterを呼び出す
+    async fn register_without_response(
+        config: &'static Config,
+    ) -> Result<ResponseResult, error::Error> {
+        let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
+        let (event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
+        let channels: Arc<dyn Channels> = Arc::new(ChannelsImpl::new(
+            message_tx,
+            event_tx,
+            Mutex::new(event_rx),
+        ));
+        let channels: &'static Arc<dyn Channels> = Box::leak(Box::new(channels));
+
+        let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
+        state.expect_channels().returning(move || channels);
+        state.expect_config().returning(move || config);
+
+        let module = RepositoryModule::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let repository_impl: &dyn Repository = module.resolve_ref();
+
+        // oneshotのSenderを保持したまま応答しない
+        tokio::spawn(async move {
+            let (_response_message_tx, _) = message_rx.recv().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        repository_impl.register(create_request()).await
+    }
+
+    #[tokio::test]
+    // request_typeごとの設定値の期限内に応答がないケース
+    async fn timeout() {
+        static CONFIG: OnceCell<Config> = OnceCell::new();
+        let config = CONFIG.get_or_init(|| Config {
+            request_timeout: crate::config::RequestTimeoutConfig {
+                peer_ms: 10,
+                ..Default::default()
+            },
+            ..Config::default()
+        });
+
+        let result = register_without_response(config).await;
+        assert!(matches!(
+            result,
+            Err(error::Error::Timeout { timeout_ms: 10 })
+        ));
+    }
+
+    #[tokio::test]
+    // リクエストで指定された期限が設定値より優先されるケース
+    async fn timeout_overridden_by_request() {
+        let result = REQUEST_TIMEOUT
+            .scope(
+                Some(Duration::from_millis(20)),
+                register_without_response(CONFIG.get_or_init(Config::default)),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(error::Error::Timeout { timeout_ms: 20 })
+        ));
+    }
+
+    #[tokio::test]
+    // 期限を過ぎてから成功の応答が返ったケース
+    // 生成されたPeer Objectは管理外として残らないよう削除されるはずである
+    async fn late_success_is_released() {
+        static CONFIG: OnceCell<Config> = OnceCell::new();
+        let config = CONFIG.get_or_init(|| Config {
+            request_timeout: crate::config::RequestTimeoutConfig {
+                peer_ms: 10,
+                ..Default::default()
+            },
+            ..Config::default()
+        });
+
+        let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
+        let (event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
+        let channels: Arc<dyn Channels> = Arc::new(ChannelsImpl::new(
+            message_tx,
+            event_tx,
+            Mutex::new(event_rx),
+        ));
+        let channels: &'static Arc<dyn Channels> = Box::leak(Box::new(channels));
+
+        let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
+        state.expect_channels().returning(move || channels);
+        state.expect_config().returning(move || config);
+
+        let module = RepositoryModule::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let repository_impl: &dyn Repository = module.resolve_ref();
+
+        let result = repository_impl.register(create_request()).await;
+        assert!(matches!(
+            result,
+            Err(error::Error::Timeout { timeout_ms: 10 })
+        ));
+
+        // 期限を過ぎた後にPEER CREATEの成功を返す
+        let (response_message_tx, _) = message_rx.recv().await.unwrap();
+        let response_str = r#"{
+            "is_success":true,
+            "result":{
+                "request_type":"PEER",
+                "command":"CREATE",
+                "peer_id":"peer_id",
+                "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
             }
-            _ => assert!(false),
-        }
+        }"#;
+        let _ = response_message_tx.send(response_str.into());
+
+        // 生成されたPeer ObjectのPEER DELETEが送られてくる
+        let (_, request_message) = tokio::time::timeout(Duration::from_secs(5), message_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let request = Request::from_str(&request_message).unwrap();
+        assert_eq!(
+            request,
+            Request::Peer(PeerRequest::Delete {
+                params: PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308")
+                    .unwrap()
+            })
+        );
+    }
+
+    #[test]
+    // 既存のオブジェクトを操作するだけのリクエストは、遅れて成功しても何も削除しない
+    fn release_request_for_non_creating_response() {
+        let peer_info =
+            PeerInfo::try_create("peer_id", "pt-9749250e-d157-4f80-9ee2-359ce8524308").unwrap();
+        let response = Response::Peer(PeerResponse::Delete(peer_info));
+        assert_eq!(release_request(&response), None);
+    }
+
+    #[tokio::test]
+    // call_serviceの処理の中で生成されたオブジェクトは、そのリクエストのrequest_idと紐づける
+    async fn correlated_with_request() {
+        let (message_tx, mut message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
+        let (event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
+        static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
+        let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
+            message_tx,
+            event_tx,
+            Mutex::new(event_rx),
+        )));
+        static CORRELATIONS: Lazy<Correlations> = Lazy::new(Correlations::default);
+
+        let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
+        state
+            .expect_channels()
+            .returning(move || CHANNELS.get().unwrap());
+        state
+            .expect_config()
+            .returning(move || CONFIG.get_or_init(Config::default));
+        state
+            .expect_correlations()
+            .times(1)
+            .returning(|| &CORRELATIONS);
+
+        let module = RepositoryModule::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let repository_impl: &dyn Repository = module.resolve_ref();
+
+        tokio::spawn(async move {
+            let (response_message_tx, _) = message_rx.recv().await.unwrap();
+            let response_str = r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"CREATE",
+                    "peer_id":"hoge",
+                    "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                }
+            }"#;
+            let _ = response_message_tx.send(response_str.into());
+        });
+
+        let result = REQUEST_ID
+            .scope(
+                "create-hoge".to_string(),
+                repository_impl.register(create_request()),
+            )
+            .await;
+        assert!(result.is_ok());
+
+        let open = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"EVENT",
+                    "event":"OPEN",
+                    "params":{
+                        "peer_id":"hoge",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            CORRELATIONS.correlate(&open),
+            Some("create-hoge".to_string())
+        );
     }
 }
 
@@ -296,10 +781,11 @@ mod infra_receive_event_test {
         // WebRTC Gatewayが生成するSenderとReceiver相当のものを作成
         let (message_tx, _message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
         // eventのtestは他でやるので、txは使わない
-        let (event_tx, event_rx) = mpsc::channel::<String>(1000);
+        let (event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
         static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
         let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
             message_tx,
+            event_tx.clone(),
             Mutex::new(event_rx),
         )));
 
@@ -312,8 +798,12 @@ mod infra_receive_event_test {
             helper::shutdown,
         ));
 
+        static CONFIG: OnceCell<Config> = OnceCell::new();
+        let _ = CONFIG.set(Config::default());
+
         // GlobalStateのMockを生成
         let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
         state
             .expect_channels()
             .times(1)
@@ -322,6 +812,10 @@ mod infra_receive_event_test {
             .expect_program_state()
             .times(1)
             .returning(move || PROGRAM_STATE_INSTANCE.get().unwrap());
+        state
+            .expect_config()
+            .times(1)
+            .returning(move || CONFIG.get().unwrap());
 
         // サービスを生成
         let module = RepositoryModule::builder()
@@ -339,15 +833,14 @@ mod infra_receive_event_test {
                     "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
                 }
             }"#;
-            let _ = event_tx.send(response_str.to_string()).await;
+            let _ = event_tx
+                .send(EventMessage::Gateway(response_str.to_string()))
+                .await;
         });
 
         // 実行
         let result = repository_impl.receive_event().await;
-        match result {
-            Ok(_) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(result.is_ok());
     }
 
     #[tokio::test]
@@ -356,10 +849,11 @@ mod infra_receive_event_test {
         // WebRTC Gatewayが生成するSenderとReceiver相当のものを作成
         let (message_tx, _message_rx) = mpsc::channel::<(oneshot::Sender<String>, String)>(10);
         // eventのtestは他でやるので、txは使わない
-        let (event_tx, event_rx) = mpsc::channel::<String>(1000);
+        let (event_tx, event_rx) = mpsc::channel::<EventMessage>(1000);
         static CHANNELS: OnceCell<Arc<dyn Channels>> = OnceCell::new();
         let _ = CHANNELS.set(Arc::new(ChannelsImpl::new(
             message_tx,
+            event_tx.clone(),
             Mutex::new(event_rx),
         )));
 
@@ -372,8 +866,12 @@ mod infra_receive_event_test {
             helper::shutdown,
         ));
 
+        static CONFIG: OnceCell<Config> = OnceCell::new();
+        let _ = CONFIG.set(Config::default());
+
         // GlobalStateのMockを生成
         let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
         state
             .expect_channels()
             .times(1)
@@ -382,6 +880,10 @@ mod infra_receive_event_test {
             .expect_program_state()
             .times(1)
             .returning(move || PROGRAM_STATE_INSTANCE.get().unwrap());
+        state
+            .expect_config()
+            .times(1)
+            .returning(move || CONFIG.get().unwrap());
 
         // サービスを生成
         let module = RepositoryModule::builder()
@@ -390,16 +892,324 @@ mod infra_receive_event_test {
         let repository_impl: &dyn Repository = module.resolve_ref();
 
         tokio::spawn(async move {
-            let _ = event_tx.send("invalid json".to_string()).await;
+            let _ = event_tx
+                .send(EventMessage::Gateway("invalid json".to_string()))
+                .await;
         });
 
         // 実行
         let result = repository_impl.receive_event().await;
-        match result {
-            Err(error::Error::SerdeError { error: _ }) => {
-                assert!(true)
+        assert!(matches!(result, Err(error::Error::GatewayApi { .. })));
+    }
+}
+
+#[cfg(test)]
+mod infra_fake_gateway_test {
+    // 模擬WebRTC Gatewayに対してSkyWay Crateを起動し、PEER CREATEからCONNECT, CALL, ANSWERまでを通しで確認する
+    // SkyWay CrateはプロセスでGatewayのURLを1度しか設定できないので、全てのテストで1つの模擬WebRTC Gatewayを共有する
+    use once_cell::sync::Lazy;
+    use serde_json::Value;
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::usecase::system::metrics::Metrics;
+    use crate::application::usecase::system::resume::{ResumeSequence, Resumed};
+    use crate::di::{RepositoryModule, ResumeService};
+    use crate::domain::entity::event::SystemEvent;
+    use crate::domain::entity::response::{DataResponse, MediaResponse, PeerResponse, Response};
+    use crate::domain::entity::{FromStr, PeerInfo, SerializableId, SerializableSocket};
+    use crate::fake_gateway::{Failure, FakeGateway, Scenario};
+    use crate::ffi::rust_to_c_bridge::state_objects::{
+        Channels, MockGlobalState, PeerRegistry, RUNTIME,
+    };
+
+    struct Fixture {
+        gateway: FakeGateway,
+        config: &'static Config,
+        channels: &'static Arc<dyn Channels>,
+        // イベントは全てのテストで同じキューから受け取るので、テストを1つずつ実行する
+        lock: tokio::sync::Mutex<()>,
+    }
+
+    // テストごとのruntimeは終了時に破棄されるので、SkyWay Crateと模擬WebRTC Gatewayは別のruntimeで動かし続ける
+    static FIXTURE: Lazy<Fixture> = Lazy::new(|| {
+        std::thread::spawn(|| {
+            RUNTIME.block_on(async {
+                let scenario = Scenario {
+                    long_poll_timeout_ms: 200,
+                    ..Default::default()
+                };
+                let gateway = FakeGateway::start(0, scenario).await.unwrap();
+                // イベント取得の失敗1回でSkyWay Crateを再起動させる
+                let config: &'static Config = Box::leak(Box::new(Config {
+                    gateway_url: gateway.base_url().to_string(),
+                    reconnect_failure_threshold: 1,
+                    ..Default::default()
+                }));
+                let channels: Arc<dyn Channels> = Arc::new(run(config));
+                Fixture {
+                    gateway,
+                    config,
+                    channels: Box::leak(Box::new(channels)),
+                    lock: tokio::sync::Mutex::new(()),
+                }
+            })
+        })
+        .join()
+        .unwrap()
+    });
+
+    fn state() -> MockGlobalState {
+        let mut state = MockGlobalState::new();
+        state.expect_recorder().returning(|| None);
+        state.expect_config().returning(|| FIXTURE.config);
+        state.expect_channels().returning(|| FIXTURE.channels);
+        state
+            .expect_metrics()
+            .returning(|| Box::leak(Box::new(Metrics::default())));
+        state
+    }
+
+    fn repository() -> Arc<dyn Repository> {
+        let module = RepositoryModule::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state()))
+            .build();
+        module.resolve()
+    }
+
+    async fn register(repository: &Arc<dyn Repository>, request: &str) -> Response {
+        let request = Request::from_str(request).unwrap();
+        match repository.register(request).await {
+            Ok(ResponseResult::Success(response)) => response,
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    // 条件に一致するイベントが届くまで待つ。それ以前のテストで発生したイベントは読み捨てる
+    async fn wait_event(predicate: impl Fn(&Value) -> bool) -> Value {
+        let receiver = FIXTURE.channels.receiver();
+        let wait = async {
+            loop {
+                if let Some(EventMessage::Gateway(message)) = receiver.lock().await.recv().await {
+                    let event: Value = serde_json::from_str(&message).unwrap();
+                    if predicate(&event["result"]) {
+                        return event["result"].clone();
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("event is not received")
+    }
+
+    async fn create_peer(repository: &Arc<dyn Repository>, peer_id: &str) -> String {
+        let request = format!(
+            r#"{{"type":"PEER","command":"CREATE","params":{{"key":"API_KEY","domain":"localhost","peer_id":"{}","turn":false}}}}"#,
+            peer_id
+        );
+        match register(repository, &request).await {
+            Response::Peer(PeerResponse::Create(peer_info)) => {
+                assert_eq!(peer_info.peer_id().as_str(), peer_id);
+                peer_info.token().as_str().to_string()
             }
-            _ => assert!(false),
+            response => panic!("unexpected response {:?}", response),
         }
     }
+
+    #[tokio::test]
+    // DataChannelを確立し、OPEN, CLOSEイベントを受け取る
+    async fn connect() {
+        let _lock = FIXTURE.lock.lock().await;
+        let repository = repository();
+        let token = create_peer(&repository, "e2e_connect").await;
+
+        let data_id = match register(
+            &repository,
+            r#"{"type":"DATA","command":"CREATE","params":true}"#,
+        )
+        .await
+        {
+            Response::Data(DataResponse::Create(socket)) => socket.get_id().unwrap(),
+            response => panic!("unexpected response {:?}", response),
+        };
+        let request = format!(
+            r#"{{"type":"DATA","command":"CONNECT","params":{{"peer_id":"e2e_connect","token":"{}","target_id":"operator","params":{{"data_id":"{}"}}}}}}"#,
+            token,
+            data_id.as_str()
+        );
+        let data_connection_id = match register(&repository, &request).await {
+            Response::Data(DataResponse::Connect(wrapper)) => {
+                wrapper.data_connection_id.as_str().to_string()
+            }
+            response => panic!("unexpected response {:?}", response),
+        };
+        wait_event(|event| {
+            event["event"] == "OPEN" && event["data_connection_id"] == data_connection_id.as_str()
+        })
+        .await;
+
+        let request = format!(
+            r#"{{"type":"DATA","command":"DISCONNECT","params":{{"data_connection_id":"{}"}}}}"#,
+            data_connection_id
+        );
+        register(&repository, &request).await;
+        wait_event(|event| {
+            event["event"] == "CLOSE" && event["data_connection_id"] == data_connection_id.as_str()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    // 映像を送信するMediaConnectionを確立し、READY, STREAMイベントを受け取る
+    async fn call() {
+        let _lock = FIXTURE.lock.lock().await;
+        let repository = repository();
+        let token = create_peer(&repository, "e2e_call").await;
+
+        let media_id = match register(
+            &repository,
+            r#"{"type":"MEDIA","command":"CONTENT_CREATE","params":{"is_video":true}}"#,
+        )
+        .await
+        {
+            Response::Media(MediaResponse::ContentCreate(socket)) => socket.get_id().unwrap(),
+            response => panic!("unexpected response {:?}", response),
+        };
+        let rtcp_id = match register(
+            &repository,
+            r#"{"type":"MEDIA","command":"RTCP_CREATE","params":null}"#,
+        )
+        .await
+        {
+            Response::Media(MediaResponse::RtcpCreate(socket)) => socket.get_id().unwrap(),
+            response => panic!("unexpected response {:?}", response),
+        };
+        let request = format!(
+            r#"{{"type":"MEDIA","command":"CALL","params":{{"peer_id":"e2e_call","token":"{}","target_id":"operator","constraints":{{"video":true,"videoReceiveEnabled":false,"audio":false,"audioReceiveEnabled":false,"video_params":{{"band_width":1500,"codec":"H264","media_id":"{}","rtcp_id":"{}","payload_type":100}}}}}}}}"#,
+            token,
+            media_id.as_str(),
+            rtcp_id.as_str()
+        );
+        let media_connection_id = match register(&repository, &request).await {
+            Response::Media(MediaResponse::Call(wrapper)) => {
+                wrapper.media_connection_id.as_str().to_string()
+            }
+            response => panic!("unexpected response {:?}", response),
+        };
+        for event_name in ["READY", "STREAM"] {
+            wait_event(|event| {
+                event["event"] == event_name
+                    && event["media_connection_id"] == media_connection_id.as_str()
+            })
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    // 相手側からのCALLを受け取ってANSWERし、READYイベントを受け取る
+    async fn answer() {
+        let _lock = FIXTURE.lock.lock().await;
+        let repository = repository();
+        create_peer(&repository, "e2e_answer").await;
+
+        let media_connection_id = FIXTURE.gateway.call_from("e2e_answer", "operator").unwrap();
+        wait_event(|event| {
+            event["event"] == "CALL"
+                && event["call_params"]["media_connection_id"] == media_connection_id.as_str()
+        })
+        .await;
+
+        let request = format!(
+            r#"{{"type":"MEDIA","command":"ANSWER","params":{{"media_connection_id":"{}","answer_query":{{"constraints":{{"video":true,"videoReceiveEnabled":true,"audio":false,"audioReceiveEnabled":false}}}}}}}}"#,
+            media_connection_id
+        );
+        match register(&repository, &request).await {
+            Response::Media(MediaResponse::Answer(answer)) => {
+                assert_eq!(answer.media_connection_id.as_str(), media_connection_id);
+            }
+            response => panic!("unexpected response {:?}", response),
+        }
+        wait_event(|event| {
+            event["event"] == "READY"
+                && event["media_connection_id"] == media_connection_id.as_str()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    // SkyWay Crateの再起動後も、監視を再開したPeer Objectのイベントを受け取れる
+    async fn resume_after_reconnect() {
+        let _lock = FIXTURE.lock.lock().await;
+        let repository = repository();
+        let token = create_peer(&repository, "e2e_reconnect").await;
+
+        // イベント取得の失敗でSkyWay Crateを再起動させる
+        FIXTURE.gateway.fail(Failure {
+            method: "GET".to_string(),
+            path: "/peers/e2e_reconnect/events".to_string(),
+            status: 500,
+            times: Some(1),
+        });
+        let receiver = FIXTURE.channels.receiver();
+        let wait = async {
+            loop {
+                if let Some(EventMessage::System(SystemEvent::GatewayReconnected { .. })) =
+                    receiver.lock().await.recv().await
+                {
+                    return;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("gateway is not reconnected");
+
+        let registry: &'static std::sync::Mutex<PeerRegistry> =
+            Box::leak(Box::new(std::sync::Mutex::new(PeerRegistry::default())));
+        registry
+            .lock()
+            .unwrap()
+            .insert_peer(&PeerInfo::try_create("e2e_reconnect", &token).unwrap());
+        let mut state = state();
+        state.expect_peer_registry().returning(move || registry);
+        let module = ResumeService::builder()
+            .with_component_override::<dyn GlobalState>(Box::new(state))
+            .build();
+        let resume: &dyn ResumeSequence = module.resolve_ref();
+        let steps = resume.execute().await;
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].result, Ok(Resumed::Listening));
+
+        let data_connection_id = FIXTURE
+            .gateway
+            .connect_from("e2e_reconnect", "operator")
+            .unwrap();
+        wait_event(|event| {
+            event["event"] == "CONNECTION" && event.to_string().contains(&data_connection_id)
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    // WebRTC Gatewayが返したエラーは、エラーの応答として返す
+    async fn gateway_error() {
+        let _lock = FIXTURE.lock.lock().await;
+        let repository = repository();
+        FIXTURE.gateway.fail(Failure {
+            method: "POST".to_string(),
+            path: "/data".to_string(),
+            status: 400,
+            times: Some(1),
+        });
+
+        let request =
+            Request::from_str(r#"{"type":"DATA","command":"CREATE","params":true}"#).unwrap();
+        let result = repository.register(request).await;
+        assert!(
+            matches!(result, Ok(ResponseResult::Error(_))),
+            "{:?}",
+            result
+        );
+    }
 }
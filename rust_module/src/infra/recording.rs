@@ -0,0 +1,442 @@
+// WebRTC Gatewayとのやり取りを記録するためのRepositoryのデコレータ
+// 設定でrecord_pathを指定した場合のみ、全てのリクエスト、応答、イベントを時刻とともにJSON Linesとして追記する
+// 記録したファイルはReplayRepositoryで再生でき、現地で発生した不具合を回帰テストとして再現できる
+// ファイルへの書き込みは専用のスレッドで行い、WebRTC Gatewayとのやり取りを書き込みで待たせない
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::entity::event::{Event, EventSource, SystemEvent};
+use crate::domain::entity::request::{PeerRequest, Request};
+use crate::domain::entity::response::ResponseResult;
+use crate::domain::repository::{Repository, REQUEST_ID};
+use crate::error;
+
+// 記録するPEER CREATEのAPI keyを置き換える文字列
+const REDACTED: &str = "REDACTED";
+
+// 記録するtokenを置き換える文字列
+// 再生時にTokenとして読み込めるよう、tokenの形式に合わせる
+const REDACTED_TOKEN: &str = "pt-00000000-0000-0000-0000-000000000000";
+
+// 記録の書き出しの完了を待つ上限
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 記録の1行
+/// 応答は、対応するリクエストと同じsequenceを持つ
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Record {
+    #[serde(rename = "REQUEST")]
+    Request {
+        timestamp_ms: u64,
+        sequence: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        request: Request,
+    },
+    #[serde(rename = "RESPONSE")]
+    Response {
+        timestamp_ms: u64,
+        sequence: u64,
+        elapsed_ms: u64,
+        #[serde(deserialize_with = "deserialize_response")]
+        response: ResponseResult,
+    },
+    #[serde(rename = "ERROR")]
+    Error {
+        timestamp_ms: u64,
+        sequence: u64,
+        elapsed_ms: u64,
+        error: error::Error,
+    },
+    #[serde(rename = "GATEWAY_EVENT")]
+    GatewayEvent {
+        timestamp_ms: u64,
+        #[serde(deserialize_with = "deserialize_response")]
+        event: ResponseResult,
+    },
+    #[serde(rename = "SYSTEM_EVENT")]
+    SystemEvent {
+        timestamp_ms: u64,
+        event: SystemEvent,
+    },
+}
+
+// ResponseResultのDeserializeはSerializeした形式と対応していないので、
+// SkyWay Crateのfrom_strで"is_success"と"result"の形式から読み込む
+fn deserialize_response<'de, D>(deserializer: D) -> Result<ResponseResult, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    ResponseResult::from_str(&value.to_string())
+        .map_err(|e| serde::de::Error::custom(format!("{:?}", e)))
+}
+
+/// API keyとtokenを記録に残さないよう置き換える
+/// ReplayRepositoryも同じ置き換えを行ってからリクエストを照合する
+pub(crate) fn redact(request: &Request) -> Request {
+    let mut request = request.clone();
+    if let Request::Peer(PeerRequest::Create { ref mut params }) = request {
+        params.key = REDACTED.to_string();
+    }
+    serde_json::to_value(&request)
+        .ok()
+        .and_then(|mut value| {
+            redact_token(&mut value);
+            serde_json::from_value(value).ok()
+        })
+        .unwrap_or(request)
+}
+
+// Peer Objectを操作するためのtokenは、リクエスト、応答、イベントのいずれにも含まれるので、
+// 型によらずJSONの"token"という項目を全て置き換える
+fn redact_token(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "token" && value.is_string() {
+                    *value = serde_json::Value::String(REDACTED_TOKEN.to_string());
+                } else {
+                    redact_token(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_token),
+        _ => {}
+    }
+}
+
+fn timestamp_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// 書き込み用のスレッドへの依頼
+enum Message {
+    Line(String),
+    Flush(mpsc::Sender<()>),
+}
+
+/// 記録先のファイル
+/// 再起動の前後を続けて確認できるよう、既存のファイルには追記する
+pub(crate) struct Recorder {
+    sender: mpsc::Sender<Message>,
+    sequence: AtomicU64,
+}
+
+impl Recorder {
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || Self::run(BufWriter::new(file), receiver));
+        Ok(Recorder {
+            sender,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    // 溜まっている記録をまとめて書き込み、依頼がなくなった時点でファイルに反映する
+    fn run(mut writer: BufWriter<std::fs::File>, receiver: mpsc::Receiver<Message>) {
+        while let Ok(message) = receiver.recv() {
+            let mut next = Some(message);
+            while let Some(message) = next {
+                match message {
+                    Message::Line(line) => {
+                        if let Err(e) = writer.write_all(line.as_bytes()) {
+                            tracing::warn!("recorder: failed to write: {}", e);
+                        }
+                    }
+                    Message::Flush(done) => {
+                        if let Err(e) = writer.flush() {
+                            tracing::warn!("recorder: failed to write: {}", e);
+                        }
+                        let _ = done.send(());
+                    }
+                }
+                next = receiver.try_recv().ok();
+            }
+            if let Err(e) = writer.flush() {
+                tracing::warn!("recorder: failed to write: {}", e);
+            }
+        }
+    }
+
+    fn next_sequence(&self) -> u64 {
+        self.sequence.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // 記録に失敗しても本来の処理は続ける
+    fn write(&self, record: &Record) {
+        let mut value = match serde_json::to_value(record) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("recorder: failed to serialize: {}", e);
+                return;
+            }
+        };
+        redact_token(&mut value);
+        let mut line = value.to_string();
+        line.push('\n');
+        if self.sender.send(Message::Line(line)).is_err() {
+            tracing::warn!("recorder: writer thread is not running");
+        }
+    }
+
+    /// それまでに依頼した記録がファイルに書き出されるまで待つ
+    /// 終了時に記録が失われないよう呼び出す。書き込みが滞っている場合もFLUSH_TIMEOUTで諦める
+    pub fn flush(&self) {
+        let (done, receiver) = mpsc::channel();
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = receiver.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+/// innerへの呼び出しをそのまま行い、その内容をRecorderに記録する
+pub(crate) struct RecordingRepository<R> {
+    inner: R,
+    recorder: &'static Recorder,
+}
+
+impl<R: Repository> RecordingRepository<R> {
+    pub fn new(inner: R, recorder: &'static Recorder) -> Self {
+        RecordingRepository { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl<R: Repository> Repository for RecordingRepository<R> {
+    async fn register(&self, params: Request) -> Result<ResponseResult, error::Error> {
+        let sequence = self.recorder.next_sequence();
+        self.recorder.write(&Record::Request {
+            timestamp_ms: timestamp_ms(),
+            sequence,
+            request_id: REQUEST_ID.try_with(|request_id| request_id.clone()).ok(),
+            request: redact(&params),
+        });
+
+        let started_at = Instant::now();
+        let result = self.inner.register(params).await;
+        let elapsed_ms = started_at.elapsed().as_millis() as u64;
+        self.recorder.write(&match result {
+            Ok(ref response) => Record::Response {
+                timestamp_ms: timestamp_ms(),
+                sequence,
+                elapsed_ms,
+                response: response.clone(),
+            },
+            Err(ref e) => Record::Error {
+                timestamp_ms: timestamp_ms(),
+                sequence,
+                elapsed_ms,
+                error: e.clone(),
+            },
+        });
+        result
+    }
+
+    async fn receive_event(&self) -> Result<Event, error::Error> {
+        let result = self.inner.receive_event().await;
+        if let Ok(ref event) = result {
+            self.recorder.write(&match event {
+                Event::Gateway(event) => Record::GatewayEvent {
+                    timestamp_ms: timestamp_ms(),
+                    event: event.clone(),
+                },
+                Event::System(event) => Record::SystemEvent {
+                    timestamp_ms: timestamp_ms(),
+                    event: event.clone(),
+                },
+            });
+        }
+        result
+    }
+
+    // 発行したイベントはreceive_eventで受け取った時点で記録される
+    async fn publish_event(&self, event: SystemEvent) -> Result<(), error::Error> {
+        self.inner.publish_event(event).await
+    }
+
+    // 監視により得たイベントや補ったイベントは、receive_eventで受け取った時点で記録される
+    async fn listen(&self, source: EventSource) -> Result<(), error::Error> {
+        self.inner.listen(source).await
+    }
+
+    async fn supplement_event(&self, event: ResponseResult) -> Result<(), error::Error> {
+        self.inner.supplement_event(event).await
+    }
+}
+
+#[cfg(test)]
+mod recording_test {
+    use super::*;
+    use crate::domain::entity::FromStr;
+    use crate::domain::repository::MockRepository;
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "skyway_record_{}_{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn read(path: &std::path::Path) -> Vec<Record> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    // リクエストと応答を同じsequenceで記録し、API keyとtokenは記録しない
+    async fn record_register() {
+        let path = path("register");
+        let _ = std::fs::remove_file(&path);
+        let recorder: &'static Recorder = Box::leak(Box::new(Recorder::open(&path).unwrap()));
+
+        let response = ResponseResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"CREATE",
+                    "peer_id":"robot",
+                    "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut inner = MockRepository::new();
+        let expected = response.clone();
+        inner
+            .expect_register()
+            .times(1)
+            .return_once(move |_| Ok(expected));
+        inner
+            .expect_register()
+            .times(1)
+            .return_once(|_| Err(error::Error::Timeout { timeout_ms: 100 }));
+        let repository = RecordingRepository::new(inner, recorder);
+
+        let request = Request::from_str(
+            r#"{"type":"PEER","command":"CREATE","params":{"key":"API_KEY","domain":"localhost","peer_id":"robot","turn":false}}"#,
+        )
+        .unwrap();
+        let result = REQUEST_ID
+            .scope(
+                "create-robot".to_string(),
+                repository.register(request.clone()),
+            )
+            .await;
+        assert_eq!(result.unwrap(), response);
+        let result = repository.register(request.clone()).await;
+        assert!(result.is_err());
+
+        recorder.flush();
+        let records = read(&path);
+        assert_eq!(records.len(), 4);
+        match &records[0] {
+            Record::Request {
+                sequence,
+                request_id,
+                request: recorded,
+                ..
+            } => {
+                assert_eq!(*sequence, 1);
+                assert_eq!(request_id.as_deref(), Some("create-robot"));
+                assert_eq!(recorded, &redact(&request));
+                assert_ne!(recorded, &request);
+            }
+            record => panic!("unexpected record {:?}", record),
+        }
+        let redacted = ResponseResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"CREATE",
+                    "peer_id":"robot",
+                    "token":"pt-00000000-0000-0000-0000-000000000000"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(&records[1], Record::Response { sequence: 1, response: recorded, .. } if recorded == &redacted)
+        );
+        assert!(matches!(
+            &records[2],
+            Record::Request {
+                sequence: 2,
+                request_id: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &records[3],
+            Record::Error {
+                sequence: 2,
+                error: error::Error::Timeout { timeout_ms: 100 },
+                ..
+            }
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    // リクエストに含まれるtokenも置き換える
+    fn redact_token_in_request() {
+        let request = Request::from_str(
+            r#"{"type":"PEER","command":"DELETE","params":{"peer_id":"robot","token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}"#,
+        )
+        .unwrap();
+        let expected = Request::from_str(
+            r#"{"type":"PEER","command":"DELETE","params":{"peer_id":"robot","token":"pt-00000000-0000-0000-0000-000000000000"}}"#,
+        )
+        .unwrap();
+        assert_eq!(redact(&request), expected);
+        assert_eq!(redact(&expected), expected);
+    }
+
+    #[tokio::test]
+    // 受け取ったイベントを記録する
+    async fn record_event() {
+        let path = path("event");
+        let _ = std::fs::remove_file(&path);
+        let recorder: &'static Recorder = Box::leak(Box::new(Recorder::open(&path).unwrap()));
+
+        let mut inner = MockRepository::new();
+        inner.expect_receive_event().times(1).returning(|| {
+            Ok(Event::System(SystemEvent::GatewayReconnected {
+                attempts: 2,
+            }))
+        });
+        let repository = RecordingRepository::new(inner, recorder);
+        let _ = repository.receive_event().await;
+
+        recorder.flush();
+        let records = read(&path);
+        assert!(matches!(
+            records.as_slice(),
+            [Record::SystemEvent {
+                event: SystemEvent::GatewayReconnected { attempts: 2 },
+                ..
+            }]
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,260 @@
+// RecordingRepositoryで記録したWebRTC Gatewayとのやり取りを再生するRepository
+// 現地で記録したファイルを与えて、EventReceiveImplや各UseCaseの回帰テストとして利用する
+// registerは記録の中から同じリクエストを記録順に探して、その応答を返す
+// receive_eventは記録したイベントを記録順に返す
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::recording::{redact, Record};
+use crate::domain::entity::event::{Event, EventSource, SystemEvent};
+use crate::domain::entity::request::Request;
+use crate::domain::entity::response::ResponseResult;
+use crate::domain::repository::Repository;
+use crate::error;
+
+pub(crate) struct ReplayRepository {
+    exchanges: Mutex<Vec<(Request, Result<ResponseResult, error::Error>)>>,
+    events: Mutex<VecDeque<Event>>,
+    published: Mutex<Vec<SystemEvent>>,
+}
+
+impl ReplayRepository {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, error::Error> {
+        let jsonl =
+            std::fs::read_to_string(path).map_err(|e| error::Error::internal(e.to_string()))?;
+        Self::parse(&jsonl)
+    }
+
+    /// JSON Linesとして記録された内容から生成する
+    /// 応答が記録されていないリクエストは、再生の対象外とする
+    pub fn parse(jsonl: &str) -> Result<Self, error::Error> {
+        let mut exchanges = vec![];
+        // 再起動の前後でsequenceは重複するので、応答を待っているリクエストのみを対応付ける
+        let mut pending = HashMap::new();
+        let mut events = VecDeque::new();
+        for line in jsonl.lines().filter(|line| !line.trim().is_empty()) {
+            let record = serde_json::from_str::<Record>(line)
+                .map_err(|e| error::Error::internal(format!("invalid record: {}", e)))?;
+            match record {
+                Record::Request {
+                    sequence, request, ..
+                } => {
+                    // 手で書いた記録も照合できるよう、受け取ったリクエストと同じ置き換えを行う
+                    exchanges.push((redact(&request), None));
+                    pending.insert(sequence, exchanges.len() - 1);
+                }
+                Record::Response {
+                    sequence, response, ..
+                } => {
+                    if let Some(index) = pending.remove(&sequence) {
+                        exchanges[index].1 = Some(Ok(response));
+                    }
+                }
+                Record::Error {
+                    sequence, error, ..
+                } => {
+                    if let Some(index) = pending.remove(&sequence) {
+                        exchanges[index].1 = Some(Err(error));
+                    }
+                }
+                Record::GatewayEvent { event, .. } => events.push_back(Event::Gateway(event)),
+                Record::SystemEvent { event, .. } => events.push_back(Event::System(event)),
+            }
+        }
+
+        Ok(ReplayRepository {
+            exchanges: Mutex::new(
+                exchanges
+                    .into_iter()
+                    .filter_map(|(request, result)| Some((request, result?)))
+                    .collect(),
+            ),
+            events: Mutex::new(events),
+            published: Mutex::new(vec![]),
+        })
+    }
+
+    /// 記録された応答とイベントを全て返し終えたか
+    pub fn is_finished(&self) -> bool {
+        self.exchanges.lock().unwrap().is_empty() && self.events.lock().unwrap().is_empty()
+    }
+
+    /// 再生中にpublish_eventで発行されたイベント
+    pub fn published(&self) -> Vec<SystemEvent> {
+        self.published.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Repository for ReplayRepository {
+    async fn register(&self, params: Request) -> Result<ResponseResult, error::Error> {
+        let request = redact(&params);
+        let mut exchanges = self.exchanges.lock().unwrap();
+        match exchanges
+            .iter()
+            .position(|(recorded, _)| recorded == &request)
+        {
+            Some(index) => exchanges.remove(index).1,
+            None => Err(error::Error::internal(format!(
+                "replay: request is not recorded: {:?}",
+                request
+            ))),
+        }
+    }
+
+    async fn receive_event(&self) -> Result<Event, error::Error> {
+        self.events
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| error::Error::gateway_unreachable("replay: no more recorded events"))
+    }
+
+    // 発行されたイベントは記録にも含まれているので、再生はせずに保持のみ行う
+    async fn publish_event(&self, event: SystemEvent) -> Result<(), error::Error> {
+        self.published.lock().unwrap().push(event);
+        Ok(())
+    }
+
+    // 監視により得たイベントや補ったイベントも記録に含まれているので、何もしない
+    async fn listen(&self, _source: EventSource) -> Result<(), error::Error> {
+        Ok(())
+    }
+
+    async fn supplement_event(&self, _event: ResponseResult) -> Result<(), error::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod replay_test {
+    use shaku::HasComponent;
+
+    use super::*;
+    use crate::application::dto::request::RequestDto;
+    use crate::application::dto::response::ResponseDtoResult;
+    use crate::application::usecase::event::EventReceive;
+    use crate::application::usecase::Service;
+    use crate::di::{EventReceiveService, GeneralService};
+
+    // 現地で記録したファイルと同じ形式の記録
+    const RECORDING: &str = r#"
+{"kind":"REQUEST","timestamp_ms":1700000000000,"sequence":1,"request_id":"create-robot","request":{"type":"PEER","command":"CREATE","params":{"key":"REDACTED","domain":"localhost","peer_id":"robot","turn":false}}}
+{"kind":"GATEWAY_EVENT","timestamp_ms":1700000000120,"event":{"is_success":true,"result":{"request_type":"PEER","command":"EVENT","event":"OPEN","params":{"peer_id":"robot","token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}}}
+{"kind":"RESPONSE","timestamp_ms":1700000000150,"sequence":1,"elapsed_ms":150,"response":{"is_success":true,"result":{"request_type":"PEER","command":"CREATE","peer_id":"robot","token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}}
+{"kind":"REQUEST","timestamp_ms":1700000001000,"sequence":2,"request":{"type":"DATA","command":"CREATE","params":true}}
+{"kind":"ERROR","timestamp_ms":1700000011000,"sequence":2,"elapsed_ms":10000,"error":{"code":"TIMEOUT","timeout_ms":10000}}
+{"kind":"SYSTEM_EVENT","timestamp_ms":1700000012000,"event":{"event":"GATEWAY_DISCONNECTED"}}
+"#;
+
+    #[tokio::test]
+    // 記録したイベントを、記録した順にEventReceiveImplで処理させる
+    async fn event_receive() {
+        let path = std::env::temp_dir().join(format!("skyway_replay_{}.jsonl", std::process::id()));
+        std::fs::write(&path, RECORDING).unwrap();
+        let repository = ReplayRepository::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let module = EventReceiveService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn EventReceive = module.resolve_ref();
+
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"EVENT",
+                    "event":"OPEN",
+                    "params":{
+                        "peer_id":"robot",
+                        "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(service.execute().await.unwrap(), expected);
+
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"SYSTEM",
+                    "command":"EVENT",
+                    "event":"GATEWAY_DISCONNECTED"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(service.execute().await.unwrap(), expected);
+
+        // 記録されたイベントを返し終えた
+        assert!(matches!(
+            service.execute().await,
+            Err(error::Error::GatewayUnreachable { .. })
+        ));
+    }
+
+    #[tokio::test]
+    // 記録したリクエストには記録した応答を返す。API keyは照合しない
+    async fn register() {
+        let repository = ReplayRepository::parse(RECORDING).unwrap();
+        let module = GeneralService::builder()
+            .with_component_override::<dyn Repository>(Box::new(repository))
+            .build();
+        let service: &dyn Service = module.resolve_ref();
+
+        let dto = RequestDto::from_str(r#"{"request_type":"DATA","command":"CREATE"}"#).unwrap();
+        assert_eq!(
+            service.execute(dto.clone()).await,
+            Err(error::Error::Timeout { timeout_ms: 10000 })
+        );
+        // 同じリクエストは記録された回数だけ再生する
+        assert!(matches!(
+            service.execute(dto).await,
+            Err(error::Error::Internal { .. })
+        ));
+
+        let dto = RequestDto::from_str(
+            r#"{
+                "request_type":"PEER",
+                "command":"CREATE",
+                "params":{"key":"API_KEY","domain":"localhost","peer_id":"robot","turn":false}
+            }"#,
+        )
+        .unwrap();
+        let expected = ResponseDtoResult::from_str(
+            r#"{
+                "is_success":true,
+                "result":{
+                    "request_type":"PEER",
+                    "command":"CREATE",
+                    "peer_id":"robot",
+                    "token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(service.execute(dto).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    // 発行されたイベントは再生せずに保持する
+    async fn publish_event() {
+        let repository = ReplayRepository::parse("").unwrap();
+        assert!(repository.is_finished());
+        repository
+            .publish_event(SystemEvent::GatewayDisconnected)
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.published(),
+            vec![SystemEvent::GatewayDisconnected]
+        );
+        assert!(repository.receive_event().await.is_err());
+    }
+}
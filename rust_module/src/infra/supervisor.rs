@@ -0,0 +1,473 @@
+// SkyWay Crateとの通信を監視し、通信が途絶えた場合はSkyWay Crateを再起動する
+// Repositoryが利用するSender, Receiverは起動時に生成したものを使い続け、
+// このモジュールがSkyWay CrateのSender, Receiverとの間を中継する。
+// 再起動時は中継先のみを新しいSender, Receiverに差し替えるので、Repository側は再起動を意識しなくてよい
+//
+// SkyWay Crateとのchannelが閉じた場合の他に、WebRTC Gatewayに到達できないという応答や、
+// イベント取得(long poll)の失敗が連続した場合も、通信が途絶えたものとみなして再起動する
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+use crate::domain::entity::event::{EventMessage, SystemEvent};
+use crate::domain::entity::response::{is_network_error, ResponseResult};
+use crate::utils::Backoff;
+
+type RequestSender = mpsc::Sender<(oneshot::Sender<String>, String)>;
+type RequestReceiver = mpsc::Receiver<(oneshot::Sender<String>, String)>;
+
+// 通信の失敗が連続した回数を数える
+// 成功した応答やイベントを受信した時点で数え直す
+struct FailureStreak {
+    count: AtomicU32,
+    threshold: u32,
+}
+
+impl FailureStreak {
+    fn new(threshold: u32) -> Self {
+        FailureStreak {
+            count: AtomicU32::new(0),
+            threshold,
+        }
+    }
+
+    // 失敗が閾値に達した場合はtrueを返し、数え直す
+    fn observe(&self, failed: bool) -> bool {
+        if !failed {
+            self.count.store(0, Ordering::SeqCst);
+            return false;
+        }
+        if self.count.fetch_add(1, Ordering::SeqCst) + 1 >= self.threshold {
+            self.count.store(0, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::SeqCst);
+    }
+}
+
+// 操作要求の応答が、WebRTC Gatewayに到達できなかったことを示すか
+fn is_unreachable_response(message: &str) -> bool {
+    match ResponseResult::from_str(message) {
+        Ok(ResponseResult::Error(message)) => is_network_error(&message),
+        _ => false,
+    }
+}
+
+// イベント取得に失敗したことを示すイベントか
+// SkyWay Crateはlong pollに失敗すると、エラーを1度だけイベントとして通知して監視を終える
+// 解釈できないメッセージは判断の材料にしない
+fn is_failed_event(message: &str) -> Option<bool> {
+    match ResponseResult::from_str(message) {
+        Ok(ResponseResult::Error(_)) => Some(true),
+        Ok(_) => Some(false),
+        Err(_) => None,
+    }
+}
+
+/// connectで生成したSkyWay CrateのSender, Receiverと、
+/// Repositoryが利用するrequest_rx, event_txの間を中継し続ける
+/// request_rxのSenderが全て破棄されるか、event_txのReceiverが破棄された時点で終了する
+/// failure_threshold回連続して通信に失敗した場合も再起動する
+pub(crate) async fn supervise<F, Fut>(
+    connect: F,
+    backoff: Backoff,
+    failure_threshold: u32,
+    mut request_rx: RequestReceiver,
+    event_tx: mpsc::Sender<EventMessage>,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = (RequestSender, mpsc::Receiver<String>)>,
+{
+    // 通信の途絶を検知したら、その世代番号を通知する
+    let (closed_tx, mut closed_rx) = mpsc::channel::<u64>(2);
+
+    let failures = Arc::new(FailureStreak::new(failure_threshold));
+
+    let (gateway_sender, gateway_receiver) = connect().await;
+    let current = Arc::new(RwLock::new((0u64, gateway_sender)));
+
+    // 操作要求の中継は再起動をまたいで継続し、送信先のみを差し替える
+    let request_relay = {
+        let current = current.clone();
+        let closed_tx = closed_tx.clone();
+        let failures = failures.clone();
+        tokio::spawn(async move {
+            while let Some((response_tx, message)) = request_rx.recv().await {
+                let (generation, sender) = current.read().await.clone();
+                // 応答の内容から通信の失敗を数えるため、応答も中継する
+                let (gateway_response_tx, gateway_response_rx) = oneshot::channel::<String>();
+                // 送信に失敗した場合、oneshotのSenderが破棄されるので、要求元にはエラーが返る
+                if sender.send((gateway_response_tx, message)).await.is_err() {
+                    let _ = closed_tx.try_send(generation);
+                    continue;
+                }
+
+                let closed_tx = closed_tx.clone();
+                let failures = failures.clone();
+                tokio::spawn(async move {
+                    if let Ok(response) = gateway_response_rx.await {
+                        if failures.observe(is_unreachable_response(&response)) {
+                            let _ = closed_tx.try_send(generation);
+                        }
+                        let _ = response_tx.send(response);
+                    }
+                });
+            }
+        })
+    };
+
+    let mut event_relay = spawn_event_relay(
+        0,
+        gateway_receiver,
+        event_tx.clone(),
+        closed_tx.clone(),
+        failures.clone(),
+    );
+    let mut generation = 0u64;
+    let mut attempts = 0u32;
+    let mut connected_at = Instant::now();
+
+    loop {
+        tokio::select! {
+            closed = closed_rx.recv() => {
+                match closed {
+                    Some(closed) if closed == generation => {}
+                    // 既に差し替え済みの世代からの通知は無視する
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+            // event_txのReceiverが破棄された場合のみ終了する
+            _ = &mut event_relay => break,
+        }
+
+        if event_tx
+            .send(EventMessage::System(SystemEvent::GatewayDisconnected))
+            .await
+            .is_err()
+        {
+            break;
+        }
+
+        // 十分な時間通信できていた場合は、一時的な障害とみなして待ち時間を初期値に戻す
+        if connected_at.elapsed() > backoff.max {
+            attempts = 0;
+        }
+        attempts += 1;
+        tokio::time::sleep(backoff.delay(attempts)).await;
+
+        let (gateway_sender, gateway_receiver) = connect().await;
+        generation += 1;
+        *current.write().await = (generation, gateway_sender);
+        failures.reset();
+        event_relay.abort();
+        event_relay = spawn_event_relay(
+            generation,
+            gateway_receiver,
+            event_tx.clone(),
+            closed_tx.clone(),
+            failures.clone(),
+        );
+        connected_at = Instant::now();
+
+        if event_tx
+            .send(EventMessage::System(SystemEvent::GatewayReconnected {
+                attempts,
+            }))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    request_relay.abort();
+    event_relay.abort();
+}
+
+fn spawn_event_relay(
+    generation: u64,
+    mut gateway_receiver: mpsc::Receiver<String>,
+    event_tx: mpsc::Sender<EventMessage>,
+    closed_tx: mpsc::Sender<u64>,
+    failures: Arc<FailureStreak>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = gateway_receiver.recv().await {
+            let restart = match is_failed_event(&event) {
+                Some(failed) => failures.observe(failed),
+                None => false,
+            };
+            if event_tx.send(EventMessage::Gateway(event)).await.is_err() {
+                return;
+            }
+            // 失敗を通知するイベント自体は届けた上で再起動させる
+            if restart {
+                let _ = closed_tx.send(generation).await;
+            }
+        }
+        let _ = closed_tx.send(generation).await;
+        // 差し替えられるまで待機する
+        std::future::pending::<()>().await;
+    })
+}
+
+#[cfg(test)]
+mod supervisor_test {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+
+    type GatewaySide = (RequestReceiver, mpsc::Sender<String>);
+
+    // supervise関数にconnectとして与えるmock
+    // 呼ばれるたびに新しいchannelを生成し、SkyWay Crate側の端をテストに渡す
+    fn mock_connect(
+        gateway_tx: mpsc::UnboundedSender<GatewaySide>,
+    ) -> impl Fn() -> std::future::Ready<(RequestSender, mpsc::Receiver<String>)> {
+        let gateway_tx = Mutex::new(gateway_tx);
+        move || {
+            let (message_tx, message_rx) = mpsc::channel(10);
+            let (event_tx, event_rx) = mpsc::channel(10);
+            let _ = gateway_tx.lock().unwrap().send((message_rx, event_tx));
+            std::future::ready((message_tx, event_rx))
+        }
+    }
+
+    fn backoff() -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    // SkyWay Crateとの間でメッセージを中継するケース
+    async fn relay() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            3,
+            request_rx,
+            event_tx,
+        ));
+
+        let (mut message_rx, gateway_event_tx) = gateway_rx.recv().await.unwrap();
+
+        // 操作要求はSkyWay Crateに届く
+        let (response_tx, _response_rx) = oneshot::channel();
+        request_tx
+            .send((response_tx, "request".to_string()))
+            .await
+            .unwrap();
+        let (_, message) = message_rx.recv().await.unwrap();
+        assert_eq!(message, "request");
+
+        // イベントはそのまま中継される
+        gateway_event_tx.send("event".to_string()).await.unwrap();
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::Gateway("event".to_string())
+        );
+    }
+
+    #[tokio::test]
+    // SkyWay Crateとの通信が途絶えたら、再起動して中継先を差し替えるケース
+    async fn reconnect() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            3,
+            request_rx,
+            event_tx,
+        ));
+
+        // SkyWay Crateが終了した
+        let first = gateway_rx.recv().await.unwrap();
+        drop(first);
+
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayDisconnected)
+        );
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayReconnected { attempts: 1 })
+        );
+
+        // 再起動後のSkyWay Crateに操作要求が届き、イベントも中継される
+        let (mut message_rx, gateway_event_tx) = gateway_rx.recv().await.unwrap();
+        let (response_tx, _response_rx) = oneshot::channel();
+        request_tx
+            .send((response_tx, "request".to_string()))
+            .await
+            .unwrap();
+        let (_, message) = message_rx.recv().await.unwrap();
+        assert_eq!(message, "request");
+
+        gateway_event_tx.send("event".to_string()).await.unwrap();
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::Gateway("event".to_string())
+        );
+    }
+
+    #[tokio::test]
+    // 操作要求の送信に失敗した場合は、要求元にエラーを返して再起動するケース
+    async fn request_to_closed_gateway() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            3,
+            request_rx,
+            event_tx,
+        ));
+
+        // SkyWay Crateが操作要求を受け付けなくなった
+        let (message_rx, _gateway_event_tx) = gateway_rx.recv().await.unwrap();
+        drop(message_rx);
+
+        let (response_tx, response_rx) = oneshot::channel();
+        request_tx
+            .send((response_tx, "request".to_string()))
+            .await
+            .unwrap();
+        assert!(response_rx.await.is_err());
+
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayDisconnected)
+        );
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayReconnected { attempts: 1 })
+        );
+    }
+
+    #[tokio::test]
+    // WebRTC Gatewayに到達できないという応答が連続した場合も再起動するケース
+    async fn unreachable_streak() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            3,
+            request_rx,
+            event_tx,
+        ));
+
+        let (mut message_rx, _gateway_event_tx) = gateway_rx.recv().await.unwrap();
+        let unreachable = r#"{"is_success":false,"result":"{\"reason\":\"NetworkError\",\"message\":\"connection refused\"}"}"#;
+        for _ in 0..3 {
+            let (response_tx, response_rx) = oneshot::channel();
+            request_tx
+                .send((response_tx, "request".to_string()))
+                .await
+                .unwrap();
+            let (gateway_response_tx, _) = message_rx.recv().await.unwrap();
+            gateway_response_tx.send(unreachable.to_string()).unwrap();
+            // 応答はそのまま要求元に届く
+            assert_eq!(response_rx.await.unwrap(), unreachable);
+        }
+
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayDisconnected)
+        );
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayReconnected { attempts: 1 })
+        );
+        assert!(gateway_rx.recv().await.is_some());
+    }
+
+    #[tokio::test]
+    // 成功した応答を挟んだ場合は、連続した失敗とはみなさないケース
+    async fn unreachable_streak_is_reset() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            2,
+            request_rx,
+            event_tx,
+        ));
+
+        let (mut message_rx, gateway_event_tx) = gateway_rx.recv().await.unwrap();
+        let unreachable = r#"{"is_success":false,"result":"{\"reason\":\"NetworkError\",\"message\":\"connection refused\"}"}"#;
+        let success = r#"{"is_success":true,"result":{"request_type":"PEER","command":"DELETE","peer_id":"peer_id","token":"pt-9749250e-d157-4f80-9ee2-359ce8524308"}}"#;
+        for response in [unreachable, success, unreachable] {
+            let (response_tx, response_rx) = oneshot::channel();
+            request_tx
+                .send((response_tx, "request".to_string()))
+                .await
+                .unwrap();
+            let (gateway_response_tx, _) = message_rx.recv().await.unwrap();
+            gateway_response_tx.send(response.to_string()).unwrap();
+            assert_eq!(response_rx.await.unwrap(), response);
+        }
+
+        // 再起動していなければ、イベントはそのまま中継される
+        gateway_event_tx.send("event".to_string()).await.unwrap();
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::Gateway("event".to_string())
+        );
+    }
+
+    #[tokio::test]
+    // イベント取得の失敗が連続した場合も再起動するケース
+    async fn event_failure_streak() {
+        let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel();
+        let (_request_tx, request_rx) = mpsc::channel(10);
+        let (event_tx, mut event_rx) = mpsc::channel(10);
+        tokio::spawn(supervise(
+            mock_connect(gateway_tx),
+            backoff(),
+            2,
+            request_rx,
+            event_tx,
+        ));
+
+        let (_message_rx, gateway_event_tx) = gateway_rx.recv().await.unwrap();
+        let failed = r#"{"is_success":false,"result":"error in EventService for Peer"}"#;
+        for _ in 0..2 {
+            gateway_event_tx.send(failed.to_string()).await.unwrap();
+            // 失敗を示すイベントも中継される
+            assert_eq!(
+                event_rx.recv().await.unwrap(),
+                EventMessage::Gateway(failed.to_string())
+            );
+        }
+
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayDisconnected)
+        );
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            EventMessage::System(SystemEvent::GatewayReconnected { attempts: 1 })
+        );
+    }
+}
@@ -3,36 +3,91 @@
 // skyway_webrtc_gateway_controller crate(以下SkyWay Crate)をInfra層として利用し、
 // ROS側で持つべきDomain知識を定義し、サービスを提供するのが主な目的である
 mod application;
+mod config;
 mod di;
 mod domain;
 mod error;
+pub mod fake_gateway;
 mod ffi;
+pub mod host;
 mod infra;
 mod utils;
+mod version;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::config::Config;
 use crate::ffi::rust_to_c_bridge::c_functions_wrapper::{LoggerHolder, ProgramStateHolder};
 use crate::ffi::rust_to_c_bridge::state_objects::{
-    ChannelsImpl, CHANNELS, DATA_CONNECTION_STATE_INSTANCE, MEDIA_CONNECTION_STATE_INSTANCE,
+    CHANNELS, CONFIG, DATA_CONNECTION_STATE_INSTANCE, MEDIA_CONNECTION_STATE_INSTANCE,
+    PEER_REGISTRY_INSTANCE, RECORDER, RECOVERY_STATE_INSTANCE, STARTED_AT, SUBSCRIPTIONS_INSTANCE,
 };
 
-/// C++側から、 `crate::ffi::c_to_rust_bridge::run` 経由で呼ばれる
-pub(crate) async fn rust_main() {
+/// SkyWayControl, SkyWayEventsで送受信するJSONのSchemaを返す
+/// `crate::ffi::c_to_rust_bridge::skyway_protocol_schema` とprotocol_schemaバイナリから利用される
+pub fn protocol_schema() -> String {
+    crate::application::dto::schema::ProtocolSchema::generate().to_json()
+}
+
+/// C++側から、 `crate::ffi::c_to_rust_bridge::run` または
+/// `crate::ffi::c_to_rust_bridge::run_with_config` 経由で呼ばれる
+pub(crate) async fn rust_main(config: Config) {
+    let _ = STARTED_AT.set(std::time::Instant::now());
     let _ = DATA_CONNECTION_STATE_INSTANCE.set(std::sync::Mutex::new(HashMap::new()));
     let _ = MEDIA_CONNECTION_STATE_INSTANCE.set(std::sync::Mutex::new(HashMap::new()));
+    let _ = RECOVERY_STATE_INSTANCE.set(std::sync::Mutex::new(Default::default()));
+    let _ = PEER_REGISTRY_INSTANCE.set(std::sync::Mutex::new(Default::default()));
+    let _ = SUBSCRIPTIONS_INSTANCE.set(
+        crate::application::usecase::event::subscription::Subscriptions::new(
+            config.event_queue_size,
+        ),
+    );
+
+    // 設定されている場合のみ、WebRTC Gatewayとのやり取りを記録する
+    if let Some(path) = config.record_path.as_ref() {
+        match crate::infra::recording::Recorder::open(path) {
+            Ok(recorder) => {
+                let _ = RECORDER.set(recorder);
+            }
+            Err(e) => LoggerHolder::global().error(format!("recorder error: {}", e)),
+        }
+    }
 
-    let (sender, receiver) = skyway_webrtc_gateway_caller::run("http://localhost:8000").await;
+    let channels = crate::infra::run(&config);
+    if CONFIG.set(config).is_err() {
+        LoggerHolder::global().error("CONFIG set error");
+        ProgramStateHolder::global().shutdown();
+    }
     // SkyWay Crateにアクセスするためのsender, receiverを保持する
     // Channels objectに入れた上でOnceCellで保持する
-    let channels = ChannelsImpl::new(sender, tokio::sync::Mutex::new(receiver));
     let result = CHANNELS.set(Arc::new(channels));
     if result.is_err() {
         LoggerHolder::global().error("CHANNELS set error");
         ProgramStateHolder::global().shutdown();
     }
 
+    // WebRTC Gatewayのイベントを処理し、全ての購読に配信し続ける
+    tokio::spawn(crate::application::dispatch_events());
+
+    // 設定されている場合のみ、SYSTEM METRICSの内容をPrometheus向けに公開する
+    if let Some(port) = CONFIG.get().and_then(|config| config.metrics_port) {
+        match crate::infra::metrics_server::bind(port).await {
+            Ok(listener) => {
+                tokio::spawn(crate::infra::metrics_server::serve(
+                    listener,
+                    crate::application::metrics_text,
+                ));
+            }
+            Err(e) => LoggerHolder::global().error(format!("metrics server error: {}", e)),
+        }
+    }
+
+    // 前回異常終了した際にWebRTC Gateway上に残ったオブジェクトを、引き継ぐか削除する
+    if let Some(path) = CONFIG.get().and_then(|config| config.snapshot_path.clone()) {
+        crate::application::reconcile_snapshot(path.into()).await;
+    }
+
     // ROS Serviceからの操作を別スレッドで受け付ける。
     // ROSが終了するまで待機する
     ProgramStateHolder::global().wait_for_shutdown();
@@ -1,4 +1,5 @@
 use std::net::TcpListener;
+use std::time::Duration;
 
 #[allow(dead_code)]
 pub(crate) fn available_port() -> std::io::Result<u16> {
@@ -7,3 +8,36 @@ pub(crate) fn available_port() -> std::io::Result<u16> {
         Err(e) => Err(e),
     }
 }
+
+/// 失敗するたびに待ち時間を倍にしながら再試行するための待ち時間の計算
+pub(crate) struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    // attempts回目の再試行を行う前の待ち時間
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod utils_test {
+    use super::*;
+
+    #[test]
+    fn backoff_delay() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(4), Duration::from_millis(800));
+        // 上限を超えない
+        assert_eq!(backoff.delay(5), Duration::from_millis(1000));
+        assert_eq!(backoff.delay(100), Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,27 @@
+// SYSTEM VERSIONで返すバージョン情報
+
+/// rust_module自身のバージョン
+pub(crate) const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// call_serviceとreceive_eventsでやり取りするJSONの形式のバージョン
+/// 既存のクライアントが扱えなくなる変更を加えた場合はメジャーバージョンを上げる
+pub(crate) const PROTOCOL_VERSION: &str = "1.0.0";
+
+/// 依存しているskyway-webrtc-gateway-callerのバージョン
+/// 依存crateのバージョンはコンパイル時に取得できないので、Cargo.lockと一致することをテストで確認する
+pub(crate) const GATEWAY_CALLER_VERSION: &str = "0.2.1";
+
+#[cfg(test)]
+mod version_test {
+    use super::*;
+
+    #[test]
+    fn gateway_caller_version_matches_lock_file() {
+        let lock = include_str!("../Cargo.lock");
+        let entry = format!(
+            "name = \"skyway-webrtc-gateway-caller\"\nversion = \"{}\"",
+            GATEWAY_CALLER_VERSION
+        );
+        assert!(lock.contains(&entry));
+    }
+}
@@ -25,6 +25,7 @@ using bool_void_func = bool (*)();
 using void_char_char_func = void (*)(char*, char*);
 using void_char_func = void (*)(char*);
 using void_void_func = void (*)();
+using void_uint64_char_func = void (*)(uint64_t, char*);
 using plugin_topicparam_func = PluginLoadResult (*)(char*, uint16_t, char*,
                                                     char*);
 
@@ -43,8 +44,19 @@ struct run_response_t {
 
 void register_callbacks(Function& functions);
 char* call_service(const char* message);
+// call_serviceの非同期版。完了時にrequest_idとレスポンスを引数としてcallbackが呼ばれる
+// callbackはRust側(tokio runtime)のワーカースレッドから呼ばれるので、スレッドセーフに実装すること
+// callbackの中でcall_serviceなど、Rust側の処理の完了を待つ関数を呼び出してはならない
+// messageがUTF-8でない場合もエラーのレスポンスとともにcallbackが呼ばれる。レスポンスはrelease_stringで開放すること
+void call_service_async(const char* message, uint64_t request_id,
+                        void_uint64_char_func callback);
 char* receive_events();
+// EVENT SUBSCRIBEで開始した購読からイベントを取得する
+// subscription_idがnullまたはUTF-8でない場合は、INVALID_REQUESTのエラーを返す
+char* receive_subscription_events(const char* subscription_id);
 void release_string(char* message);
+// プロトコルのJSON Schemaを返す。戻り値はrelease_stringで開放すること
+char* skyway_protocol_schema();
 void create_peer_callback(char* peer_id, char* token);
 void peer_deleted_callback();
 PluginLoadResult create_data_callback(char* parameter);
@@ -58,6 +70,10 @@ void register_program_state(bool_void_func is_running_c,
                             void_void_func wait_for_shutdown_c,
                             void_void_func shutdown_c);
 run_response_t run();
+run_response_t run_with_config(const char* config);
+// 終了時に呼ぶ。このノードが生成したPeer Object, Connection, Socketを全て開放してから戻る
+// 開放後にpeer_deleted_callbackが呼ばれる
+void shutdown_service();
 void join_handler(void* handler);
 
 void print_string(char* message);
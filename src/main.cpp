@@ -17,7 +17,14 @@ int main(int argc, char** argv) {
   register_program_state(is_ok_c, is_shutting_down_c, ros_sleep_c,
                          wait_for_shutdown_c, shutdown_c);
   // Rust側の処理開始
-  run_response_t response = run();
+  // ~configパラメータ(JSON文字列)が与えられた場合はその設定値で起動する
+  std::string config;
+  run_response_t response;
+  if (ros::param::get("~config", config)) {
+    response = run_with_config(config.c_str());
+  } else {
+    response = run();
+  }
 
   if (response.flag) {
     // Rust側の処理が正常に開始した
@@ -30,7 +37,7 @@ int main(int argc, char** argv) {
     ros::waitForShutdown();
   } else {
     // Rust側の処理が正常に開始しなかった
-    // registerを忘れているケースなので通常発生しない
+    // registerを忘れているか、~configパラメータが不正なケース
     ROS_ERROR("some errors occurred");
   }
 
@@ -7,13 +7,14 @@ using fruit::Injector;
 // エンドユーザプログラムから与えられたメッセージをCallerに与え、レスポンスをServiceのClientに返す
 bool EventsServiceImpl::callback(skyway::SkyWayEvents::Request &req,
                                  skyway::SkyWayEvents::Response &res) {
-  res.response = callback_();
+  res.response = callback_(req.subscription_id);
   return true;
 }
 
 // コンストラクタでは、サービス名とCaller内のSender Objectを受け取る
 EventsServiceImpl::EventsServiceImpl(ASSISTED(std::string) name,
-                                     ASSISTED(std::function<std::string()>)
+                                     ASSISTED(
+                                         std::function<std::string(std::string)>)
                                          callback)
     : name_(name), callback_(callback) {
   service_ = nh_.advertiseService(name, &EventsServiceImpl::callback, this);
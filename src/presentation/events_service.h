@@ -6,6 +6,7 @@
 // 終了処理中でなければ、オブザーバに対して通知を行う。
 // 通知を受けたオブザーバーは、Callerからイベントを取得して戻り値として返す。
 // 戻り値を受け取ったらServiceとして返す。
+// リクエストのsubscription_idが空の場合は、既定の購読からイベントを取得する。
 
 // TODO: unit test
 
@@ -30,7 +31,7 @@ class EventsServiceImpl : public EventsService {
   ros::NodeHandle nh_;
   ros::ServiceServer service_;
   std::string name_;
-  std::function<std::string()> callback_;
+  std::function<std::string(std::string)> callback_;
   bool is_running_ = true;
 
   // エンドユーザプログラムから与えられたメッセージをCallerに与え、レスポンスをServiceのClientに返す
@@ -40,7 +41,8 @@ class EventsServiceImpl : public EventsService {
  public:
   // コンストラクタでは、サービス名とCaller内のSender Objectを受け取る
   INJECT(EventsServiceImpl(ASSISTED(std::string) name,
-                           ASSISTED(std::function<std::string()>) callback));
+                           ASSISTED(std::function<std::string(std::string)>)
+                               callback));
   ~EventsServiceImpl() {}
   virtual void Shutdown() override {
     is_running_ = false;
@@ -49,7 +51,7 @@ class EventsServiceImpl : public EventsService {
 };
 
 using EventsServiceFactory = std::function<std::unique_ptr<EventsService>(
-    std::string, std::function<std::string()>)>;
+    std::string, std::function<std::string(std::string)>)>;
 
 fruit::Component<EventsServiceFactory> getEventsServiceComponent();
 
@@ -1,5 +1,8 @@
 #include "router.h"
 
+#include <atomic>
+#include <thread>
+
 namespace {
 std::function<void(int)> shutdown_handler;
 void signal_handler(int signal) { shutdown_handler(signal); }
@@ -25,7 +28,8 @@ RouterImpl::RouterImpl(
   ROS_DEBUG("start /skyway_events");
   // SkyWayEvent Serviceの起動
   event_service_ = event_service_factory_(
-      "skyway_events", std::bind(&RouterImpl::on_event_request, this));
+      "skyway_events", std::bind(&RouterImpl::on_event_request, this,
+                                 std::placeholders::_1));
 }
 
 std::string RouterImpl::on_control_message(std::string request) {
@@ -36,9 +40,12 @@ std::string RouterImpl::on_control_message(std::string request) {
   return response;
 }
 
-std::string RouterImpl::on_event_request() {
+std::string RouterImpl::on_event_request(std::string subscription_id) {
   // これ以降の処理はcallbackを除き全てRust側で実装する
-  char* message = receive_events();
+  // subscription_idが指定されている場合は、EVENT SUBSCRIBEで開始した購読から取得する
+  char* message = subscription_id.empty()
+                      ? receive_events()
+                      : receive_subscription_events(subscription_id.c_str());
   // Rust側でCString.into_raw()しているので、開放が必要
   std::string event = message;
   release_string(message);
@@ -47,14 +54,17 @@ std::string RouterImpl::on_event_request() {
 
 void RouterImpl::shutdown(int signal) {
   // ctrl-cを受けたあとの終了処理は全てここで行う
-
-  // Peer Object生成後であれば、勝手に終了されると
-  // Controllerが困るので、何もしない
-  if (peer_id_ != "" && token_ != "") {
-  } else {
-    // Peer Object生成前であれば終了してしまう
-    ros::shutdown();
+  // Rust側で、このノードが生成したPeer Object, Connection, Socketを全て開放してからROSを終了させる
+  // シグナルにより中断されたスレッドがRust側のロックを保持している場合があるので、
+  // ハンドラの中では待たずに別スレッドで開放処理を行う
+  static std::atomic_flag is_shutting_down = ATOMIC_FLAG_INIT;
+  if (is_shutting_down.test_and_set()) {
+    return;
   }
+  std::thread([] {
+    shutdown_service();
+    ros::shutdown();
+  }).detach();
 }
 
 void RouterImpl::OnCreatePeer(char* peer_id, char* token) {
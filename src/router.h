@@ -44,7 +44,7 @@ class RouterImpl : public Router {
 
   void shutdown(int signal);
   std::string on_control_message(std::string);
-  std::string on_event_request();
+  std::string on_event_request(std::string subscription_id);
 
  public:
   RouterImpl() = delete;